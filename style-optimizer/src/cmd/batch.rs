@@ -0,0 +1,282 @@
+//! `optimize --batch`: optimize many styles with one MIR load, in parallel.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use maplibre_style_optimizer::complexity::complexity_report;
+use maplibre_style_optimizer::{OptimizeReport, SkippedPass};
+use maplibre_style_spec::validate::validate_style_value;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde_json::Value;
+
+use super::optimize::Optimizer;
+
+/// Size and complexity of one style before and after optimization.
+#[derive(Debug, Default, Clone, Copy)]
+struct Metrics {
+    bytes: usize,
+    layers: usize,
+    expression_nodes: usize,
+}
+
+impl std::ops::AddAssign for Metrics {
+    fn add_assign(&mut self, rhs: Self) {
+        self.bytes += rhs.bytes;
+        self.layers += rhs.layers;
+        self.expression_nodes += rhs.expression_nodes;
+    }
+}
+
+struct Outcome {
+    before: Metrics,
+    after: Metrics,
+    /// Skipped passes, with `--allow-skipped-passes`.
+    skipped: Vec<SkippedPass>,
+}
+
+pub(super) fn run(optimizer: &Optimizer<'_>, pattern: &str, out_dir: &Path) -> anyhow::Result<()> {
+    let inputs = collect_inputs(pattern)?;
+    fs::create_dir_all(out_dir).with_context(|| out_dir.display().to_string())?;
+
+    let results: Vec<(&PathBuf, anyhow::Result<Outcome>)> = inputs
+        .par_iter()
+        .map(|input| (input, optimize_file(optimizer, input, out_dir)))
+        .collect();
+
+    report(&results, &mut std::io::stderr())
+}
+
+/// Write a line per style and the aggregated totals to `out`.  Fails when any
+/// style failed, so the command exits nonzero.
+fn report(
+    results: &[(&PathBuf, anyhow::Result<Outcome>)],
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    let mut total_before = Metrics::default();
+    let mut total_after = Metrics::default();
+    let mut failed = 0;
+    for (input, result) in results {
+        let name = input.display();
+        match result {
+            Ok(Outcome {
+                before,
+                after,
+                skipped,
+            }) => {
+                writeln!(out, "  {name}: {}", summary(before, after))?;
+                for skip in skipped {
+                    writeln!(out, "    warning: {skip}")?;
+                }
+                total_before += *before;
+                total_after += *after;
+            }
+            Err(e) => {
+                writeln!(out, "  {name}: FAILED: {e:#}")?;
+                failed += 1;
+            }
+        }
+    }
+    writeln!(
+        out,
+        "Styles: {} optimized, {failed} failed; {}",
+        results.len() - failed,
+        summary(&total_before, &total_after)
+    )?;
+
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} styles failed", results.len());
+    }
+    Ok(())
+}
+
+/// Style files named by `pattern`: the `*.json` files of a directory, or the
+/// files matching a glob.  Sorted, so the summary is stable.
+fn collect_inputs(pattern: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let mut inputs: Vec<PathBuf> = if Path::new(pattern).is_dir() {
+        fs::read_dir(pattern)
+            .with_context(|| pattern.to_owned())?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<std::io::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "json"))
+            .collect()
+    } else {
+        glob::glob(pattern)
+            .with_context(|| format!("invalid glob `{pattern}`"))?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|p| p.is_file())
+            .collect()
+    };
+    inputs.sort();
+    if inputs.is_empty() {
+        anyhow::bail!("no style files match `{pattern}`");
+    }
+
+    // Outputs are named after the input file, so names must be unique.
+    let mut names = HashSet::new();
+    for input in &inputs {
+        if !names.insert(input.file_name()) {
+            anyhow::bail!(
+                "several inputs are named `{}`; they would overwrite each other in the output directory",
+                input.display()
+            );
+        }
+    }
+    Ok(inputs)
+}
+
+/// Optimize and validate one style, writing it to `out_dir` only if it is valid.
+fn optimize_file(
+    optimizer: &Optimizer<'_>,
+    input: &Path,
+    out_dir: &Path,
+) -> anyhow::Result<Outcome> {
+    let text = fs::read_to_string(input).with_context(|| input.display().to_string())?;
+    let mut value: Value = serde_json::from_str(&text).context("parse style JSON")?;
+    let before = metrics(optimizer, &mut value, text.len());
+
+    let outcome = optimizer.optimize(&mut value, &mut OptimizeReport::default())?;
+    validate_style_value(&value)
+        .map_err(anyhow::Error::msg)
+        .context("validation")?;
+
+    let out = optimizer.serialize(&value)?;
+    let output = output_path(input, out_dir)?;
+    fs::write(&output, &out).with_context(|| output.display().to_string())?;
+    Ok(Outcome {
+        before,
+        after: metrics(optimizer, &mut value, out.len()),
+        skipped: outcome.skipped,
+    })
+}
+
+/// Where the optimized `input` is written: its file name, in `out_dir`.
+fn output_path(input: &Path, out_dir: &Path) -> anyhow::Result<PathBuf> {
+    Ok(out_dir.join(input.file_name().context("input has no file name")?))
+}
+
+fn metrics(optimizer: &Optimizer<'_>, value: &mut Value, bytes: usize) -> Metrics {
+    let report = complexity_report(value, optimizer.mir);
+    Metrics {
+        bytes,
+        layers: report.layer_count,
+        expression_nodes: report.total_expression_nodes,
+    }
+}
+
+fn summary(before: &Metrics, after: &Metrics) -> String {
+    format!(
+        "{} → {} bytes ({}), {} → {} layers, {} → {} expression nodes",
+        before.bytes,
+        after.bytes,
+        percent_change(before.bytes, after.bytes),
+        before.layers,
+        after.layers,
+        before.expression_nodes,
+        after.expression_nodes,
+    )
+}
+
+#[expect(clippy::cast_precision_loss)]
+fn percent_change(before: usize, after: usize) -> String {
+    if before == 0 {
+        return "n/a".into();
+    }
+    format!(
+        "{:+.1}%",
+        (after as f64 - before as f64) / before as f64 * 100.0
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(before_bytes: usize, after_bytes: usize) -> Outcome {
+        Outcome {
+            before: Metrics {
+                bytes: before_bytes,
+                layers: 4,
+                expression_nodes: 10,
+            },
+            after: Metrics {
+                bytes: after_bytes,
+                layers: 2,
+                expression_nodes: 6,
+            },
+            skipped: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn summary_aggregates_every_style() {
+        let (a, b) = (PathBuf::from("a.json"), PathBuf::from("b.json"));
+        let results = vec![(&a, Ok(outcome(100, 80))), (&b, Ok(outcome(300, 120)))];
+        let mut out = Vec::new();
+        report(&results, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "  a.json: 100 → 80 bytes (-20.0%), 4 → 2 layers, 10 → 6 expression nodes\n\
+             \x20 b.json: 300 → 120 bytes (-60.0%), 4 → 2 layers, 10 → 6 expression nodes\n\
+             Styles: 2 optimized, 0 failed; \
+             400 → 200 bytes (-50.0%), 8 → 4 layers, 20 → 12 expression nodes\n"
+        );
+    }
+
+    #[test]
+    fn one_failed_style_fails_the_run() {
+        let (a, b) = (PathBuf::from("a.json"), PathBuf::from("b.json"));
+        let results = vec![
+            (&a, Ok(outcome(100, 80))),
+            (&b, Err(anyhow::anyhow!("validation"))),
+        ];
+        let mut out = Vec::new();
+        let err = report(&results, &mut out).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 styles failed");
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("  b.json: FAILED: validation\n"));
+        assert!(out.contains("Styles: 1 optimized, 1 failed; 100 → 80 bytes"));
+    }
+
+    #[test]
+    fn outputs_keep_input_file_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("nested")).unwrap();
+        for file in ["b.json", "a.json", "notes.txt", "nested/c.json"] {
+            fs::write(root.join(file), "{}").unwrap();
+        }
+
+        let inputs = collect_inputs(root.to_str().unwrap()).unwrap();
+        assert_eq!(inputs, [root.join("a.json"), root.join("b.json")]);
+        let out_dir = Path::new("out");
+        assert_eq!(
+            output_path(&inputs[0], out_dir).unwrap(),
+            out_dir.join("a.json")
+        );
+
+        let glob = format!("{}/**/*.json", root.display());
+        let inputs = collect_inputs(&glob).unwrap();
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(
+            output_path(&root.join("nested/c.json"), out_dir).unwrap(),
+            out_dir.join("c.json")
+        );
+    }
+
+    #[test]
+    fn inputs_sharing_a_file_name_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["x", "y"] {
+            fs::create_dir(dir.path().join(sub)).unwrap();
+            fs::write(dir.path().join(sub).join("style.json"), "{}").unwrap();
+        }
+        let glob = format!("{}/*/style.json", dir.path().display());
+        let err = collect_inputs(&glob).unwrap_err();
+        assert!(err.to_string().contains("would overwrite each other"));
+    }
+}
@@ -0,0 +1,215 @@
+//! Optimizer config file (`maplibre-optimizer.toml`, or JSON).
+//!
+//! ```toml
+//! reference = "upstream/src/reference/v8.json"
+//! level = "O2"
+//! passes = ["layer_merge"]
+//! keep-layers = ["water"]
+//!
+//! [stats]
+//! openmaptiles = "stats/openmaptiles.json"
+//!
+//! [[layer]]
+//! id = "poi-*"
+//! skip = ["layer_merge"]
+//! ```
+//!
+//! Paths are relative to the config file.  Command-line flags add to (or, for
+//! single values such as `-O`, override) the config.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use maplibre_style_optimizer::{LayerOverride, OptLevel, PassRegistry, TileStatistics};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// File name looked up in the working directory and its ancestors.
+pub(super) const CONFIG_FILE_NAME: &str = "maplibre-optimizer.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(super) struct Config {
+    /// `v8.json` style reference.
+    reference: Option<PathBuf>,
+    /// Optimization level preset, as for `-O`.
+    level: Option<String>,
+    /// Pass names, as for `--passes`.
+    pub(super) passes: Vec<String>,
+    pub(super) keep_layers: Vec<String>,
+    pub(super) keep_layer_regex: Vec<String>,
+    /// Global-state values, as for `--state`.
+    pub(super) state: Map<String, Value>,
+    /// Collinear stop tolerance, as for `--stop-tolerance`.
+    pub(super) stop_tolerance: Option<f64>,
+    /// Layer merge cost weight, as for `--merge-cost-weight`.
+    pub(super) merge_cost_weight: Option<f64>,
+    /// Layer split cardinality threshold, as for `--split-cardinality`.
+    pub(super) split_cardinality: Option<u64>,
+    /// `TileStatistics` JSON per source name.
+    stats: BTreeMap<String, PathBuf>,
+    /// Per-layer pass opt-outs.
+    #[serde(rename = "layer")]
+    layers: Vec<LayerRule>,
+
+    /// Directory relative paths are resolved against.
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayerRule {
+    /// Layer id, or a glob over ids (`poi-*`).
+    id: String,
+    /// Passes to skip; every pass when empty or absent.
+    #[serde(default)]
+    skip: Vec<String>,
+}
+
+impl Config {
+    /// Load `path`, or the discovered [`CONFIG_FILE_NAME`] when `path` is
+    /// `None`.  No file means an empty config.
+    pub(super) fn load(path: Option<&Path>, discover: bool) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None if discover => match find_config()? {
+                Some(path) => path,
+                None => return Ok(Self::default()),
+            },
+            None => return Ok(Self::default()),
+        };
+        let text =
+            fs::read_to_string(&path).with_context(|| format!("read config {}", path.display()))?;
+        let mut config: Self = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::from_str(&text)
+                .with_context(|| format!("parse config JSON {}", path.display()))?
+        } else {
+            toml::from_str(&text).with_context(|| format!("parse config {}", path.display()))?
+        };
+        config.dir = path.parent().map(Path::to_owned).unwrap_or_default();
+        Ok(config)
+    }
+
+    pub(super) fn reference(&self) -> Option<PathBuf> {
+        self.reference.as_ref().map(|p| self.dir.join(p))
+    }
+
+    pub(super) fn level(&self) -> anyhow::Result<Option<OptLevel>> {
+        self.level
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(anyhow::Error::msg)
+            .context("config `level`")
+    }
+
+    /// Per-layer overrides; every skipped name must be a registered pass.
+    pub(super) fn layer_overrides(
+        &self,
+        registry: &PassRegistry,
+    ) -> anyhow::Result<Vec<LayerOverride>> {
+        self.layers
+            .iter()
+            .map(|rule| {
+                let rule_override = LayerOverride::new(&rule.id, rule.skip.clone())
+                    .with_context(|| format!("config layer `{}`", rule.id))?;
+                if let Some(name) = rule_override.passes().find(|p| !registry.contains(p)) {
+                    anyhow::bail!(
+                        "config layer `{}`: unknown pass `{name}` (available: {})",
+                        rule.id,
+                        registry.names().collect::<Vec<_>>().join(", ")
+                    );
+                }
+                Ok(rule_override)
+            })
+            .collect()
+    }
+
+    /// Add the per-source stats files to `stats`.  Sources `stats` already
+    /// covers keep their statistics.
+    pub(super) fn merge_stats(
+        &self,
+        stats: Option<TileStatistics>,
+    ) -> anyhow::Result<Option<TileStatistics>> {
+        let mut merged = stats;
+        for (source, path) in &self.stats {
+            let path = self.dir.join(path);
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("read stats {}", path.display()))?;
+            let mut file: TileStatistics = serde_json::from_str(&text)
+                .with_context(|| format!("parse stats JSON {}", path.display()))?;
+            let source_stats = match file.sources.remove(source) {
+                Some(s) => s,
+                None if file.sources.len() == 1 => {
+                    file.sources.into_values().next().expect("one source")
+                }
+                None => anyhow::bail!("{} has no statistics for source `{source}`", path.display()),
+            };
+            let merged = merged.get_or_insert_with(|| TileStatistics {
+                sources: BTreeMap::new(),
+                sample_rate: file.sample_rate,
+            });
+            merged.sample_rate = merged.sample_rate.min(file.sample_rate);
+            merged.sources.entry(source.clone()).or_insert(source_stats);
+        }
+        Ok(merged)
+    }
+}
+
+fn find_config() -> anyhow::Result<Option<PathBuf>> {
+    let cwd = std::env::current_dir().context("current directory")?;
+    Ok(cwd
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|path| path.is_file()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_toml_with_layer_rules() {
+        let config: Config = toml::from_str(
+            r#"
+            level = "Os"
+            passes = ["layer_merge"]
+            keep-layers = ["water"]
+
+            [state]
+            theme = "dark"
+
+            [stats]
+            openmaptiles = "stats/omt.json"
+
+            [[layer]]
+            id = "poi-*"
+            skip = ["layer_merge"]
+
+            [[layer]]
+            id = "hillshade"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.level().unwrap(), Some(OptLevel::Os));
+        assert_eq!(config.keep_layers, ["water"]);
+        assert_eq!(config.state["theme"], "dark");
+        assert_eq!(config.stats["openmaptiles"], Path::new("stats/omt.json"));
+        let overrides = config.layer_overrides(&PassRegistry::builtin()).unwrap();
+        assert_eq!(overrides.len(), 2);
+        assert_eq!(overrides[1].passes().count(), 0);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_passes() {
+        assert!(toml::from_str::<Config>("levle = \"O2\"").is_err());
+        let config: Config = toml::from_str("[[layer]]\nid = \"x\"\nskip = [\"nope\"]").unwrap();
+        let err = config
+            .layer_overrides(&PassRegistry::builtin())
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown pass `nope`"));
+    }
+}
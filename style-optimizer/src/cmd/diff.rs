@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Args;
+use maplibre_style_optimizer::diff::diff_styles;
+
+/// Compare two style JSON documents layer by layer.
+///
+/// Both styles are parsed into the typed style model first, so reordered keys and
+/// equivalent number spellings are not reported.  Layers are matched by `id`.
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// Old (e.g. original) style JSON path.
+    #[arg(long)]
+    old: PathBuf,
+
+    /// New (e.g. optimized) style JSON path.
+    #[arg(long)]
+    new: PathBuf,
+
+    /// Write an RFC 6902 JSON Patch turning the old style into the new one to this path.
+    #[arg(long)]
+    patch: Option<PathBuf>,
+
+    /// Print the diff as JSON instead of a readable summary.
+    #[arg(long)]
+    json: bool,
+}
+
+fn read_style(path: &Path) -> anyhow::Result<serde_json::Value> {
+    let text = fs::read_to_string(path).with_context(|| path.display().to_string())?;
+    serde_json::from_str(&text).with_context(|| format!("parse style JSON {}", path.display()))
+}
+
+pub fn run(args: &DiffArgs) -> anyhow::Result<()> {
+    let old = read_style(&args.old)?;
+    let new = read_style(&args.new)?;
+    let diff = diff_styles(&old, &new)?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        print!("{diff}");
+    }
+
+    if let Some(patch_path) = &args.patch {
+        let patch_json = serde_json::to_string_pretty(diff.patch())?;
+        fs::write(patch_path, patch_json).with_context(|| patch_path.display().to_string())?;
+    }
+
+    Ok(())
+}
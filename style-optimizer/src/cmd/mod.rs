@@ -1,4 +1,7 @@
 pub mod advisory;
+mod batch;
 pub mod complexity;
+mod config;
+pub mod diff;
 pub mod optimize;
 pub mod stats;
@@ -1,30 +1,66 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use clap::Args;
 use maplibre_style_optimizer::{
-    OptPasses, TileStatistics, compute_advisory, ensure_expression_operator,
-    load_intermediate_spec_from_v8_path, optimize_style_json_value_with_stats,
+    ChangeLog, KeepLayers, LayerMap, MergeCostWeight, OptLevel, OptPasses, OptimizeError,
+    OptimizeOutcome, OptimizeReport, PassRegistry, SplitCardinality, StopTolerance, TileStatistics,
+    compute_advisory, ensure_expression_operator, load_intermediate_spec_from_v8_path,
 };
+use maplibre_style_spec::mir::MirSpec;
 use maplibre_style_spec::validate::validate_style_value;
+use serde_json::Value;
+
+use super::batch;
+use super::config::Config;
 
 /// Optimize a `MapLibre` style JSON document (preserves unmodeled root keys).
 #[derive(Args, Debug)]
 #[expect(clippy::struct_excessive_bools)]
 pub struct OptimizeArgs {
     /// Input style JSON path.
-    #[arg(long)]
-    input: PathBuf,
+    #[arg(long, required_unless_present = "batch")]
+    input: Option<PathBuf>,
 
     /// Output style JSON path.
+    #[arg(long, required_unless_present = "batch")]
+    output: Option<PathBuf>,
+
+    /// Batch mode: optimize every `*.json` style in this directory, or every
+    /// file matching this glob (e.g. `'styles/**/*.json'`), into `--out-dir`.
+    ///
+    /// Styles are optimized in parallel and always validated.  Prints a size
+    /// and complexity summary and exits nonzero if any style fails.
+    #[arg(
+        long,
+        value_name = "DIR|GLOB",
+        conflicts_with_all = ["input", "output", "advisory", "explain", "layer_map"],
+        requires = "out_dir"
+    )]
+    batch: Option<String>,
+
+    /// Output directory for `--batch`; each style keeps its file name.
     #[arg(long)]
-    output: PathBuf,
+    out_dir: Option<PathBuf>,
 
-    /// Path to `v8.json` style reference (defaults to repo `upstream/src/reference/v8.json`).
+    /// Path to `v8.json` style reference (defaults to the config's `reference`,
+    /// then repo `upstream/src/reference/v8.json`).
     #[arg(long)]
     reference: Option<PathBuf>,
 
+    /// Config file (TOML, or JSON by extension) with pass selection, stats
+    /// per source, the reference path and per-layer pass opt-outs.
+    ///
+    /// Defaults to the first `maplibre-optimizer.toml` found in the working
+    /// directory or its ancestors.  Flags add to, or override, its settings.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Ignore `maplibre-optimizer.toml` files.
+    #[arg(long, conflicts_with = "config")]
+    no_config: bool,
+
     /// Load pre-computed `TileStatistics` JSON and enable data-driven passes.
     ///
     /// The stats file must use the same source key names as the style's `"sources"` map.
@@ -33,10 +69,47 @@ pub struct OptimizeArgs {
     #[arg(long)]
     stats: Option<PathBuf>,
 
-    /// Enable all optimization passes (overrides individual flags).
+    /// Enable all optimization passes (overrides individual flags and `-O`).
     #[arg(long)]
     all: bool,
 
+    /// Optimization level preset: `-O0` (nothing) to `-O3` (adds layer merging
+    /// and stats-driven passes), or `-Os` to favour output size.
+    ///
+    /// Individual flags and `--passes` add to the preset.  The preset is
+    /// recorded in the output style's root `metadata`.
+    #[arg(short = 'O', value_name = "LEVEL")]
+    opt_level: Option<OptLevel>,
+
+    /// Comma-separated pass names to enable in addition to individual flags.
+    ///
+    /// Accepts every flag name below in `snake_case` (e.g. `constant_fold,cleanup`)
+    /// and every registered pass name (e.g. `normalize_fold`).  Passes run in
+    /// dependency order regardless of the order given here.
+    #[arg(long, value_delimiter = ',')]
+    passes: Vec<String>,
+
+    /// Layer id that must survive optimization: never removed by
+    /// `dead_elimination`/`cleanup` nor merged by `layer_merge`.  Repeatable.
+    #[arg(long, value_name = "ID")]
+    keep_layer: Vec<String>,
+
+    /// Like `--keep-layer`, for every layer id matching this regular
+    /// expression (unanchored).  Repeatable.
+    #[arg(long, value_name = "REGEX")]
+    keep_layer_regex: Vec<String>,
+
+    /// Specialize the style to a global-state value: fold
+    /// `["global-state", KEY]` into `VALUE`.  `VALUE` is parsed as JSON,
+    /// falling back to a plain string (`theme=dark`).  Repeatable; overrides
+    /// `--state-file`.
+    #[arg(long = "state", value_name = "KEY=VALUE")]
+    state: Vec<String>,
+
+    /// JSON object of global-state values to specialize the style to (see `--state`).
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
     /// Simplify unary boolean ops: `["any"|"all", e]` → `e`, `["!",["!",e]]` → `e`.
     #[arg(long)]
     simplify_unary: bool,
@@ -90,6 +163,18 @@ pub struct OptimizeArgs {
     #[arg(long)]
     simplify_expressions: bool,
 
+    /// Let `simplify_expressions` drop linear `interpolate` stops up to this
+    /// far (in output units) off the line through their neighbours.
+    ///
+    /// Defaults to the config's `stop-tolerance`, then 0: only collinear stops.
+    #[arg(long, value_name = "EPSILON")]
+    stop_tolerance: Option<f64>,
+
+    /// Fold zoom comparisons and drop `interpolate`/`step` stops outside the
+    /// layer's zoom range.
+    #[arg(long)]
+    prune_zoom_ramps: bool,
+
     /// Minify CSS color strings to their shortest hex/rgba representation.
     #[arg(long)]
     minify_colors: bool,
@@ -98,16 +183,42 @@ pub struct OptimizeArgs {
     #[arg(long)]
     cleanup: bool,
 
-    /// Merge adjacent layers with the same type/source/source-layer into fewer
-    /// layers using data-driven `case`/`match` expressions and synthesised sort-keys.
+    /// Merge layers with the same type/source/source-layer into fewer layers using
+    /// data-driven `case`/`match` expressions and synthesised sort-keys. Layers need
+    /// not be adjacent when the layers between them provably commute with them.
     #[arg(long)]
     layer_merge: bool,
 
+    /// Only merge layers when the bytes saved outweigh the added per-feature
+    /// expression evaluation, at this many bytes per node per feature.
+    ///
+    /// Defaults to the config's `merge-cost-weight`, then 0.01; 0 merges
+    /// whenever the merged layer is smaller.
+    #[arg(long, value_name = "WEIGHT")]
+    merge_cost_weight: Option<f64>,
+
+    /// Split layers whose paint/layout `match`es on a string property with few
+    /// distinct values (per `--stats`) into one constant-paint layer per value.
+    /// Not enabled by `--all` or any level.
+    #[arg(long)]
+    layer_split: bool,
+
+    /// Most distinct values a `match` input may take for `--layer-split`.
+    ///
+    /// Defaults to the config's `split-cardinality`, then 4.
+    #[arg(long, value_name = "N")]
+    split_cardinality: Option<u64>,
+
     /// Tighten source minzoom/maxzoom based on the effective zoom range of
     /// referencing layers.
     #[arg(long)]
     source_zoom_tightening: bool,
 
+    /// Hoist subexpressions repeated within one property or filter into
+    /// `let`/`var` bindings when that makes the expression smaller.
+    #[arg(long)]
+    common_subexpressions: bool,
+
     /// Run JSON-tree validation after optimization (`maplibre_style_spec::validate`).
     #[arg(long)]
     validate: bool,
@@ -116,63 +227,273 @@ pub struct OptimizeArgs {
     #[arg(long)]
     pretty: bool,
 
+    /// Write the style even if passes had to skip parts of it, warning
+    /// instead of failing.
+    ///
+    /// Typed passes (dead-layer removal, cleanup, merging, …) skip a style
+    /// the typed model cannot deserialize; the error names the offending path.
+    #[arg(long)]
+    allow_skipped_passes: bool,
+
     /// Write a tile pruning advisory JSON to this path (requires --stats).
     #[arg(long)]
     advisory: Option<PathBuf>,
+
+    /// Write a per-pass change log JSON to this path.
+    ///
+    /// Lists, in execution order, every pass that changed the style together with
+    /// JSON pointer paths and before/after values.
+    #[arg(long)]
+    explain: Option<PathBuf>,
+
+    /// Write a layer id map JSON to this path.
+    ///
+    /// Maps every input layer id to the optimized layer that now renders it,
+    /// with the filter selecting its features when layers were merged, or to
+    /// the pass that removed it.
+    #[arg(long)]
+    layer_map: Option<PathBuf>,
 }
 
 fn default_reference_path() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../upstream/src/reference/v8.json")
 }
 
-pub fn run(args: OptimizeArgs) -> anyhow::Result<()> {
-    let reference_path = args.reference.unwrap_or_else(default_reference_path);
+/// Apply `--passes`: option names set their [`OptPasses`] field, other names
+/// must be registered passes and are returned for explicit scheduling.
+fn select_passes<'a>(
+    registry: &PassRegistry,
+    passes: &mut OptPasses,
+    names: &'a [String],
+) -> anyhow::Result<Vec<&'a str>> {
+    let mut extra = Vec::new();
+    for name in names.iter().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+        if passes.enable(name) {
+            continue;
+        }
+        if !registry.contains(name) {
+            let mut known: Vec<&str> = OptPasses::NAMES
+                .iter()
+                .copied()
+                .chain(registry.names())
+                .collect();
+            known.sort_unstable();
+            known.dedup();
+            anyhow::bail!("unknown pass `{name}` (known: {})", known.join(", "));
+        }
+        extra.push(name);
+    }
+    Ok(extra)
+}
+
+/// Merge the config's `state`, `--state-file` and `--state`, later ones
+/// winning, into one map of global-state values.
+fn load_global_state(
+    config: &Config,
+    file: Option<&Path>,
+    pairs: &[String],
+) -> anyhow::Result<serde_json::Map<String, Value>> {
+    let mut state = config.state.clone();
+    if let Some(path) = file {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("read state {}", path.display()))?;
+        let file: serde_json::Map<String, Value> = serde_json::from_str(&text)
+            .with_context(|| format!("parse state JSON object {}", path.display()))?;
+        state.extend(file);
+    }
+    for pair in pairs {
+        let (key, value) = pair
+            .split_once('=')
+            .with_context(|| format!("--state `{pair}`: expected KEY=VALUE"))?;
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()));
+        state.insert(key.to_owned(), value);
+    }
+    Ok(state)
+}
+
+/// One optimization configuration, shared by every style of a run.
+pub(super) struct Optimizer<'a> {
+    pub(super) mir: &'a MirSpec,
+    registry: &'a PassRegistry,
+    passes: &'a OptPasses,
+    extra: &'a [&'a str],
+    stats: Option<&'a TileStatistics>,
+    level: Option<OptLevel>,
+    pretty: bool,
+    allow_skipped: bool,
+}
+
+impl Optimizer<'_> {
+    /// Optimize `value`.  The outcome lists skipped passes only when
+    /// `--allow-skipped-passes` turned them from an error into warnings.
+    pub(super) fn optimize(
+        &self,
+        value: &mut Value,
+        report: &mut OptimizeReport,
+    ) -> anyhow::Result<OptimizeOutcome> {
+        let outcome = match self.registry.optimize_json_reported(
+            value,
+            self.mir,
+            self.passes,
+            self.stats,
+            self.extra,
+            report,
+        ) {
+            Ok(outcome) => outcome,
+            Err(OptimizeError::Incomplete(outcome)) if self.allow_skipped => outcome,
+            Err(e @ OptimizeError::Incomplete(_)) => {
+                return Err(anyhow::Error::new(e).context(
+                    "style only partially optimized (pass --allow-skipped-passes to write it anyway)",
+                ));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(level) = self.level {
+            level.record(value);
+        }
+        Ok(outcome)
+    }
+
+    pub(super) fn serialize(&self, value: &Value) -> serde_json::Result<String> {
+        if self.pretty {
+            serde_json::to_string_pretty(value)
+        } else {
+            serde_json::to_string(value)
+        }
+    }
+}
+
+/// Pass switches and options from the command line and config.
+fn pass_options(
+    args: &OptimizeArgs,
+    config: &Config,
+    level: Option<OptLevel>,
+) -> anyhow::Result<OptPasses> {
+    let mut passes = match level {
+        _ if args.all => OptPasses::all(),
+        Some(level) => level.passes(),
+        None => OptPasses::default(),
+    };
+    for (name, enabled) in [
+        ("simplify_unary", args.simplify_unary),
+        ("expression_kind", args.expression_kind),
+        ("constant_fold", args.constant_fold),
+        ("constant_fold_stats", args.constant_fold_stats),
+        ("dead_elimination", args.dead_elimination),
+        ("dead_elimination_stats", args.dead_elimination_stats),
+        ("metadata_refinement", args.metadata_refinement),
+        ("metadata_refinement_paint", args.metadata_refinement_paint),
+        ("metadata_refinement_stats", args.metadata_refinement_stats),
+        ("selectivity_reorder", args.selectivity_reorder),
+        ("strip_metadata", args.strip_metadata),
+        ("strip_defaults", args.strip_defaults),
+        ("simplify_expressions", args.simplify_expressions),
+        ("prune_zoom_ramps", args.prune_zoom_ramps),
+        ("minify_colors", args.minify_colors),
+        ("cleanup", args.cleanup),
+        ("layer_merge", args.layer_merge),
+        ("source_zoom_tightening", args.source_zoom_tightening),
+        ("common_subexpressions", args.common_subexpressions),
+        ("layer_split", args.layer_split),
+    ] {
+        if enabled {
+            passes.enable(name);
+        }
+    }
+    passes.keep_layers = KeepLayers::new(
+        config.keep_layers.iter().chain(&args.keep_layer).cloned(),
+        config.keep_layer_regex.iter().chain(&args.keep_layer_regex),
+    )
+    .context("invalid --keep-layer-regex")?;
+    passes.global_state = load_global_state(config, args.state_file.as_deref(), &args.state)?;
+    if let Some(epsilon) = args.stop_tolerance.or(config.stop_tolerance) {
+        passes.stop_tolerance = StopTolerance::new(epsilon).with_context(|| {
+            format!("stop tolerance {epsilon}: expected a finite, non-negative number")
+        })?;
+    }
+    if let Some(weight) = args.merge_cost_weight.or(config.merge_cost_weight) {
+        passes.merge_cost_weight = MergeCostWeight::new(weight).with_context(|| {
+            format!("merge cost weight {weight}: expected a finite, non-negative number")
+        })?;
+    }
+    if let Some(max) = args.split_cardinality.or(config.split_cardinality) {
+        passes.split_cardinality = SplitCardinality::new(max)
+            .with_context(|| format!("split cardinality {max}: expected at least 1"))?;
+    }
+    Ok(passes)
+}
+
+pub fn run(args: &OptimizeArgs) -> anyhow::Result<()> {
+    let config = Config::load(args.config.as_deref(), !args.no_config)?;
+    let reference_path = args
+        .reference
+        .clone()
+        .or_else(|| config.reference())
+        .unwrap_or_else(default_reference_path);
 
     let mir = load_intermediate_spec_from_v8_path(&reference_path)?;
     for op in ["any", "all", "!"] {
         ensure_expression_operator(&mir, op)?;
     }
 
-    let json_text =
-        fs::read_to_string(&args.input).with_context(|| args.input.display().to_string())?;
-    let mut value: serde_json::Value = serde_json::from_str(&json_text)
-        .with_context(|| format!("parse style JSON {}", args.input.display()))?;
-
     let tile_stats = args
         .stats
+        .as_ref()
         .map(|path| {
-            let text = fs::read_to_string(&path)
+            let text = fs::read_to_string(path)
                 .with_context(|| format!("read stats {}", path.display()))?;
             let stats: TileStatistics = serde_json::from_str(&text)
                 .with_context(|| format!("parse stats JSON {}", path.display()))?;
             Ok::<_, anyhow::Error>(stats)
         })
         .transpose()?;
+    let tile_stats = config.merge_stats(tile_stats)?;
+
+    let level = args.opt_level.or(config.level()?);
+    let mut passes = pass_options(args, &config, level)?;
+    let registry = PassRegistry::builtin();
+    passes.layer_overrides = config.layer_overrides(&registry)?;
+    let names: Vec<String> = config.passes.iter().chain(&args.passes).cloned().collect();
+    let extra = select_passes(&registry, &mut passes, &names)?;
+    let optimizer = Optimizer {
+        mir: &mir,
+        registry: &registry,
+        passes: &passes,
+        extra: &extra,
+        stats: tile_stats.as_ref(),
+        // `--all` replaces the preset's passes, so the preset was not applied.
+        level: level.filter(|_| !args.all),
+        pretty: args.pretty,
+        allow_skipped: args.allow_skipped_passes,
+    };
 
-    let passes = if args.all {
-        OptPasses::all()
-    } else {
-        OptPasses {
-            simplify_unary: args.simplify_unary,
-            expression_kind: args.expression_kind,
-            constant_fold: args.constant_fold,
-            constant_fold_stats: args.constant_fold_stats,
-            dead_elimination: args.dead_elimination,
-            dead_elimination_stats: args.dead_elimination_stats,
-            metadata_refinement: args.metadata_refinement,
-            metadata_refinement_paint: args.metadata_refinement_paint,
-            metadata_refinement_stats: args.metadata_refinement_stats,
-            selectivity_reorder: args.selectivity_reorder,
-            strip_metadata: args.strip_metadata,
-            strip_defaults: args.strip_defaults,
-            simplify_expressions: args.simplify_expressions,
-            minify_colors: args.minify_colors,
-            cleanup: args.cleanup,
-            layer_merge: args.layer_merge,
-            source_zoom_tightening: args.source_zoom_tightening,
-        }
+    if let (Some(pattern), Some(out_dir)) = (&args.batch, &args.out_dir) {
+        return batch::run(&optimizer, pattern, out_dir);
+    }
+    let (Some(input), Some(output)) = (&args.input, &args.output) else {
+        anyhow::bail!("--input and --output are required without --batch");
+    };
+
+    let json_text = fs::read_to_string(input).with_context(|| input.display().to_string())?;
+    let mut value: Value = serde_json::from_str(&json_text)
+        .with_context(|| format!("parse style JSON {}", input.display()))?;
+
+    let mut report = OptimizeReport {
+        changes: args.explain.as_ref().map(|_| ChangeLog::default()),
+        layer_map: args.layer_map.as_ref().map(|_| LayerMap::default()),
     };
-    optimize_style_json_value_with_stats(&mut value, &mir, &passes, tile_stats.as_ref());
+    let outcome = optimizer.optimize(&mut value, &mut report)?;
+    for skip in &outcome.skipped {
+        eprintln!("warning: {skip}");
+    }
+    if let (Some(path), Some(log)) = (&args.explain, &report.changes) {
+        let log_json = serde_json::to_string_pretty(log)?;
+        fs::write(path, log_json).with_context(|| path.display().to_string())?;
+    }
+    if let (Some(path), Some(map)) = (&args.layer_map, &report.layer_map) {
+        let map_json = serde_json::to_string_pretty(map)?;
+        fs::write(path, map_json).with_context(|| path.display().to_string())?;
+    }
 
     if let Some(advisory_path) = &args.advisory {
         let stats = tile_stats
@@ -188,12 +509,8 @@ pub fn run(args: OptimizeArgs) -> anyhow::Result<()> {
         validate_style_value(&value).map_err(anyhow::Error::msg)?;
     }
 
-    let out = if args.pretty {
-        serde_json::to_string_pretty(&value)?
-    } else {
-        serde_json::to_string(&value)?
-    };
-    fs::write(&args.output, out).with_context(|| args.output.display().to_string())?;
+    let out = optimizer.serialize(&value)?;
+    fs::write(output, out).with_context(|| output.display().to_string())?;
 
     Ok(())
 }
@@ -71,6 +71,17 @@ pub fn complexity_report(style: &mut Value, mir: &MirSpec) -> ComplexityReport {
     visitor.report
 }
 
+/// Number of JSON nodes in `value`, counted as for
+/// [`ComplexityReport::total_expression_nodes`].
+#[must_use]
+pub fn expression_node_count(value: &Value) -> usize {
+    match value {
+        Value::Array(arr) => 1 + arr.iter().map(expression_node_count).sum::<usize>(),
+        Value::Object(obj) => 1 + obj.values().map(expression_node_count).sum::<usize>(),
+        _ => 1,
+    }
+}
+
 struct ComplexityVisitor {
     report: ComplexityReport,
 }
@@ -0,0 +1,612 @@
+//! Semantic, layer-aware diff between two style documents.
+//!
+//! Both styles are parsed into the typed [`MaplibreStyleSpecification`] model and
+//! re-serialized before comparison, so key order and equivalent number spellings
+//! (`1` vs `1.0`) never show up as changes.  Layers are matched by `id`.
+//!
+//! The result can be rendered as a human-readable summary ([`std::fmt::Display`])
+//! or as an RFC 6902 JSON Patch ([`StyleDiff::patch`]) that turns the typed
+//! serialization of the old style into the typed serialization of the new one.
+//! Root keys the typed model does not know (`sprite`, `glyphs`, …) are kept
+//! from the raw JSON and compared as they are.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use maplibre_style_spec::spec::MaplibreStyleSpecification;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::optimize::Change;
+use crate::optimize::explain::diff_values;
+
+/// Layer-by-layer comparison of two styles.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StyleDiff {
+    /// Changes outside `layers` (sources, root properties, …).
+    pub root: Vec<Change>,
+    /// Layers only present in the old style, with their old index.
+    pub removed_layers: Vec<IndexedLayer>,
+    /// Layers only present in the new style, with their new index.
+    pub added_layers: Vec<IndexedLayer>,
+    /// Layers present in both styles whose relative draw order changed.
+    pub moved_layers: Vec<MovedLayer>,
+    /// Layers present in both styles whose definition changed.
+    pub modified_layers: Vec<ModifiedLayer>,
+    /// Total layer count in the old and new style.
+    pub layer_counts: (usize, usize),
+    #[serde(skip)]
+    patch: Vec<PatchOp>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedLayer {
+    pub id: String,
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MovedLayer {
+    pub id: String,
+    pub from: usize,
+    pub to: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModifiedLayer {
+    pub id: String,
+    /// Changes with JSON pointers relative to the layer object.
+    pub changes: Vec<Change>,
+}
+
+/// A single RFC 6902 operation.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+}
+
+impl StyleDiff {
+    /// `true` when the two styles are semantically identical.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.root.is_empty()
+            && self.removed_layers.is_empty()
+            && self.added_layers.is_empty()
+            && self.moved_layers.is_empty()
+            && self.modified_layers.is_empty()
+    }
+
+    /// RFC 6902 JSON Patch transforming the old style into the new one.
+    ///
+    /// Operations are ordered so that every index is valid at the point it is
+    /// applied: layer removals, then moves, then additions, then in-place edits.
+    #[must_use]
+    pub fn patch(&self) -> &[PatchOp] {
+        &self.patch
+    }
+}
+
+/// Compare two styles given as JSON.
+///
+/// Root keys the typed model does not cover (e.g. `sprite`, `glyphs`) are
+/// carried over from the raw JSON, so changes to them are reported too.
+pub fn diff_styles(old: &Value, new: &Value) -> anyhow::Result<StyleDiff> {
+    let old_typed: MaplibreStyleSpecification =
+        serde_json::from_value(old.clone()).map_err(|e| anyhow::anyhow!("parse old style: {e}"))?;
+    let new_typed: MaplibreStyleSpecification =
+        serde_json::from_value(new.clone()).map_err(|e| anyhow::anyhow!("parse new style: {e}"))?;
+    let mut old_v = serde_json::to_value(&old_typed)?;
+    let mut new_v = serde_json::to_value(&new_typed)?;
+    carry_untyped_root(old, &mut old_v);
+    carry_untyped_root(new, &mut new_v);
+    Ok(diff_serialized(old_v, new_v))
+}
+
+/// Compare two typed styles.
+pub fn diff_typed_styles(
+    old: &MaplibreStyleSpecification,
+    new: &MaplibreStyleSpecification,
+) -> anyhow::Result<StyleDiff> {
+    Ok(diff_serialized(
+        serde_json::to_value(old)?,
+        serde_json::to_value(new)?,
+    ))
+}
+
+fn diff_serialized(mut old_v: Value, mut new_v: Value) -> StyleDiff {
+    canonicalize_numbers(&mut old_v);
+    canonicalize_numbers(&mut new_v);
+
+    let old_layers = take_layers(&mut old_v);
+    let new_layers = take_layers(&mut new_v);
+
+    let mut diff = StyleDiff {
+        layer_counts: (old_layers.len(), new_layers.len()),
+        ..StyleDiff::default()
+    };
+
+    let old_ids: Vec<&str> = old_layers.iter().map(layer_id).collect();
+    let new_ids: Vec<&str> = new_layers.iter().map(layer_id).collect();
+    let old_pos: HashMap<&str, usize> =
+        old_ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let new_pos: HashMap<&str, usize> =
+        new_ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+    // 1. Removals, highest index first so earlier indices stay valid.
+    for (i, id) in old_ids.iter().enumerate() {
+        if !new_pos.contains_key(id) {
+            diff.removed_layers.push(IndexedLayer {
+                id: (*id).to_string(),
+                index: i,
+            });
+        }
+    }
+    for removed in diff.removed_layers.iter().rev() {
+        diff.patch.push(PatchOp::Remove {
+            path: layer_path(removed.index),
+        });
+    }
+
+    // 2. Moves among common layers.
+    push_moves(&mut diff, &old_ids, &new_ids, &old_pos, &new_pos);
+
+    // 3. Additions, lowest index first so each lands at its final position.
+    for (j, id) in new_ids.iter().enumerate() {
+        if !old_pos.contains_key(id) {
+            diff.added_layers.push(IndexedLayer {
+                id: (*id).to_string(),
+                index: j,
+            });
+            diff.patch.push(PatchOp::Add {
+                path: layer_path(j),
+                value: new_layers[j].clone(),
+            });
+        }
+    }
+
+    // 4. In-place edits of common layers, addressed by their final index.
+    for (j, id) in new_ids.iter().enumerate() {
+        let Some(&i) = old_pos.get(id) else {
+            continue;
+        };
+        let mut changes = Vec::new();
+        diff_values(
+            &old_layers[i],
+            &new_layers[j],
+            &mut String::new(),
+            &mut changes,
+        );
+        if changes.is_empty() {
+            continue;
+        }
+        let prefix = layer_path(j);
+        diff.patch
+            .extend(changes.iter().map(|c| patch_op(&prefix, c)));
+        diff.modified_layers.push(ModifiedLayer {
+            id: (*id).to_string(),
+            changes,
+        });
+    }
+
+    // 5. Everything outside `layers`.
+    diff_values(&old_v, &new_v, &mut String::new(), &mut diff.root);
+    diff.patch.extend(diff.root.iter().map(|c| patch_op("", c)));
+
+    diff
+}
+
+/// Copy the root keys of `raw` that the typed serialization `typed` lacks.
+fn carry_untyped_root(raw: &Value, typed: &mut Value) {
+    let (Some(raw), Some(typed)) = (raw.as_object(), typed.as_object_mut()) else {
+        return;
+    };
+    for (key, value) in raw {
+        if !typed.contains_key(key) {
+            typed.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Moves among layers present in both styles.  Layers on the longest run that
+/// kept its relative order stay put; every other common layer is moved right
+/// behind its predecessor in the new order.
+fn push_moves(
+    diff: &mut StyleDiff,
+    old_ids: &[&str],
+    new_ids: &[&str],
+    old_pos: &HashMap<&str, usize>,
+    new_pos: &HashMap<&str, usize>,
+) {
+    let mut current: Vec<&str> = old_ids
+        .iter()
+        .copied()
+        .filter(|id| new_pos.contains_key(id))
+        .collect();
+    let target: Vec<&str> = new_ids
+        .iter()
+        .copied()
+        .filter(|id| old_pos.contains_key(id))
+        .collect();
+    let target_pos: HashMap<&str, usize> =
+        target.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let target_rank: Vec<usize> = current.iter().map(|id| target_pos[id]).collect();
+    let stable: HashSet<&str> = longest_increasing_subsequence(&target_rank)
+        .into_iter()
+        .map(|i| current[i])
+        .collect();
+    for (j, id) in target.iter().enumerate() {
+        if stable.contains(id) {
+            continue;
+        }
+        let from = current.iter().position(|c| c == id).unwrap_or(0);
+        current.remove(from);
+        let to = if j == 0 {
+            0
+        } else {
+            current
+                .iter()
+                .position(|c| *c == target[j - 1])
+                .map_or(0, |p| p + 1)
+        };
+        current.insert(to, id);
+        diff.patch.push(PatchOp::Move {
+            from: layer_path(from),
+            path: layer_path(to),
+        });
+        diff.moved_layers.push(MovedLayer {
+            id: (*id).to_string(),
+            from: old_pos[id],
+            to: new_pos[id],
+        });
+    }
+}
+
+fn take_layers(style: &mut Value) -> Vec<Value> {
+    match style.as_object_mut().and_then(|o| o.remove("layers")) {
+        Some(Value::Array(layers)) => layers,
+        _ => Vec::new(),
+    }
+}
+
+fn layer_id(layer: &Value) -> &str {
+    layer.get("id").and_then(Value::as_str).unwrap_or_default()
+}
+
+fn layer_path(index: usize) -> String {
+    format!("/layers/{index}")
+}
+
+fn patch_op(prefix: &str, change: &Change) -> PatchOp {
+    let path = format!("{prefix}{}", change.path);
+    match (&change.before, &change.after) {
+        (None, Some(value)) => PatchOp::Add {
+            path,
+            value: value.clone(),
+        },
+        (Some(_), None) => PatchOp::Remove { path },
+        (_, after) => PatchOp::Replace {
+            path,
+            value: after.clone().unwrap_or(Value::Null),
+        },
+    }
+}
+
+/// Rewrite integral floats (`1.0`) as integers (`1`) so number spelling does
+/// not register as a change.
+fn canonicalize_numbers(v: &mut Value) {
+    match v {
+        Value::Number(n) if !n.is_i64() && !n.is_u64() => {
+            if let Some(f) = n.as_f64()
+                && f.fract() == 0.0
+                && f.abs() < 9_007_199_254_740_992.0
+            {
+                #[expect(clippy::cast_possible_truncation)]
+                let i = f as i64;
+                *v = Value::from(i);
+            }
+        }
+        Value::Array(arr) => arr.iter_mut().for_each(canonicalize_numbers),
+        Value::Object(map) => map.values_mut().for_each(canonicalize_numbers),
+        _ => {}
+    }
+}
+
+/// Indices of one longest strictly increasing subsequence of `seq`.
+fn longest_increasing_subsequence(seq: &[usize]) -> Vec<usize> {
+    // `tails[k]` is the index of the smallest tail of an increasing run of length k+1.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev: Vec<Option<usize>> = vec![None; seq.len()];
+    for (i, &x) in seq.iter().enumerate() {
+        let k = tails.partition_point(|&t| seq[t] < x);
+        if k > 0 {
+            prev[i] = Some(tails[k - 1]);
+        }
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+    let mut out = Vec::with_capacity(tails.len());
+    let mut cur = tails.last().copied();
+    while let Some(i) = cur {
+        out.push(i);
+        cur = prev[i];
+    }
+    out.reverse();
+    out
+}
+
+// ── Summary rendering ───────────────────────────────────────────────────────
+
+const MAX_VALUE_WIDTH: usize = 60;
+
+impl fmt::Display for StyleDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "styles are semantically identical");
+        }
+        let (old_count, new_count) = self.layer_counts;
+        writeln!(
+            f,
+            "layers: {old_count} -> {new_count} ({} removed, {} added, {} moved, {} modified)",
+            self.removed_layers.len(),
+            self.added_layers.len(),
+            self.moved_layers.len(),
+            self.modified_layers.len(),
+        )?;
+        for l in &self.removed_layers {
+            writeln!(f, "- {} (was #{})", l.id, l.index)?;
+        }
+        for l in &self.added_layers {
+            writeln!(f, "+ {} (at #{})", l.id, l.index)?;
+        }
+        for l in &self.moved_layers {
+            writeln!(f, "~ {} (#{} -> #{})", l.id, l.from, l.to)?;
+        }
+        for l in &self.modified_layers {
+            writeln!(f, "* {}", l.id)?;
+            for c in &l.changes {
+                writeln!(f, "    {}", format_change(c))?;
+            }
+        }
+        if !self.root.is_empty() {
+            writeln!(f, "root:")?;
+            for c in &self.root {
+                writeln!(f, "    {}", format_change(c))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_change(c: &Change) -> String {
+    match (&c.before, &c.after) {
+        (None, Some(a)) => format!("{}: + {}", c.path, short_json(a)),
+        (Some(b), None) => format!("{}: - {}", c.path, short_json(b)),
+        (Some(b), Some(a)) => format!("{}: {} -> {}", c.path, short_json(b), short_json(a)),
+        (None, None) => c.path.clone(),
+    }
+}
+
+fn short_json(v: &Value) -> String {
+    let s = serde_json::to_string(v).unwrap_or_default();
+    if s.chars().count() <= MAX_VALUE_WIDTH {
+        return s;
+    }
+    let mut out: String = s.chars().take(MAX_VALUE_WIDTH - 1).collect();
+    out.push('…');
+    out
+}
+
+/// Apply a JSON Patch produced by [`StyleDiff::patch`].  Only used to verify
+/// round-trips; supports exactly the operations this module emits.
+#[cfg(test)]
+fn apply_patch(doc: &mut Value, ops: &[PatchOp]) {
+    fn split(path: &str) -> (&str, String) {
+        let (parent, last) = path.rsplit_once('/').unwrap();
+        (parent, last.replace("~1", "/").replace("~0", "~"))
+    }
+    fn insert(doc: &mut Value, path: &str, value: Value) {
+        let (parent, key) = split(path);
+        match doc.pointer_mut(parent).unwrap() {
+            Value::Array(a) => a.insert(key.parse().unwrap(), value),
+            Value::Object(o) => {
+                o.insert(key, value);
+            }
+            _ => panic!("bad parent {parent}"),
+        }
+    }
+    fn remove(doc: &mut Value, path: &str) -> Value {
+        let (parent, key) = split(path);
+        match doc.pointer_mut(parent).unwrap() {
+            Value::Array(a) => a.remove(key.parse().unwrap()),
+            Value::Object(o) => o.remove(&key).unwrap(),
+            _ => panic!("bad parent {parent}"),
+        }
+    }
+    for op in ops {
+        match op {
+            PatchOp::Add { path, value } => insert(doc, path, value.clone()),
+            PatchOp::Remove { path } => {
+                remove(doc, path);
+            }
+            PatchOp::Replace { path, value } => *doc.pointer_mut(path).unwrap() = value.clone(),
+            PatchOp::Move { from, path } => {
+                let v = remove(doc, from);
+                insert(doc, path, v);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn style(layers: &Value) -> Value {
+        json!({
+            "version": 8,
+            "sources": {"s": {"type": "vector", "url": "https://example/tiles.json"}},
+            "layers": layers
+        })
+    }
+
+    fn fill(id: &str, color: &str) -> Value {
+        json!({"id": id, "type": "fill", "source": "s", "source-layer": "l", "paint": {"fill-color": color}})
+    }
+
+    fn typed_value(v: &Value) -> Value {
+        let typed: MaplibreStyleSpecification = serde_json::from_value(v.clone()).unwrap();
+        let mut out = serde_json::to_value(typed).unwrap();
+        carry_untyped_root(v, &mut out);
+        canonicalize_numbers(&mut out);
+        out
+    }
+
+    fn assert_patch_roundtrips(old: &Value, new: &Value) -> StyleDiff {
+        let diff = diff_styles(old, new).unwrap();
+        let mut patched = typed_value(old);
+        apply_patch(&mut patched, diff.patch());
+        assert_eq!(patched, typed_value(new));
+        diff
+    }
+
+    #[test]
+    fn identical_styles_are_empty() {
+        let s = style(&json!([fill("a", "#fff")]));
+        let diff = diff_styles(&s, &s).unwrap();
+        assert!(diff.is_empty());
+        assert!(diff.patch().is_empty());
+    }
+
+    #[test]
+    fn number_spelling_and_key_order_ignored() {
+        let old = style(
+            &json!([{"id": "a", "type": "fill", "source": "s", "source-layer": "l", "minzoom": 5.0}]),
+        );
+        let new = style(
+            &json!([{"minzoom": 5, "source-layer": "l", "source": "s", "type": "fill", "id": "a"}]),
+        );
+        assert!(diff_styles(&old, &new).unwrap().is_empty());
+    }
+
+    #[test]
+    fn removed_added_and_modified_layers() {
+        let old = style(&json!([
+            fill("a", "#fff"),
+            fill("b", "#000"),
+            fill("c", "#f00")
+        ]));
+        let new = style(&json!([
+            fill("a", "#eee"),
+            fill("c", "#f00"),
+            fill("d", "#0f0")
+        ]));
+        let diff = assert_patch_roundtrips(&old, &new);
+        assert_eq!(diff.removed_layers.len(), 1);
+        assert_eq!(diff.removed_layers[0].id, "b");
+        assert_eq!(diff.added_layers[0].id, "d");
+        assert!(diff.moved_layers.is_empty());
+        assert_eq!(diff.modified_layers.len(), 1);
+        assert_eq!(diff.modified_layers[0].id, "a");
+        assert_eq!(diff.modified_layers[0].changes[0].path, "/paint/fill-color");
+    }
+
+    #[test]
+    fn reordering_reports_minimal_moves() {
+        let old = style(&json!([
+            fill("a", "#000"),
+            fill("b", "#000"),
+            fill("c", "#000")
+        ]));
+        let new = style(&json!([
+            fill("b", "#000"),
+            fill("c", "#000"),
+            fill("a", "#000")
+        ]));
+        let diff = assert_patch_roundtrips(&old, &new);
+        assert_eq!(diff.moved_layers.len(), 1);
+        assert_eq!(diff.moved_layers[0].id, "a");
+        assert_eq!(diff.patch().len(), 1);
+    }
+
+    #[test]
+    fn combined_changes_roundtrip() {
+        let old = style(&json!([
+            fill("a", "#000"),
+            fill("b", "#111"),
+            fill("c", "#222"),
+            fill("d", "#333"),
+            fill("e", "#444")
+        ]));
+        let new = style(&json!([
+            fill("x", "#999"),
+            fill("d", "#333"),
+            fill("a", "#000"),
+            fill("e", "#abc"),
+            fill("b", "#111")
+        ]));
+        let diff = assert_patch_roundtrips(&old, &new);
+        assert_eq!(diff.removed_layers.len(), 1);
+        assert_eq!(diff.added_layers.len(), 1);
+    }
+
+    #[test]
+    fn root_changes_reported() {
+        let old = style(&json!([]));
+        let mut new = style(&json!([]));
+        new["name"] = json!("renamed");
+        let diff = assert_patch_roundtrips(&old, &new);
+        assert_eq!(diff.root.len(), 1);
+        assert_eq!(diff.root[0].path, "/name");
+    }
+
+    #[test]
+    fn untyped_root_keys_reported() {
+        let mut old = style(&json!([]));
+        old["sprite"] = json!("https://example/sprite");
+        old["glyphs"] = json!("https://example/{fontstack}/{range}.pbf");
+        let mut new = style(&json!([]));
+        new["sprite"] = json!("https://example/sprite2");
+        let diff = assert_patch_roundtrips(&old, &new);
+        let paths: Vec<_> = diff.root.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["/glyphs", "/sprite"]);
+    }
+
+    #[test]
+    fn nested_id_arrays_roundtrip_with_removals_and_reordering() {
+        let sprites = |ids: &[&str]| {
+            let mut s = style(&json!([]));
+            s["sprite"] = ids
+                .iter()
+                .map(|id| json!({"id": id, "url": format!("https://example/{id}")}))
+                .collect();
+            s
+        };
+        let diff = assert_patch_roundtrips(&sprites(&["a", "b", "c"]), &sprites(&["b"]));
+        assert_eq!(diff.root.len(), 2);
+        let diff = assert_patch_roundtrips(&sprites(&["a", "b", "c"]), &sprites(&["c", "a", "d"]));
+        assert_eq!(diff.root.len(), 1);
+        assert_eq!(diff.root[0].path, "/sprite");
+    }
+
+    #[test]
+    fn summary_lists_layers() {
+        let old = style(&json!([fill("a", "#fff"), fill("b", "#000")]));
+        let new = style(&json!([fill("a", "#eee")]));
+        let summary = diff_styles(&old, &new).unwrap().to_string();
+        assert_eq!(
+            summary,
+            "layers: 2 -> 1 (1 removed, 0 added, 0 moved, 1 modified)\n\
+             - b (was #1)\n\
+             * a\n    /paint/fill-color: \"#fff\" -> \"#eee\"\n"
+        );
+    }
+}
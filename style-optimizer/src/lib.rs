@@ -4,10 +4,16 @@
 //! [`maplibre_style_spec::spec::MaplibreStyleSpecification`].
 //!
 //! JSON wrappers [`optimize_style_json_value`] / [`optimize_style_json_value_with_stats`]
-//! are provided for backward compatibility.
+//! are provided for backward compatibility.  [`optimize_style_json_value_explained`]
+//! additionally returns a per-pass [`ChangeLog`].
+//!
+//! All of them run the built-in passes of a [`PassRegistry`]; register your own
+//! [`Pass`] implementations and call [`PassRegistry::optimize_json`] to extend
+//! the pipeline.
 
 pub mod advisory;
 pub mod complexity;
+pub mod diff;
 pub mod encode_mlt;
 pub mod mbtiles;
 #[expect(
@@ -28,7 +34,12 @@ use anyhow::Context;
 use maplibre_style_spec::decoder::StyleReference;
 use maplibre_style_spec::mir::MirSpec;
 pub use optimize::{
-    OptPasses, optimize_style, optimize_style_json_value, optimize_style_json_value_with_stats,
+    Change, ChangeLog, KeepLayers, LayerFate, LayerMap, LayerOverride, LayerTarget,
+    MergeCostWeight, OPT_LEVEL_METADATA_KEY, OptLevel, OptPasses, OptimizeError, OptimizeOutcome,
+    OptimizeReport, PASS_FIXPOINT_CAP, Pass, PassChanges, PassContext, PassData, PassRegistry,
+    SKIP_METADATA_KEY, SkippedPass, SplitCardinality, StopTolerance, optimize_style,
+    optimize_style_json_value, optimize_style_json_value_explained,
+    optimize_style_json_value_with_stats,
 };
 pub use stats::TileStatistics;
 pub use stats::collect::collect_statistics;
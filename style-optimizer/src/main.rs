@@ -12,7 +12,7 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Command {
     /// Optimize a `MapLibre` style JSON document.
-    Optimize(cmd::optimize::OptimizeArgs),
+    Optimize(Box<cmd::optimize::OptimizeArgs>),
 
     /// Collect tile statistics from an `MBTiles` file.
     Stats(cmd::stats::StatsArgs),
@@ -22,14 +22,18 @@ enum Command {
 
     /// Compute static complexity metrics for a style JSON document.
     Complexity(cmd::complexity::ComplexityArgs),
+
+    /// Compare two style JSON documents layer by layer.
+    Diff(cmd::diff::DiffArgs),
 }
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
     match cli.command {
-        Command::Optimize(args) => cmd::optimize::run(args),
+        Command::Optimize(ref args) => cmd::optimize::run(args),
         Command::Stats(ref args) => cmd::stats::run(args),
         Command::Advisory(ref args) => cmd::advisory::run(args),
         Command::Complexity(ref args) => cmd::complexity::run(args),
+        Command::Diff(ref args) => cmd::diff::run(args),
     }
 }
@@ -0,0 +1,576 @@
+//! Built-in passes, registered by [`PassRegistry::builtin`].
+//!
+//! Registration order is the order passes run in when dependencies leave a
+//! choice: JSON expression passes first, then typed structural passes, then
+//! zoom-ramp simplification, layer merging and splitting, and common
+//! subexpression elimination.
+
+use maplibre_style_spec::spec::MaplibreStyleSpecification;
+use serde_json::Value;
+
+use super::pass::{Pass, PassContext, PassData, PassRegistry};
+use super::{
+    CommonSubexpressionVisitor, GlobalStateVisitor, MinifyColorsVisitor,
+    NORMALIZE_FOLD_FIXPOINT_CAP, OptPasses, ReorderSelectivityVisitor, StripDefaultsVisitor,
+    TypedNormalizeFoldVisitor, cleanup, dead_elimination, merge, metadata_refinement,
+    precompute_vector_layer_info, precompute_vector_layer_info_typed, ramp,
+    run_normalize_fold_only, split, strip_metadata, tighten_source_zoom_bounds, walk_style_mut,
+    walk_typed_filters, wants_expression_passes, wants_normalize_fold, wants_structural_passes,
+};
+
+pub(super) fn register(registry: &mut PassRegistry) {
+    registry
+        .register(GlobalState)
+        .register(NormalizeFold)
+        .register(StripDefaults)
+        .register(MinifyColors)
+        .register(SelectivityReorder)
+        .register(StripMetadata)
+        .register(DeadElimination)
+        .register(MetadataRefinement)
+        .register(Cleanup)
+        .register(NormalizeFoldFilters)
+        .register(SourceZoomTightening)
+        .register(PruneZoomRamps)
+        .register(LayerMerge)
+        .register(LayerSplit)
+        .register(CommonSubexpressions);
+}
+
+// ── JSON expression passes ────────────────────────────────────────────────────
+
+/// Specializes the style to `options.global_state` before anything else runs,
+/// so every later pass sees the folded values.
+struct GlobalState;
+
+impl Pass for GlobalState {
+    fn name(&self) -> &'static str {
+        "global_state"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Json
+    }
+
+    // Folded lookups turn `case`/`match` conditions and filters into constants.
+    fn invalidates(&self) -> &[&'static str] {
+        &[
+            "normalize_fold",
+            "dead_elimination",
+            "prune_zoom_ramps",
+            "cleanup",
+        ]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        !options.global_state.is_empty()
+    }
+
+    fn run_json(&self, style: &mut Value, cx: &PassContext<'_>) {
+        walk_style_mut(
+            style,
+            cx.mir,
+            &mut GlobalStateVisitor {
+                state: &cx.options.global_state,
+            },
+        );
+    }
+}
+
+/// The fused `simplify_unary`/`expression_kind`/`constant_fold`/
+/// `constant_fold_stats`/`simplify_expressions` rewrite fixpoint.
+struct NormalizeFold;
+
+impl Pass for NormalizeFold {
+    fn name(&self) -> &'static str {
+        "normalize_fold"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Json
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &["global_state"]
+    }
+
+    fn invalidates(&self) -> &[&'static str] {
+        &[
+            "strip_defaults",
+            "minify_colors",
+            "selectivity_reorder",
+            "dead_elimination",
+            "metadata_refinement",
+            "cleanup",
+            "common_subexpressions",
+        ]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        wants_normalize_fold(options)
+    }
+
+    fn run_json(&self, style: &mut Value, cx: &PassContext<'_>) {
+        run_normalize_fold_only(style, cx.mir, cx.options, cx.stats);
+    }
+}
+
+struct StripDefaults;
+
+impl Pass for StripDefaults {
+    fn name(&self) -> &'static str {
+        "strip_defaults"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Json
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &["normalize_fold"]
+    }
+
+    // Stripping the last property leaves an empty `paint`/`layout` object.
+    fn invalidates(&self) -> &[&'static str] {
+        &["cleanup"]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.strip_defaults
+    }
+
+    fn run_json(&self, style: &mut Value, cx: &PassContext<'_>) {
+        walk_style_mut(style, cx.mir, &mut StripDefaultsVisitor { mir: cx.mir });
+    }
+}
+
+struct MinifyColors;
+
+impl Pass for MinifyColors {
+    fn name(&self) -> &'static str {
+        "minify_colors"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Json
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &["normalize_fold"]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.minify_colors
+    }
+
+    fn run_json(&self, style: &mut Value, cx: &PassContext<'_>) {
+        walk_style_mut(style, cx.mir, &mut MinifyColorsVisitor);
+    }
+}
+
+struct SelectivityReorder;
+
+impl Pass for SelectivityReorder {
+    fn name(&self) -> &'static str {
+        "selectivity_reorder"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Json
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &["normalize_fold"]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.selectivity_reorder
+    }
+
+    fn run_json(&self, style: &mut Value, cx: &PassContext<'_>) {
+        let layer_info = cx.stats.map(|_| precompute_vector_layer_info(style));
+        walk_style_mut(
+            style,
+            cx.mir,
+            &mut ReorderSelectivityVisitor {
+                mir: cx.mir,
+                stats: cx.stats,
+                layer_info: layer_info.as_deref(),
+            },
+        );
+    }
+}
+
+// ── Typed structural passes ───────────────────────────────────────────────────
+
+struct StripMetadata;
+
+impl Pass for StripMetadata {
+    fn name(&self) -> &'static str {
+        "strip_metadata"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Typed
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.strip_metadata
+    }
+
+    fn run_typed(&self, style: &mut MaplibreStyleSpecification, _: &PassContext<'_>) {
+        strip_metadata(style);
+    }
+}
+
+struct DeadElimination;
+
+impl Pass for DeadElimination {
+    fn name(&self) -> &'static str {
+        "dead_elimination"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Typed
+    }
+
+    // Always-false filters come out of constant folding.
+    fn dependencies(&self) -> &[&'static str] {
+        &["normalize_fold"]
+    }
+
+    fn invalidates(&self) -> &[&'static str] {
+        &["source_zoom_tightening"]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.dead_elimination
+    }
+
+    fn run_typed(&self, style: &mut MaplibreStyleSpecification, cx: &PassContext<'_>) {
+        let layer_info = cx.stats.map(|_| precompute_vector_layer_info_typed(style));
+        let keep = cx.keep_layers(self.name());
+        let (overdrawn, duplicates) =
+            dead_elimination(style, cx.options, &keep, cx.stats, layer_info.as_deref());
+        for layer in overdrawn {
+            cx.record_merge(&layer.by, &[(layer.id, layer.filter)]);
+        }
+        for layer in duplicates {
+            cx.record_duplicate(&layer.id, &layer.of);
+        }
+    }
+}
+
+struct MetadataRefinement;
+
+impl Pass for MetadataRefinement {
+    fn name(&self) -> &'static str {
+        "metadata_refinement"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Typed
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &["normalize_fold", "dead_elimination"]
+    }
+
+    // Removing zoom predicates leaves residual wrappers (e.g. `["all", x]`),
+    // and tighter layer zoom bounds let ramps and sources shrink.
+    fn invalidates(&self) -> &[&'static str] {
+        &[
+            "normalize_fold",
+            "normalize_fold_filters",
+            "prune_zoom_ramps",
+            "source_zoom_tightening",
+        ]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.metadata_refinement
+    }
+
+    fn run_typed(&self, style: &mut MaplibreStyleSpecification, cx: &PassContext<'_>) {
+        let layer_info = cx.stats.map(|_| precompute_vector_layer_info_typed(style));
+        metadata_refinement(style, cx.options, cx.stats, layer_info.as_deref());
+    }
+}
+
+struct Cleanup;
+
+impl Pass for Cleanup {
+    fn name(&self) -> &'static str {
+        "cleanup"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Typed
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &["strip_defaults", "dead_elimination", "metadata_refinement"]
+    }
+
+    fn invalidates(&self) -> &[&'static str] {
+        &["source_zoom_tightening"]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.cleanup
+    }
+
+    fn run_typed(&self, style: &mut MaplibreStyleSpecification, cx: &PassContext<'_>) {
+        cleanup(style, &cx.keep_layers(self.name()));
+    }
+}
+
+/// Normalize/fold fixpoint on typed filters, catching residual filter
+/// simplification after structural passes without a JSON round-trip.
+struct NormalizeFoldFilters;
+
+impl Pass for NormalizeFoldFilters {
+    fn name(&self) -> &'static str {
+        "normalize_fold_filters"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Typed
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &["metadata_refinement", "cleanup"]
+    }
+
+    // Folding can leave trivially-true filters or invisible layers behind.
+    fn invalidates(&self) -> &[&'static str] {
+        &["cleanup"]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        wants_expression_passes(options) && wants_structural_passes(options)
+    }
+
+    fn run_typed(&self, style: &mut MaplibreStyleSpecification, cx: &PassContext<'_>) {
+        let layer_info = cx.stats.map(|_| precompute_vector_layer_info_typed(style));
+        for _ in 0..NORMALIZE_FOLD_FIXPOINT_CAP {
+            let mut visitor = TypedNormalizeFoldVisitor {
+                passes: cx.options,
+                stats: cx.stats,
+                layer_info: layer_info.as_deref(),
+                changed: false,
+            };
+            walk_typed_filters(style, &mut visitor);
+            if !visitor.changed {
+                break;
+            }
+        }
+    }
+}
+
+struct SourceZoomTightening;
+
+impl Pass for SourceZoomTightening {
+    fn name(&self) -> &'static str {
+        "source_zoom_tightening"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Typed
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &[
+            "dead_elimination",
+            "metadata_refinement",
+            "cleanup",
+            "normalize_fold_filters",
+        ]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.source_zoom_tightening
+    }
+
+    fn run_typed(&self, style: &mut MaplibreStyleSpecification, _: &PassContext<'_>) {
+        tighten_source_zoom_bounds(style);
+    }
+}
+
+// ── Zoom ramps and layer merging ──────────────────────────────────────────────
+
+/// Zoom-comparison folding and out-of-range stop pruning, using the layer
+/// zoom bounds tightened by `metadata_refinement`.
+struct PruneZoomRamps;
+
+impl Pass for PruneZoomRamps {
+    fn name(&self) -> &'static str {
+        "prune_zoom_ramps"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Json
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &["metadata_refinement", "source_zoom_tightening"]
+    }
+
+    // Collapsed ramps become literals that fold further, possibly to zero opacity.
+    fn invalidates(&self) -> &[&'static str] {
+        &["normalize_fold", "cleanup", "common_subexpressions"]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.prune_zoom_ramps
+    }
+
+    fn run_json(&self, style: &mut Value, cx: &PassContext<'_>) {
+        ramp::fold_zoom_comparisons(style);
+        for (path, reason) in ramp::prune_zoom_stops(style) {
+            cx.record_skip(self.name(), path, reason);
+        }
+    }
+}
+
+/// Runs after every other pass so dead layers are gone and expressions are
+/// simplified before grouping.
+struct LayerMerge;
+
+impl Pass for LayerMerge {
+    fn name(&self) -> &'static str {
+        "layer_merge"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Json
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &[
+            "normalize_fold",
+            "strip_defaults",
+            "minify_colors",
+            "selectivity_reorder",
+            "dead_elimination",
+            "metadata_refinement",
+            "cleanup",
+            "prune_zoom_ramps",
+        ]
+    }
+
+    // Merging synthesises new `case`/`match`/`any` expressions.
+    fn invalidates(&self) -> &[&'static str] {
+        &[
+            "normalize_fold",
+            "strip_defaults",
+            "minify_colors",
+            "selectivity_reorder",
+            "prune_zoom_ramps",
+            "cleanup",
+            "common_subexpressions",
+        ]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.layer_merge
+    }
+
+    fn run_json(&self, style: &mut Value, cx: &PassContext<'_>) {
+        let keep = cx.keep_layers(self.name());
+        let cost = merge::MergeCost {
+            weight: cx.options.merge_cost_weight,
+            stats: cx.stats,
+        };
+        for group in merge::layer_merge(style, cx.mir, &keep, Some(&cost)) {
+            cx.record_merge(&group.into, &group.members);
+        }
+    }
+}
+
+/// Runs after `layer_merge`, so that merged layers with a low-cardinality
+/// `match` are split back into constant-paint layers rather than re-merged.
+struct LayerSplit;
+
+impl Pass for LayerSplit {
+    fn name(&self) -> &'static str {
+        "layer_split"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Json
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &[
+            "normalize_fold",
+            "strip_defaults",
+            "minify_colors",
+            "selectivity_reorder",
+            "dead_elimination",
+            "metadata_refinement",
+            "cleanup",
+            "prune_zoom_ramps",
+            "layer_merge",
+        ]
+    }
+
+    // Split-off layers get `all` filters and may be left with default paint.
+    fn invalidates(&self) -> &[&'static str] {
+        &[
+            "normalize_fold",
+            "strip_defaults",
+            "selectivity_reorder",
+            "cleanup",
+            "common_subexpressions",
+        ]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.layer_split
+    }
+
+    fn run_json(&self, style: &mut Value, cx: &PassContext<'_>) {
+        let Some(stats) = cx.stats else {
+            return;
+        };
+        let keep = cx.keep_layers(self.name());
+        for layer in split::layer_split(style, stats, &keep, cx.options.split_cardinality) {
+            cx.record_split(&layer.from, &layer.parts);
+        }
+    }
+}
+
+/// Runs last: `var` hides subtrees from the rewrite rules of other passes.
+struct CommonSubexpressions;
+
+impl Pass for CommonSubexpressions {
+    fn name(&self) -> &'static str {
+        "common_subexpressions"
+    }
+
+    fn data(&self) -> PassData {
+        PassData::Json
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &[
+            "normalize_fold",
+            "strip_defaults",
+            "minify_colors",
+            "selectivity_reorder",
+            "cleanup",
+            "prune_zoom_ramps",
+            "layer_merge",
+            "layer_split",
+        ]
+    }
+
+    fn enabled(&self, options: &OptPasses) -> bool {
+        options.common_subexpressions
+    }
+
+    fn run_json(&self, style: &mut Value, cx: &PassContext<'_>) {
+        walk_style_mut(style, cx.mir, &mut CommonSubexpressionVisitor);
+    }
+}
@@ -3,14 +3,22 @@
 use std::collections::HashSet;
 
 use maplibre_style_spec::spec::{
-    AnyLayer, Boolean, MaplibreStyleSpecification, RootBearing, RootPitch, RootRoll, RootState,
-    RootTransition, Transition, TransitionDelay, TransitionDuration, TypedLayer, Visibility,
+    AnyLayer, Boolean, LayerMaxzoom, LayerMinzoom, MaplibreStyleSpecification, RootBearing,
+    RootPitch, RootRoll, RootState, RootTransition, Transition, TransitionDelay,
+    TransitionDuration, TypedLayer, Visibility,
 };
 
+use serde_json::Value;
+
+use super::color::parse_color;
 use super::dead::{collect_used_sources, prune_sources};
+use super::keep::KeepLayers;
+use super::zoom::hidden_minzoom_from_value;
 
-/// Remove empty paint/layout, visibility:none layers, zero-opacity layers.
-pub(crate) fn cleanup(style: &mut MaplibreStyleSpecification) {
+/// Remove empty paint/layout and layers that draw nothing, and raise `minzoom`
+/// past zooms at which a layer's paint draws nothing.
+/// Invisible layers in `keep` stay, so clients can still toggle them.
+pub(crate) fn cleanup(style: &mut MaplibreStyleSpecification, keep: &KeepLayers) {
     // Collect referenced layer IDs so we never remove ref targets.
     let referenced_ids: HashSet<String> = style
         .layers
@@ -31,19 +39,30 @@ pub(crate) fn cleanup(style: &mut MaplibreStyleSpecification) {
         }
     }
 
-    // Remove invisible layers.
-    let to_remove: Vec<usize> = style
-        .layers
-        .iter()
-        .enumerate()
-        .filter_map(|(i, layer)| {
-            let id = layer.id().as_str();
-            if referenced_ids.contains(id) {
-                return None; // never remove a ref target
-            }
-            if is_invisible(layer) { Some(i) } else { None }
-        })
-        .collect();
+    // Remove invisible layers, and start the rest at the zoom they first draw at.
+    let mut to_remove = Vec::new();
+    for (i, layer) in style.layers.iter_mut().enumerate() {
+        let AnyLayer::Typed(t) = layer else {
+            continue;
+        };
+        let id = t.common().id.as_str();
+        if referenced_ids.contains(id) || keep.contains(id) {
+            continue; // never touch a ref target or a kept layer
+        }
+        if layout_visibility(t) == Some(Visibility::None) {
+            to_remove.push(i);
+            continue;
+        }
+        let hidden = hidden_below(t);
+        let common = t.common_mut();
+        let maxzoom = common.maxzoom.as_ref().and_then(LayerMaxzoom::as_f64);
+        let minzoom = common.minzoom.as_ref().and_then(LayerMinzoom::as_f64);
+        if hidden >= maxzoom.unwrap_or(24.0) {
+            to_remove.push(i);
+        } else if hidden > minzoom.unwrap_or(0.0) {
+            common.minzoom = LayerMinzoom::from_f64(hidden);
+        }
+    }
 
     if !to_remove.is_empty() {
         for i in to_remove.into_iter().rev() {
@@ -212,82 +231,95 @@ fn layout_visibility(layer: &TypedLayer) -> Option<Visibility> {
     }
 }
 
-/// Extract the primary opacity as a literal f64, if present.
-fn literal_opacity(layer: &TypedLayer) -> Option<f64> {
-    match layer {
-        TypedLayer::Background { paint: Some(p), .. } => p.background_opacity.as_ref()?.0.as_f64(),
-        TypedLayer::Circle { paint: Some(p), .. } => p.circle_opacity.as_ref()?.0.as_f64(),
-        TypedLayer::ColorRelief { paint: Some(p), .. } => {
-            p.color_relief_opacity.as_ref()?.0.as_f64()
+// ── Paint-based invisibility ────────────────────────────────────────────────
+
+/// The zoom below which a layer draws nothing, judged from its literal and
+/// zoom-driven paint and layout values: `f64::INFINITY` when it never draws,
+/// `0.0` when nothing is known.
+///
+/// Each property yields the zoom below which it hides what it applies to, so
+/// properties that each hide the same thing combine with `max`, and separately
+/// drawn parts (circle fill and stroke, text and icon) with `min`.
+fn hidden_below(layer: &TypedLayer) -> f64 {
+    let Ok(json) = serde_json::to_value(layer) else {
+        return 0.0;
+    };
+    let paint = |key: &str| json.get("paint").and_then(|p| p.get(key));
+    let layout = |key: &str| json.get("layout").and_then(|l| l.get(key));
+    // Patterns and gradients are drawn instead of the colour.
+    let color = |key: &str, overrides: &[&str]| {
+        if overrides.iter().any(|o| paint(o).is_some()) {
+            0.0
+        } else {
+            transparent_below(paint(key))
         }
-        TypedLayer::Fill { paint: Some(p), .. } => p.fill_opacity.as_ref()?.0.as_f64(),
-        TypedLayer::FillExtrusion { paint: Some(p), .. } => {
-            p.fill_extrusion_opacity.as_ref()?.0.as_f64()
+    };
+
+    match json.get("type").and_then(Value::as_str).unwrap_or_default() {
+        "background" => zero_below(paint("background-opacity"), 1.0)
+            .max(color("background-color", &["background-pattern"])),
+        "fill" => {
+            // The outline defaults to `fill-color`.
+            let outline = match paint("fill-outline-color") {
+                Some(c) => transparent_below(Some(c)),
+                None => f64::INFINITY,
+            };
+            zero_below(paint("fill-opacity"), 1.0)
+                .max(color("fill-color", &["fill-pattern"]).min(outline))
         }
-        TypedLayer::Heatmap { paint: Some(p), .. } => p.heatmap_opacity.as_ref()?.0.as_f64(),
-        TypedLayer::Line { paint: Some(p), .. } => p.line_opacity.as_ref()?.0.as_f64(),
-        TypedLayer::Raster { paint: Some(p), .. } => p.raster_opacity.as_ref()?.0.as_f64(),
-        TypedLayer::Symbol { paint: Some(p), .. } => {
-            // Symbol is invisible only when both icon and text opacity are zero.
-            let icon = p
-                .icon_opacity
-                .as_ref()
-                .and_then(|o| o.0.as_f64())
-                .unwrap_or(1.0);
-            let text = p
-                .text_opacity
-                .as_ref()
-                .and_then(|o| o.0.as_f64())
-                .unwrap_or(1.0);
-            if icon == 0.0 && text == 0.0 {
-                Some(0.0)
-            } else {
-                None
-            }
+        "line" => zero_below(paint("line-opacity"), 1.0)
+            .max(zero_below(paint("line-width"), 1.0))
+            .max(color("line-color", &["line-pattern", "line-gradient"])),
+        "circle" => {
+            let fill = zero_below(paint("circle-opacity"), 1.0)
+                .max(zero_below(paint("circle-radius"), 5.0))
+                .max(transparent_below(paint("circle-color")));
+            let stroke = zero_below(paint("circle-stroke-opacity"), 1.0)
+                .max(zero_below(paint("circle-stroke-width"), 0.0))
+                .max(transparent_below(paint("circle-stroke-color")));
+            fill.min(stroke)
         }
-        TypedLayer::Hillshade { .. }
-        | TypedLayer::Background { paint: None, .. }
-        | TypedLayer::Circle { paint: None, .. }
-        | TypedLayer::ColorRelief { paint: None, .. }
-        | TypedLayer::Fill { paint: None, .. }
-        | TypedLayer::FillExtrusion { paint: None, .. }
-        | TypedLayer::Heatmap { paint: None, .. }
-        | TypedLayer::Line { paint: None, .. }
-        | TypedLayer::Raster { paint: None, .. }
-        | TypedLayer::Symbol { paint: None, .. } => None,
+        "symbol" => {
+            let unset = |key: &str| {
+                if layout(key).is_none() {
+                    f64::INFINITY
+                } else {
+                    0.0
+                }
+            };
+            let text = unset("text-field")
+                .max(zero_below(paint("text-opacity"), 1.0))
+                .max(zero_below(layout("text-size"), 16.0));
+            let icon = unset("icon-image").max(zero_below(paint("icon-opacity"), 1.0));
+            text.min(icon)
+        }
+        "color-relief" => zero_below(paint("color-relief-opacity"), 1.0),
+        "fill-extrusion" => zero_below(paint("fill-extrusion-opacity"), 1.0),
+        "heatmap" => zero_below(paint("heatmap-opacity"), 1.0),
+        "raster" => zero_below(paint("raster-opacity"), 1.0),
+        _ => 0.0,
     }
 }
 
-fn is_invisible(layer: &AnyLayer) -> bool {
-    let AnyLayer::Typed(t) = layer else {
-        return false;
-    };
-
-    // Check visibility.
-    if layout_visibility(t) == Some(Visibility::None) {
-        return true;
-    }
-
-    // Check zero opacity.
-    if literal_opacity(t) == Some(0.0) {
-        // Circle special case: visible stroke keeps the layer alive.
-        if let TypedLayer::Circle { paint: Some(p), .. } = t {
-            let stroke_opacity = p
-                .circle_stroke_opacity
-                .as_ref()
-                .and_then(|o| o.0.as_f64())
-                .unwrap_or(1.0);
-            let stroke_width = p
-                .circle_stroke_width
-                .as_ref()
-                .and_then(|w| w.0.as_f64())
-                .unwrap_or(0.0);
-            if stroke_opacity != 0.0 && stroke_width != 0.0 {
-                return false;
-            }
-        }
-        return true;
+/// The zoom below which a numeric property is zero; an absent property takes
+/// `default`.
+#[allow(clippy::float_cmp)]
+fn zero_below(value: Option<&Value>, default: f64) -> f64 {
+    match value {
+        Some(v) => hidden_minzoom_from_value(v, &|v| v.as_f64().map(|n| n == 0.0)).unwrap_or(0.0),
+        None if default == 0.0 => f64::INFINITY,
+        None => 0.0,
     }
+}
 
-    false
+/// The zoom below which a colour property is fully transparent.
+#[allow(clippy::float_cmp)]
+fn transparent_below(value: Option<&Value>) -> f64 {
+    let is_transparent = |v: &Value| {
+        let (_, _, _, alpha) = parse_color(v.as_str()?)?;
+        Some(alpha == 0.0)
+    };
+    value
+        .and_then(|v| hidden_minzoom_from_value(v, &is_transparent))
+        .unwrap_or(0.0)
 }
@@ -75,7 +75,7 @@ pub(crate) fn minify_color_string(s: &str) -> Option<String> {
 
 /// Parse a CSS color string into RGBA (0–255 for rgb, 0.0–1.0 for alpha).
 #[expect(clippy::many_single_char_names)]
-fn parse_color(s: &str) -> Option<(u8, u8, u8, f64)> {
+pub(super) fn parse_color(s: &str) -> Option<(u8, u8, u8, f64)> {
     let s = s.trim();
 
     if let Some(hex) = s.strip_prefix('#') {
@@ -0,0 +1,356 @@
+//! Common subexpression elimination: hoist subtrees repeated within one
+//! property or filter into a `["let", …]` binding read back with `["var", …]`.
+//!
+//! `var` re-evaluates its binding lazily in GL JS, so hoisting never changes
+//! which branches run.  A subtree is hoisted only when it shrinks the
+//! serialized expression, and only when its output type does not depend on
+//! where it sits: GL JS types a binding without the expected type of its use
+//! site, so `["match", …, "red", "blue"]` bound in a colour property would
+//! become a string.  Expressions that already bind variables, and subtrees
+//! reading `["zoom"]` (which must stay the input of the top-level curve), are
+//! left alone.
+
+use std::collections::HashMap;
+
+use maplibre_style_spec::expression_eval::is_supported;
+use serde_json::{Value, json};
+
+use super::walk::{PropertyContext, StyleVisitor};
+
+pub(crate) struct CommonSubexpressionVisitor;
+
+impl StyleVisitor for CommonSubexpressionVisitor {
+    fn visit_filter(&mut self, _: usize, _: &str, filter: &mut Value) {
+        hoist_common_subexpressions(filter);
+    }
+
+    fn visit_property(&mut self, ctx: &PropertyContext<'_>, value: &mut Value) {
+        if ctx.field.expression.is_some() {
+            hoist_common_subexpressions(value);
+        }
+    }
+}
+
+/// Operators whose output type is fixed, whatever the expected type.
+const CONTEXT_FREE: &[&str] = &[
+    "!",
+    "!=",
+    "%",
+    "*",
+    "+",
+    "-",
+    "/",
+    "<",
+    "<=",
+    "==",
+    ">",
+    ">=",
+    "^",
+    "abs",
+    "acos",
+    "all",
+    "any",
+    "asin",
+    "atan",
+    "boolean",
+    "ceil",
+    "concat",
+    "cos",
+    "distance",
+    "downcase",
+    "feature-state",
+    "floor",
+    "geometry-type",
+    "get",
+    "global-state",
+    "has",
+    "id",
+    "image",
+    "in",
+    "index-of",
+    "is-supported-script",
+    "length",
+    "ln",
+    "log10",
+    "log2",
+    "max",
+    "min",
+    "number",
+    "number-format",
+    "object",
+    "properties",
+    "resolved-locale",
+    "rgb",
+    "rgba",
+    "round",
+    "sin",
+    "sqrt",
+    "string",
+    "tan",
+    "to-boolean",
+    "to-color",
+    "to-number",
+    "to-rgba",
+    "to-string",
+    "typeof",
+    "upcase",
+    "within",
+];
+
+fn operator(v: &Value) -> Option<&str> {
+    v.as_array()?.first()?.as_str()
+}
+
+/// Indices of the arguments of `arr` that are expressions (not labels, stop
+/// inputs, type names or geometries).
+fn expression_args(arr: &[Value]) -> Vec<usize> {
+    let len = arr.len();
+    match arr.first().and_then(Value::as_str) {
+        Some("literal" | "var" | "within" | "distance") | None => Vec::new(),
+        // [match, input, label, out, …, fallback]
+        Some("match") => std::iter::once(1)
+            .chain((3..len).step_by(2))
+            .chain((len > 2).then_some(len - 1))
+            .filter(|&i| i < len)
+            .collect(),
+        // [interpolate, curve, input, stop, out, …]
+        Some("interpolate" | "interpolate-hcl" | "interpolate-lab") => std::iter::once(2)
+            .chain((4..len).step_by(2))
+            .filter(|&i| i < len)
+            .collect(),
+        // [step, input, out, stop, out, …]
+        Some("step") => std::iter::once(1).chain((2..len).step_by(2)).collect(),
+        Some("array") => (len > 1).then_some(len - 1).into_iter().collect(),
+        Some(_) => (1..len).collect(),
+    }
+}
+
+/// Visit the expression-position children of `v`, descending into option
+/// objects (`format`, `collator`, `number-format`).
+fn for_each_child<'v>(v: &'v Value, f: &mut impl FnMut(&'v Value)) {
+    let Value::Array(arr) = v else {
+        return;
+    };
+    for i in expression_args(arr) {
+        match &arr[i] {
+            Value::Object(options) => options.values().for_each(&mut *f),
+            child => f(child),
+        }
+    }
+}
+
+/// Whether `v` contains an operator in `ops`.
+fn mentions(v: &Value, ops: &[&str]) -> bool {
+    if operator(v).is_some_and(|op| ops.contains(&op)) {
+        return true;
+    }
+    let mut found = false;
+    for_each_child(v, &mut |child| found = found || mentions(child, ops));
+    found
+}
+
+/// Whether `v` has the same type wherever it is used.
+fn context_free(v: &Value) -> bool {
+    let Value::Array(arr) = v else {
+        return false;
+    };
+    match operator(v) {
+        Some(op) if CONTEXT_FREE.contains(&op) => true,
+        // Branching operators take the type of their outputs.
+        Some("coalesce") => arr[1..].iter().all(context_free),
+        Some("case") => {
+            arr.len() >= 2
+                && arr[2..].iter().step_by(2).all(context_free)
+                && context_free(&arr[arr.len() - 1])
+        }
+        Some("match") => {
+            arr.len() >= 3
+                && arr[3..].iter().step_by(2).all(context_free)
+                && context_free(&arr[arr.len() - 1])
+        }
+        Some("step") => arr.len() >= 3 && arr[2..].iter().step_by(2).all(context_free),
+        _ => false,
+    }
+}
+
+fn is_candidate(v: &Value) -> bool {
+    operator(v).is_some_and(is_supported)
+        && context_free(v)
+        && !mentions(v, &["zoom", "var", "let"])
+}
+
+fn serialized_len(v: &Value) -> usize {
+    serde_json::to_string(v).map_or(0, |s| s.len())
+}
+
+/// Count the candidate subtrees of `v`, keyed by their serialization.
+fn count_candidates(v: &Value, counts: &mut HashMap<String, (usize, Value)>) {
+    if is_candidate(v) {
+        let key = v.to_string();
+        counts.entry(key).or_insert_with(|| (0, v.clone())).0 += 1;
+    }
+    for_each_child(v, &mut |child| count_candidates(child, counts));
+}
+
+/// Replace every expression-position occurrence of `target` in `v`.
+fn replace(v: &mut Value, target: &Value, replacement: &Value) {
+    if v == target {
+        *v = replacement.clone();
+        return;
+    }
+    let Value::Array(arr) = v else {
+        return;
+    };
+    for i in expression_args(arr) {
+        match &mut arr[i] {
+            Value::Object(options) => {
+                for child in options.values_mut() {
+                    replace(child, target, replacement);
+                }
+            }
+            child => replace(child, target, replacement),
+        }
+    }
+}
+
+/// Bytes saved by binding `expr`, used `uses` times, to a variable called
+/// `name`; `first` adds the cost of the `let` wrapper itself.
+fn savings(expr: &Value, uses: usize, name: &str, first: bool) -> isize {
+    let size = serialized_len(expr);
+    let reference = serialized_len(&json!(["var", name]));
+    // `"name",expr,` in the binding list.
+    let binding = name.len() + 4 + size;
+    // `["let",` … `]`
+    let wrapper = if first { 8 } else { 0 };
+    let before = uses * size;
+    let after = uses * reference + binding + wrapper;
+    isize::try_from(before).unwrap_or(isize::MAX) - isize::try_from(after).unwrap_or(isize::MAX)
+}
+
+/// Hoist repeated subtrees of the expression `v`, most profitable first,
+/// into one `let` around it.  Returns `true` if anything was hoisted.
+pub(crate) fn hoist_common_subexpressions(v: &mut Value) -> bool {
+    if !operator(v).is_some_and(is_supported) || mentions(v, &["let", "var"]) {
+        return false;
+    }
+    let mut bindings: Vec<(String, Value)> = Vec::new();
+    loop {
+        let mut counts = HashMap::new();
+        count_candidates(v, &mut counts);
+        let name = format!("v{}", bindings.len());
+        let best = counts
+            .into_values()
+            .filter(|(uses, _)| *uses > 1)
+            .map(|(uses, expr)| (savings(&expr, uses, &name, bindings.is_empty()), expr))
+            .filter(|(saved, _)| *saved > 0)
+            // Largest saving; ties broken by serialization for determinism.
+            .max_by(|(a, x), (b, y)| a.cmp(b).then_with(|| y.to_string().cmp(&x.to_string())));
+        let Some((_, expr)) = best else {
+            break;
+        };
+        replace(v, &expr, &json!(["var", name]));
+        bindings.push((name, expr));
+    }
+    if bindings.is_empty() {
+        return false;
+    }
+    let mut wrapped = vec![json!("let")];
+    for (name, expr) in bindings {
+        wrapped.push(Value::String(name));
+        wrapped.push(expr);
+    }
+    wrapped.push(v.take());
+    *v = Value::Array(wrapped);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hoists_repeated_coalesce_across_case_arms() {
+        let name = json!(["coalesce", ["get", "name:de"], ["get", "name"]]);
+        let mut expr = json!([
+            "case",
+            ["==", ["get", "class"], "city"],
+            ["upcase", name],
+            ["==", ["get", "class"], "town"],
+            ["concat", name, " (town)"],
+            name
+        ]);
+        assert!(hoist_common_subexpressions(&mut expr));
+        assert_eq!(
+            expr,
+            json!([
+                "let",
+                "v0",
+                ["coalesce", ["get", "name:de"], ["get", "name"]],
+                [
+                    "case",
+                    ["==", ["get", "class"], "city"],
+                    ["upcase", ["var", "v0"]],
+                    ["==", ["get", "class"], "town"],
+                    ["concat", ["var", "v0"], " (town)"],
+                    ["var", "v0"]
+                ]
+            ])
+        );
+        // Already bound: a second run leaves it alone.
+        let hoisted = expr.clone();
+        assert!(!hoist_common_subexpressions(&mut expr));
+        assert_eq!(expr, hoisted);
+    }
+
+    #[test]
+    fn leaves_small_or_context_typed_repeats() {
+        // Two short `get`s cost more to bind than they save.
+        let mut small = json!(["+", ["get", "a"], ["get", "a"]]);
+        assert!(!hoist_common_subexpressions(&mut small));
+
+        // The output of this `match` is a colour only in a colour property.
+        let colour = json!([
+            "match",
+            ["get", "kind"],
+            ["river", "canal", "stream"],
+            "#0000ff",
+            "#00ff00"
+        ]);
+        let mut expr = json!([
+            "case",
+            ["get", "night"],
+            colour,
+            ["get", "day"],
+            colour,
+            colour
+        ]);
+        assert!(!hoist_common_subexpressions(&mut expr));
+
+        // Zoom-dependent subtrees stay where the curve can see them.
+        let zoomed = json!(["*", ["zoom"], 1.5]);
+        let mut expr = json!(["max", zoomed, zoomed, zoomed]);
+        assert!(!hoist_common_subexpressions(&mut expr));
+    }
+
+    #[test]
+    fn binds_each_repeated_subtree_once() {
+        let name = json!(["coalesce", ["get", "name_en"], ["get", "name"]]);
+        let rank = json!(["to-number", ["get", "population_rank"], 0]);
+        let mut expr = json!([
+            "case",
+            [">", rank, 10],
+            ["concat", name, "!"],
+            [">", rank, 5],
+            ["concat", name, "?"],
+            [">", rank, 1],
+            name,
+            ""
+        ]);
+        assert!(hoist_common_subexpressions(&mut expr));
+        assert_eq!(expr[0], "let");
+        assert_eq!(expr.as_array().unwrap().len(), 6);
+        let bound = [&expr[2], &expr[4]];
+        assert!(bound.contains(&&name) && bound.contains(&&rank));
+        assert!(!expr[5].to_string().contains("population_rank"));
+    }
+}
@@ -3,25 +3,53 @@
 use std::collections::{HashMap, HashSet};
 
 use maplibre_style_spec::spec::{AnyLayer, MaplibreStyleSpecification, TypedLayer};
+use serde_json::Value;
 
 use super::OptPasses;
+use super::color::parse_color;
+use super::keep::KeepLayers;
+use super::merge::layer_zoom_range;
 use super::source_util::VectorLayerInfo;
 use crate::stats::TileStatistics;
 
-/// Remove layers with always-false filters and geometry-type mismatches,
-/// then prune unused sources.
+/// A layer removed because a later layer draws over everything it draws.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Overdrawn {
+    pub id: String,
+    /// The later layer that covers it.
+    pub by: String,
+    /// Selects the removed layer's features within `by`.
+    pub filter: Value,
+}
+
+/// A layer drawn again by a later copy of itself that could not be removed,
+/// because the copy is not an opaque fill.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Duplicate {
+    pub id: String,
+    /// The later copy.
+    pub of: String,
+}
+
+/// Remove layers with always-false filters and geometry-type mismatches, and
+/// layers a later layer draws over completely, then prune unused sources.
+/// Layers in `keep` are never removed.  Returns the overdrawn layers removed
+/// and the duplicates left in place.
 pub(crate) fn dead_elimination(
     style: &mut MaplibreStyleSpecification,
     passes: &OptPasses,
+    keep: &KeepLayers,
     stats: Option<&TileStatistics>,
     layer_info: Option<&[Option<VectorLayerInfo>]>,
-) {
+) -> (Vec<Overdrawn>, Vec<Duplicate>) {
     // Reject sampled stats for irreversible dead-layer elimination.
     let stats = stats.filter(|s| (s.sample_rate - 1.0).abs() <= f64::EPSILON);
 
     let mut to_drop: Vec<usize> = Vec::new();
     for (i, layer) in style.layers.iter().enumerate() {
-        if let AnyLayer::Typed(t) = layer {
+        if let AnyLayer::Typed(t) = layer
+            && !keep.contains(t.common().id.as_str())
+        {
             // Filter is always false.
             if let Some(ref filter) = t.common().filter
                 && filter.is_always_false()
@@ -40,6 +68,17 @@ pub(crate) fn dead_elimination(
         }
     }
 
+    let json: Vec<Value> = style
+        .layers
+        .iter()
+        .map(|l| serde_json::to_value(l).unwrap_or_default())
+        .collect();
+    let (overdrawn, duplicates) = find_overdrawn(&json, |i| {
+        to_drop.contains(&i) || keep.contains(style.layers[i].id().as_str())
+    });
+    to_drop.extend(overdrawn.iter().map(|&(i, _)| i));
+    to_drop.sort_unstable();
+
     if !to_drop.is_empty() {
         for i in to_drop.into_iter().rev() {
             style.layers.remove(i);
@@ -49,6 +88,190 @@ pub(crate) fn dead_elimination(
     // Prune unused sources.
     let used = collect_used_sources(&style.layers);
     prune_sources(style, &used);
+
+    (overdrawn.into_iter().map(|(_, o)| o).collect(), duplicates)
+}
+
+// ── Overdrawn layers ─────────────────────────────────────────────────────────
+
+/// Layers fully covered by a later layer, with their index, and duplicates
+/// that have to stay.
+///
+/// A layer is covered by a later copy of itself (identical except for `id`)
+/// when the copy is an opaque fill; other copies are only reported.  Fills
+/// are also covered by a later opaque fill on the same source-layer whose
+/// filter and zoom range include their own.  An opaque fill hides what is
+/// under it whatever draws in between.  Layers for which `skip` holds, and
+/// targets of `ref` layers, are neither removed nor used as cover.
+fn find_overdrawn(
+    layers: &[Value],
+    skip: impl Fn(usize) -> bool,
+) -> (Vec<(usize, Overdrawn)>, Vec<Duplicate>) {
+    let referenced: HashSet<&str> = layers
+        .iter()
+        .filter_map(|l| l.get("ref").and_then(Value::as_str))
+        .collect();
+    let usable = |i: usize| {
+        !skip(i) && layers[i].get("ref").is_none() && !referenced.contains(id(&layers[i]))
+    };
+
+    let mut overdrawn = Vec::new();
+    let mut duplicates = Vec::new();
+    for i in (0..layers.len()).filter(|&i| usable(i)) {
+        let lower = &layers[i];
+        let mut copy = None;
+        let cover = (i + 1..layers.len()).filter(|&j| usable(j)).find_map(|j| {
+            let upper = &layers[j];
+            if is_duplicate(lower, upper) {
+                if is_opaque_fill(upper) {
+                    return Some((upper, Value::Bool(true)));
+                }
+                copy.get_or_insert(upper);
+                None
+            } else if fill_covers(upper, lower) {
+                let filter = lower.get("filter").cloned().unwrap_or(Value::Bool(true));
+                Some((upper, filter))
+            } else {
+                None
+            }
+        });
+        if let Some((upper, filter)) = cover {
+            overdrawn.push((
+                i,
+                Overdrawn {
+                    id: id(lower).to_owned(),
+                    by: id(upper).to_owned(),
+                    filter,
+                },
+            ));
+        } else if let Some(upper) = copy {
+            duplicates.push(Duplicate {
+                id: id(lower).to_owned(),
+                of: id(upper).to_owned(),
+            });
+        }
+    }
+    (overdrawn, duplicates)
+}
+
+fn id(layer: &Value) -> &str {
+    layer.get("id").and_then(Value::as_str).unwrap_or_default()
+}
+
+/// Identical except for `id`.
+fn is_duplicate(a: &Value, b: &Value) -> bool {
+    let (Some(a), Some(b)) = (a.as_object(), b.as_object()) else {
+        return false;
+    };
+    a.len() == b.len()
+        && a.iter()
+            .all(|(k, v)| k == "id" || b.get(k).is_some_and(|w| w == v))
+}
+
+/// `upper` is an opaque fill drawing every feature of fill `lower` at every
+/// zoom `lower` is drawn at, with the same geometry offset.
+fn fill_covers(upper: &Value, lower: &Value) -> bool {
+    let same = |key: &str| upper.get(key) == lower.get(key);
+    if upper.get("type").and_then(Value::as_str) != Some("fill")
+        || !same("type")
+        || !same("source")
+        || !same("source-layer")
+    {
+        return false;
+    }
+    let paint = |l: &Value, key: &str| l.get("paint").and_then(|p| p.get(key)).cloned();
+    if paint(upper, "fill-translate") != paint(lower, "fill-translate")
+        || paint(upper, "fill-translate-anchor") != paint(lower, "fill-translate-anchor")
+    {
+        return false;
+    }
+    let hidden = upper
+        .get("layout")
+        .and_then(|l| l.get("visibility"))
+        .and_then(Value::as_str)
+        == Some("none");
+    let (lower_min, lower_max) = layer_zoom_range(lower);
+    let (upper_min, upper_max) = layer_zoom_range(upper);
+    !hidden
+        && upper_min <= lower_min
+        && upper_max >= lower_max
+        && is_opaque_fill(upper)
+        && filter_implies(lower.get("filter"), upper.get("filter"))
+}
+
+/// A fill with constant, fully opaque `fill-color` and `fill-outline-color`,
+/// full `fill-opacity`, and no `fill-pattern`.
+fn is_opaque_fill(layer: &Value) -> bool {
+    let paint = |key: &str| layer.get("paint").and_then(|p| p.get(key));
+    let opaque_color = |v: Option<&Value>| {
+        v.is_none_or(|c| {
+            c.as_str()
+                .and_then(parse_color)
+                .is_some_and(|(_, _, _, a)| a >= 1.0)
+        })
+    };
+    layer.get("type").and_then(Value::as_str) == Some("fill")
+        && paint("fill-pattern").is_none()
+        && paint("fill-opacity").is_none_or(|o| o.as_f64() == Some(1.0))
+        && opaque_color(paint("fill-color"))
+        && opaque_color(paint("fill-outline-color"))
+}
+
+/// Whether every feature passing filter `a` also passes `b`, judged
+/// syntactically: equal filters, `any`/`all` operands, and `==`/`in` tests of
+/// one property against literal sets.
+fn filter_implies(a: Option<&Value>, b: Option<&Value>) -> bool {
+    let Some(b) = b.filter(|b| **b != Value::Bool(true)) else {
+        return true;
+    };
+    let Some(a) = a else {
+        return false;
+    };
+    if a == b {
+        return true;
+    }
+    let operands = |v: &Value, op: &str| -> Option<Vec<Value>> {
+        let arr = v.as_array()?;
+        (arr.first()?.as_str()? == op).then(|| arr[1..].to_vec())
+    };
+    if operands(b, "any").is_some_and(|ops| ops.iter().any(|o| filter_implies(Some(a), Some(o)))) {
+        return true;
+    }
+    if operands(a, "all").is_some_and(|ops| ops.iter().any(|o| filter_implies(Some(o), Some(b)))) {
+        return true;
+    }
+    match (equality_set(a), equality_set(b)) {
+        (Some((a_input, a_values)), Some((b_input, b_values))) => {
+            a_input == b_input && a_values.iter().all(|v| b_values.contains(v))
+        }
+        _ => false,
+    }
+}
+
+/// `["==", input, literal]` (either order) or `["in", input, ["literal", [...]]]`
+/// as the input and its accepted values.
+fn equality_set(filter: &Value) -> Option<(&Value, Vec<&Value>)> {
+    let arr = filter.as_array()?;
+    if arr.len() != 3 {
+        return None;
+    }
+    let is_input = |v: &Value| {
+        v.as_array()
+            .is_some_and(|a| a.first().is_some_and(Value::is_string))
+    };
+    let is_scalar = |v: &Value| v.is_string() || v.is_number() || v.is_boolean();
+    match arr[0].as_str()? {
+        "==" if is_input(&arr[1]) && is_scalar(&arr[2]) => Some((&arr[1], vec![&arr[2]])),
+        "==" if is_input(&arr[2]) && is_scalar(&arr[1]) => Some((&arr[2], vec![&arr[1]])),
+        "in" if is_input(&arr[1]) => {
+            let lit = arr[2].as_array()?;
+            if lit.len() != 2 || lit[0].as_str()? != "literal" {
+                return None;
+            }
+            Some((&arr[1], lit[1].as_array()?.iter().collect()))
+        }
+        _ => None,
+    }
 }
 
 /// Prune sources not referenced by any layer.
@@ -153,3 +376,136 @@ fn resolve_ref_source(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn overdrawn(layers: &[Value]) -> Vec<(String, String)> {
+        find_overdrawn(layers, |_| false)
+            .0
+            .into_iter()
+            .map(|(_, o)| (o.id, o.by))
+            .collect()
+    }
+
+    fn fill(id: &str, filter: &Value, paint: &Value) -> Value {
+        json!({
+            "id": id, "type": "fill", "source": "s", "source-layer": "landuse",
+            "filter": filter, "paint": paint
+        })
+    }
+
+    fn duplicates(layers: &[Value]) -> Vec<(String, String)> {
+        find_overdrawn(layers, |_| false)
+            .1
+            .into_iter()
+            .map(|d| (d.id, d.of))
+            .collect()
+    }
+
+    #[test]
+    fn earlier_opaque_duplicate_is_overdrawn() {
+        let park = |id: &str| fill(id, &json!(["has", "park"]), &json!({"fill-color": "#0f0"}));
+        let mut other = park("c");
+        other["filter"] = json!(["has", "wood"]);
+        let layers = [park("a"), park("b"), other];
+        assert_eq!(overdrawn(&layers), [("a".to_owned(), "b".to_owned())]);
+        assert!(duplicates(&layers).is_empty());
+    }
+
+    #[test]
+    fn translucent_duplicate_is_only_reported() {
+        let park = |id: &str| {
+            fill(
+                id,
+                &json!(["has", "park"]),
+                &json!({"fill-color": "#0f0", "fill-opacity": 0.5}),
+            )
+        };
+        let layers = [park("a"), park("b")];
+        assert!(overdrawn(&layers).is_empty());
+        assert_eq!(duplicates(&layers), [("a".to_owned(), "b".to_owned())]);
+
+        // Lines are never opaque fills.
+        let line = |id: &str| {
+            json!({"id": id, "type": "line", "source": "s", "source-layer": "roads",
+                   "paint": {"line-color": "#f00"}})
+        };
+        let lines = [line("a"), line("b")];
+        assert!(overdrawn(&lines).is_empty());
+        assert_eq!(duplicates(&lines), [("a".to_owned(), "b".to_owned())]);
+    }
+
+    #[test]
+    fn opaque_duplicate_covers_across_layers_drawn_between() {
+        let park = |id: &str, paint: &Value| fill(id, &json!(["has", "park"]), paint);
+        let road = json!({"id": "road", "type": "line", "source": "s", "source-layer": "roads"});
+        let opaque = json!({"fill-color": "#0f0"});
+        let layers = [park("a", &opaque), road.clone(), park("b", &opaque)];
+        assert_eq!(overdrawn(&layers), [("a".to_owned(), "b".to_owned())]);
+
+        let translucent = json!({"fill-color": "rgba(0, 255, 0, 0.5)"});
+        let layers = [park("a", &translucent), road, park("b", &translucent)];
+        assert!(overdrawn(&layers).is_empty());
+        assert_eq!(duplicates(&layers), [("a".to_owned(), "b".to_owned())]);
+    }
+
+    #[test]
+    fn opaque_fill_with_wider_filter_covers_earlier_fill() {
+        let lower = fill(
+            "park",
+            &json!(["==", ["get", "class"], "park"]),
+            &json!({"fill-color": "#0f0"}),
+        );
+        let upper = fill(
+            "green",
+            &json!(["in", ["get", "class"], ["literal", ["park", "wood"]]]),
+            &json!({"fill-color": "rgb(0, 128, 0)"}),
+        );
+        let (found, _) = find_overdrawn(&[lower.clone(), upper.clone()], |_| false);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1.by, "green");
+        assert_eq!(found[0].1.filter, lower["filter"]);
+
+        // Translucent, narrower, or zoom-limited covers do not hide it.
+        let mut translucent = upper.clone();
+        translucent["paint"]["fill-color"] = json!("rgba(0, 128, 0, 0.5)");
+        let mut narrower = upper.clone();
+        narrower["filter"] = json!(["==", ["get", "class"], "wood"]);
+        let mut zoomed = upper;
+        zoomed["minzoom"] = json!(10);
+        for cover in [translucent, narrower, zoomed] {
+            assert!(overdrawn(&[lower.clone(), cover]).is_empty());
+        }
+    }
+
+    #[test]
+    fn skipped_and_referenced_layers_are_left_alone() {
+        let a = fill("a", &json!(true), &json!({}));
+        let b = fill("b", &json!(true), &json!({}));
+        let (found, _) = find_overdrawn(&[a.clone(), b.clone()], |i| i == 1);
+        assert!(found.is_empty());
+        let r = json!({"id": "r", "ref": "a"});
+        assert!(overdrawn(&[a, b, r]).is_empty());
+    }
+
+    #[test]
+    fn filter_implication() {
+        let class = |v: &str| json!(["==", ["get", "class"], v]);
+        assert!(filter_implies(Some(&class("a")), None));
+        assert!(filter_implies(None, Some(&json!(true))));
+        assert!(!filter_implies(None, Some(&class("a"))));
+        assert!(filter_implies(
+            Some(&json!(["all", class("a"), ["has", "name"]])),
+            Some(&json!(["any", class("b"), class("a")]))
+        ));
+        assert!(!filter_implies(Some(&class("a")), Some(&class("b"))));
+        assert!(!filter_implies(
+            Some(&json!(["in", ["get", "class"], ["literal", ["a", "c"]]])),
+            Some(&json!(["in", ["get", "class"], ["literal", ["a", "b"]]]))
+        ));
+    }
+}
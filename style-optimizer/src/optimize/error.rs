@@ -0,0 +1,147 @@
+//! What an optimization run did, and why it may not have done everything.
+//!
+//! Typed passes need the style to deserialize into
+//! [`MaplibreStyleSpecification`](maplibre_style_spec::spec::MaplibreStyleSpecification);
+//! when it does not, they cannot run.  Rather than silently shipping a style
+//! that only had expression passes applied, the run reports every skipped
+//! pass together with the path the deserializer stopped at.
+
+use std::fmt;
+
+/// The passes an optimization run executed, and those it had to skip.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptimizeOutcome {
+    /// Passes that ran at least once, in first-run order.
+    pub ran: Vec<&'static str>,
+    /// Passes, or parts of passes, that did not run.
+    pub skipped: Vec<SkippedPass>,
+}
+
+impl OptimizeOutcome {
+    /// Whether every scheduled pass ran on the whole style.
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+    }
+
+    pub(crate) fn record_run(&mut self, pass: &'static str) {
+        if !self.ran.contains(&pass) {
+            self.ran.push(pass);
+        }
+    }
+
+    pub(crate) fn record_skip(&mut self, skip: SkippedPass) {
+        if !self.skipped.contains(&skip) {
+            self.skipped.push(skip);
+        }
+    }
+
+    /// `Ok(self)` if complete, otherwise [`OptimizeError::Incomplete`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if any pass was skipped.
+    pub fn into_result(self) -> Result<Self, OptimizeError> {
+        if self.is_complete() {
+            Ok(self)
+        } else {
+            Err(OptimizeError::Incomplete(self))
+        }
+    }
+}
+
+/// A pass that skipped the whole style, or one value of it, because the
+/// value did not deserialize into the type the pass works on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedPass {
+    pub pass: &'static str,
+    /// Where deserialization failed, e.g. `layers[3].paint.line-width`; empty
+    /// for the style root.
+    pub path: String,
+    /// The deserialization error.
+    pub reason: String,
+}
+
+impl fmt::Display for SkippedPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "."
+        } else {
+            &self.path
+        };
+        write!(f, "{} skipped at `{path}`: {}", self.pass, self.reason)
+    }
+}
+
+/// Why an optimization run failed or is incomplete.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum OptimizeError {
+    #[error("unknown pass `{name}` (available: {})", .available.join(", "))]
+    UnknownPass {
+        name: String,
+        available: Vec<&'static str>,
+    },
+    #[error("pass dependency cycle among: {}", .0.join(", "))]
+    DependencyCycle(Vec<&'static str>),
+    /// Some passes were skipped.  The style has still been optimized by the
+    /// passes in [`OptimizeOutcome::ran`].
+    #[error(
+        "incomplete optimization: {}",
+        .0.skipped.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+    )]
+    Incomplete(OptimizeOutcome),
+    /// The optimized style no longer deserializes into the typed model, so
+    /// [`optimize_style`](crate::optimize_style) left its input unchanged.
+    #[error("optimized style does not deserialize at `{path}`: {reason}")]
+    Roundtrip { path: String, reason: String },
+}
+
+/// Deserialize `v`, reporting the path of the first value that fails.
+pub(crate) fn deserialize_at_path<T: serde::de::DeserializeOwned>(
+    v: &serde_json::Value,
+) -> Result<T, (String, String)> {
+    serde_path_to_error::deserialize(v).map_err(|e| {
+        let path = e.path().to_string();
+        let path = if path == "." { String::new() } else { path };
+        (path, e.into_inner().to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use maplibre_style_spec::spec::MaplibreStyleSpecification;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn deserialize_errors_carry_the_path() {
+        let style = json!({"version": 8, "sources": {}, "layers": [
+            {"id": "ok", "type": "background"},
+            {"id": "bad", "type": "line", "source": "s", "paint": {"line-width": {"oops": 1}}}
+        ]});
+        let (path, _) = deserialize_at_path::<MaplibreStyleSpecification>(&style).unwrap_err();
+        assert!(path.starts_with("layers[1]"), "{path}");
+    }
+
+    #[test]
+    fn incomplete_outcome_lists_skipped_passes() {
+        let mut outcome = OptimizeOutcome::default();
+        outcome.record_run("normalize_fold");
+        outcome.record_run("normalize_fold");
+        let skip = SkippedPass {
+            pass: "cleanup",
+            path: "layers[1]".into(),
+            reason: "bad".into(),
+        };
+        outcome.record_skip(skip.clone());
+        outcome.record_skip(skip);
+        assert_eq!(outcome.ran, ["normalize_fold"]);
+        let err = outcome.into_result().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "incomplete optimization: cleanup skipped at `layers[1]`: bad"
+        );
+    }
+}
@@ -0,0 +1,362 @@
+//! Per-pass provenance log: which pass changed what, as JSON pointer diffs.
+//!
+//! When enabled, every pipeline step snapshots the style before it runs and
+//! diffs the result afterwards.  Steps that change nothing are omitted, so the
+//! log only lists passes that actually rewrote the style.
+
+use maplibre_style_spec::spec::MaplibreStyleSpecification;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Structured record of what each pipeline step changed, in execution order.
+///
+/// A pass that runs more than once (e.g. `normalize_fold` re-run after
+/// `metadata_refinement` or `layer_merge` invalidated it) appears once per run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChangeLog {
+    pub passes: Vec<PassChanges>,
+}
+
+/// Changes made by a single run of one pipeline step.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PassChanges {
+    /// Step name: the [`Pass::name`](super::Pass::name) of the pass, or
+    /// `legacy_filter` / `legacy_function` for the legacy syntax conversions
+    /// that run first.  `normalize_fold` is the fused `simplify_unary`/
+    /// `expression_kind`/`constant_fold`/`constant_fold_stats`/
+    /// `simplify_expressions` fixpoint.
+    pub pass: &'static str,
+    pub changes: Vec<Change>,
+}
+
+/// A single rewritten location.
+///
+/// `path` is an RFC 6901 JSON pointer.  Layers are matched by `id`, so a
+/// modified or added layer is addressed by its index *after* the step, and a
+/// removed layer by its index *before* the step.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    pub path: String,
+    /// Value before the step; absent when the step added this location.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    /// Value after the step; absent when the step removed this location.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+impl ChangeLog {
+    /// Iterate over all changes recorded for `pass`, across all of its runs.
+    pub fn changes_for<'a>(&'a self, pass: &'a str) -> impl Iterator<Item = &'a Change> + 'a {
+        self.passes
+            .iter()
+            .filter(move |p| p.pass == pass)
+            .flat_map(|p| &p.changes)
+    }
+}
+
+/// Wraps pipeline steps and records their effect into a [`ChangeLog`] when enabled.
+///
+/// Disabled recorders call the step directly, so the default pipeline pays no
+/// snapshot/diff cost.
+pub(super) struct Recorder<'a> {
+    log: Option<&'a mut ChangeLog>,
+}
+
+impl<'a> Recorder<'a> {
+    pub(super) fn new(log: &'a mut ChangeLog) -> Self {
+        Self { log: Some(log) }
+    }
+
+    pub(super) fn disabled() -> Self {
+        Self { log: None }
+    }
+
+    /// Run a step on the JSON style and record its changes.
+    pub(super) fn json<T>(
+        &mut self,
+        pass: &'static str,
+        v: &mut Value,
+        step: impl FnOnce(&mut Value) -> T,
+    ) -> T {
+        let Some(log) = self.log.as_deref_mut() else {
+            return step(v);
+        };
+        let before = v.clone();
+        let result = step(v);
+        push_diff(log, pass, &before, v);
+        result
+    }
+
+    /// Run a step on the typed style and record its changes (diffed on the
+    /// typed serialization).
+    pub(super) fn typed<T>(
+        &mut self,
+        pass: &'static str,
+        style: &mut MaplibreStyleSpecification,
+        step: impl FnOnce(&mut MaplibreStyleSpecification) -> T,
+    ) -> T {
+        let Some(log) = self.log.as_deref_mut() else {
+            return step(style);
+        };
+        let Ok(before) = serde_json::to_value(&*style) else {
+            return step(style);
+        };
+        let result = step(style);
+        if let Ok(after) = serde_json::to_value(&*style) {
+            push_diff(log, pass, &before, &after);
+        }
+        result
+    }
+}
+
+fn push_diff(log: &mut ChangeLog, pass: &'static str, before: &Value, after: &Value) {
+    let mut changes = Vec::new();
+    diff_values(before, after, &mut String::new(), &mut changes);
+    if !changes.is_empty() {
+        log.passes.push(PassChanges { pass, changes });
+    }
+}
+
+/// Collect the changes between `before` and `after`, rooted at JSON pointer `path`.
+///
+/// Objects are diffed key by key.  Arrays of objects that all carry a string
+/// `id` (i.e. `layers`) are matched by id as long as the ids they share keep
+/// their relative order; a reordered array is reported as replaced, since the
+/// changes never move elements.  Other arrays are diffed element by
+/// element only when their length and head (the expression operator) agree;
+/// otherwise the whole array is reported as replaced, which keeps expression
+/// rewrites readable.
+pub(crate) fn diff_values(before: &Value, after: &Value, path: &mut String, out: &mut Vec<Change>) {
+    if before == after {
+        return;
+    }
+    match (before, after) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, av) in a {
+                let len = push_token(path, key);
+                match b.get(key) {
+                    Some(bv) => diff_values(av, bv, path, out),
+                    None => out.push(removed(path, av)),
+                }
+                path.truncate(len);
+            }
+            for (key, bv) in b {
+                if !a.contains_key(key) {
+                    let len = push_token(path, key);
+                    out.push(added(path, bv));
+                    path.truncate(len);
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            if let (Some(a_ids), Some(b_ids)) = (element_ids(a), element_ids(b)) {
+                if keeps_order(&a_ids, &b_ids) {
+                    diff_by_id(a, &a_ids, b, &b_ids, path, out);
+                } else {
+                    out.push(replaced(path, before, after));
+                }
+            } else if a.len() == b.len() && a.first() == b.first() {
+                for (i, (av, bv)) in a.iter().zip(b).enumerate() {
+                    let len = push_token(path, &i.to_string());
+                    diff_values(av, bv, path, out);
+                    path.truncate(len);
+                }
+            } else {
+                out.push(replaced(path, before, after));
+            }
+        }
+        _ => out.push(replaced(path, before, after)),
+    }
+}
+
+fn diff_by_id(
+    a: &[Value],
+    a_ids: &[&str],
+    b: &[Value],
+    b_ids: &[&str],
+    path: &mut String,
+    out: &mut Vec<Change>,
+) {
+    // Removals highest index first, so each index is still valid when the
+    // changes are applied in order.
+    for (i, id) in a_ids.iter().enumerate().rev() {
+        if !b_ids.contains(id) {
+            let len = push_token(path, &i.to_string());
+            out.push(removed(path, &a[i]));
+            path.truncate(len);
+        }
+    }
+    for (j, id) in b_ids.iter().enumerate() {
+        let len = push_token(path, &j.to_string());
+        match a_ids.iter().position(|x| x == id) {
+            Some(i) => diff_values(&a[i], &b[j], path, out),
+            None => out.push(added(path, &b[j])),
+        }
+        path.truncate(len);
+    }
+}
+
+/// `true` when the ids present in both arrays appear in the same order.
+fn keeps_order(a_ids: &[&str], b_ids: &[&str]) -> bool {
+    let shared_a = a_ids.iter().filter(|id| b_ids.contains(id));
+    let shared_b = b_ids.iter().filter(|id| a_ids.contains(id));
+    shared_a.eq(shared_b)
+}
+
+/// Return the `id` of every element when all elements are objects with a
+/// unique string `id` (vacuously true for an empty array).
+fn element_ids(arr: &[Value]) -> Option<Vec<&str>> {
+    let ids: Vec<&str> = arr
+        .iter()
+        .map(|v| v.get("id").and_then(Value::as_str))
+        .collect::<Option<_>>()?;
+    let mut sorted = ids.clone();
+    sorted.sort_unstable();
+    sorted.dedup();
+    (sorted.len() == ids.len()).then_some(ids)
+}
+
+/// Append an escaped reference token; returns the length to truncate back to.
+fn push_token(path: &mut String, token: &str) -> usize {
+    let len = path.len();
+    path.push('/');
+    path.push_str(&token.replace('~', "~0").replace('/', "~1"));
+    len
+}
+
+fn removed(path: &str, before: &Value) -> Change {
+    Change {
+        path: path.to_string(),
+        before: Some(before.clone()),
+        after: None,
+    }
+}
+
+fn added(path: &str, after: &Value) -> Change {
+    Change {
+        path: path.to_string(),
+        before: None,
+        after: Some(after.clone()),
+    }
+}
+
+fn replaced(path: &str, before: &Value, after: &Value) -> Change {
+    Change {
+        path: path.to_string(),
+        before: Some(before.clone()),
+        after: Some(after.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn diff(before: &Value, after: &Value) -> Vec<Change> {
+        let mut out = Vec::new();
+        diff_values(before, after, &mut String::new(), &mut out);
+        out
+    }
+
+    #[test]
+    fn identical_values_produce_no_changes() {
+        let v = json!({"layers": [{"id": "a", "type": "fill"}]});
+        assert!(diff(&v, &v).is_empty());
+    }
+
+    #[test]
+    fn expression_rewrite_reported_at_smallest_enclosing_array() {
+        let before =
+            json!({"layers": [{"id": "a", "filter": ["all", ["==", 1, 1], ["has", "x"]]}]});
+        let after = json!({"layers": [{"id": "a", "filter": ["all", true, ["has", "x"]]}]});
+        assert_eq!(
+            diff(&before, &after),
+            vec![Change {
+                path: "/layers/0/filter/1".to_string(),
+                before: Some(json!(["==", 1, 1])),
+                after: Some(json!(true)),
+            }]
+        );
+    }
+
+    #[test]
+    fn operator_change_reports_whole_expression() {
+        let before = json!(["!", ["==", ["get", "a"], 1]]);
+        let after = json!(["!=", ["get", "a"], 1]);
+        let changes = diff(&before, &after);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "");
+    }
+
+    #[test]
+    fn layers_matched_by_id() {
+        let before = json!({"layers": [
+            {"id": "gone", "type": "fill"},
+            {"id": "kept", "type": "line", "minzoom": 2}
+        ]});
+        let after = json!({"layers": [
+            {"id": "kept", "type": "line", "minzoom": 5}
+        ]});
+        assert_eq!(
+            diff(&before, &after),
+            vec![
+                Change {
+                    path: "/layers/0".to_string(),
+                    before: Some(json!({"id": "gone", "type": "fill"})),
+                    after: None,
+                },
+                Change {
+                    path: "/layers/0/minzoom".to_string(),
+                    before: Some(json!(2)),
+                    after: Some(json!(5)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn removals_by_id_listed_highest_index_first() {
+        let before = json!({"layers": [{"id": "a"}, {"id": "b"}, {"id": "c"}]});
+        let after = json!({"layers": [{"id": "b"}]});
+        let paths: Vec<_> = diff(&before, &after).into_iter().map(|c| c.path).collect();
+        assert_eq!(paths, ["/layers/2", "/layers/0"]);
+    }
+
+    #[test]
+    fn reordered_ids_replace_the_whole_array() {
+        let before = json!({"layers": [{"id": "a"}, {"id": "b"}, {"id": "c"}]});
+        let after = json!({"layers": [{"id": "c"}, {"id": "a"}]});
+        assert_eq!(
+            diff(&before, &after),
+            vec![Change {
+                path: "/layers".to_string(),
+                before: Some(before["layers"].clone()),
+                after: Some(after["layers"].clone()),
+            }]
+        );
+    }
+
+    #[test]
+    fn keys_are_escaped() {
+        let before = json!({"metadata": {"a/b~c": 1}});
+        let after = json!({"metadata": {}});
+        assert_eq!(diff(&before, &after)[0].path, "/metadata/a~1b~0c");
+    }
+
+    #[test]
+    fn recorder_skips_unchanged_steps() {
+        let mut log = ChangeLog::default();
+        let mut v = json!({"layers": [{"id": "a", "type": "fill", "paint": {}}]});
+        let mut rec = Recorder::new(&mut log);
+        rec.json("noop", &mut v, |_| {});
+        rec.json("cleanup", &mut v, |v| {
+            v["layers"] = json!([{"id": "a", "type": "fill"}]);
+        });
+        assert_eq!(log.passes.len(), 1);
+        assert_eq!(log.passes[0].pass, "cleanup");
+        assert_eq!(log.changes_for("cleanup").count(), 1);
+    }
+}
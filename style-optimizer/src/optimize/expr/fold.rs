@@ -1,12 +1,13 @@
 //! Constant folding passes: boolean algebra, comparison, arithmetic, dead branches, contradictions.
 
+use maplibre_style_spec::expression_eval::{EvalValue, EvaluationContext, evaluate};
 use maplibre_style_spec::mir::{MirExpressionOperator, MirSpec};
-use serde_json::Value;
+use serde_json::{Map, Value};
 
 use super::util::{
-    bool_literal, clamp_channel, compare_json_values, extract_json_literal, finite, is_num,
-    replace_arr_with_value,
+    bool_literal, compare_json_values, extract_json_literal, is_num, replace_arr_with_value,
 };
+use crate::optimize::color::minify_color_string;
 
 /// `["!", [op, a, b]]` → `[negation_of(op), a, b]` when the negated operator exists in MIR.
 ///
@@ -131,32 +132,11 @@ pub(super) fn try_fold_boolean_algebra(arr: &mut Vec<Value>) -> bool {
     false
 }
 
-/// Try to evaluate a pure operator whose all arguments are literal values.
+/// Try to evaluate a pure operator whose arguments are all constant.
 pub(super) fn try_fold_pure_operator(arr: &mut Vec<Value>, mir: &MirSpec) -> bool {
-    if arr.is_empty() {
-        return false;
-    }
-    let Some(op) = arr[0].as_str() else {
-        return false;
-    };
-
-    // Purity check: either flagged pure in MIR, or one of the special foldable lookups.
-    let pure_in_mir = mir
-        .expressions
-        .operators
-        .get(op)
-        .is_some_and(MirExpressionOperator::is_pure);
-    if !pure_in_mir && !matches!(op, "length" | "at" | "in") {
-        return false;
-    }
-
-    // All arguments must be literals.
-    let args: Option<Vec<Value>> = arr[1..].iter().map(extract_json_literal).collect();
-    let Some(args) = args else {
-        return false;
-    };
-
-    let Some(result) = evaluate_pure_operator(op, &args) else {
+    let Some(result) =
+        evaluate_pure_operator(arr, &|op| is_pure_operator(op, mir)).and_then(literal_value)
+    else {
         return false;
     };
 
@@ -165,249 +145,222 @@ pub(super) fn try_fold_pure_operator(arr: &mut Vec<Value>, mir: &MirSpec) -> boo
     true
 }
 
-#[allow(clippy::too_many_lines)]
-fn evaluate_pure_operator(op: &str, args: &[Value]) -> Option<Value> {
+/// Purity check: either flagged pure in MIR, or one of the special foldable lookups.
+pub(super) fn is_pure_operator(op: &str, mir: &MirSpec) -> bool {
+    mir.expressions
+        .operators
+        .get(op)
+        .is_some_and(MirExpressionOperator::is_pure)
+        || matches!(op, "length" | "at" | "in" | "slice" | "index-of")
+}
+
+/// Evaluate `[op, ...args]` with the reference interpreter when every
+/// argument is a literal, an option object of literals, or itself a
+/// constant pure expression.
+///
+/// Only the fold-specific gating lives here: operators whose result depends
+/// on the viewer, or which GL JS could evaluate differently, are not folded.
+fn evaluate_pure_operator(expr: &[Value], is_pure: &dyn Fn(&str) -> bool) -> Option<EvalValue> {
+    let op = expr.first()?.as_str()?;
+    if !is_pure(op) {
+        return None;
+    }
+    let args = &expr[1..];
+    let constant = |a: &Value| {
+        extract_json_literal(a).is_some()
+            || a.as_array()
+                .is_some_and(|e| evaluate_pure_operator(e, is_pure).is_some())
+    };
+    if !args.iter().all(|a| match a {
+        Value::Object(options) => options.values().all(constant),
+        other => constant(other),
+    }) {
+        return None;
+    }
     match op {
-        // ── Math: unary ────────────────────────────────────────────────────────
-        "abs" => finite(args.first()?.as_f64()?.abs()),
-        "ceil" => finite(args.first()?.as_f64()?.ceil()),
-        "floor" => finite(args.first()?.as_f64()?.floor()),
-        "round" => finite(args.first()?.as_f64()?.round()),
-        "sqrt" => {
-            let n = args.first()?.as_f64()?;
-            if n < 0.0 {
-                return None;
-            }
-            finite(n.sqrt())
-        }
-        "ln" => {
-            let n = args.first()?.as_f64()?;
-            if n <= 0.0 {
-                return None;
-            }
-            finite(n.ln())
-        }
-        "log2" => {
-            let n = args.first()?.as_f64()?;
-            if n <= 0.0 {
-                return None;
-            }
-            finite(n.log2())
-        }
-        "log10" => {
-            let n = args.first()?.as_f64()?;
-            if n <= 0.0 {
+        // Already a literal; the viewer's locale, images and RTL plugin; the
+        // feature's geometry.
+        "literal"
+        | "collator"
+        | "resolved-locale"
+        | "image"
+        | "is-supported-script"
+        | "distance" => return None,
+        "number-format" => {
+            let options = args.get(1)?.as_object()?;
+            // Without an explicit English locale the result follows the
+            // viewer's; currency formats vary between `Intl` versions.
+            if !matches!(
+                options.get("locale").and_then(extract_json_literal),
+                Some(Value::String(locale)) if locale == "en" || locale == "en-US"
+            ) {
                 return None;
             }
-            finite(n.log10())
-        }
-        "sin" => finite(args.first()?.as_f64()?.sin()),
-        "cos" => finite(args.first()?.as_f64()?.cos()),
-        "tan" => finite(args.first()?.as_f64()?.tan()),
-        "asin" => {
-            let n = args.first()?.as_f64()?;
-            if !(-1.0..=1.0).contains(&n) {
-                return None;
+            let mut digits = [None, None];
+            for (key, value) in options {
+                let slot = match key.as_str() {
+                    "locale" => continue,
+                    "min-fraction-digits" => &mut digits[0],
+                    "max-fraction-digits" => &mut digits[1],
+                    _ => return None,
+                };
+                let n = extract_json_literal(value)?.as_f64()?;
+                // `Intl.NumberFormat` throws a `RangeError` outside 0..=20.
+                if n.fract() != 0.0 || !(0.0..=20.0).contains(&n) {
+                    return None;
+                }
+                *slot = Some(n);
             }
-            finite(n.asin())
-        }
-        "acos" => {
-            let n = args.first()?.as_f64()?;
-            if !(-1.0..=1.0).contains(&n) {
+            if let [Some(min), Some(max)] = digits
+                && min > max
+            {
                 return None;
             }
-            finite(n.acos())
+            let n = extract_json_literal(args.first()?)?.as_f64()?;
+            let folded = evaluate(&Value::Array(expr.to_vec()), &EvaluationContext::default());
+            // Negative numbers that round to zero print as `-0` in newer engines.
+            return match folded.ok()? {
+                EvalValue::String(s) if n.is_sign_negative() && !s.starts_with('-') => None,
+                folded => Some(folded),
+            };
         }
-        "atan" => finite(args.first()?.as_f64()?.atan()),
+        // Only the first input: a fallback would be taken whenever our colour
+        // parser rejects something GL JS accepts.
+        "to-color" if args.len() != 1 => return None,
+        _ => {}
+    }
+    evaluate(&Value::Array(expr.to_vec()), &EvaluationContext::default()).ok()
+}
 
-        // ── Math: nullary constants ────────────────────────────────────────────
-        "e" if args.is_empty() => finite(std::f64::consts::E),
-        "pi" if args.is_empty() => finite(std::f64::consts::PI),
-        "ln2" if args.is_empty() => finite(std::f64::consts::LN_2),
+/// The literal for a folded `value`.
+///
+/// Results a literal cannot carry (non-finite numbers, formatted text,
+/// images, collators) are not folded, and neither are colours: as a string
+/// a colour would change `typeof`, `to-string` and type assertions around
+/// it.  [`fold_color_outputs`] folds them where a colour is expected.
+fn literal_value(value: EvalValue) -> Option<Value> {
+    match value {
+        EvalValue::Number(n) if !n.is_finite() => None,
+        EvalValue::Color(_)
+        | EvalValue::Formatted(_)
+        | EvalValue::Image(_)
+        | EvalValue::Collator(_) => None,
+        other => Some(other.to_json()),
+    }
+}
 
-        // ── Math: variadic ────────────────────────────────────────────────────
-        "+" => {
-            if args.is_empty() {
-                return None;
-            }
-            let mut s = 0.0_f64;
-            for a in args {
-                s += a.as_f64()?;
-            }
-            finite(s)
-        }
-        "-" => match args.len() {
-            1 => finite(-args[0].as_f64()?),
-            2 => finite(args[0].as_f64()? - args[1].as_f64()?),
-            _ => None,
-        },
-        "*" => {
-            if args.is_empty() {
-                return None;
-            }
-            let mut p = 1.0_f64;
-            for a in args {
-                p *= a.as_f64()?;
-            }
-            finite(p)
-        }
-        "/" => {
-            if args.len() != 2 {
-                return None;
-            }
-            let b = args[1].as_f64()?;
-            if b == 0.0 {
-                return None;
-            }
-            finite(args[0].as_f64()? / b)
-        }
-        "%" => {
-            if args.len() != 2 {
-                return None;
-            }
-            let b = args[1].as_f64()?;
-            if b == 0.0 {
-                return None;
-            }
-            finite(args[0].as_f64()? % b)
-        }
-        "^" => {
-            if args.len() != 2 {
-                return None;
-            }
-            finite(args[0].as_f64()?.powf(args[1].as_f64()?))
-        }
-        "min" => {
-            if args.is_empty() {
-                return None;
-            }
-            let mut m = f64::INFINITY;
-            for a in args {
-                m = m.min(a.as_f64()?);
-            }
-            finite(m)
-        }
-        "max" => {
-            if args.is_empty() {
-                return None;
-            }
-            let mut m = f64::NEG_INFINITY;
-            for a in args {
-                m = m.max(a.as_f64()?);
-            }
-            finite(m)
-        }
+/// Whether `arr` is an `interpolate`/`interpolate-hcl`/`interpolate-lab`
+/// at a literal input between literal stops.
+fn is_literal_interpolate(arr: &[Value]) -> bool {
+    matches!(
+        arr.first().and_then(Value::as_str),
+        Some("interpolate" | "interpolate-hcl" | "interpolate-lab")
+    ) && arr.len() >= 5
+        && !arr.len().is_multiple_of(2)
+        && extract_json_literal(&arr[2]).is_some_and(|input| input.is_number())
+        && arr[3..].iter().all(|v| extract_json_literal(v).is_some())
+}
 
-        // ── String ────────────────────────────────────────────────────────────
-        "concat" => {
-            let mut s = String::new();
-            for a in args {
-                match a {
-                    Value::String(t) => s.push_str(t),
-                    Value::Number(n) => s.push_str(&n.to_string()),
-                    Value::Bool(b) => s.push_str(if *b { "true" } else { "false" }),
-                    Value::Null => {}
-                    _ => return None,
-                }
-            }
-            Some(Value::String(s))
-        }
-        "downcase" => Some(Value::String(args.first()?.as_str()?.to_lowercase())),
-        "upcase" => Some(Value::String(args.first()?.as_str()?.to_uppercase())),
-
-        // ── Type coercion ─────────────────────────────────────────────────────
-        "to-number" => match args.first()? {
-            Value::Number(n) => Some(Value::Number(n.clone())),
-            Value::String(s) => s.parse::<f64>().ok().and_then(finite),
-            Value::Bool(b) => finite(if *b { 1.0 } else { 0.0 }),
-            Value::Null => finite(0.0),
-            _ => None,
-        },
-        "to-string" => match args.first()? {
-            Value::String(s) => Some(Value::String(s.clone())),
-            Value::Number(n) => Some(Value::String(n.to_string())),
-            Value::Bool(b) => Some(Value::String(b.to_string())),
-            Value::Null => Some(Value::String(String::new())),
-            _ => None,
-        },
-        "to-boolean" => {
-            let b = match args.first()? {
-                Value::Bool(b) => *b,
-                Value::Number(n) => n.as_f64().is_some_and(|f| f != 0.0),
-                Value::String(s) => !s.is_empty(),
-                Value::Null => false,
-                Value::Array(_) | Value::Object(_) => true,
-            };
-            Some(Value::Bool(b))
-        }
-        "typeof" => {
-            let t = match args.first()? {
-                Value::Bool(_) => "boolean",
-                Value::Number(_) => "number",
-                Value::String(_) => "string",
-                Value::Null => "null",
-                Value::Array(_) => "array",
-                Value::Object(_) => "object",
-            };
-            Some(Value::String(t.to_string()))
-        }
+/// Evaluate an `interpolate` at a literal input between literal non-colour
+/// stops.  Colour ramps are left to [`fold_color_outputs`].
+///
+/// `["interpolate", ["linear"], 5, 0, 1, 10, 3]` → `2`
+pub(super) fn try_fold_literal_interpolate(arr: &mut Vec<Value>) -> bool {
+    if !is_literal_interpolate(arr) {
+        return false;
+    }
+    let Some(result) = evaluate(&Value::Array(arr.clone()), &EvaluationContext::default())
+        .ok()
+        .and_then(literal_value)
+    else {
+        return false;
+    };
+    *arr = vec![Value::String("literal".to_string()), result];
+    true
+}
 
-        // ── Color ─────────────────────────────────────────────────────────────
-        "rgb" => {
-            if args.len() != 3 {
-                return None;
-            }
-            let r = clamp_channel(args[0].as_f64()?);
-            let g = clamp_channel(args[1].as_f64()?);
-            let b = clamp_channel(args[2].as_f64()?);
-            Some(Value::String(format!("rgba({r},{g},{b},1)")))
-        }
-        "rgba" => {
-            if args.len() != 4 {
-                return None;
-            }
-            let r = clamp_channel(args[0].as_f64()?);
-            let g = clamp_channel(args[1].as_f64()?);
-            let b = clamp_channel(args[2].as_f64()?);
-            let a = args[3].as_f64()?;
-            if !(0.0..=1.0).contains(&a) {
-                return None;
-            }
-            Some(Value::String(format!("rgba({r},{g},{b},{a})")))
-        }
+/// Fold constant colour expressions where they feed straight into a
+/// colour-typed property, to their shortest CSS string: the property value
+/// itself and the outputs of `case`, `match`, `step`, the `interpolate`
+/// variants and `coalesce`, recursively.
+///
+/// `["match", ["get", "k"], "a", ["rgb", 255, 128, 0], "red"]` →
+/// `["match", ["get", "k"], "a", "#ff8000", "red"]`
+pub(super) fn fold_color_outputs(v: &mut Value, is_pure: &dyn Fn(&str) -> bool) -> bool {
+    if let Some(color) = constant_color(v, is_pure) {
+        *v = color;
+        return true;
+    }
+    let Value::Array(arr) = v else {
+        return false;
+    };
+    let n = arr.len();
+    let outputs: Vec<usize> = match arr.first().and_then(Value::as_str) {
+        Some("case") if n >= 4 => (2..n).step_by(2).chain([n - 1]).collect(),
+        Some("match") if n >= 5 => (3..n).step_by(2).chain([n - 1]).collect(),
+        Some("step") => (2..n).step_by(2).collect(),
+        Some("interpolate" | "interpolate-hcl" | "interpolate-lab") => (4..n).step_by(2).collect(),
+        Some("coalesce") => (1..n).collect(),
+        _ => return false,
+    };
+    let mut changed = false;
+    for i in outputs {
+        changed |= fold_color_outputs(&mut arr[i], is_pure);
+    }
+    changed
+}
 
-        // ── Lookup on literal arrays/strings ──────────────────────────────────
-        "length" => match args.first()? {
-            Value::String(s) => Some(Value::Number(s.chars().count().into())),
-            Value::Array(a) => Some(Value::Number(a.len().into())),
-            _ => None,
-        },
-        "at" => {
-            if args.len() != 2 {
-                return None;
-            }
-            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-            let idx = args[0].as_f64()? as usize;
-            args[1].as_array()?.get(idx).cloned()
-        }
+/// The shortest CSS string of `v` when it is a constant colour expression.
+///
+/// Lab and HCL interpolation can leave the sRGB gamut, which a CSS string
+/// would clamp, so such colours are not folded.
+fn constant_color(v: &Value, is_pure: &dyn Fn(&str) -> bool) -> Option<Value> {
+    let arr = v.as_array()?;
+    let value = if is_literal_interpolate(arr) {
+        evaluate(v, &EvaluationContext::default()).ok()?
+    } else {
+        evaluate_pure_operator(arr, is_pure)?
+    };
+    let EvalValue::Color(c) = value else {
+        return None;
+    };
+    let in_gamut = [c.r, c.g, c.b]
+        .iter()
+        .all(|v| (-1e-6..=1.0 + 1e-6).contains(v));
+    let css = c.to_js_string();
+    in_gamut.then(|| Value::String(minify_color_string(&css).unwrap_or(css)))
+}
 
-        // ── Membership ───────────────────────────────────────────────────────
-        "in" => {
-            if args.len() != 2 {
-                return None;
-            }
-            let needle = &args[0];
-            match &args[1] {
-                Value::Array(haystack) => Some(Value::Bool(haystack.contains(needle))),
-                Value::String(haystack) => {
-                    let needle_str = needle.as_str()?;
-                    Some(Value::Bool(haystack.contains(needle_str)))
-                }
-                _ => None,
-            }
-        }
+/// `["format", x]` or `["format", x, {}]` → `x`: a single section without
+/// options formats as plain text.
+///
+/// Image sections are kept, since an image is not text, and so are strings
+/// with `{`: a bare string `text-field` would read them as `{token}`s.
+pub(super) fn try_fold_plain_format(arr: &mut Vec<Value>) -> bool {
+    if arr.first().and_then(Value::as_str) != Some("format") {
+        return false;
+    }
+    let plain = match arr.len() {
+        2 => !arr[1].is_object(),
+        3 => arr[2].as_object().is_some_and(Map::is_empty) && !arr[1].is_object(),
+        _ => false,
+    };
+    if !plain || mentions_image(&arr[1]) || arr[1].as_str().is_some_and(|t| t.contains('{')) {
+        return false;
+    }
+    let section = arr[1].take();
+    replace_arr_with_value(arr, section);
+    true
+}
 
-        _ => None,
+fn mentions_image(v: &Value) -> bool {
+    match v {
+        Value::Array(items) => {
+            items.first().and_then(Value::as_str) == Some("image")
+                || items.iter().any(mentions_image)
+        }
+        Value::Object(map) => map.values().any(mentions_image),
+        _ => false,
     }
 }
 
@@ -1688,3 +1641,293 @@ fn try_prune_multi_geom_node(arr: &mut [Value]) -> bool {
 fn is_geometry_type_expr(v: &Value) -> bool {
     matches!(v, Value::Array(a) if a.len() == 1 && a[0].as_str() == Some("geometry-type"))
 }
+
+#[cfg(test)]
+mod tests {
+    use maplibre_style_spec::expression_eval::{EvaluationContext, evaluate};
+    use serde_json::json;
+
+    use super::*;
+
+    /// Stands in for the MIR's pure groups: everything but the feature
+    /// lookups, decisions and ramps these tests use.
+    fn pure(op: &str) -> bool {
+        !matches!(
+            op,
+            "get" | "has" | "zoom" | "==" | "case" | "match" | "coalesce" | "step" | "interpolate"
+        )
+    }
+
+    /// The folding table must agree with the reference interpreter wherever
+    /// it folds.
+    #[test]
+    fn pure_operators_agree_with_the_interpreter() {
+        let cases = [
+            json!(["abs", -2.5]),
+            json!(["round", 2.5]),
+            json!(["sqrt", 2]),
+            json!(["ln", 10]),
+            json!(["log2", 8]),
+            json!(["asin", 0.5]),
+            json!(["pi"]),
+            json!(["+", 1, 2.25, 3]),
+            json!(["-", 7]),
+            json!(["-", 7, 10]),
+            json!(["*", 2, 3.5]),
+            json!(["/", 1, 4]),
+            json!(["%", -7, 3]),
+            json!(["^", 2, 10]),
+            json!(["min", 3, 1, 2]),
+            json!(["max", 3, 1, 2]),
+            json!(["concat", "a", 1, true, null]),
+            json!(["downcase", "ÀB"]),
+            json!(["upcase", "straße"]),
+            json!(["to-number", "12.5"]),
+            json!(["to-number", true]),
+            json!(["to-number", null]),
+            json!(["to-string", 3]),
+            json!(["to-string", 1.0]),
+            json!(["to-string", 1e21]),
+            json!(["concat", 1.0, "a", 0.1]),
+            json!(["typeof", ["literal", [1, 2]]]),
+            json!(["typeof", ["literal", {"a": 1}]]),
+            json!(["typeof", 1.5]),
+            json!(["to-boolean", ""]),
+            json!(["to-boolean", 0]),
+            json!(["to-rgba", "#ff880080"]),
+            json!(["to-rgba", ["rgb", 1, 2, 3]]),
+            json!(["to-string", ["rgb", 255, 128, 0]]),
+            json!(["concat", "fill: ", ["rgba", 0, 0, 255, 0.5]]),
+            json!(["typeof", ["to-color", "#f80"]]),
+            json!(["length", "abc"]),
+            json!(["length", ["literal", [1, 2, 3]]]),
+            json!(["at", 1, ["literal", ["a", "b"]]]),
+            json!(["in", "b", "abc"]),
+            json!(["in", 2, ["literal", [1, 2]]]),
+            json!(["slice", "Straße", 2, -1]),
+            json!(["slice", "a😀b", 1]),
+            json!(["slice", ["literal", [1, 2, 3]], -2]),
+            json!(["index-of", "ß", "Straße"]),
+            json!(["index-of", "b", "a😀b"]),
+            json!(["index-of", 2, ["literal", [1, 2, 2]], 2]),
+            json!(["index-of", "x", "abc"]),
+            json!([
+                "number-format",
+                1234.5678,
+                {"locale": "en-US", "max-fraction-digits": 2}
+            ]),
+            json!(["number-format", 5, {"locale": "en", "min-fraction-digits": 1}]),
+        ];
+        for expr in cases {
+            let folded = evaluate_pure_operator(expr.as_array().unwrap(), &pure)
+                .and_then(literal_value)
+                .unwrap_or_else(|| panic!("{expr} folds"));
+            assert_same_value(&expr, &folded);
+        }
+    }
+
+    /// `folded` evaluates like `expr`; colours may differ by channel rounding.
+    fn assert_same_value(expr: &Value, folded: &Value) {
+        let reference =
+            evaluate(expr, &EvaluationContext::default()).unwrap_or_else(|e| panic!("{expr}: {e}"));
+        if let EvalValue::Color(reference) = reference {
+            let rgba = |v: &Value| {
+                let Ok(EvalValue::Array(channels)) =
+                    evaluate(&json!(["to-rgba", v]), &EvaluationContext::default())
+                else {
+                    panic!("{v} is not a colour");
+                };
+                channels
+            };
+            let expected = reference.to_rgba_array();
+            for (i, channel) in rgba(folded).iter().enumerate() {
+                let EvalValue::Number(n) = channel else {
+                    unreachable!("to-rgba returns numbers");
+                };
+                // Half a channel step, plus the `f32` precision of parsed colours.
+                let tolerance = if i == 3 { 0.005 } else { 0.501 };
+                assert!(
+                    (n - expected[i]).abs() <= tolerance,
+                    "{expr}: {folded} != {expected:?}"
+                );
+            }
+            return;
+        }
+        let reference = reference.to_json();
+        match (folded.as_f64(), reference.as_f64()) {
+            (Some(a), Some(b)) => assert!((a - b).abs() < 1e-12, "{expr}: {a} != {b}"),
+            _ => assert_eq!(*folded, reference, "{expr}"),
+        }
+    }
+
+    #[test]
+    fn colour_ramps_fold_in_their_colour_space() {
+        for expr in [
+            json!(["interpolate", ["linear"], 5, 0, "#000", 10, "#fff"]),
+            json!(["interpolate-lab", ["linear"], 5, 0, "#fc0", 10, "#0cf"]),
+            json!([
+                "interpolate-hcl",
+                ["exponential", 2],
+                7,
+                0,
+                "#369",
+                10,
+                "rgba(153,204,255,0.5)"
+            ]),
+        ] {
+            let folded = constant_color(&expr, &pure).unwrap_or_else(|| panic!("{expr}"));
+            assert_same_value(&expr, &folded);
+        }
+        let expr = json!(["interpolate", ["linear"], 2.5, 0, 1, 10, 3]);
+        let Value::Array(mut arr) = expr.clone() else {
+            unreachable!("literal arrays");
+        };
+        assert!(try_fold_literal_interpolate(&mut arr));
+        assert_same_value(&expr, &arr[1]);
+
+        // Lab and HCL midpoints differ from the sRGB one.
+        let fold = |op: &str| {
+            let ramp = json!([op, ["linear"], 5, 0, "#369", 10, "#9cf"]);
+            constant_color(&ramp, &pure).expect("folds")
+        };
+        assert_eq!(fold("interpolate"), json!("#69c"));
+        assert_ne!(fold("interpolate-lab"), fold("interpolate"));
+        assert_ne!(fold("interpolate-hcl"), fold("interpolate"));
+
+        // The Lab midpoint of red and blue has a negative green channel.
+        let ramp = json!(["interpolate-lab", ["linear"], 5, 0, "red", 10, "blue"]);
+        assert_eq!(constant_color(&ramp, &pure), None);
+
+        // Zoom-driven ramps stay.
+        let ramp = json!(["interpolate", ["linear"], ["zoom"], 0, "red", 10, "blue"]);
+        assert_eq!(constant_color(&ramp, &pure), None);
+
+        // Outside a colour output a colour ramp keeps its type.
+        let Value::Array(mut ramp) = json!(["interpolate", ["linear"], 5, 0, "red", 10, "blue"])
+        else {
+            unreachable!("literal arrays");
+        };
+        assert!(!try_fold_literal_interpolate(&mut ramp));
+    }
+
+    /// Colours become their shortest string only where a colour property
+    /// reads them; `to-string` and `concat` see GL JS's `rgba(...)` form.
+    #[test]
+    fn colours_fold_to_the_shortest_string_in_colour_outputs() {
+        let fold = |mut v: Value| {
+            fold_color_outputs(&mut v, &pure);
+            v
+        };
+        assert_eq!(fold(json!(["rgb", 255, 128, 0])), json!("#ff8000"));
+        assert_eq!(
+            fold(json!(["rgba", 0, 0, 255, 0.5])),
+            json!("rgba(0,0,255,0.5)")
+        );
+        assert_eq!(
+            fold(json!([
+                "match",
+                ["get", "k"],
+                "a",
+                ["rgb", 255, 136, 0],
+                [
+                    "case",
+                    ["has", "x"],
+                    ["to-color", "#000000"],
+                    ["rgb", 0, 0, 0]
+                ]
+            ])),
+            json!([
+                "match",
+                ["get", "k"],
+                "a",
+                "#f80",
+                ["case", ["has", "x"], "#000", "#000"]
+            ])
+        );
+        // Inputs, conditions and labels are not colour outputs.
+        let kept = json!([
+            "case",
+            [
+                "==",
+                ["to-string", ["get", "c"]],
+                ["to-string", ["rgb", 0, 0, 0]]
+            ],
+            "red",
+            "blue"
+        ]);
+        assert_eq!(fold(kept.clone()), kept);
+
+        let expr = json!(["to-string", ["rgb", 255, 128, 0]]);
+        let folded =
+            evaluate_pure_operator(expr.as_array().unwrap(), &pure).and_then(literal_value);
+        assert_eq!(folded, Some(json!("rgba(255,128,0,1)")));
+    }
+
+    #[test]
+    fn viewer_dependent_or_throwing_operators_are_not_folded() {
+        let cases = [
+            // The viewer's locale.
+            ("number-format", vec![json!(1234), json!({})]),
+            ("number-format", vec![json!(1234), json!({"locale": "de"})]),
+            (
+                "number-format",
+                vec![json!(1), json!({"locale": "en", "currency": "USD"})],
+            ),
+            // `RangeError`.
+            (
+                "number-format",
+                vec![
+                    json!(1),
+                    json!({"locale": "en", "min-fraction-digits": 3, "max-fraction-digits": 1}),
+                ],
+            ),
+            (
+                "number-format",
+                vec![json!(-0.0001), json!({"locale": "en"})],
+            ),
+            // The viewer's RTL plugin and images.
+            ("is-supported-script", vec![json!("שלום")]),
+            ("image", vec![json!("shield")]),
+            // `typeof` would see a string.
+            ("to-color", vec![json!("#f80")]),
+            ("rgb", vec![json!(255), json!(128), json!(0)]),
+            // Throws, so the fallback would be taken at runtime.
+            ("to-color", vec![json!("not a colour"), json!("red")]),
+            ("slice", vec![json!(true), json!(1)]),
+            ("rgb", vec![json!(256), json!(0), json!(0)]),
+            ("rgba", vec![json!(0), json!(0), json!(0), json!(1.5)]),
+        ];
+        for (op, args) in cases {
+            let mut expr = vec![json!(op)];
+            expr.extend(args);
+            let folded = evaluate_pure_operator(&expr, &pure).and_then(literal_value);
+            assert_eq!(folded, None, "{expr:?}");
+        }
+    }
+
+    #[test]
+    fn plain_format_sections_unwrap() {
+        let fold = |v: Value| {
+            let Value::Array(mut arr) = v else {
+                unreachable!("format is an array");
+            };
+            try_fold_plain_format(&mut arr).then_some(Value::Array(arr))
+        };
+        assert_eq!(
+            fold(json!(["format", ["get", "name"], {}])),
+            Some(json!(["get", "name"]))
+        );
+        assert_eq!(
+            fold(json!(["format", "Main St"])),
+            Some(json!(["literal", "Main St"]))
+        );
+        for kept in [
+            json!(["format", ["get", "name"], {"font-scale": 0.8}]),
+            json!(["format", ["image", "shield"], {}]),
+            json!(["format", "{name}", {}]),
+            json!(["format", "a", {}, "b", {}]),
+        ] {
+            assert_eq!(fold(kept.clone()), None, "{kept}");
+        }
+    }
+}
@@ -762,6 +762,76 @@ pub(super) fn try_fold_coalesce_from_stats(
     changed
 }
 
+/// Drop `number`/`string`/`boolean` assertions and `to-number`/`to-string`/`to-boolean`
+/// coercions whose argument stats prove already has the target type.
+///
+/// `["to-number", ["get", p]]` → `["get", p]` when `p` is numeric on every feature; the
+/// parser re-inserts an implicit assertion wherever a typed argument is expected. When the
+/// proven argument is not the first, the fallbacks after it are unreachable and are dropped.
+///
+/// Guard: only when `sample_rate == 1.0`, the property is present on every feature and its
+/// wire type is never `Mixed`.
+pub(super) fn try_specialize_coercion_from_stats(
+    arr: &mut Vec<Value>,
+    stats: Option<&crate::stats::TileStatistics>,
+    layer_info: Option<&[Option<crate::optimize::source_util::VectorLayerInfo>]>,
+    layer_index: usize,
+) -> bool {
+    let target = match arr.first().and_then(Value::as_str) {
+        Some("number" | "to-number") => "number",
+        Some("string" | "to-string") => "string",
+        Some("boolean" | "to-boolean") => "boolean",
+        _ => return false,
+    };
+    if arr.len() < 2 {
+        return false;
+    }
+    let Some(layer_stats) = super::util::resolve_layer_stats(stats, layer_info, layer_index) else {
+        return false;
+    };
+    let Some(i) = (1..arr.len()).find(|&i| proven_type(&arr[i], layer_stats) == Some(target))
+    else {
+        return false;
+    };
+    if i == 1 && super::util::is_get_expr(&arr[1]) {
+        let inner = arr[1].clone();
+        replace_arr_with_value(arr, inner);
+        return true;
+    }
+    if i + 1 < arr.len() {
+        arr.truncate(i + 1);
+        return true;
+    }
+    false
+}
+
+/// The runtime type every feature yields for `v`, when it is a literal or a `["get", p]`
+/// whose stats show a single wire type on every feature.
+fn proven_type(v: &Value, layer_stats: &crate::stats::LayerStats) -> Option<&'static str> {
+    use crate::stats::PropertyStats;
+
+    if let Some(prop) = super::util::get_prop_name(v) {
+        let prop_stats = layer_stats.properties.get(prop)?;
+        if prop_stats.present_count() != layer_stats.total_features {
+            return None;
+        }
+        return match prop_stats {
+            PropertyStats::Bool { .. } => Some("boolean"),
+            PropertyStats::Integer { .. }
+            | PropertyStats::UnsignedInteger { .. }
+            | PropertyStats::Double { .. } => Some("number"),
+            PropertyStats::String { .. } => Some("string"),
+            PropertyStats::Mixed { .. } => None,
+        };
+    }
+    match extract_json_literal(v)? {
+        Value::Bool(_) => Some("boolean"),
+        Value::Number(_) => Some("number"),
+        Value::String(_) => Some("string"),
+        _ => None,
+    }
+}
+
 /// Prune unreachable stops from property-driven `step` and `interpolate` expressions
 /// using min/max from `PropertyStats`.
 ///
@@ -1204,3 +1274,161 @@ fn string_expr_as_str(s: &maplibre_style_spec::spec::String) -> Option<&str> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::optimize::source_util::VectorLayerInfo;
+    use crate::stats::{LayerStats, PropertyStats, SourceStats, TileStatistics};
+
+    /// 100 features with an always-present integer `rank`, string `name` and boolean `open`,
+    /// plus a `ref` that is missing on some features and a `code` of mixed wire type.
+    fn typed_stats() -> TileStatistics {
+        let layer = LayerStats {
+            total_features: 100,
+            properties: BTreeMap::from([
+                (
+                    "rank".to_string(),
+                    PropertyStats::Integer {
+                        present_count: 100,
+                        min: 1,
+                        max: 12,
+                        cardinality: 12,
+                        value_counts: None,
+                    },
+                ),
+                (
+                    "name".to_string(),
+                    PropertyStats::String {
+                        present_count: 100,
+                        cardinality: 80,
+                        value_counts: None,
+                    },
+                ),
+                (
+                    "open".to_string(),
+                    PropertyStats::Bool {
+                        present_count: 100,
+                        true_count: 40,
+                    },
+                ),
+                (
+                    "ref".to_string(),
+                    PropertyStats::String {
+                        present_count: 60,
+                        cardinality: 10,
+                        value_counts: None,
+                    },
+                ),
+                (
+                    "code".to_string(),
+                    PropertyStats::Mixed {
+                        present_count: 100,
+                        cardinality: 30,
+                    },
+                ),
+            ]),
+            ..Default::default()
+        };
+        TileStatistics {
+            sources: BTreeMap::from([(
+                "src".to_string(),
+                SourceStats {
+                    layers: BTreeMap::from([("lyr".to_string(), layer)]),
+                },
+            )]),
+            sample_rate: 1.0,
+        }
+    }
+
+    fn specialize(v: Value, stats: &TileStatistics) -> Value {
+        let layer_info = [Some(VectorLayerInfo {
+            source: "src".to_string(),
+            source_layer: "lyr".to_string(),
+            source_maxzoom: None,
+        })];
+        let Value::Array(mut arr) = v else {
+            panic!("expected an expression array");
+        };
+        try_specialize_coercion_from_stats(&mut arr, Some(stats), Some(&layer_info), 0);
+        Value::Array(arr)
+    }
+
+    #[test]
+    fn drops_coercions_of_properties_with_a_proven_type() {
+        let stats = typed_stats();
+        for (expr, expected) in [
+            (
+                json!(["to-number", ["get", "rank"]]),
+                json!(["get", "rank"]),
+            ),
+            (json!(["number", ["get", "rank"]]), json!(["get", "rank"])),
+            (
+                json!(["to-string", ["get", "name"]]),
+                json!(["get", "name"]),
+            ),
+            (json!(["string", ["get", "name"]]), json!(["get", "name"])),
+            (
+                json!(["to-boolean", ["get", "open"]]),
+                json!(["get", "open"]),
+            ),
+            (
+                json!(["boolean", ["get", "open"], false]),
+                json!(["get", "open"]),
+            ),
+        ] {
+            assert_eq!(specialize(expr.clone(), &stats), expected, "{expr}");
+        }
+    }
+
+    #[test]
+    fn drops_unreachable_assertion_fallbacks() {
+        let stats = typed_stats();
+        assert_eq!(
+            specialize(
+                json!(["string", ["get", "ref"], ["get", "name"], "x"]),
+                &stats
+            ),
+            json!(["string", ["get", "ref"], ["get", "name"]]),
+        );
+        assert_eq!(
+            specialize(
+                json!(["to-number", ["get", "code"], ["get", "rank"], 0]),
+                &stats
+            ),
+            json!(["to-number", ["get", "code"], ["get", "rank"]]),
+        );
+    }
+
+    #[test]
+    fn keeps_coercions_that_stats_cannot_prove() {
+        let stats = typed_stats();
+        for expr in [
+            // Missing on some features: the coercion handles null.
+            json!(["to-string", ["get", "ref"]]),
+            // Mixed wire type.
+            json!(["to-number", ["get", "code"]]),
+            // Wrong type: the coercion converts.
+            json!(["to-string", ["get", "rank"]]),
+            json!(["to-boolean", ["get", "name"]]),
+            // Unknown property.
+            json!(["number", ["get", "missing"], 0]),
+            // Fallback already last.
+            json!(["number", ["get", "code"], 0]),
+        ] {
+            assert_eq!(specialize(expr.clone(), &stats), expr);
+        }
+    }
+
+    #[test]
+    fn sampled_stats_are_not_trusted() {
+        let mut stats = typed_stats();
+        stats.sample_rate = 0.5;
+        let expr = json!(["to-number", ["get", "rank"]]);
+        assert_eq!(specialize(expr.clone(), &stats), expr);
+    }
+}
@@ -0,0 +1,427 @@
+//! Interval range analysis: fold comparisons on derived numeric values.
+//!
+//! Every numeric subexpression is given a closed interval that contains all
+//! values it can take: `["zoom"]` from the layer's zoom bounds, `["get", p]`
+//! from the `min`/`max` of numeric tile statistics, and arithmetic by
+//! interval arithmetic.  A comparison whose operand intervals decide it, such
+//! as `["<", ["*", ["get", "rank"], 2], 40]` with `rank` in `[1, 12]`, folds
+//! to a literal.
+//!
+//! IEEE rounding is monotone, so evaluating an operator on the interval ends
+//! bounds what GL JS computes for any value inside; no widening is needed.
+
+use serde_json::Value;
+
+use super::util::{extract_json_literal, get_prop_name};
+use crate::stats::{LayerStats, PropertyStats};
+
+/// Highest zoom a `MapLibre` map renders at.
+const MAX_ZOOM: f64 = 24.0;
+
+/// A closed interval `[lo, hi]`; either end may be infinite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Interval {
+    fn new(lo: f64, hi: f64) -> Option<Self> {
+        (lo <= hi).then_some(Self { lo, hi })
+    }
+
+    fn point(n: f64) -> Option<Self> {
+        Self::new(n, n)
+    }
+
+    /// The zooms a layer with the given bounds is evaluated at.
+    ///
+    /// GL JS hides a layer from `maxzoom` on, but evaluates symbol layout
+    /// sizes one level ahead, so the upper end is `maxzoom + 1`.
+    pub(crate) fn layer_zoom(minzoom: Option<f64>, maxzoom: Option<f64>) -> Self {
+        let lo = minzoom.unwrap_or(0.0);
+        let hi = maxzoom.unwrap_or(MAX_ZOOM) + 1.0;
+        Self::new(lo, hi).unwrap_or(Self {
+            lo: 0.0,
+            hi: MAX_ZOOM + 1.0,
+        })
+    }
+
+    /// The tile zooms filters and layout properties are evaluated at.
+    ///
+    /// Past a fractional `minzoom` GL JS draws the layer from tiles of zoom
+    /// `floor(minzoom)`, so those see the integer zoom below it.
+    pub(crate) fn tile_zoom(self) -> Self {
+        Self {
+            lo: self.lo.floor(),
+            ..self
+        }
+    }
+
+    /// The smallest interval containing `f(x)` for the corners `x`.  Only
+    /// valid for `f` monotone in each argument.
+    fn hull(corners: [f64; 4]) -> Option<Self> {
+        if corners.iter().any(|c| c.is_nan()) {
+            return None;
+        }
+        let lo = corners.iter().copied().fold(f64::INFINITY, f64::min);
+        let hi = corners.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        Self::new(lo, hi)
+    }
+
+    fn add(self, other: Self) -> Option<Self> {
+        Self::new(self.lo + other.lo, self.hi + other.hi)
+    }
+
+    fn neg(self) -> Self {
+        Self {
+            lo: -self.hi,
+            hi: -self.lo,
+        }
+    }
+
+    fn mul(self, o: Self) -> Option<Self> {
+        Self::hull([
+            self.lo * o.lo,
+            self.lo * o.hi,
+            self.hi * o.lo,
+            self.hi * o.hi,
+        ])
+    }
+
+    fn div(self, o: Self) -> Option<Self> {
+        if o.lo <= 0.0 && o.hi >= 0.0 {
+            return None;
+        }
+        Self::hull([
+            self.lo / o.lo,
+            self.lo / o.hi,
+            self.hi / o.lo,
+            self.hi / o.hi,
+        ])
+    }
+
+    /// `x ^ y` for a non-negative base, where `powf` is monotone in each
+    /// argument.
+    fn pow(self, e: Self) -> Option<Self> {
+        if self.lo < 0.0 {
+            return None;
+        }
+        Self::hull([
+            self.lo.powf(e.lo),
+            self.lo.powf(e.hi),
+            self.hi.powf(e.lo),
+            self.hi.powf(e.hi),
+        ])
+    }
+
+    fn min(self, o: Self) -> Self {
+        Self {
+            lo: self.lo.min(o.lo),
+            hi: self.hi.min(o.hi),
+        }
+    }
+
+    fn max(self, o: Self) -> Self {
+        Self {
+            lo: self.lo.max(o.lo),
+            hi: self.hi.max(o.hi),
+        }
+    }
+
+    fn abs(self) -> Self {
+        if self.lo >= 0.0 {
+            self
+        } else if self.hi <= 0.0 {
+            self.neg()
+        } else {
+            Self {
+                lo: 0.0,
+                hi: self.hi.max(-self.lo),
+            }
+        }
+    }
+
+    /// `Some(self < o)` if it holds for every pair of values, or for none.
+    fn less(self, o: Self) -> Option<bool> {
+        if self.hi < o.lo {
+            Some(true)
+        } else if self.lo >= o.hi {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn less_equal(self, o: Self) -> Option<bool> {
+        if self.hi <= o.lo {
+            Some(true)
+        } else if self.lo > o.hi {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    #[expect(clippy::float_cmp, reason = "single-point intervals compare exactly")]
+    fn equal(self, o: Self) -> Option<bool> {
+        if self.hi < o.lo || o.hi < self.lo {
+            Some(false)
+        } else if self.lo == self.hi && o.lo == o.hi {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+/// [`Interval::layer_zoom`] of every layer in `style`, by index.
+pub(crate) fn layer_zoom_ranges(style: &Value) -> Vec<Interval> {
+    let layers = style
+        .get("layers")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+    layers
+        .iter()
+        .map(|layer| {
+            Interval::layer_zoom(
+                layer.get("minzoom").and_then(Value::as_f64),
+                layer.get("maxzoom").and_then(Value::as_f64),
+            )
+        })
+        .collect()
+}
+
+/// What is known about the inputs of the expressions of one layer.
+#[derive(Clone, Copy)]
+pub(crate) struct RangeEnv<'a> {
+    pub zoom: Interval,
+    /// Statistics of the layer's source layer, when present and complete.
+    pub stats: Option<&'a LayerStats>,
+}
+
+impl RangeEnv<'_> {
+    /// The range of the property `name`, if it is numeric on every feature.
+    /// A missing value would make arithmetic on it an evaluation error.
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "GL JS reads tile integers as f64 too"
+    )]
+    fn property(&self, name: &str) -> Option<Interval> {
+        let stats = self.stats?;
+        let prop = stats.properties.get(name)?;
+        if prop.present_count() != stats.total_features {
+            return None;
+        }
+        match *prop {
+            PropertyStats::Integer { min, max, .. } => Interval::new(min as f64, max as f64),
+            PropertyStats::UnsignedInteger { min, max, .. } => {
+                Interval::new(min as f64, max as f64)
+            }
+            PropertyStats::Double { min, max, .. } if min.is_finite() && max.is_finite() => {
+                Interval::new(min, max)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The range of the numeric expression `v`, or `None` if nothing is known.
+pub(crate) fn range_of(v: &Value, env: &RangeEnv<'_>) -> Option<Interval> {
+    if let Value::Number(n) = v {
+        return Interval::point(n.as_f64()?);
+    }
+    let arr = v.as_array()?;
+    let args = arr.get(1..).unwrap_or_default();
+    match arr.first()?.as_str()? {
+        "literal" => Interval::point(extract_json_literal(v)?.as_f64()?),
+        "zoom" if args.is_empty() => Some(env.zoom),
+        "get" if args.len() == 1 => env.property(get_prop_name(v)?),
+        "+" if !args.is_empty() => fold_args(args, env, Interval::add),
+        "*" if !args.is_empty() => fold_args(args, env, Interval::mul),
+        "-" => match *args {
+            [ref x] => Some(range_of(x, env)?.neg()),
+            [ref x, ref y] => range_of(x, env)?.add(range_of(y, env)?.neg()),
+            _ => None,
+        },
+        "/" => match *args {
+            [ref x, ref y] => range_of(x, env)?.div(range_of(y, env)?),
+            _ => None,
+        },
+        "^" => match *args {
+            [ref x, ref y] => range_of(x, env)?.pow(range_of(y, env)?),
+            _ => None,
+        },
+        "min" if !args.is_empty() => fold_args(args, env, |a, b| Some(a.min(b))),
+        "max" if !args.is_empty() => fold_args(args, env, |a, b| Some(a.max(b))),
+        "abs" if args.len() == 1 => Some(range_of(&args[0], env)?.abs()),
+        _ => None,
+    }
+}
+
+fn fold_args(
+    args: &[Value],
+    env: &RangeEnv<'_>,
+    op: impl Fn(Interval, Interval) -> Option<Interval>,
+) -> Option<Interval> {
+    let (first, rest) = args.split_first()?;
+    rest.iter().try_fold(range_of(first, env)?, |acc, arg| {
+        op(acc, range_of(arg, env)?)
+    })
+}
+
+/// The value of the comparison `arr` if the ranges of its operands decide it.
+fn decide_comparison(arr: &[Value], env: &RangeEnv<'_>) -> Option<bool> {
+    let [Value::String(op), lhs, rhs] = arr else {
+        return None;
+    };
+    // Literal-only comparisons are left to constant folding.
+    if !lhs.is_array() && !rhs.is_array() {
+        return None;
+    }
+    let (a, b) = (range_of(lhs, env)?, range_of(rhs, env)?);
+    match op.as_str() {
+        "<" => a.less(b),
+        "<=" => a.less_equal(b),
+        ">" => b.less(a),
+        ">=" => b.less_equal(a),
+        "==" => a.equal(b),
+        "!=" => a.equal(b).map(|eq| !eq),
+        _ => None,
+    }
+}
+
+/// Replace every comparison in `v` that the ranges in `env` decide with
+/// `["literal", bool]`.  Returns `true` if anything was folded.
+pub(crate) fn fold_range_comparisons(v: &mut Value, env: &RangeEnv<'_>) -> bool {
+    let Value::Array(arr) = v else {
+        return false;
+    };
+    if arr.first().and_then(Value::as_str) == Some("literal") {
+        return false;
+    }
+    if let Some(result) = decide_comparison(arr, env) {
+        *v = Value::Array(vec![Value::String("literal".into()), Value::Bool(result)]);
+        return true;
+    }
+    let mut changed = false;
+    for child in arr.iter_mut() {
+        changed |= fold_range_comparisons(child, env);
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn rank_stats(present_count: u64, min: i64, max: i64) -> LayerStats {
+        LayerStats {
+            total_features: 100,
+            properties: BTreeMap::from([(
+                "rank".to_string(),
+                PropertyStats::Integer {
+                    present_count,
+                    min,
+                    max,
+                    cardinality: 12,
+                    value_counts: None,
+                },
+            )]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn folds_comparisons_on_derived_property_values() {
+        let stats = rank_stats(100, 1, 12);
+        let env = RangeEnv {
+            zoom: Interval::layer_zoom(None, None),
+            stats: Some(&stats),
+        };
+        let mut filter = json!([
+            "all",
+            ["<", ["*", ["get", "rank"], 2], 40],
+            [">=", ["max", ["-", ["get", "rank"], 3], ["abs", -2]], 2],
+            ["<", ["^", ["get", "rank"], 2], 100]
+        ]);
+        assert!(fold_range_comparisons(&mut filter, &env));
+        assert_eq!(
+            filter,
+            json!([
+                "all",
+                ["literal", true],
+                ["literal", true],
+                ["<", ["^", ["get", "rank"], 2], 100]
+            ])
+        );
+        let mut never = json!(["==", ["/", ["get", "rank"], 4], 10]);
+        assert!(fold_range_comparisons(&mut never, &env));
+        assert_eq!(never, json!(["literal", false]));
+    }
+
+    #[test]
+    fn folds_zoom_arithmetic_from_layer_bounds() {
+        let env = RangeEnv {
+            zoom: Interval::layer_zoom(Some(10.0), Some(14.0)),
+            stats: None,
+        };
+        let mut filter = json!([">=", ["+", ["zoom"], 2], 12]);
+        assert!(fold_range_comparisons(&mut filter, &env));
+        assert_eq!(filter, json!(["literal", true]));
+        // Symbol sizes are evaluated one zoom past `maxzoom`.
+        let mut filter = json!(["<", ["zoom"], 15]);
+        assert!(!fold_range_comparisons(&mut filter, &env));
+        let mut filter = json!(["<", ["zoom"], 16]);
+        assert!(fold_range_comparisons(&mut filter, &env));
+    }
+
+    #[test]
+    fn filters_see_the_tile_zoom_below_a_fractional_minzoom() {
+        let paint = RangeEnv {
+            zoom: Interval::layer_zoom(Some(4.5), None),
+            stats: None,
+        };
+        let tile = RangeEnv {
+            zoom: paint.zoom.tile_zoom(),
+            ..paint
+        };
+        let mut filter = json!([">=", ["zoom"], 4.5]);
+        assert!(!fold_range_comparisons(&mut filter, &tile));
+        let mut filter = json!([">=", ["zoom"], 4]);
+        assert!(fold_range_comparisons(&mut filter, &tile));
+        let mut paint_value = json!([">=", ["zoom"], 4.5]);
+        assert!(fold_range_comparisons(&mut paint_value, &paint));
+    }
+
+    #[test]
+    fn unknown_or_partial_ranges_do_not_fold() {
+        // Absent on some features: `*` would fail there instead of comparing.
+        let stats = rank_stats(90, 1, 12);
+        let env = RangeEnv {
+            zoom: Interval::layer_zoom(None, None),
+            stats: Some(&stats),
+        };
+        let mut filter = json!(["<", ["*", ["get", "rank"], 2], 40]);
+        assert!(!fold_range_comparisons(&mut filter, &env));
+
+        let stats = rank_stats(100, -2, 12);
+        let env = RangeEnv {
+            stats: Some(&stats),
+            ..env
+        };
+        // Division by a range containing zero, and a possibly negative base.
+        for expr in [
+            json!(["<", ["/", 1, ["get", "rank"]], 40]),
+            json!(["<", ["^", ["get", "rank"], 0.5], 40]),
+            json!(["<", ["*", ["get", "rank"], 2], 20]),
+        ] {
+            let mut folded = expr.clone();
+            assert!(!fold_range_comparisons(&mut folded, &env), "{expr}");
+        }
+    }
+}
@@ -2,25 +2,31 @@
 
 mod fold;
 mod fold_stats;
+mod interval;
 mod reorder;
 mod simplify;
+mod solver;
 pub(crate) mod util;
 
 use fold::{
     try_algebraic_simplify, try_boolean_absorption, try_dead_branch_case,
     try_dead_branch_match_literal, try_distributive_factoring, try_equivalence_substitution,
-    try_filter_contradiction, try_fold_boolean_algebra, try_fold_comparison, try_fold_not,
-    try_fold_pure_operator, try_fold_redundant_coercion, try_fold_redundant_properties,
-    try_negate_comparison, try_predicate_subsumption, try_range_tightening, try_sccp_case,
-    try_sccp_match, try_strip_typeof_eq_guard,
+    try_filter_contradiction, try_fold_boolean_algebra, try_fold_comparison,
+    try_fold_literal_interpolate, try_fold_not, try_fold_plain_format, try_fold_pure_operator,
+    try_fold_redundant_coercion, try_fold_redundant_properties, try_negate_comparison,
+    try_predicate_subsumption, try_range_tightening, try_sccp_case, try_sccp_match,
+    try_strip_typeof_eq_guard,
 };
 use fold_stats::{
     try_fold_coalesce_from_stats, try_fold_comparison_from_stats,
     try_fold_geometry_type_from_stats, try_fold_get_from_stats, try_fold_has_from_stats,
     try_prune_data_ramp_from_stats, try_prune_in_from_stats, try_prune_match_from_stats,
-    try_reorder_match_from_stats,
+    try_reorder_match_from_stats, try_specialize_coercion_from_stats,
 };
-use maplibre_style_spec::mir::MirSpec;
+pub(crate) use interval::layer_zoom_ranges;
+use interval::{Interval, RangeEnv, fold_range_comparisons};
+use maplibre_style_spec::mir::types::MirType;
+use maplibre_style_spec::mir::{MirPropertySection, MirSpec};
 use maplibre_style_spec::spec::Boolean;
 use reorder::{LayerContext, reorder_selectivity};
 use serde_json::Value;
@@ -32,6 +38,7 @@ use simplify::{
     try_simplify_single_in, try_strip_coalesce_in_comparison, try_strip_coalesce_in_in,
     try_strip_coalesce_in_match,
 };
+use solver::simplify_filter;
 pub(crate) use util::extract_json_literal;
 
 use super::OptPasses;
@@ -98,6 +105,12 @@ enum RewriteRule {
         scope: RuleScope,
         apply: fn(&mut Vec<Value>, &MirSpec) -> bool,
     },
+    /// Rule tuned by the pass options.
+    WithOptions {
+        gate: RuleGate,
+        scope: RuleScope,
+        apply: fn(&mut Vec<Value>, &OptPasses) -> bool,
+    },
     /// Stats-driven rule that needs tile statistics and layer info.
     WithStats {
         gate: RuleGate,
@@ -120,9 +133,10 @@ impl RuleGate {
 impl RewriteRule {
     const fn gate(&self) -> &RuleGate {
         match self {
-            Self::Pure { gate, .. } | Self::WithMir { gate, .. } | Self::WithStats { gate, .. } => {
-                gate
-            }
+            Self::Pure { gate, .. }
+            | Self::WithMir { gate, .. }
+            | Self::WithOptions { gate, .. }
+            | Self::WithStats { gate, .. } => gate,
         }
     }
 
@@ -130,15 +144,17 @@ impl RewriteRule {
         match self {
             Self::Pure { scope, .. }
             | Self::WithMir { scope, .. }
+            | Self::WithOptions { scope, .. }
             | Self::WithStats { scope, .. } => scope,
         }
     }
 
     /// Try to apply as a peephole rewrite (single node). Returns true if rewritten.
-    fn apply_peephole(&self, arr: &mut Vec<Value>, mir: &MirSpec) -> bool {
+    fn apply_peephole(&self, arr: &mut Vec<Value>, mir: &MirSpec, passes: &OptPasses) -> bool {
         match self {
             Self::Pure { apply, .. } => apply(arr),
             Self::WithMir { apply, .. } => apply(arr, mir),
+            Self::WithOptions { apply, .. } => apply(arr, passes),
             Self::WithStats { .. } => false,
         }
     }
@@ -186,6 +202,16 @@ static RULES: &[RewriteRule] = &[
         scope: RuleScope::Peephole,
         apply: try_fold_pure_operator,
     },
+    RewriteRule::Pure {
+        gate: RuleGate::ConstantFold,
+        scope: RuleScope::Peephole,
+        apply: try_fold_plain_format,
+    },
+    RewriteRule::Pure {
+        gate: RuleGate::ConstantFold,
+        scope: RuleScope::Peephole,
+        apply: try_fold_literal_interpolate,
+    },
     RewriteRule::Pure {
         gate: RuleGate::ConstantFold,
         scope: RuleScope::Peephole,
@@ -297,6 +323,11 @@ static RULES: &[RewriteRule] = &[
         scope: RuleScope::FilterAndProperty,
         apply: try_fold_coalesce_from_stats,
     },
+    RewriteRule::WithStats {
+        gate: RuleGate::ConstantFoldStats,
+        scope: RuleScope::FilterAndProperty,
+        apply: try_specialize_coercion_from_stats,
+    },
     RewriteRule::WithStats {
         gate: RuleGate::ConstantFoldStats,
         scope: RuleScope::FilterAndProperty,
@@ -308,7 +339,7 @@ static RULES: &[RewriteRule] = &[
         scope: RuleScope::Peephole,
         apply: try_canonicalize_interpolation_curve,
     },
-    RewriteRule::Pure {
+    RewriteRule::WithOptions {
         gate: RuleGate::SimplifyExpressions,
         scope: RuleScope::Peephole,
         apply: try_simplify_interpolate_or_step,
@@ -402,12 +433,40 @@ pub(crate) struct NormalizeFoldVisitor<'a> {
     pub passes: &'a OptPasses,
     pub stats: Option<&'a TileStatistics>,
     pub layer_info: Option<&'a [Option<VectorLayerInfo>]>,
+    /// Zoom range of each layer, from [`layer_zoom_ranges`].
+    pub zoom_ranges: &'a [Interval],
     pub changed: bool,
     /// Typed filter constraints extracted for propagation into properties.
     /// Each entry is a `Boolean` variant from the generated spec types.
     pub filter_constraints: Vec<Boolean>,
 }
 
+impl NormalizeFoldVisitor<'_> {
+    /// `tile` for filters and layout properties, which GL JS evaluates at
+    /// the integer tile zoom.
+    fn range_env(&self, layer_index: usize, tile: bool) -> RangeEnv<'_> {
+        let zoom = self
+            .zoom_ranges
+            .get(layer_index)
+            .copied()
+            .unwrap_or_else(|| Interval::layer_zoom(None, None));
+        RangeEnv {
+            zoom: if tile { zoom.tile_zoom() } else { zoom },
+            stats: self
+                .passes
+                .constant_fold_stats
+                .then(|| {
+                    super::source_util::resolve_layer_stats(
+                        self.stats,
+                        self.layer_info,
+                        layer_index,
+                    )
+                })
+                .flatten(),
+        }
+    }
+}
+
 impl StyleVisitor for NormalizeFoldVisitor<'_> {
     fn visit_filter(&mut self, layer_index: usize, layer_type: &str, filter: &mut Value) {
         if self.passes.constant_fold {
@@ -445,6 +504,8 @@ impl StyleVisitor for NormalizeFoldVisitor<'_> {
                     &mut self.changed,
                 );
             }
+            self.changed |= fold_range_comparisons(filter, &self.range_env(layer_index, true));
+            self.changed |= simplify_filter(filter);
         }
         normalize_and_fold(filter, self.mir, self.passes, &mut self.changed);
 
@@ -477,6 +538,10 @@ impl StyleVisitor for NormalizeFoldVisitor<'_> {
                     &mut self.changed,
                 );
             }
+            self.changed |= fold_range_comparisons(
+                value,
+                &self.range_env(ctx.layer_index, ctx.section == MirPropertySection::Layout),
+            );
         }
         normalize_and_fold(value, self.mir, self.passes, &mut self.changed);
         // Unwrap ["literal", scalar] → scalar.
@@ -489,6 +554,10 @@ impl StyleVisitor for NormalizeFoldVisitor<'_> {
             *value = arr[1].take();
             self.changed = true;
         }
+        if self.passes.constant_fold && matches!(ctx.field.r#type, MirType::Color) {
+            self.changed |=
+                fold::fold_color_outputs(value, &|op| fold::is_pure_operator(op, self.mir));
+        }
     }
 
     fn visit_layer(&mut self, _layer_index: usize, _layer_type: &str, _layer: &mut Value) {
@@ -707,7 +776,7 @@ fn apply_one_rewrite_pass(arr: &mut Vec<Value>, mir: &MirSpec, passes: &OptPasse
     RULES
         .iter()
         .filter(|r| matches!(r.scope(), RuleScope::Peephole) && r.gate().enabled(passes))
-        .any(|r| r.apply_peephole(arr, mir))
+        .any(|r| r.apply_peephole(arr, mir, passes))
 }
 
 // ── Typed filter passes ──────────────────────────────────────────────────────
@@ -865,6 +934,7 @@ impl TypedFilterVisitor for TypedNormalizeFoldVisitor<'_> {
 mod tests {
     use std::path::Path;
 
+    use maplibre_style_spec::expression_eval::{EvaluationContext, Feature, evaluate};
     use serde_json::json;
 
     use super::*;
@@ -895,4 +965,80 @@ mod tests {
         assert!(changed);
         assert_eq!(expr, json!(["==", ["get", "k"], "v"]));
     }
+
+    /// Folding must not change what an upstream expression fixture
+    /// evaluates to for any of its inputs.  Needs the `upstream` submodule
+    /// checked out.
+    #[test]
+    #[ignore = "needs the upstream test fixtures"]
+    fn folding_agrees_with_upstream_expression_fixtures() {
+        let mir = sample_mir();
+        let passes = OptPasses {
+            constant_fold: true,
+            ..Default::default()
+        };
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../upstream/test/integration/expression/tests");
+        let mut stack = vec![root];
+        let mut failures = Vec::new();
+        let mut checked = 0;
+        while let Some(dir) = stack.pop() {
+            for entry in std::fs::read_dir(&dir).expect("fixture directory") {
+                let path = entry.expect("directory entry").path();
+                if path.is_dir() {
+                    stack.push(path);
+                    continue;
+                }
+                if path.file_name().is_none_or(|n| n != "test.json") {
+                    continue;
+                }
+                let fixture = std::fs::read_to_string(&path).expect("fixture");
+                let test: Value = serde_json::from_str(&fixture).expect("fixture JSON");
+                if test["expected"]["compiled"]["result"] != "success"
+                    || test.get("propertySpec").is_some()
+                {
+                    continue;
+                }
+                let Some(inputs) = test.get("inputs").and_then(Value::as_array) else {
+                    continue;
+                };
+                let mut folded = test["expression"].clone();
+                normalize_and_fold(&mut folded, &mir, &passes, &mut false);
+                for input in inputs {
+                    let f = &input[1];
+                    let feature = Feature {
+                        id: f.get("id").cloned(),
+                        properties: f
+                            .get("properties")
+                            .and_then(Value::as_object)
+                            .cloned()
+                            .unwrap_or_default(),
+                        geometry_type: f
+                            .pointer("/geometry/type")
+                            .and_then(Value::as_str)
+                            .map(str::to_owned),
+                        ..Feature::default()
+                    };
+                    let ctx = EvaluationContext {
+                        zoom: input[0].get("zoom").and_then(Value::as_f64),
+                        feature: Some(&feature),
+                        ..EvaluationContext::default()
+                    };
+                    let expected = evaluate(&test["expression"], &ctx).map(|v| v.to_json());
+                    let actual = evaluate(&folded, &ctx).map(|v| v.to_json());
+                    checked += 1;
+                    if expected.is_ok() != actual.is_ok()
+                        || expected.as_ref().ok() != actual.as_ref().ok()
+                    {
+                        failures.push(format!(
+                            "{}: {folded} gives {actual:?}, not {expected:?}",
+                            path.display()
+                        ));
+                    }
+                }
+            }
+        }
+        assert!(checked > 0, "no fixtures found");
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
 }
@@ -1,9 +1,9 @@
-//! Selectivity-based reordering of `any`/`all` operands.
+//! Selectivity-based reordering of `any`/`all` operands and mutually exclusive `case` branches.
 
 use maplibre_style_spec::mir::MirSpec;
 use serde_json::Value;
 
-use super::util::bool_literal;
+use super::util::{bool_literal, compare_json_values, extract_json_literal, is_get_expr};
 use crate::optimize::selectivity::estimate_selectivity;
 use crate::stats::TileStatistics;
 
@@ -24,6 +24,7 @@ pub(super) fn reorder_selectivity(
                 reorder_selectivity(x, mir, stats, ctx);
             }
             maybe_reorder_any_all(arr, mir, stats, ctx);
+            maybe_reorder_case(arr, stats, ctx);
         }
         Value::Object(map) => {
             for x in map.values_mut() {
@@ -123,7 +124,212 @@ pub(super) fn maybe_reorder_any_all(
     *arr = out;
 }
 
+/// Reorder `["case", c1, o1, c2, o2, ..., fallback]` branches by descending estimated
+/// selectivity, so the most common branch is tested first.
+///
+/// Only fires when every condition compares the same input (`["get", p]` or
+/// `["geometry-type"]`) against literals with `==` or `in`, and no literal appears in two
+/// conditions. At most one condition can then hold for any feature, so the branch order
+/// does not affect the result.
+pub(super) fn maybe_reorder_case(
+    arr: &mut Vec<Value>,
+    stats: Option<&TileStatistics>,
+    ctx: Option<&LayerContext<'_>>,
+) {
+    let (Some(stats), Some(ctx)) = (stats, ctx) else {
+        return;
+    };
+    if arr.first().and_then(Value::as_str) != Some("case") || arr.len() < 6 || arr.len() % 2 == 1 {
+        return;
+    }
+    let branch_count = (arr.len() - 2) / 2;
+    let mut input: Option<&Value> = None;
+    let mut seen: Vec<Value> = Vec::new();
+    for b in 0..branch_count {
+        let Some((cond_input, values)) = exclusive_condition(&arr[1 + 2 * b]) else {
+            return;
+        };
+        if input.is_some_and(|i| i != cond_input) {
+            return;
+        }
+        input = Some(cond_input);
+        for v in values {
+            if seen
+                .iter()
+                .any(|s| compare_json_values(s, &v) == Some(std::cmp::Ordering::Equal))
+            {
+                return;
+            }
+            seen.push(v);
+        }
+    }
+
+    let mut branches = Vec::with_capacity(branch_count);
+    for b in 0..branch_count {
+        let cond = &arr[1 + 2 * b];
+        let Some(sel) = estimate_selectivity(cond, ctx.source, ctx.source_layer, stats) else {
+            return;
+        };
+        branches.push((ordered_float(sel), b));
+    }
+    // Stable sort keeps the authored order among equally likely branches.
+    branches.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    if branches.iter().enumerate().all(|(i, &(_, b))| i == b) {
+        return;
+    }
+
+    let fallback = arr.pop().expect("case has a fallback");
+    let pairs: Vec<Value> = arr.drain(1..).collect();
+    for (_, b) in branches {
+        arr.extend_from_slice(&pairs[2 * b..2 * b + 2]);
+    }
+    arr.push(fallback);
+}
+
+/// Split an `==`/`in` condition into its input expression and the scalar literals it matches.
+fn exclusive_condition(cond: &Value) -> Option<(&Value, Vec<Value>)> {
+    let Value::Array(c) = cond else {
+        return None;
+    };
+    if c.len() != 3 {
+        return None;
+    }
+    let is_input = |v: &Value| {
+        is_get_expr(v)
+            || matches!(v, Value::Array(a) if a.len() == 1 && a[0].as_str() == Some("geometry-type"))
+    };
+    let scalar =
+        |v: Value| matches!(v, Value::String(_) | Value::Number(_) | Value::Bool(_)).then_some(v);
+    match c[0].as_str()? {
+        "==" if is_input(&c[1]) => Some((&c[1], vec![scalar(extract_json_literal(&c[2])?)?])),
+        "==" if is_input(&c[2]) => Some((&c[2], vec![scalar(extract_json_literal(&c[1])?)?])),
+        "in" if is_get_expr(&c[1]) => {
+            let Value::Array(values) = extract_json_literal(&c[2])? else {
+                return None;
+            };
+            let values = values.into_iter().map(scalar).collect::<Option<Vec<_>>>()?;
+            Some((&c[1], values))
+        }
+        _ => None,
+    }
+}
+
 /// Wrapper for f64 ordering that handles NaN.
 fn ordered_float(f: f64) -> f64 {
     if f.is_nan() { 0.5 } else { f }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use indexmap::IndexMap;
+    use serde_json::json;
+
+    use super::*;
+    use crate::stats::{LayerStats, PropertyStats, SourceStats};
+
+    fn class_stats() -> TileStatistics {
+        let value_counts = IndexMap::from([
+            ("motorway".to_string(), 50u64),
+            ("primary".to_string(), 150),
+            ("service".to_string(), 800),
+        ]);
+        let layer = LayerStats {
+            total_features: 1000,
+            properties: BTreeMap::from([(
+                "class".to_string(),
+                PropertyStats::String {
+                    present_count: 1000,
+                    cardinality: 3,
+                    value_counts: Some(value_counts),
+                },
+            )]),
+            ..Default::default()
+        };
+        TileStatistics {
+            sources: BTreeMap::from([(
+                "src".to_string(),
+                SourceStats {
+                    layers: BTreeMap::from([("roads".to_string(), layer)]),
+                },
+            )]),
+            sample_rate: 1.0,
+        }
+    }
+
+    fn reorder_case(mut v: Value) -> Value {
+        let stats = class_stats();
+        let ctx = LayerContext {
+            source: "src",
+            source_layer: "roads",
+        };
+        let Value::Array(arr) = &mut v else {
+            panic!("expected an expression array");
+        };
+        maybe_reorder_case(arr, Some(&stats), Some(&ctx));
+        v
+    }
+
+    #[test]
+    fn exclusive_case_branches_are_ordered_by_frequency() {
+        let v = reorder_case(json!([
+            "case",
+            ["==", ["get", "class"], "motorway"],
+            3,
+            ["in", ["get", "class"], ["literal", ["primary", "trunk"]]],
+            2,
+            ["==", "service", ["get", "class"]],
+            1,
+            0
+        ]));
+        assert_eq!(
+            v,
+            json!([
+                "case",
+                ["==", "service", ["get", "class"]],
+                1,
+                ["in", ["get", "class"], ["literal", ["primary", "trunk"]]],
+                2,
+                ["==", ["get", "class"], "motorway"],
+                3,
+                0
+            ])
+        );
+    }
+
+    #[test]
+    fn overlapping_or_unrelated_conditions_keep_their_order() {
+        for v in [
+            // "service" is matched by both the first and the last branch.
+            json!([
+                "case",
+                ["in", ["get", "class"], ["literal", ["motorway", "service"]]],
+                3,
+                ["==", ["get", "class"], "service"],
+                1,
+                0
+            ]),
+            // Different inputs can both hold.
+            json!([
+                "case",
+                ["==", ["get", "class"], "motorway"],
+                3,
+                ["==", ["get", "kind"], "service"],
+                1,
+                0
+            ]),
+            // Not an equality test.
+            json!([
+                "case",
+                ["==", ["get", "class"], "motorway"],
+                3,
+                ["!=", ["get", "class"], "service"],
+                1,
+                0
+            ]),
+        ] {
+            assert_eq!(reorder_case(v.clone()), v);
+        }
+    }
+}
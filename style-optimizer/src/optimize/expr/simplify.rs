@@ -3,8 +3,11 @@
 use serde_json::Value;
 
 use super::util::{compare_json_values, extract_json_literal, replace_arr_with_value};
+use crate::optimize::OptPasses;
 
-/// Canonicalize `["exponential", 1]` → `["linear"]` in interpolate expressions.
+/// Canonicalize `["exponential", 1]` and identity `["cubic-bezier", x1, y1, x2, y2]`
+/// (control points on the diagonal, `x1 == y1` and `x2 == y2`) → `["linear"]` in
+/// interpolate expressions.
 #[expect(clippy::ptr_arg, reason = "to make trait happy")]
 pub(super) fn try_canonicalize_interpolation_curve(arr: &mut Vec<Value>) -> bool {
     let Some(op) = arr[0].as_str() else {
@@ -16,10 +19,18 @@ pub(super) fn try_canonicalize_interpolation_curve(arr: &mut Vec<Value>) -> bool
     let Some(method) = arr.get(1).and_then(Value::as_array) else {
         return false;
     };
-    if method.len() == 2
-        && method[0].as_str() == Some("exponential")
-        && method[1].as_f64() == Some(1.0)
-    {
+    let identity = match method.first().and_then(Value::as_str) {
+        Some("exponential") => method.len() == 2 && method[1].as_f64() == Some(1.0),
+        Some("cubic-bezier") => {
+            method.len() == 5
+                && method[1].is_number()
+                && method[3].is_number()
+                && method[1] == method[2]
+                && method[3] == method[4]
+        }
+        _ => false,
+    };
+    if identity {
         arr[1] = Value::Array(vec![Value::String("linear".into())]);
         return true;
     }
@@ -27,8 +38,8 @@ pub(super) fn try_canonicalize_interpolation_curve(arr: &mut Vec<Value>) -> bool
 }
 
 /// Simplify `interpolate`/`interpolate-hcl`/`interpolate-lab` and `step` expressions
-/// when all output values are structurally equal.
-pub(super) fn try_simplify_interpolate_or_step(arr: &mut Vec<Value>) -> bool {
+/// when all output values are structurally equal, and prune redundant stops.
+pub(super) fn try_simplify_interpolate_or_step(arr: &mut Vec<Value>, passes: &OptPasses) -> bool {
     let Some(op) = arr[0].as_str() else {
         return false;
     };
@@ -49,7 +60,7 @@ pub(super) fn try_simplify_interpolate_or_step(arr: &mut Vec<Value>) -> bool {
                 return true;
             }
             // Prune intermediate stops between identical values.
-            try_interpolate_stop_pruning(arr)
+            try_interpolate_stop_pruning(arr, passes.stop_tolerance.get())
         }
         "step" => {
             if arr.len() < 3 {
@@ -117,7 +128,7 @@ pub(super) fn try_simplify_match(arr: &mut Vec<Value>) -> bool {
 
     let all_same_as_fallback = arms.iter().all(|(_, out)| *out == fallback);
     if all_same_as_fallback {
-        *arr = vec![Value::String("literal".to_string()), fallback];
+        replace_arr_with_value(arr, fallback);
         return true;
     }
 
@@ -130,7 +141,11 @@ pub(super) fn try_simplify_match(arr: &mut Vec<Value>) -> bool {
     'arm: for (labels, output) in arms {
         for (existing_labels, existing_out) in &mut grouped {
             if *existing_out == output {
-                existing_labels.extend(labels);
+                for label in labels {
+                    if !existing_labels.contains(&label) {
+                        existing_labels.push(label);
+                    }
+                }
                 continue 'arm;
             }
         }
@@ -167,7 +182,7 @@ pub(super) fn try_simplify_match(arr: &mut Vec<Value>) -> bool {
 
     // All arms were merged away — collapse to fallback.
     if new_arr.len() == 3 {
-        *arr = vec![Value::String("literal".to_string()), new_arr.remove(2)];
+        replace_arr_with_value(arr, new_arr.remove(2));
     } else {
         *arr = new_arr;
     }
@@ -333,46 +348,98 @@ pub(super) fn try_simplify_case(arr: &mut Vec<Value>) -> bool {
     false
 }
 
-/// Rewrite `case` to `match` when all arms test `["==", same_expr, literal]`.
+/// Rewrite `case` to `match` when its leading arms test the same input
+/// against literals, with `["==", input, literal]` or
+/// `["in", input, ["literal", [...]]]`.
 ///
 /// `match` uses hash-based O(1) dispatch vs `case`'s sequential O(n) evaluation,
 /// providing both a size reduction and runtime speedup.
 ///
-/// Requires at least 2 arms, and all labels must be strings or numbers (not
-/// booleans or null, which `match` doesn't accept per the spec).
+/// `in` arms become array labels.  Labels already claimed by an earlier arm
+/// are dropped, since `case` returns the first arm that matches and `match`
+/// requires unique labels.  Arms after the first condition that is not such a
+/// test stay behind in a `case` fallback:
+///
+/// `["case", ["==", x, "a"], 1, ["in", x, ["literal", ["b", "c"]]], 2, c, 3, 4]`
+/// → `["match", x, "a", 1, ["b", "c"], 2, ["case", c, 3, 4]]`
+///
+/// Requires at least two labels in total, all strings or all integers (the
+/// spec rejects booleans, null, fractional numbers and mixed label types).
 pub(super) fn try_rewrite_case_to_match(arr: &mut Vec<Value>) -> bool {
     if arr.first().and_then(Value::as_str) != Some("case") {
         return false;
     }
-    // Need at least 2 arms: ["case", c1, v1, c2, v2, fallback] → len >= 6, even
-    if arr.len() < 6 || !arr.len().is_multiple_of(2) {
+    if arr.len() < 4 || !arr.len().is_multiple_of(2) {
         return false;
     }
 
     let n_arms = (arr.len() - 2) / 2;
-    let conditions: Vec<Value> = (0..n_arms).map(|i| arr[1 + 2 * i].clone()).collect();
-
-    let Some((common_expr, labels)) = extract_eq_chain(&conditions) else {
-        return false;
-    };
-
-    // match labels must be string or number — not bool or null.
-    if labels
-        .iter()
-        .any(|l| !matches!(l, Value::String(_) | Value::Number(_)))
-    {
+    let mut input: Option<Value> = None;
+    let mut label_is_string: Option<bool> = None;
+    let mut seen: Vec<Value> = Vec::new();
+    let mut arms: Vec<(Vec<Value>, usize)> = Vec::new();
+    let mut prefix = 0;
+    for i in 0..n_arms {
+        let cond = &arr[1 + 2 * i];
+        let Some((expr, labels)) =
+            extract_eq_chain(std::slice::from_ref(cond)).or_else(|| extract_in_expr(cond))
+        else {
+            break;
+        };
+        match &input {
+            None if extract_json_literal(&expr).is_some() => break,
+            None => input = Some(expr),
+            Some(e) if *e == expr => {}
+            Some(_) => break,
+        }
+        let kinds: Option<Vec<bool>> = labels.iter().map(match_label_kind).collect();
+        let Some(kinds) = kinds else {
+            break;
+        };
+        let expected = label_is_string.or(kinds.first().copied());
+        if kinds.iter().any(|&k| Some(k) != expected) {
+            break;
+        }
+        label_is_string = expected;
+        let mut fresh = Vec::new();
+        for label in labels {
+            if !seen.contains(&label) {
+                seen.push(label.clone());
+                fresh.push(label);
+            }
+        }
+        // An arm whose labels were all claimed earlier can never be taken.
+        if !fresh.is_empty() {
+            arms.push((fresh, 2 + 2 * i));
+        }
+        prefix = i + 1;
+    }
+    if seen.len() < 2 {
         return false;
     }
+    let Some(input) = input else {
+        return false;
+    };
 
-    let fallback = arr.last().unwrap().clone();
-    let outputs: Vec<Value> = (0..n_arms).map(|i| arr[2 + 2 * i].clone()).collect();
+    let fallback = if prefix == n_arms {
+        arr.last().unwrap().clone()
+    } else {
+        let mut rest = vec![Value::String("case".to_string())];
+        rest.extend_from_slice(&arr[1 + 2 * prefix..]);
+        Value::Array(rest)
+    };
 
-    let mut result = Vec::with_capacity(2 + 2 * n_arms + 1);
+    let mut result = Vec::with_capacity(3 + 2 * arms.len());
     result.push(Value::String("match".to_string()));
-    result.push(common_expr);
-    for (label, output) in labels.into_iter().zip(outputs) {
+    result.push(input);
+    for (labels, output_idx) in arms {
+        let label = if labels.len() == 1 {
+            labels.into_iter().next().unwrap()
+        } else {
+            Value::Array(labels)
+        };
         result.push(label);
-        result.push(output);
+        result.push(arr[output_idx].clone());
     }
     result.push(fallback);
 
@@ -380,6 +447,20 @@ pub(super) fn try_rewrite_case_to_match(arr: &mut Vec<Value>) -> bool {
     true
 }
 
+/// Whether `v` can be a `match` label: `Some(true)` for strings, `Some(false)`
+/// for integers within the safe range, `None` otherwise.
+fn match_label_kind(v: &Value) -> Option<bool> {
+    const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+    match v {
+        Value::String(_) => Some(true),
+        Value::Number(n) => n
+            .as_f64()
+            .is_some_and(|f| f.fract() == 0.0 && f.abs() <= MAX_SAFE_INTEGER)
+            .then_some(false),
+        _ => None,
+    }
+}
+
 /// Strip redundant `coalesce` wrapping inside comparison operators.
 ///
 /// `["op", ["coalesce", inner, default], cmp_value]` → `["op", inner, cmp_value]`
@@ -881,7 +962,11 @@ fn substitute_var(v: &Value, name: &str, replacement: &Value) -> Value {
 
 /// Remove intermediate stops in `interpolate` expressions where adjacent stops have
 /// identical output values.
-fn try_interpolate_stop_pruning(arr: &mut Vec<Value>) -> bool {
+///
+/// Linear curves (`["linear"]`, `["exponential", 1]`) also lose numeric stops
+/// that lie on the line between the stops kept around them, within `epsilon`
+/// output units.  Editors tend to leave many such stops behind.
+fn try_interpolate_stop_pruning(arr: &mut Vec<Value>, epsilon: f64) -> bool {
     // ["interpolate", method, input, z0, v0, z1, v1, z2, v2, ...]
     // Header is 3 elements, then pairs of (zoom, value).
     if arr.len() < 7 {
@@ -902,6 +987,9 @@ fn try_interpolate_stop_pruning(arr: &mut Vec<Value>) -> bool {
             to_remove.push(i);
         }
     }
+    if to_remove.is_empty() && is_linear_interpolation(arr) {
+        to_remove = collinear_stops(arr, n_stops, epsilon);
+    }
 
     if to_remove.is_empty() {
         return false;
@@ -916,6 +1004,54 @@ fn try_interpolate_stop_pruning(arr: &mut Vec<Value>) -> bool {
     true
 }
 
+fn is_linear_interpolation(arr: &[Value]) -> bool {
+    if arr[0].as_str() != Some("interpolate") {
+        return false;
+    }
+    let Some(method) = arr[1].as_array() else {
+        return false;
+    };
+    match method.first().and_then(Value::as_str) {
+        Some("linear") => method.len() == 1,
+        Some("exponential") => method.len() == 2 && method[1].as_f64() == Some(1.0),
+        _ => false,
+    }
+}
+
+/// Interior stops of a linear numeric ramp that can go: each one lies within
+/// `epsilon` of the line between the nearest kept stop before it and the
+/// first kept stop after it.
+fn collinear_stops(arr: &[Value], n_stops: usize, epsilon: f64) -> Vec<usize> {
+    let stops: Option<Vec<(f64, f64)>> = (0..n_stops)
+        .map(|i| Some((arr[3 + 2 * i].as_f64()?, arr[4 + 2 * i].as_f64()?)))
+        .collect();
+    let Some(stops) = stops else {
+        return Vec::new();
+    };
+    // Every stop dropped since the last kept one must stay close to the line
+    // from that stop to the next candidate anchor.
+    let on_line = |from: usize, to: usize| {
+        let ((x0, y0), (x1, y1)) = (stops[from], stops[to]);
+        x1 > x0
+            && stops[from + 1..to].iter().all(|&(x, y)| {
+                let expected = y0 + (y1 - y0) * (x - x0) / (x1 - x0);
+                // Allow for rounding in the interpolation itself.
+                let slack = f64::EPSILON * 4.0 * y0.abs().max(y1.abs()).max(y.abs()).max(1.0);
+                (y - expected).abs() <= epsilon.max(slack)
+            })
+    };
+    let mut removed = Vec::new();
+    let mut kept = 0;
+    for i in 1..n_stops - 1 {
+        if on_line(kept, i + 1) {
+            removed.push(i);
+        } else {
+            kept = i;
+        }
+    }
+    removed
+}
+
 /// Deduplicate adjacent step stops with the same output value.
 /// `["step", input, default, z1, v1, z2, v1, z3, v2]`
 /// → `["step", input, default, z1, v1, z3, v2]`
@@ -1395,3 +1531,325 @@ pub(super) fn try_simplify_unary_typed(filter: &mut Boolean) -> bool {
         _ => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn apply(rule: fn(&mut Vec<Value>) -> bool, v: Value) -> Option<Value> {
+        let Value::Array(mut arr) = v else {
+            unreachable!("rules apply to arrays");
+        };
+        rule(&mut arr).then_some(Value::Array(arr))
+    }
+
+    #[test]
+    fn case_with_eq_and_in_arms_becomes_match() {
+        let class = json!(["get", "class"]);
+        let case = json!([
+            "case",
+            ["==", class, "motorway"],
+            6,
+            ["in", class, ["literal", ["primary", "trunk", "motorway"]]],
+            4,
+            ["==", "service", class],
+            1,
+            2
+        ]);
+        assert_eq!(
+            apply(try_rewrite_case_to_match, case),
+            Some(json!([
+                "match",
+                ["get", "class"],
+                "motorway",
+                6,
+                ["primary", "trunk"],
+                4,
+                "service",
+                1,
+                2
+            ]))
+        );
+    }
+
+    #[test]
+    fn case_keeps_trailing_arms_on_other_inputs() {
+        let case = json!([
+            "case",
+            ["==", ["get", "rank"], 1],
+            "big",
+            ["in", ["get", "rank"], ["literal", [2, 3]]],
+            "medium",
+            ["has", "capital"],
+            "capital",
+            ["==", ["get", "rank"], 4],
+            "small",
+            "tiny"
+        ]);
+        assert_eq!(
+            apply(try_rewrite_case_to_match, case),
+            Some(json!([
+                "match",
+                ["get", "rank"],
+                1,
+                "big",
+                [2, 3],
+                "medium",
+                [
+                    "case",
+                    ["has", "capital"],
+                    "capital",
+                    ["==", ["get", "rank"], 4],
+                    "small",
+                    "tiny"
+                ]
+            ]))
+        );
+    }
+
+    #[test]
+    fn case_with_invalid_labels_is_left_alone() {
+        for case in [
+            // Mixed label types.
+            json!([
+                "case",
+                ["==", ["get", "a"], 1],
+                1,
+                ["==", ["get", "a"], "x"],
+                2,
+                0
+            ]),
+            // Fractional labels.
+            json!(["case", ["in", ["get", "a"], ["literal", [0.5, 1]]], 1, 0]),
+            // Booleans.
+            json!([
+                "case",
+                ["==", ["get", "a"], true],
+                1,
+                ["==", ["get", "a"], false],
+                2,
+                0
+            ]),
+            // Different inputs.
+            json!([
+                "case",
+                ["==", ["get", "a"], "x"],
+                1,
+                ["==", ["get", "b"], "y"],
+                2,
+                0
+            ]),
+            // A single label.
+            json!(["case", ["==", ["get", "a"], "x"], 1, 0]),
+        ] {
+            assert_eq!(
+                apply(try_rewrite_case_to_match, case.clone()),
+                None,
+                "{case}"
+            );
+        }
+    }
+
+    #[test]
+    fn match_merges_identical_outputs() {
+        let m = json!([
+            "match",
+            ["get", "kind"],
+            "river",
+            "#00f",
+            ["lake", "pond"],
+            "#0af",
+            ["canal", "river"],
+            "#00f",
+            "#fff"
+        ]);
+        assert_eq!(
+            apply(try_simplify_match, m),
+            Some(json!([
+                "match",
+                ["get", "kind"],
+                ["river", "canal"],
+                "#00f",
+                ["lake", "pond"],
+                "#0af",
+                "#fff"
+            ]))
+        );
+
+        // Collapsing keeps an expression fallback as an expression.
+        let m = json!(["match", ["get", "kind"], "a", ["get", "x"], ["get", "x"]]);
+        assert_eq!(apply(try_simplify_match, m), Some(json!(["get", "x"])));
+    }
+
+    fn simplify_ramp(v: Value, epsilon: f64) -> Option<Value> {
+        let passes = OptPasses {
+            stop_tolerance: crate::optimize::StopTolerance::new(epsilon).unwrap(),
+            ..OptPasses::default()
+        };
+        let Value::Array(mut arr) = v else {
+            unreachable!("rules apply to arrays");
+        };
+        try_simplify_interpolate_or_step(&mut arr, &passes).then_some(Value::Array(arr))
+    }
+
+    #[test]
+    fn drops_collinear_interpolate_stops() {
+        let ramp = json!([
+            "interpolate",
+            ["linear"],
+            ["zoom"],
+            10,
+            1,
+            12,
+            2,
+            14,
+            3,
+            16,
+            4,
+            18,
+            10
+        ]);
+        assert_eq!(
+            simplify_ramp(ramp, 0.0),
+            Some(json!([
+                "interpolate",
+                ["linear"],
+                ["zoom"],
+                10,
+                1,
+                16,
+                4,
+                18,
+                10
+            ]))
+        );
+
+        // `["exponential", 1]` is linear; fractional steps collinear up to rounding.
+        let ramp = json!([
+            "interpolate",
+            ["exponential", 1],
+            ["zoom"],
+            0,
+            0.1,
+            1,
+            0.2,
+            2,
+            0.3
+        ]);
+        assert_eq!(
+            simplify_ramp(ramp, 0.0),
+            Some(json!([
+                "interpolate",
+                ["exponential", 1],
+                ["zoom"],
+                0,
+                0.1,
+                2,
+                0.3
+            ]))
+        );
+
+        // Other curves and non-numeric outputs keep their stops.
+        for ramp in [
+            json!([
+                "interpolate",
+                ["exponential", 1.5],
+                ["zoom"],
+                0,
+                0,
+                1,
+                1,
+                2,
+                2
+            ]),
+            json!([
+                "interpolate",
+                ["linear"],
+                ["zoom"],
+                0,
+                "#000",
+                1,
+                "#111",
+                2,
+                "#222"
+            ]),
+        ] {
+            assert_eq!(simplify_ramp(ramp.clone(), 0.0), None, "{ramp}");
+        }
+    }
+
+    #[test]
+    fn collinear_tolerance_bounds_every_dropped_stop() {
+        let ramp = json!([
+            "interpolate",
+            ["linear"],
+            ["zoom"],
+            0,
+            0,
+            1,
+            0.09,
+            2,
+            0,
+            3,
+            -0.135
+        ]);
+        assert_eq!(simplify_ramp(ramp.clone(), 0.0), None);
+        // The stop at 2 is within 0.1 of the line from 0 to 3, but dropping it
+        // too would leave the already dropped stop at 1 0.135 off.
+        assert_eq!(
+            simplify_ramp(ramp.clone(), 0.1),
+            Some(json!([
+                "interpolate",
+                ["linear"],
+                ["zoom"],
+                0,
+                0,
+                2,
+                0,
+                3,
+                -0.135
+            ]))
+        );
+        assert_eq!(
+            simplify_ramp(ramp, 0.2),
+            Some(json!([
+                "interpolate",
+                ["linear"],
+                ["zoom"],
+                0,
+                0,
+                3,
+                -0.135
+            ]))
+        );
+    }
+
+    #[test]
+    fn identity_cubic_bezier_becomes_linear() {
+        let ramp = json!([
+            "interpolate",
+            ["cubic-bezier", 0.3, 0.3, 0.8, 0.8],
+            ["zoom"],
+            0,
+            0,
+            10,
+            5
+        ]);
+        assert_eq!(
+            apply(try_canonicalize_interpolation_curve, ramp),
+            Some(json!(["interpolate", ["linear"], ["zoom"], 0, 0, 10, 5]))
+        );
+        let eased = json!([
+            "interpolate",
+            ["cubic-bezier", 0.4, 0, 0.2, 1],
+            ["zoom"],
+            0,
+            0,
+            10,
+            5
+        ]);
+        assert_eq!(apply(try_canonicalize_interpolation_curve, eased), None);
+    }
+}
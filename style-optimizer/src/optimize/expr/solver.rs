@@ -0,0 +1,777 @@
+//! Solver-backed filter simplification over `all`/`any`/`!`/`case`.
+//!
+//! The pairwise rules in `fold.rs` miss filters whose clauses contradict only
+//! jointly, e.g. `["in", c, "a", "b"]`, `["!=", c, "a"]` and `["!=", c, "b"]`.
+//! Here the whole filter is compiled to a BDD over its atoms and every
+//! candidate simplification (dropping an `all`/`any` operand, replacing a
+//! subtree by `true` or `false`) is kept if the compiled filter is unchanged.
+//!
+//! Atoms comparing the same subject (`["get", p]`, `["zoom"]`,
+//! `["geometry-type"]`, …) against literals share one finite-domain variable:
+//! the subject's values are split into cells (each literal mentioned, the
+//! intervals between numeric thresholds, "any other string", …) so that
+//! `==`, `in`, `has` and range comparisons on it are related.
+//!
+//! Evaluation errors are modelled exactly.  GL JS `<` on a non-number throws,
+//! `all`/`any` stop at the first deciding operand, and `!` propagates the
+//! error, so a formula compiles to two BDDs, "evaluates to true" and
+//! "evaluates to false".  A filter that throws hides the feature, so two
+//! filters are equivalent when their "true" BDDs are.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::util::{bool_literal, extract_json_literal};
+
+type Node = u32;
+const FALSE: Node = 0;
+const TRUE: Node = 1;
+/// Terminals sort after every variable.
+const TERMINAL: u32 = u32::MAX;
+
+/// Give up on filters whose BDD grows past this many nodes.
+const NODE_CAP: usize = 1 << 16;
+/// Give up on filters with more nodes than this.
+const FILTER_SIZE_CAP: usize = 512;
+
+/// A reduced ordered BDD manager.
+struct Bdd {
+    /// `(variable, low, high)`.
+    nodes: Vec<(u32, Node, Node)>,
+    unique: HashMap<(u32, Node, Node), Node>,
+    ite_cache: HashMap<(Node, Node, Node), Node>,
+}
+
+impl Bdd {
+    fn new() -> Self {
+        Self {
+            nodes: vec![(TERMINAL, FALSE, FALSE), (TERMINAL, TRUE, TRUE)],
+            unique: HashMap::new(),
+            ite_cache: HashMap::new(),
+        }
+    }
+
+    fn mk(&mut self, var: u32, low: Node, high: Node) -> Option<Node> {
+        if low == high {
+            return Some(low);
+        }
+        if let Some(&n) = self.unique.get(&(var, low, high)) {
+            return Some(n);
+        }
+        if self.nodes.len() >= NODE_CAP {
+            return None;
+        }
+        let n = Node::try_from(self.nodes.len()).ok()?;
+        self.nodes.push((var, low, high));
+        self.unique.insert((var, low, high), n);
+        Some(n)
+    }
+
+    fn var(&mut self, var: u32) -> Option<Node> {
+        self.mk(var, FALSE, TRUE)
+    }
+
+    fn cofactors(&self, n: Node, var: u32) -> (Node, Node) {
+        let (v, low, high) = self.nodes[n as usize];
+        if v == var { (low, high) } else { (n, n) }
+    }
+
+    /// `if f then g else h`.
+    fn ite(&mut self, f: Node, g: Node, h: Node) -> Option<Node> {
+        match (f, g, h) {
+            (TRUE, g, _) => return Some(g),
+            (FALSE, _, h) => return Some(h),
+            (f, TRUE, FALSE) => return Some(f),
+            (_, g, h) if g == h => return Some(g),
+            _ => {}
+        }
+        if let Some(&n) = self.ite_cache.get(&(f, g, h)) {
+            return Some(n);
+        }
+        let var = [f, g, h]
+            .iter()
+            .map(|&n| self.nodes[n as usize].0)
+            .min()
+            .unwrap_or(TERMINAL);
+        let (f0, f1) = self.cofactors(f, var);
+        let (g0, g1) = self.cofactors(g, var);
+        let (h0, h1) = self.cofactors(h, var);
+        let high = self.ite(f1, g1, h1)?;
+        let low = self.ite(f0, g0, h0)?;
+        let n = self.mk(var, low, high)?;
+        self.ite_cache.insert((f, g, h), n);
+        Some(n)
+    }
+
+    fn and(&mut self, a: Node, b: Node) -> Option<Node> {
+        self.ite(a, b, FALSE)
+    }
+
+    fn or(&mut self, a: Node, b: Node) -> Option<Node> {
+        self.ite(a, TRUE, b)
+    }
+
+    fn not(&mut self, a: Node) -> Option<Node> {
+        self.ite(a, FALSE, TRUE)
+    }
+}
+
+// ── Atoms ────────────────────────────────────────────────────────────────────
+
+/// A predicate the solver understands.
+enum Atom<'a> {
+    /// `[op, subject, literal]`, normalised so the subject is on the left.
+    Compare {
+        op: &'a str,
+        subject: &'a Value,
+        literal: Value,
+    },
+    /// `["in", subject, ["literal", [...]]]`.
+    In {
+        subject: &'a Value,
+        literals: &'a [Value],
+    },
+    /// `["has", p]`.
+    Has(&'a str),
+    /// Anything else, possibly throwing.
+    Opaque,
+}
+
+fn is_subject(v: &Value) -> bool {
+    matches!(v, Value::Array(a) if a.first().and_then(Value::as_str).is_some_and(|op| op != "literal"))
+}
+
+fn is_scalar(v: &Value) -> bool {
+    !v.is_array() && !v.is_object()
+}
+
+fn flip(op: &str) -> &str {
+    match op {
+        "<" => ">",
+        "<=" => ">=",
+        ">" => "<",
+        ">=" => "<=",
+        other => other,
+    }
+}
+
+fn classify(v: &Value) -> Atom<'_> {
+    let Some(arr) = v.as_array() else {
+        return Atom::Opaque;
+    };
+    match (arr.first().and_then(Value::as_str), arr.len()) {
+        (Some(op @ ("==" | "!=" | "<" | "<=" | ">" | ">=")), 3) => {
+            let (op, subject, literal) = if is_subject(&arr[1]) {
+                (op, &arr[1], extract_json_literal(&arr[2]))
+            } else if is_subject(&arr[2]) {
+                (flip(op), &arr[2], extract_json_literal(&arr[1]))
+            } else {
+                return Atom::Opaque;
+            };
+            match literal {
+                Some(literal @ Value::Number(_)) => Atom::Compare {
+                    op,
+                    subject,
+                    literal,
+                },
+                Some(literal) if matches!(op, "==" | "!=") && is_scalar(&literal) => {
+                    Atom::Compare {
+                        op,
+                        subject,
+                        literal,
+                    }
+                }
+                _ => Atom::Opaque,
+            }
+        }
+        (Some("in"), 3) if is_subject(&arr[1]) => match arr[2].as_array().map(Vec::as_slice) {
+            Some([op, Value::Array(literals)])
+                if op == "literal" && literals.iter().all(is_scalar) =>
+            {
+                Atom::In {
+                    subject: &arr[1],
+                    literals,
+                }
+            }
+            _ => Atom::Opaque,
+        },
+        (Some("has"), 2) => arr[1].as_str().map_or(Atom::Opaque, Atom::Has),
+        _ => Atom::Opaque,
+    }
+}
+
+// ── Subject domains ──────────────────────────────────────────────────────────
+
+/// One class of values a subject can take.
+#[derive(Debug, Clone, PartialEq)]
+enum Cell {
+    /// A non-numeric literal mentioned by some atom.
+    Literal(Value),
+    /// Exactly this number.
+    Point(f64),
+    /// Numbers strictly between two thresholds.
+    Between(f64, f64),
+    NaN,
+    /// A string no atom mentions.
+    OtherString,
+    /// `null`, booleans, arrays or objects no atom mentions.
+    Other,
+    /// Evaluating the subject throws.
+    Error,
+}
+
+/// The values a subject can take, before any atom is seen.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum SubjectKind {
+    Zoom,
+    GeometryType,
+    /// `get`/`id`: any value, never throws.
+    Feature,
+    /// Any value, or an error.
+    #[default]
+    Expression,
+}
+
+fn subject_kind(v: &Value) -> SubjectKind {
+    let arr = v.as_array().map_or(&[][..], Vec::as_slice);
+    match arr {
+        [op] if op == "zoom" => SubjectKind::Zoom,
+        [op] if op == "geometry-type" => SubjectKind::GeometryType,
+        [op] if op == "id" => SubjectKind::Feature,
+        [op, Value::String(_)] if op == "get" => SubjectKind::Feature,
+        _ => SubjectKind::Expression,
+    }
+}
+
+#[derive(Default)]
+struct Domain {
+    kind: SubjectKind,
+    literals: Vec<Value>,
+    thresholds: Vec<f64>,
+    cells: Vec<Cell>,
+    first_var: u32,
+    bits: u32,
+}
+
+impl Domain {
+    fn mention(&mut self, literal: &Value) {
+        match literal.as_f64() {
+            Some(n) if literal.is_number() => self.thresholds.push(n),
+            _ if !self.literals.contains(literal) => self.literals.push(literal.clone()),
+            _ => {}
+        }
+    }
+
+    fn build_cells(&mut self) {
+        let kind = self.kind;
+        self.thresholds.sort_by(f64::total_cmp);
+        self.thresholds.dedup();
+        let mut cells = Vec::new();
+        if kind != SubjectKind::GeometryType {
+            let mut below = f64::NEG_INFINITY;
+            for &t in &self.thresholds {
+                cells.push(Cell::Between(below, t));
+                cells.push(Cell::Point(t));
+                below = t;
+            }
+            cells.push(Cell::Between(below, f64::INFINITY));
+        }
+        if kind == SubjectKind::Zoom {
+            self.cells = cells;
+            return;
+        }
+        for literal in &self.literals {
+            if kind != SubjectKind::GeometryType || literal.is_string() {
+                cells.push(Cell::Literal(literal.clone()));
+            }
+        }
+        cells.push(Cell::OtherString);
+        if kind != SubjectKind::GeometryType {
+            cells.push(Cell::Other);
+        }
+        if kind == SubjectKind::Expression {
+            cells.push(Cell::NaN);
+            cells.push(Cell::Error);
+        }
+        self.cells = cells;
+    }
+}
+
+/// A number inside a [`Cell::Between`], to decide comparisons against
+/// thresholds (which are never strictly inside).
+fn representative(low: f64, high: f64) -> f64 {
+    match (low.is_finite(), high.is_finite()) {
+        (true, true) => low / 2.0 + high / 2.0,
+        (true, false) => low + 1.0,
+        (false, true) => high - 1.0,
+        (false, false) => 0.0,
+    }
+}
+
+/// Three-valued truth of an atom on one cell: `None` when it throws.
+#[expect(clippy::float_cmp, reason = "GL JS compares numbers exactly")]
+fn eval_compare(op: &str, cell: &Cell, literal: &Value) -> Option<bool> {
+    let number = match *cell {
+        Cell::Error => return None,
+        Cell::Point(x) => Some(x),
+        Cell::Between(low, high) => Some(representative(low, high)),
+        Cell::NaN => Some(f64::NAN),
+        _ => None,
+    };
+    let equal = || match (number, literal.as_f64()) {
+        (Some(x), Some(n)) if literal.is_number() => x == n,
+        (None, _) if !literal.is_number() => *cell == Cell::Literal(literal.clone()),
+        _ => false,
+    };
+    match op {
+        "==" => Some(equal()),
+        "!=" => Some(!equal()),
+        // Ordering a non-number against a number throws.
+        _ => {
+            let (x, n) = (number?, literal.as_f64()?);
+            Some(match op {
+                "<" => x < n,
+                "<=" => x <= n,
+                ">" => x > n,
+                _ => x >= n,
+            })
+        }
+    }
+}
+
+fn eval_in(cell: &Cell, literals: &[Value]) -> Option<bool> {
+    match cell {
+        // Arrays and objects are not valid `in` needles.
+        Cell::Error | Cell::Other => None,
+        _ => Some(
+            literals
+                .iter()
+                .any(|l| eval_compare("==", cell, l) == Some(true)),
+        ),
+    }
+}
+
+// ── Compilation ──────────────────────────────────────────────────────────────
+
+struct Solver {
+    bdd: Bdd,
+    subjects: HashMap<String, Domain>,
+    /// Variables for `has` atoms and opaque atoms, keyed by serialization.
+    has_vars: HashMap<String, u32>,
+    opaque_vars: HashMap<String, u32>,
+    next_var: u32,
+    /// Facts every feature satisfies: no `has p` without `["get", p]` null.
+    care: Node,
+}
+
+/// `(evaluates to true, evaluates to false)`.
+type Sem = (Node, Node);
+
+impl Solver {
+    fn new(filter: &Value) -> Option<Self> {
+        let mut solver = Self {
+            bdd: Bdd::new(),
+            subjects: HashMap::new(),
+            has_vars: HashMap::new(),
+            opaque_vars: HashMap::new(),
+            next_var: 0,
+            care: TRUE,
+        };
+        solver.collect(filter);
+        let mut keys: Vec<String> = solver.subjects.keys().cloned().collect();
+        keys.sort();
+        for key in keys {
+            let next_var = solver.next_var;
+            let domain = solver.subjects.get_mut(&key)?;
+            domain.build_cells();
+            domain.first_var = next_var;
+            domain.bits = usize::BITS - (domain.cells.len() - 1).leading_zeros();
+            solver.next_var += domain.bits;
+        }
+        for name in solver.has_names() {
+            let var = solver.next_var;
+            solver.next_var += 1;
+            solver.has_vars.insert(name.clone(), var);
+            // Without the property, `get` returns null.
+            let has = solver.bdd.var(var)?;
+            let subject = serde_json::json!(["get", name]);
+            let null = solver.cells_where(&subject, |c| *c == Cell::Literal(Value::Null))?;
+            let fact = solver.bdd.or(has, null)?;
+            solver.care = solver.bdd.and(solver.care, fact)?;
+        }
+        Some(solver)
+    }
+
+    fn has_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.has_vars.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn domain(&mut self, subject: &Value) -> &mut Domain {
+        self.subjects
+            .entry(subject.to_string())
+            .or_insert_with(|| Domain {
+                kind: subject_kind(subject),
+                ..Domain::default()
+            })
+    }
+
+    /// Register the subjects and literals of every atom in `v`.
+    fn collect(&mut self, v: &Value) {
+        if bool_literal(v).is_some() {
+            return;
+        }
+        let Some(arr) = v.as_array() else {
+            return;
+        };
+        if let Some("all" | "any" | "!" | "case") = arr.first().and_then(Value::as_str) {
+            for child in &arr[1..] {
+                self.collect(child);
+            }
+            return;
+        }
+        match classify(v) {
+            Atom::Compare {
+                subject, literal, ..
+            } => self.domain(subject).mention(&literal),
+            Atom::In { subject, literals } => {
+                let domain = self.domain(subject);
+                for literal in literals {
+                    domain.mention(literal);
+                }
+            }
+            Atom::Has(name) => {
+                self.has_vars.insert(name.to_string(), 0);
+                self.domain(&serde_json::json!(["get", name]))
+                    .mention(&Value::Null);
+            }
+            Atom::Opaque => {}
+        }
+    }
+
+    /// The set of assignments where `subject` lies in a cell satisfying `f`.
+    fn cells_where(&mut self, subject: &Value, f: impl Fn(&Cell) -> bool) -> Option<Node> {
+        let domain = self.subjects.get(&subject.to_string())?;
+        let (first_var, bits) = (domain.first_var, domain.bits);
+        let last = domain.cells.len() - 1;
+        let wanted: Vec<bool> = domain.cells.iter().map(f).collect();
+        let mut set = FALSE;
+        // Codes past the last cell stand for the last cell.
+        for code in 0..1usize << bits {
+            if !wanted[code.min(last)] {
+                continue;
+            }
+            let mut minterm = TRUE;
+            for bit in 0..bits {
+                let var = self.bdd.var(first_var + bit)?;
+                let literal = if (code >> bit) & 1 == 1 {
+                    var
+                } else {
+                    self.bdd.not(var)?
+                };
+                minterm = self.bdd.and(minterm, literal)?;
+            }
+            set = self.bdd.or(set, minterm)?;
+        }
+        Some(set)
+    }
+
+    fn three_valued(
+        &mut self,
+        subject: &Value,
+        eval: impl Fn(&Cell) -> Option<bool>,
+    ) -> Option<Sem> {
+        let t = self.cells_where(subject, |c| eval(c) == Some(true))?;
+        let f = self.cells_where(subject, |c| eval(c) == Some(false))?;
+        Some((t, f))
+    }
+
+    fn atom(&mut self, v: &Value) -> Option<Sem> {
+        match classify(v) {
+            Atom::Compare {
+                op,
+                subject,
+                literal,
+            } => self.three_valued(subject, |c| eval_compare(op, c, &literal)),
+            Atom::In { subject, literals } => self.three_valued(subject, |c| eval_in(c, literals)),
+            Atom::Has(name) => {
+                let has = self.bdd.var(*self.has_vars.get(name)?)?;
+                Some((has, self.bdd.not(has)?))
+            }
+            Atom::Opaque => {
+                // Two variables: its value, and whether it throws.
+                let key = v.to_string();
+                let next_var = &mut self.next_var;
+                let var = *self.opaque_vars.entry(key).or_insert_with(|| {
+                    *next_var += 2;
+                    *next_var - 2
+                });
+                let value = self.bdd.var(var)?;
+                let throws = self.bdd.var(var + 1)?;
+                let ok = self.bdd.not(throws)?;
+                let not_value = self.bdd.not(value)?;
+                Some((self.bdd.and(value, ok)?, self.bdd.and(not_value, ok)?))
+            }
+        }
+    }
+
+    fn compile(&mut self, v: &Value) -> Option<Sem> {
+        if let Some(b) = bool_literal(v) {
+            return Some(if b { (TRUE, FALSE) } else { (FALSE, TRUE) });
+        }
+        let Some(arr) = v.as_array() else {
+            return self.atom(v);
+        };
+        match arr.first().and_then(Value::as_str) {
+            Some("!") if arr.len() == 2 => {
+                let (t, f) = self.compile(&arr[1])?;
+                Some((f, t))
+            }
+            // True if every operand is; false at the first false operand
+            // reached without an error.
+            Some("all") => {
+                let (mut t, mut f) = (TRUE, FALSE);
+                for child in &arr[1..] {
+                    let (ct, cf) = self.compile(child)?;
+                    let stops = self.bdd.and(t, cf)?;
+                    f = self.bdd.or(f, stops)?;
+                    t = self.bdd.and(t, ct)?;
+                }
+                Some((t, f))
+            }
+            Some("any") => {
+                let (mut t, mut f) = (FALSE, TRUE);
+                for child in &arr[1..] {
+                    let (ct, cf) = self.compile(child)?;
+                    let stops = self.bdd.and(f, ct)?;
+                    t = self.bdd.or(t, stops)?;
+                    f = self.bdd.and(f, cf)?;
+                }
+                Some((t, f))
+            }
+            // [case, cond, out, …, fallback]
+            Some("case") if arr.len() >= 2 && arr.len().is_multiple_of(2) => {
+                let (mut t, mut f, mut reached) = (FALSE, FALSE, TRUE);
+                for pair in arr[1..arr.len() - 1].chunks_exact(2) {
+                    let (ct, cf) = self.compile(&pair[0])?;
+                    let (ot, of) = self.compile(&pair[1])?;
+                    let taken = self.bdd.and(reached, ct)?;
+                    let taken_t = self.bdd.and(taken, ot)?;
+                    let taken_f = self.bdd.and(taken, of)?;
+                    t = self.bdd.or(t, taken_t)?;
+                    f = self.bdd.or(f, taken_f)?;
+                    reached = self.bdd.and(reached, cf)?;
+                }
+                let (ft, ff) = self.compile(&arr[arr.len() - 1])?;
+                let fallback_t = self.bdd.and(reached, ft)?;
+                let fallback_f = self.bdd.and(reached, ff)?;
+                Some((self.bdd.or(t, fallback_t)?, self.bdd.or(f, fallback_f)?))
+            }
+            _ => self.atom(v),
+        }
+    }
+
+    /// The features `v` accepts, restricted to the possible ones.
+    fn accepts(&mut self, v: &Value) -> Option<Node> {
+        let (t, _) = self.compile(v)?;
+        self.bdd.and(t, self.care)
+    }
+}
+
+// ── Simplification ───────────────────────────────────────────────────────────
+
+enum Edit {
+    Replace(Vec<usize>, bool),
+    Remove(Vec<usize>, usize),
+}
+
+fn node_count(v: &Value) -> usize {
+    match v {
+        Value::Array(a) => 1 + a.iter().map(node_count).sum::<usize>(),
+        _ => 1,
+    }
+}
+
+fn at_mut<'v>(mut v: &'v mut Value, path: &[usize]) -> Option<&'v mut Value> {
+    for &i in path {
+        v = v.as_array_mut()?.get_mut(i)?;
+    }
+    Some(v)
+}
+
+/// Candidate edits, outermost first.
+///
+/// Operands of `all`/`any` are only ever removed: a literal there would be
+/// folded by rules that assume the other operands cannot throw.
+fn candidates(v: &Value, replaceable: bool, path: &mut Vec<usize>, out: &mut Vec<Edit>) {
+    if bool_literal(v).is_some() {
+        return;
+    }
+    if replaceable {
+        out.push(Edit::Replace(path.clone(), false));
+        out.push(Edit::Replace(path.clone(), true));
+    }
+    let Some(arr) = v.as_array() else {
+        return;
+    };
+    let (children, replaceable): (Vec<usize>, bool) = match arr.first().and_then(Value::as_str) {
+        Some("all" | "any") => {
+            if arr.len() > 2 {
+                out.extend((1..arr.len()).map(|i| Edit::Remove(path.clone(), i)));
+            }
+            ((1..arr.len()).collect(), false)
+        }
+        Some("!") if arr.len() == 2 => (vec![1], true),
+        Some("case") if arr.len() >= 2 && arr.len().is_multiple_of(2) => {
+            ((1..arr.len()).collect(), true)
+        }
+        _ => (Vec::new(), false),
+    };
+    for i in children {
+        path.push(i);
+        candidates(&arr[i], replaceable, path, out);
+        path.pop();
+    }
+}
+
+fn apply(v: &Value, edit: &Edit) -> Option<Value> {
+    let mut out = v.clone();
+    match edit {
+        Edit::Replace(path, b) => {
+            *at_mut(&mut out, path)? = serde_json::json!(["literal", b]);
+        }
+        Edit::Remove(path, i) => {
+            at_mut(&mut out, path)?.as_array_mut()?.remove(*i);
+        }
+    }
+    Some(out)
+}
+
+/// Simplify the filter `v` as far as equivalence allows: drop operands of
+/// `all`/`any` that do not change which features pass, and replace subtrees
+/// whose value is decided by the rest of the filter with literals.  Returns
+/// `true` if `v` changed.
+pub(super) fn simplify_filter(v: &mut Value) -> bool {
+    if bool_literal(v).is_some() || !v.is_array() || node_count(v) > FILTER_SIZE_CAP {
+        return false;
+    }
+    let Some(mut solver) = Solver::new(v) else {
+        return false;
+    };
+    let Some(target) = solver.accepts(v) else {
+        return false;
+    };
+    let mut changed = false;
+    'restart: loop {
+        let mut edits = Vec::new();
+        candidates(v, true, &mut Vec::new(), &mut edits);
+        for edit in &edits {
+            let Some(candidate) = apply(v, edit) else {
+                continue;
+            };
+            if solver.accepts(&candidate) == Some(target) {
+                *v = candidate;
+                changed = true;
+                continue 'restart;
+            }
+        }
+        return changed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn simplified(mut v: Value) -> Value {
+        simplify_filter(&mut v);
+        v
+    }
+
+    #[test]
+    fn finds_joint_contradictions() {
+        let class = json!(["get", "class"]);
+        assert_eq!(
+            simplified(json!([
+                "all",
+                ["in", class, ["literal", ["river", "canal"]]],
+                ["!=", class, "river"],
+                ["!=", class, "canal"]
+            ])),
+            json!(["literal", false])
+        );
+        let rank = json!(["get", "rank"]);
+        assert_eq!(
+            simplified(json!([
+                "all",
+                [">=", rank, 5],
+                ["any", ["<", rank, 3], ["==", class, "city"]],
+                ["!=", class, "city"]
+            ])),
+            json!(["literal", false])
+        );
+    }
+
+    #[test]
+    fn drops_subsumed_conjuncts_and_redundant_disjuncts() {
+        let class = json!(["get", "class"]);
+        assert_eq!(
+            simplified(json!([
+                "all",
+                ["==", class, "river"],
+                ["in", class, ["literal", ["river", "canal"]]],
+                ["has", "class"]
+            ])),
+            json!(["all", ["==", class, "river"]])
+        );
+        let rank = json!(["get", "rank"]);
+        assert_eq!(
+            simplified(json!(["any", ["<", rank, 5], ["<", rank, 3]])),
+            json!(["any", ["<", rank, 5]])
+        );
+        assert_eq!(
+            simplified(json!([
+                "any",
+                ["==", ["geometry-type"], "Polygon"],
+                [
+                    "all",
+                    ["==", ["geometry-type"], "Polygon"],
+                    ["get", "water"]
+                ]
+            ])),
+            json!(["any", ["==", ["geometry-type"], "Polygon"]])
+        );
+    }
+
+    #[test]
+    fn finds_tautologies_through_negation_and_case() {
+        assert_eq!(
+            simplified(json!(["any", ["has", "name"], ["!", ["has", "name"]]])),
+            json!(["literal", true])
+        );
+        assert_eq!(
+            simplified(json!([
+                "case",
+                ["has", "name"],
+                ["!", ["has", "name"]],
+                ["==", ["get", "name"], "x"]
+            ])),
+            json!(["literal", false])
+        );
+    }
+
+    #[test]
+    fn respects_evaluation_errors() {
+        // Without a numeric `rank` both comparisons throw.
+        let rank = json!(["get", "rank"]);
+        let filter = json!(["any", ["<", rank, 5], [">=", rank, 5]]);
+        assert_eq!(simplified(filter.clone()), filter);
+        // `!` of a throwing comparison is not its complement.
+        let filter = json!(["!", ["all", ["<", rank, 5], [">=", rank, 2]]]);
+        assert_eq!(simplified(filter.clone()), filter);
+    }
+}
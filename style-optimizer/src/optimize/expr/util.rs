@@ -51,26 +51,14 @@ pub(super) fn compare_json_values(x: &Value, y: &Value) -> Option<std::cmp::Orde
             let bf = b.as_f64()?;
             af.partial_cmp(&bf)
         }
-        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        // JS orders strings by UTF-16 code unit, not by code point.
+        (Value::String(a), Value::String(b)) => Some(a.encode_utf16().cmp(b.encode_utf16())),
         (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
         (Value::Null, Value::Null) => Some(std::cmp::Ordering::Equal),
         _ => None,
     }
 }
 
-pub(super) fn finite(n: f64) -> Option<Value> {
-    if n.is_finite() {
-        serde_json::Number::from_f64(n).map(Value::Number)
-    } else {
-        None
-    }
-}
-
-#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-pub(super) fn clamp_channel(v: f64) -> u8 {
-    v.round().clamp(0.0, 255.0) as u8
-}
-
 pub(crate) fn is_get_expr(v: &Value) -> bool {
     matches!(v, Value::Array(a) if a.len() == 2 && a[0].as_str() == Some("get"))
 }
@@ -0,0 +1,93 @@
+//! Layers whose ids must survive optimization (`--keep-layer`,
+//! `--keep-layer-regex`).
+//!
+//! Client code addresses layers by id (`setPaintProperty`, `setFilter`,
+//! `queryRenderedFeatures`), so a kept layer is never removed by
+//! `dead_elimination` or `cleanup` and never merged by `layer_merge`.
+
+use std::collections::HashSet;
+
+use regex::Regex;
+
+/// A set of layer ids, given literally or as regular expressions.
+#[derive(Clone, Debug, Default)]
+pub struct KeepLayers {
+    ids: HashSet<String>,
+    patterns: Vec<Regex>,
+}
+
+impl KeepLayers {
+    /// Build a set from literal `ids` and `patterns`.  Patterns are unanchored,
+    /// so use `^…$` to match whole ids.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a pattern is not a valid regular expression.
+    pub fn new<I, P>(ids: I, patterns: P) -> Result<Self, regex::Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+        P: IntoIterator,
+        P::Item: AsRef<str>,
+    {
+        Ok(Self {
+            ids: ids.into_iter().map(Into::into).collect(),
+            patterns: patterns
+                .into_iter()
+                .map(|p| Regex::new(p.as_ref()))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    #[must_use]
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id) || self.patterns.iter().any(|p| p.is_match(id))
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty() && self.patterns.is_empty()
+    }
+
+    /// This set plus `ids`.
+    #[must_use]
+    pub(crate) fn with_ids<'a>(&self, ids: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut keep = self.clone();
+        keep.ids.extend(ids.into_iter().map(str::to_owned));
+        keep
+    }
+}
+
+impl PartialEq for KeepLayers {
+    fn eq(&self, other: &Self) -> bool {
+        self.ids == other.ids
+            && self
+                .patterns
+                .iter()
+                .map(Regex::as_str)
+                .eq(other.patterns.iter().map(Regex::as_str))
+    }
+}
+
+impl Eq for KeepLayers {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_ids_and_patterns() {
+        let keep = KeepLayers::new(["water"], ["^road-", "label$"]).unwrap();
+        assert!(keep.contains("water"));
+        assert!(keep.contains("road-primary"));
+        assert!(keep.contains("poi-label"));
+        assert!(!keep.contains("water-shadow"));
+        assert!(!keep.contains("bridge-road-primary"));
+        assert!(KeepLayers::default().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(KeepLayers::new(Vec::<String>::new(), ["("]).is_err());
+    }
+}
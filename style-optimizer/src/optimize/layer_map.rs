@@ -0,0 +1,235 @@
+//! Layer id source map (`--layer-map`): which optimized layer now renders each
+//! layer of the input style.
+//!
+//! The map starts as the identity over the input layer ids.  Passes that fold
+//! one layer into another report it through
+//! [`PassContext::record_merge`](super::PassContext::record_merge), and passes
+//! that split one layer into several through
+//! [`PassContext::record_split`](super::PassContext::record_split); layers that
+//! disappear without such a report are recorded as removed by the pass that
+//! dropped them.
+
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+/// Fate of every input layer, keyed by original id in input order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LayerMap {
+    pub layers: IndexMap<String, LayerFate>,
+}
+
+/// Where an input layer ended up.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LayerFate {
+    /// Optimized layers drawing this layer's features; empty once removed.
+    pub rendered_by: Vec<LayerTarget>,
+    /// Pass that removed the layer, when nothing renders it any more.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removed_by: Option<&'static str>,
+    /// A later layer drawing the same features again, which could not be
+    /// removed because the later copy is not an opaque fill.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+}
+
+/// An optimized layer, plus the filter selecting the original layer's features
+/// within it when it was merged with others.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LayerTarget {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Value>,
+}
+
+impl LayerMap {
+    /// The identity map over the layer ids of `style`.
+    #[must_use]
+    pub fn from_style(style: &Value) -> Self {
+        let layers = layer_ids(style)
+            .map(|id| {
+                let fate = LayerFate {
+                    rendered_by: vec![LayerTarget {
+                        id: id.to_owned(),
+                        filter: None,
+                    }],
+                    removed_by: None,
+                    duplicate_of: None,
+                };
+                (id.to_owned(), fate)
+            })
+            .collect();
+        Self { layers }
+    }
+
+    /// The layers in `members` are now drawn by `into`, each restricted to the
+    /// features matching its filter.  Layers previously folded into a member
+    /// follow it, with both filters combined.  `into` may itself be a member.
+    pub fn merged(&mut self, into: &str, members: &[(String, Value)]) {
+        for target in self.layers.values_mut().flat_map(|f| &mut f.rendered_by) {
+            let Some((_, filter)) = members.iter().find(|(id, _)| *id == target.id) else {
+                continue;
+            };
+            into.clone_into(&mut target.id);
+            target.filter = Some(match target.filter.take() {
+                Some(inner) => json!(["all", filter, inner]),
+                None => filter.clone(),
+            });
+        }
+    }
+
+    /// Layer `from` is now drawn by the layers in `parts`, each restricted to
+    /// the features matching its filter.  Layers previously folded into `from`
+    /// follow every part, with both filters combined.
+    pub fn split(&mut self, from: &str, parts: &[(String, Value)]) {
+        for fate in self.layers.values_mut() {
+            fate.rendered_by = std::mem::take(&mut fate.rendered_by)
+                .into_iter()
+                .flat_map(|target| {
+                    if target.id != from {
+                        return vec![target];
+                    }
+                    parts
+                        .iter()
+                        .map(|(id, filter)| LayerTarget {
+                            id: id.clone(),
+                            filter: Some(match &target.filter {
+                                Some(inner) => json!(["all", filter, inner]),
+                                None => filter.clone(),
+                            }),
+                        })
+                        .collect()
+                })
+                .collect();
+        }
+    }
+
+    /// Layer `id` draws the same features as the later layer `of`.
+    pub fn duplicated(&mut self, id: &str, of: &str) {
+        for fate in self.layers.values_mut() {
+            if fate.rendered_by.iter().any(|t| t.id == id) {
+                fate.duplicate_of = Some(of.to_owned());
+            }
+        }
+    }
+
+    /// Layer `id` was dropped by `pass`; every input layer it rendered is gone.
+    pub fn removed(&mut self, id: &str, pass: &'static str) {
+        for fate in self.layers.values_mut() {
+            let before = fate.rendered_by.len();
+            fate.rendered_by.retain(|t| t.id != id);
+            if fate.rendered_by.is_empty() && before > 0 {
+                fate.removed_by = Some(pass);
+            }
+        }
+    }
+}
+
+/// Ids of the layers in a style JSON, in order.
+pub(super) fn layer_ids(style: &Value) -> impl Iterator<Item = &str> {
+    style
+        .get("layers")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|l| l.get("id").and_then(Value::as_str))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style() -> Value {
+        json!({"layers": [{"id": "park"}, {"id": "wood"}, {"id": "water"}]})
+    }
+
+    #[test]
+    fn starts_as_identity() {
+        let map = LayerMap::from_style(&style());
+        insta::assert_yaml_snapshot!(map, @r"
+        layers:
+          park:
+            rendered_by:
+              - id: park
+          wood:
+            rendered_by:
+              - id: wood
+          water:
+            rendered_by:
+              - id: water
+        ");
+    }
+
+    #[test]
+    fn merges_compose_filters_and_removals_follow_targets() {
+        let mut map = LayerMap::from_style(&style());
+        let class = |c: &str| json!(["==", ["get", "class"], c]);
+        map.merged(
+            "park",
+            &[
+                ("park".into(), class("park")),
+                ("wood".into(), class("wood")),
+            ],
+        );
+        map.merged("landuse", &[("park".into(), json!(["has", "class"]))]);
+        assert_eq!(
+            map.layers["wood"].rendered_by,
+            [LayerTarget {
+                id: "landuse".into(),
+                filter: Some(json!([
+                    "all",
+                    ["has", "class"],
+                    ["==", ["get", "class"], "wood"]
+                ])),
+            }]
+        );
+        assert_eq!(map.layers["park"].rendered_by[0].id, "landuse");
+
+        map.removed("landuse", "cleanup");
+        map.removed("landuse", "cleanup");
+        assert!(map.layers["wood"].rendered_by.is_empty());
+        assert_eq!(map.layers["park"].removed_by, Some("cleanup"));
+        assert_eq!(map.layers["water"].removed_by, None);
+    }
+
+    #[test]
+    fn split_parts_inherit_merged_filters() {
+        let mut map = LayerMap::from_style(&style());
+        map.merged(
+            "park",
+            &[
+                ("park".into(), json!(["has", "park"])),
+                ("wood".into(), json!(["has", "wood"])),
+            ],
+        );
+        let class = |c: &str| json!(["==", ["get", "class"], c]);
+        map.split(
+            "park",
+            &[("park-a".into(), class("a")), ("park-b".into(), class("b"))],
+        );
+        assert_eq!(
+            map.layers["wood"].rendered_by,
+            [
+                LayerTarget {
+                    id: "park-a".into(),
+                    filter: Some(json!(["all", class("a"), ["has", "wood"]])),
+                },
+                LayerTarget {
+                    id: "park-b".into(),
+                    filter: Some(json!(["all", class("b"), ["has", "wood"]])),
+                },
+            ]
+        );
+        assert_eq!(map.layers["water"].rendered_by[0].id, "water");
+    }
+
+    #[test]
+    fn duplicates_are_reported_on_every_layer_they_render() {
+        let mut map = LayerMap::from_style(&style());
+        map.merged("park", &[("wood".into(), json!(["has", "wood"]))]);
+        map.duplicated("park", "water");
+        assert_eq!(map.layers["park"].duplicate_of.as_deref(), Some("water"));
+        assert_eq!(map.layers["wood"].duplicate_of.as_deref(), Some("water"));
+        assert_eq!(map.layers["water"].duplicate_of, None);
+    }
+}
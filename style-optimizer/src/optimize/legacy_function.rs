@@ -0,0 +1,651 @@
+//! Convert legacy `MapLibre` function objects to expression syntax.
+//!
+//! Before expressions, data- and zoom-driven paint/layout values were written
+//! as function objects (`{"base": 1.4, "stops": [[12, 1], [20, 8]]}`,
+//! `{"property": "class", "type": "categorical", "stops": [...]}`).  Every
+//! expression pass skips them, so ramp pruning, constant folding and default
+//! stripping never see those properties.
+//!
+//! This module ports `convertFunction` from the upstream style-spec
+//! (`function/convert.ts`): exponential, interval, categorical and identity
+//! functions become `interpolate`/`step`/`match`/`get`, and
+//! zoom-and-property functions become a zoom curve over per-zoom property
+//! expressions.
+//!
+//! Differences from upstream: functions whose stops would produce an invalid
+//! expression (unsorted or non-numeric inputs, duplicate or non-integer
+//! categorical labels, no usable fallback) are left untouched instead of being
+//! converted to something the renderer rejects, and a single-stop `step` gets
+//! a no-op stop that repeats its output rather than upstream's literal `0`.
+
+use maplibre_style_spec::mir::MirLayerField;
+use maplibre_style_spec::mir::types::MirType;
+use serde_json::{Map, Value, json};
+
+use super::walk::{PropertyContext, StyleVisitor};
+
+// ── Visitor ───────────────────────────────────────────────────────────────────
+
+pub(crate) struct LegacyFunctionVisitor;
+
+impl StyleVisitor for LegacyFunctionVisitor {
+    fn visit_property(&mut self, ctx: &PropertyContext<'_>, value: &mut Value) {
+        if let Some(expr) = convert_function(value, ctx.field) {
+            *value = expr;
+        }
+    }
+}
+
+// ── Implementation ─────────────────────────────────────────────────────────────
+
+/// Parameters of a legacy function object.
+struct Function<'a> {
+    kind: Option<&'a str>,
+    property: Option<&'a str>,
+    base: f64,
+    color_space: Option<&'a str>,
+    default: Option<&'a Value>,
+}
+
+impl<'a> Function<'a> {
+    fn parse(obj: &'a Map<String, Value>) -> Option<Self> {
+        Some(Self {
+            kind: match obj.get("type") {
+                Some(t) => Some(t.as_str()?),
+                None => None,
+            },
+            property: match obj.get("property") {
+                Some(p) => Some(p.as_str()?),
+                None => None,
+            },
+            base: match obj.get("base") {
+                Some(b) => b.as_f64()?,
+                None => 1.0,
+            },
+            color_space: obj.get("colorSpace").and_then(Value::as_str),
+            default: obj.get("default"),
+        })
+    }
+
+    /// Explicit `type`, else the property's natural curve.
+    fn kind(&self, field: &MirLayerField) -> &'a str {
+        self.kind.unwrap_or_else(|| default_kind(field))
+    }
+
+    fn interpolate_operator(&self) -> &'static str {
+        match self.color_space {
+            Some("hcl") => "interpolate-hcl",
+            Some("lab") => "interpolate-lab",
+            _ => "interpolate",
+        }
+    }
+
+    #[allow(clippy::float_cmp)]
+    fn curve(&self) -> Value {
+        if self.base == 1.0 {
+            json!(["linear"])
+        } else {
+            json!(["exponential", self.base])
+        }
+    }
+
+    fn get(&self) -> Option<Value> {
+        Some(json!(["get", self.property?]))
+    }
+}
+
+fn default_kind(field: &MirLayerField) -> &'static str {
+    if is_interpolated(field) {
+        "exponential"
+    } else {
+        "interval"
+    }
+}
+
+/// Convert a legacy function object to an equivalent expression.
+///
+/// Returns `None` for anything that is not a function object (plain values,
+/// expressions) and for functions that have no valid expression equivalent.
+fn convert_function(value: &Value, field: &MirLayerField) -> Option<Value> {
+    let obj = value.as_object()?;
+    field.expression.as_ref()?;
+    let f = Function::parse(obj)?;
+    let Some(stops) = obj.get("stops") else {
+        if f.kind.is_none_or(|k| k == "identity") {
+            return convert_identity_function(&f, field);
+        }
+        return None;
+    };
+    let stops = stops
+        .as_array()?
+        .iter()
+        .map(|stop| match stop.as_array()?.as_slice() {
+            [input, output] => Some((input, output)),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let (first_input, _) = stops.first()?;
+
+    let zoom_and_feature = first_input.is_object();
+    let feature = zoom_and_feature || f.property.is_some();
+    let tokens = !feature && has_tokens(field);
+    let stops: Vec<(&Value, Value)> = stops
+        .into_iter()
+        .map(|(input, output)| {
+            let output = match output.as_str() {
+                Some(s) if tokens => convert_token_string(s),
+                _ => literal(output),
+            };
+            (input, output)
+        })
+        .collect();
+
+    if zoom_and_feature {
+        convert_zoom_and_property_function(&f, field, &stops)
+    } else if feature {
+        convert_property_function(&f, field, &stops)
+    } else {
+        convert_zoom_function(&f, field, &stops)
+    }
+}
+
+fn convert_identity_function(f: &Function<'_>, field: &MirLayerField) -> Option<Value> {
+    let get = f.get()?;
+    let Some(default) = f.default else {
+        // Expressions coerce string-valued properties; legacy identity
+        // functions did not, so assert the type explicitly.
+        return Some(if field.r#type == MirType::String {
+            json!(["string", get])
+        } else {
+            get
+        });
+    };
+    let assertion = match &field.r#type {
+        MirType::Enum { values } => {
+            return Some(json!(["match", get, values, get, default]));
+        }
+        MirType::Number { .. } => "number",
+        MirType::String => "string",
+        MirType::Boolean => "boolean",
+        MirType::Color => "to-color",
+        _ => return None,
+    };
+    Some(json!([assertion, get, literal(default)]))
+}
+
+fn convert_zoom_function(
+    f: &Function<'_>,
+    field: &MirLayerField,
+    stops: &[(&Value, Value)],
+) -> Option<Value> {
+    let input = json!(["zoom"]);
+    match f.kind(field) {
+        "interval" => step_curve(input, stops),
+        "exponential" if is_interpolated(field) => {
+            let head = vec![json!(f.interpolate_operator()), f.curve(), input];
+            interpolate_curve(head, stops)
+        }
+        _ => None,
+    }
+}
+
+fn convert_property_function(
+    f: &Function<'_>,
+    field: &MirLayerField,
+    stops: &[(&Value, Value)],
+) -> Option<Value> {
+    let get = f.get()?;
+    let number = json!(["number", get]);
+    let curve = match f.kind(field) {
+        "categorical" => return categorical(f, field, &get, stops),
+        "interval" => step_curve(number, stops)?,
+        "exponential" if is_interpolated(field) => {
+            let head = vec![json!(f.interpolate_operator()), f.curve(), number];
+            interpolate_curve(head, stops)?
+        }
+        _ => return None,
+    };
+    Some(match f.default {
+        None => curve,
+        Some(default) => json!([
+            "case",
+            ["==", ["typeof", get], "number"],
+            curve,
+            literal(default)
+        ]),
+    })
+}
+
+/// Zoom-and-property functions: one property function per zoom stop, combined
+/// by a zoom curve whose interpolation follows the property (linear for
+/// interpolatable properties, step otherwise).
+///
+/// As upstream, `base` is ignored and every curve is linear; `colorSpace`
+/// only picks the zoom curve's `interpolate` operator.
+fn convert_zoom_and_property_function(
+    f: &Function<'_>,
+    field: &MirLayerField,
+    stops: &[(&Value, Value)],
+) -> Option<Value> {
+    let inner = Function {
+        base: 1.0,
+        color_space: None,
+        ..*f
+    };
+    let mut zoom_stops: Vec<(Value, Vec<(&Value, Value)>)> = Vec::new();
+    for (input, output) in stops {
+        let zoom = input.get("zoom").filter(|z| z.is_number())?;
+        let value = input.get("value")?;
+        match zoom_stops.iter_mut().find(|(z, _)| z == zoom) {
+            Some((_, group)) => group.push((value, output.clone())),
+            None => zoom_stops.push((zoom.clone(), vec![(value, output.clone())])),
+        }
+    }
+    let outputs = zoom_stops
+        .iter()
+        .map(|(zoom, group)| Some((zoom, convert_property_function(&inner, field, group)?)))
+        .collect::<Option<Vec<_>>>()?;
+
+    let input = json!(["zoom"]);
+    if is_interpolated(field) {
+        let head = vec![json!(f.interpolate_operator()), json!(["linear"]), input];
+        interpolate_curve(head, &outputs)
+    } else {
+        step_curve(input, &outputs)
+    }
+}
+
+fn categorical(
+    f: &Function<'_>,
+    field: &MirLayerField,
+    get: &Value,
+    stops: &[(&Value, Value)],
+) -> Option<Value> {
+    let fallback = fallback(f, field)?;
+    let mut labels: Vec<&Value> = Vec::with_capacity(stops.len());
+    for (input, _) in stops {
+        let valid = match input {
+            Value::String(_) | Value::Bool(_) => true,
+            Value::Number(n) => n.is_i64() || n.is_u64(),
+            _ => false,
+        };
+        if !valid || labels.contains(input) {
+            return None;
+        }
+        labels.push(input);
+    }
+
+    let mut out;
+    if stops[0].0.is_boolean() {
+        // `match` has no boolean labels.
+        out = vec![json!("case")];
+        for (input, output) in stops {
+            out.push(json!(["==", get, input]));
+            out.push(output.clone());
+        }
+    } else {
+        if labels.iter().any(|l| l.is_boolean()) {
+            return None;
+        }
+        out = vec![json!("match"), get.clone()];
+        for (input, output) in stops {
+            out.push((*input).clone());
+            out.push(output.clone());
+        }
+    }
+    out.push(fallback);
+    Some(Value::Array(out))
+}
+
+/// `["step", input, out0, in1, out1, ...]`; the first stop's input is implied.
+#[allow(clippy::float_cmp)]
+fn step_curve(input: Value, stops: &[(&Value, Value)]) -> Option<Value> {
+    let mut out = vec![json!("step"), input];
+    let mut last: Option<f64> = None;
+    for (i, (stop_input, output)) in stops.iter().enumerate() {
+        let x = stop_input.as_f64()?;
+        match last {
+            Some(prev) if x == prev => continue,
+            Some(prev) if x < prev => return None,
+            _ => {}
+        }
+        last = Some(x);
+        if i > 0 {
+            out.push((*stop_input).clone());
+        }
+        out.push(output.clone());
+    }
+    if out.len() == 3 {
+        // Constant function: `step` needs at least one stop.
+        let output = out[2].clone();
+        out.push(json!(0));
+        out.push(output);
+    }
+    Some(Value::Array(out))
+}
+
+/// `head` followed by `in, out` pairs.  Repeated inputs keep their first output,
+/// matching upstream `appendStopPair`.
+#[allow(clippy::float_cmp)]
+fn interpolate_curve(mut out: Vec<Value>, stops: &[(&Value, Value)]) -> Option<Value> {
+    let mut last: Option<f64> = None;
+    for (stop_input, output) in stops {
+        let x = stop_input.as_f64()?;
+        match last {
+            Some(prev) if x == prev => continue,
+            Some(prev) if x < prev => return None,
+            _ => {}
+        }
+        last = Some(x);
+        out.push((*stop_input).clone());
+        out.push(output.clone());
+    }
+    Some(Value::Array(out))
+}
+
+/// Output for unmatched categories: the function's `default`, else the
+/// property's spec default.
+fn fallback(f: &Function<'_>, field: &MirLayerField) -> Option<Value> {
+    match f.default.or(field.default.as_ref()) {
+        Some(v) => Some(literal(v)),
+        // `resolvedImage` properties have no spec default; "" renders nothing.
+        None if matches!(field.r#type, MirType::ResolvedImage { .. }) => Some(json!("")),
+        None => None,
+    }
+}
+
+fn is_interpolated(field: &MirLayerField) -> bool {
+    field.expression.as_ref().is_some_and(|e| e.interpolated)
+}
+
+fn has_tokens(field: &MirLayerField) -> bool {
+    matches!(
+        field.r#type,
+        MirType::Formatted { tokens: true } | MirType::ResolvedImage { tokens: true }
+    )
+}
+
+/// Arrays and objects are data, not expressions, once they move into one.
+pub(super) fn literal(v: &Value) -> Value {
+    if v.is_array() || v.is_object() {
+        json!(["literal", v])
+    } else {
+        v.clone()
+    }
+}
+
+/// `"{name} St"` → `["concat", ["get", "name"], " St"]`.
+///
+/// Mirrors upstream `convertTokenString`: strings without tokens are returned
+/// as-is, and a lone token becomes `["to-string", ["get", name]]`.
+fn convert_token_string(s: &str) -> Value {
+    let mut parts = vec![json!("concat")];
+    let mut pos = 0;
+    let mut search = 0;
+    while let Some(open) = s[search..].find('{').map(|i| search + i) {
+        let Some(len) = s[open + 1..].find(['{', '}']) else {
+            break;
+        };
+        let close = open + 1 + len;
+        if &s[close..=close] == "{" || len == 0 {
+            // Not a token: `{` inside or empty braces.
+            search = close.max(open + 1);
+            continue;
+        }
+        if open > pos {
+            parts.push(json!(&s[pos..open]));
+        }
+        parts.push(json!(["get", &s[open + 1..close]]));
+        pos = close + 1;
+        search = pos;
+    }
+    if parts.len() == 1 {
+        return json!(s);
+    }
+    if pos < s.len() {
+        parts.push(json!(&s[pos..]));
+    } else if parts.len() == 2 {
+        return json!(["to-string", parts[1]]);
+    }
+    Value::Array(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use maplibre_style_spec::mir::MirExpressionCapabilities;
+
+    use super::*;
+
+    fn field(r#type: MirType, default: Option<Value>, interpolated: bool) -> MirLayerField {
+        MirLayerField {
+            r#type,
+            default,
+            doc: String::new(),
+            required: false,
+            expression: Some(MirExpressionCapabilities {
+                interpolated,
+                zoom: true,
+                feature: true,
+                global_state: false,
+            }),
+        }
+    }
+
+    fn number_field() -> MirLayerField {
+        field(
+            MirType::Number {
+                min: Some(0.0),
+                max: None,
+            },
+            Some(json!(1)),
+            true,
+        )
+    }
+
+    #[test]
+    fn exponential_zoom_function_to_interpolate() {
+        let f = json!({"base": 1.4, "stops": [[12, 1], [20, 8]]});
+        assert_eq!(
+            convert_function(&f, &number_field()),
+            Some(json!([
+                "interpolate",
+                ["exponential", 1.4],
+                ["zoom"],
+                12,
+                1,
+                20,
+                8
+            ]))
+        );
+    }
+
+    #[test]
+    fn zoom_function_on_non_interpolated_property_is_step() {
+        let visibility = field(
+            MirType::Enum {
+                values: vec!["visible".into(), "none".into()],
+            },
+            Some(json!("visible")),
+            false,
+        );
+        let f = json!({"stops": [[5, "none"], [10, "visible"]]});
+        assert_eq!(
+            convert_function(&f, &visibility),
+            Some(json!(["step", ["zoom"], "none", 10, "visible"]))
+        );
+    }
+
+    #[test]
+    fn categorical_property_function_to_match() {
+        let color = field(MirType::Color, Some(json!("#000000")), true);
+        let f = json!({
+            "property": "class",
+            "type": "categorical",
+            "stops": [["park", "#0f0"], ["water", "#00f"]],
+            "default": "#ccc"
+        });
+        assert_eq!(
+            convert_function(&f, &color),
+            Some(json!([
+                "match",
+                ["get", "class"],
+                "park",
+                "#0f0",
+                "water",
+                "#00f",
+                "#ccc"
+            ]))
+        );
+    }
+
+    #[test]
+    fn categorical_without_default_falls_back_to_spec_default() {
+        let f = json!({"property": "oneway", "type": "categorical", "stops": [[true, 3]]});
+        assert_eq!(
+            convert_function(&f, &number_field()),
+            Some(json!(["case", ["==", ["get", "oneway"], true], 3, 1]))
+        );
+    }
+
+    #[test]
+    fn interval_property_function_with_default_guards_type() {
+        let f = json!({
+            "property": "rank",
+            "type": "interval",
+            "stops": [[0, 1], [5, 2]],
+            "default": 0
+        });
+        assert_eq!(
+            convert_function(&f, &number_field()),
+            Some(json!([
+                "case",
+                ["==", ["typeof", ["get", "rank"]], "number"],
+                ["step", ["number", ["get", "rank"]], 1, 5, 2],
+                0
+            ]))
+        );
+    }
+
+    #[test]
+    fn identity_function_to_get() {
+        let f = json!({"property": "width", "type": "identity"});
+        assert_eq!(
+            convert_function(&f, &number_field()),
+            Some(json!(["get", "width"]))
+        );
+        let string = field(MirType::String, None, false);
+        assert_eq!(
+            convert_function(&f, &string),
+            Some(json!(["string", ["get", "width"]]))
+        );
+    }
+
+    #[test]
+    fn zoom_and_property_function() {
+        let f = json!({
+            "property": "rank",
+            "stops": [
+                [{"zoom": 0, "value": 0}, 1],
+                [{"zoom": 0, "value": 10}, 2],
+                [{"zoom": 10, "value": 0}, 4],
+                [{"zoom": 10, "value": 10}, 8]
+            ]
+        });
+        assert_eq!(
+            convert_function(&f, &number_field()),
+            Some(json!([
+                "interpolate",
+                ["linear"],
+                ["zoom"],
+                0,
+                [
+                    "interpolate",
+                    ["linear"],
+                    ["number", ["get", "rank"]],
+                    0,
+                    1,
+                    10,
+                    2
+                ],
+                10,
+                [
+                    "interpolate",
+                    ["linear"],
+                    ["number", ["get", "rank"]],
+                    0,
+                    4,
+                    10,
+                    8
+                ]
+            ]))
+        );
+    }
+
+    #[test]
+    fn zoom_and_property_function_ignores_base() {
+        let f = json!({
+            "property": "rank",
+            "base": 2,
+            "stops": [
+                [{"zoom": 0, "value": 0}, 1],
+                [{"zoom": 0, "value": 10}, 2],
+                [{"zoom": 10, "value": 0}, 4],
+                [{"zoom": 10, "value": 10}, 8]
+            ]
+        });
+        assert_eq!(
+            convert_function(&f, &number_field()),
+            Some(json!([
+                "interpolate",
+                ["linear"],
+                ["zoom"],
+                0,
+                [
+                    "interpolate",
+                    ["linear"],
+                    ["number", ["get", "rank"]],
+                    0,
+                    1,
+                    10,
+                    2
+                ],
+                10,
+                [
+                    "interpolate",
+                    ["linear"],
+                    ["number", ["get", "rank"]],
+                    0,
+                    4,
+                    10,
+                    8
+                ]
+            ]))
+        );
+    }
+
+    #[test]
+    fn token_strings_in_zoom_functions() {
+        let text = field(MirType::Formatted { tokens: true }, Some(json!("")), false);
+        let f = json!({"stops": [[0, "{ref}"], [12, "{name} ({ref})"]]});
+        assert_eq!(
+            convert_function(&f, &text),
+            Some(json!([
+                "step",
+                ["zoom"],
+                ["to-string", ["get", "ref"]],
+                12,
+                ["concat", ["get", "name"], " (", ["get", "ref"], ")"]
+            ]))
+        );
+    }
+
+    #[test]
+    fn leaves_invalid_functions_and_expressions_alone() {
+        let unsorted = json!({"stops": [[10, 1], [5, 2]]});
+        assert_eq!(convert_function(&unsorted, &number_field()), None);
+        let fractional = json!({"property": "x", "type": "categorical", "stops": [[1.5, 1]]});
+        assert_eq!(convert_function(&fractional, &number_field()), None);
+        let expr = json!(["get", "x"]);
+        assert_eq!(convert_function(&expr, &number_field()), None);
+    }
+}
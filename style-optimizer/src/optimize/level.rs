@@ -0,0 +1,174 @@
+//! Optimization level presets (`-O0`..`-O3`, `-Os`).
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{Value, json};
+
+use super::OptPasses;
+
+/// Root `metadata` key the applied preset is recorded under.
+pub const OPT_LEVEL_METADATA_KEY: &str = "maplibre-optimizer:level";
+
+/// A named set of passes, trading output size against render-time cost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OptLevel {
+    /// No passes.
+    O0,
+    /// Local expression folding and removal of layers that can never render.
+    O1,
+    /// `O1` plus zoom-bound refinement, default stripping, color minification
+    /// and filter reordering.
+    O2,
+    /// `O2` plus layer merging and every stats-driven refinement.
+    O3,
+    /// `O2` tuned for bytes: adds metadata stripping and layer merging, drops
+    /// the runtime-only filter reordering.
+    Os,
+}
+
+impl OptLevel {
+    #[must_use]
+    pub fn passes(self) -> OptPasses {
+        let o1 = OptPasses {
+            simplify_unary: true,
+            expression_kind: true,
+            constant_fold: true,
+            simplify_expressions: true,
+            prune_zoom_ramps: true,
+            dead_elimination: true,
+            cleanup: true,
+            ..OptPasses::default()
+        };
+        let o2 = OptPasses {
+            metadata_refinement: true,
+            metadata_refinement_paint: true,
+            selectivity_reorder: true,
+            strip_defaults: true,
+            minify_colors: true,
+            source_zoom_tightening: true,
+            ..o1.clone()
+        };
+        match self {
+            Self::O0 => OptPasses::default(),
+            Self::O1 => o1,
+            Self::O2 => o2,
+            Self::O3 => OptPasses {
+                constant_fold_stats: true,
+                dead_elimination_stats: true,
+                metadata_refinement_stats: true,
+                layer_merge: true,
+                common_subexpressions: true,
+                ..o2
+            },
+            Self::Os => OptPasses {
+                selectivity_reorder: false,
+                strip_metadata: true,
+                layer_merge: true,
+                common_subexpressions: true,
+                ..o2
+            },
+        }
+    }
+
+    /// Record this preset in the style's root `metadata`, creating it if needed.
+    pub fn record(self, style: &mut Value) {
+        let Some(root) = style.as_object_mut() else {
+            return;
+        };
+        let metadata = root.entry("metadata").or_insert_with(|| json!({}));
+        if let Some(metadata) = metadata.as_object_mut() {
+            metadata.insert(OPT_LEVEL_METADATA_KEY.to_string(), json!(self.to_string()));
+        }
+    }
+}
+
+impl fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::O0 => "O0",
+            Self::O1 => "O1",
+            Self::O2 => "O2",
+            Self::O3 => "O3",
+            Self::Os => "Os",
+        })
+    }
+}
+
+/// Parses `0`..`3` and `s`, with or without a leading `O` (so both `-O2` and
+/// `level = "O2"` work).
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('O').unwrap_or(s) {
+            "0" => Ok(Self::O0),
+            "1" => Ok(Self::O1),
+            "2" => Ok(Self::O2),
+            "3" => Ok(Self::O3),
+            "s" => Ok(Self::Os),
+            _ => Err(format!(
+                "unknown optimization level `{s}` (expected 0, 1, 2, 3 or s)"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `name` is set in `p` iff enabling it changes nothing.
+    fn has(p: &OptPasses, name: &str) -> bool {
+        let mut q = p.clone();
+        q.enable(name);
+        q == *p
+    }
+
+    fn subset(a: &OptPasses, b: &OptPasses) -> bool {
+        OptPasses::NAMES
+            .iter()
+            .all(|name| !has(a, name) || has(b, name))
+    }
+
+    #[test]
+    fn levels_are_nested() {
+        let levels = [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3];
+        for pair in levels.windows(2) {
+            assert!(subset(&pair[0].passes(), &pair[1].passes()));
+            assert_ne!(pair[0].passes(), pair[1].passes());
+        }
+        assert_eq!(OptLevel::O0.passes(), OptPasses::default());
+    }
+
+    #[test]
+    fn size_profile_strips_but_does_not_reorder() {
+        let os = OptLevel::Os.passes();
+        assert!(os.strip_metadata && os.strip_defaults && os.minify_colors);
+        assert!(!os.selectivity_reorder);
+        assert!(!OptLevel::O3.passes().strip_metadata);
+    }
+
+    #[test]
+    fn parses_with_and_without_prefix() {
+        assert_eq!("2".parse(), Ok(OptLevel::O2));
+        assert_eq!("Os".parse(), Ok(OptLevel::Os));
+        assert!("4".parse::<OptLevel>().is_err());
+        for level in [OptLevel::O0, OptLevel::O3, OptLevel::Os] {
+            assert_eq!(level.to_string().parse(), Ok(level));
+        }
+    }
+
+    #[test]
+    fn records_level_in_root_metadata() {
+        let mut style = json!({"version": 8, "metadata": {"editor": "x"}});
+        OptLevel::O3.record(&mut style);
+        assert_eq!(
+            style["metadata"],
+            json!({"editor": "x", "maplibre-optimizer:level": "O3"})
+        );
+        let mut bare = json!({"version": 8});
+        OptLevel::Os.record(&mut bare);
+        assert_eq!(bare["metadata"][OPT_LEVEL_METADATA_KEY], "Os");
+    }
+}
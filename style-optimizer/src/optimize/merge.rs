@@ -1,15 +1,91 @@
-//! Layer merging: collapse adjacent same-type/source/source-layer layers into
-//! fewer layers with data-driven `case`/`match` expressions and synthesised sort-keys.
+//! Layer merging: collapse same-type/source/source-layer layers into fewer layers
+//! with data-driven `case`/`match` expressions and synthesised sort-keys.
 //!
 //! **Phase 1** — literal-only properties, identical zoom, no existing sort-key.
 //! **Phase 2** — `match` optimisation when all filters are `["==", ["get", P], L]`.
 //! **Phase 3** — zoom-tolerant merging: layers with different minzoom/maxzoom are
 //!   merged by wrapping each sub-layer's filter contribution with zoom guards.
+//! **Phase 4** — non-adjacent merging: a member may be moved down past the layers
+//!   between it and the group when it provably commutes with each of them.
+//!
+//! With a [`MergeCost`], a layer only joins a group when that raises the group's
+//! estimated net benefit: bytes saved minus weighted per-feature evaluation cost.
 
 use maplibre_style_spec::mir::types::MirType;
 use maplibre_style_spec::mir::{MirPropertySection, MirSpec};
 use serde_json::Value;
 
+use super::MergeCostWeight;
+use super::keep::KeepLayers;
+use crate::complexity::expression_node_count;
+use crate::stats::TileStatistics;
+
+/// A run of layers folded into one by [`layer_merge`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedGroup {
+    /// Id of the merged layer (the id of the group's first layer).
+    pub into: String,
+    /// Each original layer id, with the filter selecting its features in the
+    /// merged layer.
+    pub members: Vec<(String, Value)>,
+}
+
+/// Cost model weighing the bytes a merge saves against the expression nodes it
+/// adds to per-feature evaluation.
+#[derive(Clone, Copy, Debug)]
+pub struct MergeCost<'a> {
+    pub weight: MergeCostWeight,
+    /// Feature counts per source-layer; without them every layer counts as one feature.
+    pub stats: Option<&'a TileStatistics>,
+}
+
+impl MergeCost<'_> {
+    /// Estimated benefit of folding `members` into one layer.
+    #[expect(clippy::cast_precision_loss)]
+    fn net_benefit(&self, members: &[&Value], mir: &MirSpec) -> f64 {
+        let members: Vec<Value> = members.iter().map(|&l| l.clone()).collect();
+        let (merged, _) = build_merged_layer(&members, mir);
+        let json_len = |v: &Value| serde_json::to_vec(v).map_or(0, |b| b.len()) as f64;
+
+        let bytes_saved = members.iter().map(json_len).sum::<f64>() - json_len(&merged);
+        let added_nodes = evaluated_nodes(&merged) as f64
+            - members.iter().map(evaluated_nodes).sum::<usize>() as f64;
+        bytes_saved - self.weight.get() * self.feature_count(&merged) * added_nodes
+    }
+
+    /// Features in the layer's source-layer, scaled up from sampled stats.
+    #[expect(clippy::cast_precision_loss)]
+    fn feature_count(&self, layer: &Value) -> f64 {
+        let count = self.stats.and_then(|stats| {
+            let source = layer.get("source")?.as_str()?;
+            let source_layer = layer.get("source-layer")?.as_str()?;
+            let layer_stats = stats.layer_stats(source, source_layer)?;
+            (stats.sample_rate > 0.0).then(|| layer_stats.total_features as f64 / stats.sample_rate)
+        });
+        count.unwrap_or(1.0)
+    }
+}
+
+/// Expression nodes a layer evaluates per feature: its filter and its
+/// data-driven paint/layout values.  Zoom ramps are evaluated per tile, not
+/// per feature, and are not counted.
+fn evaluated_nodes(layer: &Value) -> usize {
+    let filter = layer.get("filter").map_or(0, expression_node_count);
+    let props: usize = ["paint", "layout"]
+        .into_iter()
+        .filter_map(|section| layer.get(section).and_then(Value::as_object))
+        .flat_map(serde_json::Map::values)
+        .filter(|v| {
+            v.as_array()
+                .and_then(|a| a.first())
+                .is_some_and(Value::is_string)
+                && !super::zoom::is_zoom_ramp(v)
+        })
+        .map(expression_node_count)
+        .sum();
+    filter + props
+}
+
 /// Layer types that support a sort-key layout property.
 fn sort_key_name(layer_type: &str) -> Option<&'static str> {
     match layer_type {
@@ -21,69 +97,146 @@ fn sort_key_name(layer_type: &str) -> Option<&'static str> {
     }
 }
 
-/// Merge adjacent layers that share type, source, source-layer, and zoom range.
+/// Merge layers that share type, source, and source-layer.
 ///
 /// Synthesises `case`/`match` expressions for differing paint/layout properties
-/// and a sort-key to preserve inter-layer draw order.
-pub fn layer_merge(v: &mut Value, mir: &MirSpec) {
+/// and a sort-key to preserve inter-layer draw order.  The merged layer takes the
+/// place of the group's first layer.  Layers in `keep` are never merged.
+pub fn layer_merge(
+    v: &mut Value,
+    mir: &MirSpec,
+    keep: &KeepLayers,
+    cost: Option<&MergeCost<'_>>,
+) -> Vec<MergedGroup> {
     let Some(layers) = v.get_mut("layers").and_then(Value::as_array_mut) else {
-        return;
+        return Vec::new();
     };
 
-    let groups = find_merge_groups(layers, mir);
+    let groups = find_merge_groups(layers, mir, keep, cost);
     if groups.is_empty() {
-        return;
+        return Vec::new();
+    }
+
+    // Group index for every member; the merged layer is emitted at the first one.
+    let mut group_of = vec![None; layers.len()];
+    for (g, members) in groups.iter().enumerate() {
+        for &i in members {
+            group_of[i] = Some(g);
+        }
     }
 
     let old_layers = std::mem::take(layers);
-    let mut i = 0;
+    let mut merged = Vec::with_capacity(groups.len());
 
-    for &(start, end) in &groups {
-        while i < start {
-            layers.push(old_layers[i].clone());
-            i += 1;
+    for (i, old) in old_layers.iter().enumerate() {
+        let Some(g) = group_of[i] else {
+            layers.push(old.clone());
+            continue;
+        };
+        if groups[g][0] != i {
+            continue;
         }
-        layers.push(build_merged_layer(&old_layers[start..end], mir));
-        i = end;
-    }
-    while i < old_layers.len() {
-        layers.push(old_layers[i].clone());
-        i += 1;
+        let group: Vec<Value> = groups[g].iter().map(|&k| old_layers[k].clone()).collect();
+        let (layer, filters) = build_merged_layer(&group, mir);
+        merged.push(MergedGroup {
+            into: layer_id(&layer).to_owned(),
+            members: group
+                .iter()
+                .map(|l| layer_id(l).to_owned())
+                .zip(filters)
+                .collect(),
+        });
+        layers.push(layer);
     }
+    merged
+}
+
+fn layer_id(layer: &Value) -> &str {
+    layer.get("id").and_then(Value::as_str).unwrap_or_default()
 }
 
 // ── Grouping ─────────────────────────────────────────────────────────────────
 
-/// Returns `(start, end)` pairs of mergeable adjacent layer runs.
-fn find_merge_groups(layers: &[Value], mir: &MirSpec) -> Vec<(usize, usize)> {
+/// Returns the ascending layer indices of each mergeable group.
+///
+/// A group grows greedily from its first layer.  A later layer joins when it
+/// shares the group key, keeps the group mergeable, and commutes with every
+/// non-member layer between the group's first layer and itself, so moving it
+/// down to the merged layer cannot change what is drawn on top of what.  A
+/// kept layer in between never commutes: the map may show it or change its
+/// filter at runtime.  With a `cost` model it must also raise the group's net
+/// benefit.
+fn find_merge_groups(
+    layers: &[Value],
+    mir: &MirSpec,
+    keep: &KeepLayers,
+    cost: Option<&MergeCost<'_>>,
+) -> Vec<Vec<usize>> {
+    let candidate = |l: &Value| !keep.contains(layer_id(l)) && is_merge_candidate(l);
+    let mut grouped = vec![false; layers.len()];
     let mut groups = Vec::new();
-    let mut i = 0;
 
-    while i < layers.len() {
-        if !is_merge_candidate(&layers[i]) {
-            i += 1;
+    for start in 0..layers.len() {
+        if grouped[start] || !candidate(&layers[start]) {
             continue;
         }
-
-        let start = i;
-        i += 1;
-
-        while i < layers.len()
-            && is_merge_candidate(&layers[i])
-            && same_group_key(&layers[start], &layers[i])
-        {
-            i += 1;
+        let layer_type = layers[start]["type"].as_str().expect("validated");
+        let mut members = vec![start];
+        let mut between: Vec<usize> = Vec::new();
+        // Net benefit of the current group; a lone layer is left as is.
+        let mut benefit = 0.0;
+
+        for j in start + 1..layers.len() {
+            let movable = !grouped[j]
+                && candidate(&layers[j])
+                && same_group_key(&layers[start], &layers[j])
+                && between.iter().all(|&b| {
+                    !keep.contains(layer_id(&layers[b])) && layers_commute(&layers[b], &layers[j])
+                });
+            if movable {
+                let mut trial: Vec<&Value> = members.iter().map(|&k| &layers[k]).collect();
+                trial.push(&layers[j]);
+                if differing_props_are_mergeable(&trial, layer_type, mir) {
+                    let gain = cost.map(|c| c.net_benefit(&trial, mir));
+                    if gain.is_none_or(|g| g > benefit) {
+                        benefit = gain.unwrap_or_default();
+                        members.push(j);
+                        continue;
+                    }
+                }
+            }
+            between.push(j);
         }
 
-        let layer_type = layers[start]["type"].as_str().expect("validated");
-        if i - start >= 2 && differing_props_are_mergeable(&layers[start..i], layer_type, mir) {
-            groups.push((start, i));
+        if members.len() >= 2 {
+            for &k in &members {
+                grouped[k] = true;
+            }
+            groups.push(members);
         }
     }
 
     groups
 }
 
+/// Two layers commute when swapping their draw order cannot change a pixel:
+/// their zoom ranges are disjoint, or either one never draws anything.
+fn layers_commute(a: &Value, b: &Value) -> bool {
+    let (a_min, a_max) = layer_zoom_range(a);
+    let (b_min, b_max) = layer_zoom_range(b);
+    a_max <= b_min || b_max <= a_min || draws_nothing(a) || draws_nothing(b)
+}
+
+/// `visibility: none` or a literal `false` filter.
+fn draws_nothing(layer: &Value) -> bool {
+    let hidden = layer
+        .get("layout")
+        .and_then(|l| l.get("visibility"))
+        .and_then(Value::as_str)
+        == Some("none");
+    hidden || layer.get("filter") == Some(&Value::Bool(false))
+}
+
 /// A layer is a merge candidate if it is a typed layer whose type supports
 /// sort-key, has a filter, and has no existing sort-key property.
 fn is_merge_candidate(layer: &Value) -> bool {
@@ -130,12 +283,12 @@ fn has_layout_key(layer: &Value, key: &str) -> bool {
 ///
 /// Properties whose MIR field lacks feature-expression support (camera-only
 /// properties like `line-dasharray`, `*-translate`) must be uniform to merge.
-fn differing_props_are_mergeable(layers: &[Value], layer_type: &str, mir: &MirSpec) -> bool {
+fn differing_props_are_mergeable(layers: &[&Value], layer_type: &str, mir: &MirSpec) -> bool {
     for (section, mir_section) in [
         ("paint", MirPropertySection::Paint),
         ("layout", MirPropertySection::Layout),
     ] {
-        let props = collect_property_names(layers, section);
+        let props = collect_property_names(layers.iter().copied(), section);
         for prop in &props {
             if prop == "visibility" {
                 continue;
@@ -195,7 +348,10 @@ fn is_array_type(ty: &MirType) -> bool {
     )
 }
 
-fn collect_property_names(layers: &[Value], section: &str) -> Vec<String> {
+fn collect_property_names<'a>(
+    layers: impl IntoIterator<Item = &'a Value>,
+    section: &str,
+) -> Vec<String> {
     let mut names = Vec::new();
     for l in layers {
         if let Some(obj) = l.get(section).and_then(Value::as_object) {
@@ -271,7 +427,7 @@ fn is_get_expr(v: &Value) -> bool {
 // ── Zoom range helpers ───────────────────────────────────────────────────────
 
 /// Effective zoom range for a layer (defaults: minzoom=0, maxzoom=24).
-fn layer_zoom_range(layer: &Value) -> (f64, f64) {
+pub(super) fn layer_zoom_range(layer: &Value) -> (f64, f64) {
     let min = layer.get("minzoom").and_then(Value::as_f64).unwrap_or(0.0);
     let max = layer.get("maxzoom").and_then(Value::as_f64).unwrap_or(24.0);
     (min, max)
@@ -318,8 +474,10 @@ fn wrap_filter_with_zoom_guard(
 
 // ── Merged-layer construction ────────────────────────────────────────────────
 
+/// Returns the merged layer and, per input layer, the filter selecting its
+/// features within it.
 #[expect(clippy::too_many_lines)]
-fn build_merged_layer(layers: &[Value], mir: &MirSpec) -> Value {
+fn build_merged_layer(layers: &[Value], mir: &MirSpec) -> (Value, Vec<Value>) {
     let layer_type = layers[0]["type"].as_str().expect("validated in grouping");
     let sort_key_prop = sort_key_name(layer_type).expect("validated in grouping");
     let n = layers.len();
@@ -362,15 +520,17 @@ fn build_merged_layer(layers: &[Value], mir: &MirSpec) -> Value {
     }
 
     // ── Merged filter ────────────────────────────────────────────────────────
+    let member_filters: Vec<Value> = layers
+        .iter()
+        .zip(&zoom_ranges)
+        .filter_map(|(l, &(lo, hi))| {
+            let f = l.get("filter")?;
+            Some(wrap_filter_with_zoom_guard(f, lo, hi, group_min, group_max))
+        })
+        .collect();
     obj.insert(
         "filter".into(),
-        build_merged_filter(
-            layers,
-            match_pat.as_ref(),
-            &zoom_ranges,
-            group_min,
-            group_max,
-        ),
+        build_merged_filter(match_pat.as_ref(), &member_filters),
     );
 
     // ── Paint & layout properties ────────────────────────────────────────────
@@ -380,7 +540,7 @@ fn build_merged_layer(layers: &[Value], mir: &MirSpec) -> Value {
         } else {
             MirPropertySection::Layout
         };
-        let props = collect_property_names(layers, section);
+        let props = collect_property_names(layers.iter(), section);
 
         let section_obj = obj
             .entry(section)
@@ -443,16 +603,10 @@ fn build_merged_layer(layers: &[Value], mir: &MirSpec) -> Value {
         ),
     );
 
-    merged
+    (merged, member_filters)
 }
 
-fn build_merged_filter(
-    layers: &[Value],
-    match_pat: Option<&MatchPattern>,
-    zoom_ranges: &[(f64, f64)],
-    group_min: f64,
-    group_max: f64,
-) -> Value {
+fn build_merged_filter(match_pat: Option<&MatchPattern>, member_filters: &[Value]) -> Value {
     if let Some(pat) = match_pat {
         // ["match", ["get", P], [L0, L1, …], true, false]
         Value::Array(vec![
@@ -467,11 +621,7 @@ fn build_merged_filter(
         ])
     } else {
         let mut args = vec![Value::String("any".into())];
-        for (l, &(lo, hi)) in layers.iter().zip(zoom_ranges) {
-            if let Some(f) = l.get("filter") {
-                args.push(wrap_filter_with_zoom_guard(f, lo, hi, group_min, group_max));
-            }
-        }
+        args.extend_from_slice(member_filters);
         Value::Array(args)
     }
 }
@@ -602,7 +752,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         insta::assert_yaml_snapshot!(v, @r##"
         layers:
           - filter:
@@ -645,6 +795,72 @@ mod tests {
         "##);
     }
 
+    fn landuse(classes: &[&str]) -> Value {
+        let layers: Vec<Value> = classes
+            .iter()
+            .map(|c| {
+                json!({
+                    "id": c,
+                    "type": "fill",
+                    "source": "s",
+                    "source-layer": "landuse",
+                    "filter": ["==", ["get", "class"], c],
+                    "paint": {"fill-color": "green"}
+                })
+            })
+            .collect();
+        json!({"version": 8, "sources": {"s": {"type": "vector"}}, "layers": layers})
+    }
+
+    #[test]
+    fn merge_reports_member_filters() {
+        let mir = mir();
+        let mut v = landuse(&["park", "wood"]);
+        let groups = layer_merge(&mut v, &mir, &KeepLayers::default(), None);
+        assert_eq!(
+            groups,
+            [MergedGroup {
+                into: "park".into(),
+                members: vec![
+                    ("park".into(), json!(["==", ["get", "class"], "park"])),
+                    ("wood".into(), json!(["==", ["get", "class"], "wood"])),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn kept_layers_are_not_merged() {
+        let mir = mir();
+        let mut v = landuse(&["park", "wood", "grass", "scrub"]);
+        let keep = KeepLayers::new(["wood"], Vec::<String>::new()).unwrap();
+        let groups = layer_merge(&mut v, &mir, &keep, None);
+        let ids: Vec<&str> = v["layers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|l| l["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["park", "wood", "grass"]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].into, "grass");
+    }
+
+    #[test]
+    fn members_do_not_move_across_a_hidden_kept_layer() {
+        let mir = mir();
+        let mut v = landuse(&["park", "wood", "grass"]);
+        v["layers"][1]["layout"] = json!({"visibility": "none"});
+        let keep = KeepLayers::new(["wood"], Vec::<String>::new()).unwrap();
+        let groups = layer_merge(&mut v, &mir, &keep, None);
+        assert!(groups.is_empty());
+        assert_eq!(v["layers"].as_array().unwrap().len(), 3);
+
+        // Without `keep` the hidden layer commutes with everything.
+        let groups = layer_merge(&mut v, &mir, &KeepLayers::default(), None);
+        assert_eq!(groups.len(), 1);
+    }
+
     #[test]
     fn merge_with_case_for_complex_filters() {
         let mir = mir();
@@ -670,7 +886,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         insta::assert_yaml_snapshot!(v, @r##"
         layers:
           - filter:
@@ -753,7 +969,7 @@ mod tests {
             ]
         });
         let original = v.clone();
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         assert_eq!(v, original);
     }
 
@@ -780,7 +996,7 @@ mod tests {
             ]
         });
         let original = v.clone();
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         assert_eq!(v, original);
     }
 
@@ -805,7 +1021,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         // Layers are now merged despite differing zoom.
         assert_eq!(v["layers"].as_array().unwrap().len(), 1);
         let merged = &v["layers"][0];
@@ -841,7 +1057,7 @@ mod tests {
             ]
         });
         let original = v.clone();
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         assert_eq!(v, original);
     }
 
@@ -866,7 +1082,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         // Zoom expressions cannot be nested inside case/match arms, so layers
         // with differing zoom-dependent property values must not be merged.
         assert_eq!(v["layers"].as_array().unwrap().len(), 2);
@@ -893,7 +1109,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         // line-color differs but values are literals, so merge is valid.
         assert_eq!(v["layers"].as_array().unwrap().len(), 1);
         let merged = &v["layers"][0];
@@ -924,7 +1140,7 @@ mod tests {
             ]
         });
         let original = v.clone();
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         assert_eq!(v, original);
     }
 
@@ -955,7 +1171,7 @@ mod tests {
             ]
         });
         let original = v.clone();
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         assert_eq!(v, original);
     }
 
@@ -980,7 +1196,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         // fill-opacity absent from layer b → spec default (1)
         let merged = &v["layers"][0];
         let opacity = &merged["paint"]["fill-opacity"];
@@ -1018,10 +1234,176 @@ mod tests {
             ]
         });
         let original = v.clone();
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         assert_eq!(v, original);
     }
 
+    #[test]
+    fn non_adjacent_layers_merge_across_zoom_disjoint_layer() {
+        let mir = mir();
+        let mut v = json!({
+            "version": 8,
+            "sources": {"s": {"type": "vector"}},
+            "layers": [
+                {
+                    "id": "fill-a", "type": "fill", "source": "s",
+                    "source-layer": "land", "minzoom": 10,
+                    "filter": ["==", ["get", "t"], "a"],
+                    "paint": {"fill-color": "#aaa"}
+                },
+                {
+                    "id": "line-x", "type": "line", "source": "s",
+                    "source-layer": "road", "maxzoom": 10,
+                    "filter": ["==", ["get", "class"], "x"],
+                    "paint": {"line-color": "#000"}
+                },
+                {
+                    "id": "fill-b", "type": "fill", "source": "s",
+                    "source-layer": "land", "minzoom": 10,
+                    "filter": ["==", ["get", "t"], "b"],
+                    "paint": {"fill-color": "#bbb"}
+                }
+            ]
+        });
+        let groups = layer_merge(&mut v, &mir, &KeepLayers::default(), None);
+        let ids: Vec<&str> = v["layers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(layer_id)
+            .collect();
+        assert_eq!(ids, ["fill-a", "line-x"]);
+        assert_eq!(v["layers"][0]["filter"][0], "match");
+        assert_eq!(groups[0].members.len(), 2);
+    }
+
+    #[test]
+    fn merge_skips_layer_that_blocks_commutation() {
+        let mir = mir();
+        let fill = |id: &str, t: &str| {
+            json!({
+                "id": id, "type": "fill", "source": "s", "source-layer": "land",
+                "filter": ["==", ["get", "t"], t],
+                "paint": {"fill-color": "#aaa"}
+            })
+        };
+        let mut v = json!({
+            "version": 8,
+            "sources": {"s": {"type": "vector"}},
+            "layers": [
+                fill("a", "a"),
+                {
+                    "id": "hidden", "type": "line", "source": "s", "source-layer": "road",
+                    "filter": ["==", ["get", "class"], "x"],
+                    "layout": {"visibility": "none"}
+                },
+                fill("b", "b"),
+                {
+                    "id": "road", "type": "line", "source": "s", "source-layer": "road",
+                    "filter": ["==", ["get", "class"], "x"]
+                },
+                fill("c", "c")
+            ]
+        });
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
+        let ids: Vec<&str> = v["layers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(layer_id)
+            .collect();
+        // `b` moves past the hidden layer; `c` cannot move below `road`.
+        assert_eq!(ids, ["a", "hidden", "road", "c"]);
+    }
+
+    fn landcover(classes: &[&str]) -> Value {
+        let layers: Vec<Value> = classes
+            .iter()
+            .map(|c| {
+                json!({
+                    "id": c, "type": "fill", "source": "s", "source-layer": "landcover",
+                    "filter": ["==", ["get", "class"], c],
+                    "paint": {
+                        "fill-color": format!("#{}", &c[..1].repeat(3)),
+                        "fill-opacity": 0.5,
+                        "fill-outline-color": "#123456",
+                        "fill-antialias": false
+                    }
+                })
+            })
+            .collect();
+        json!({"version": 8, "sources": {"s": {"type": "vector"}}, "layers": layers})
+    }
+
+    #[test]
+    fn cost_model_weighs_bytes_against_feature_evaluation() {
+        let mir = mir();
+        let stats = TileStatistics {
+            sources: std::collections::BTreeMap::from([(
+                "s".to_string(),
+                crate::stats::SourceStats {
+                    layers: std::collections::BTreeMap::from([(
+                        "landcover".to_string(),
+                        crate::stats::LayerStats {
+                            total_features: 100,
+                            ..Default::default()
+                        },
+                    )]),
+                },
+            )]),
+            sample_rate: 1.0,
+        };
+        let merged_layer_count = |weight: f64| {
+            let mut v = landcover(&["ab", "cd", "ef"]);
+            let cost = MergeCost {
+                weight: MergeCostWeight::new(weight).unwrap(),
+                stats: Some(&stats),
+            };
+            layer_merge(&mut v, &mir, &KeepLayers::default(), Some(&cost));
+            v["layers"].as_array().unwrap().len()
+        };
+        // Shared paint saves a few hundred bytes; the `match`es add a few
+        // dozen nodes for each of the 100 features.
+        assert_eq!(merged_layer_count(0.01), 1);
+        assert_eq!(merged_layer_count(1.0), 3);
+    }
+
+    #[test]
+    fn evaluated_nodes_skip_constants_and_zoom_ramps() {
+        let layer = json!({
+            "filter": ["==", ["get", "t"], "a"],
+            "paint": {
+                "fill-color": ["match", ["get", "t"], "a", "#aaa", "#000"],
+                "fill-opacity": ["interpolate", ["linear"], ["zoom"], 5, 0, 10, 1],
+                "fill-translate": [1, 2],
+                "fill-outline-color": "#123"
+            }
+        });
+        assert_eq!(evaluated_nodes(&layer), 6 + 8);
+    }
+
+    #[test]
+    fn commutation_needs_disjoint_zoom_or_an_empty_layer() {
+        let layer = |extra: Value| {
+            let mut l = json!({"id": "l", "type": "line", "filter": ["has", "x"]});
+            l.as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            l
+        };
+        let low = layer(json!({"maxzoom": 8}));
+        let high = layer(json!({"minzoom": 8}));
+        let all = layer(json!({}));
+        assert!(layers_commute(&low, &high));
+        assert!(!layers_commute(&low, &all));
+        assert!(!layers_commute(&high, &all));
+        assert!(layers_commute(&all, &layer(json!({"filter": false}))));
+        assert!(layers_commute(
+            &all,
+            &layer(json!({"layout": {"visibility": "none"}}))
+        ));
+    }
+
     #[test]
     fn merge_three_fill_layers_with_match() {
         let mir = mir();
@@ -1049,7 +1431,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         assert_eq!(v["layers"].as_array().unwrap().len(), 1);
         let merged = &v["layers"][0];
         // Uses match (all filters are ["==", ["get", "class"], L]).
@@ -1082,7 +1464,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         // Symbol layers must not be merged — collision detection is per-layer.
         assert_eq!(v["layers"].as_array().unwrap().len(), 2);
     }
@@ -1108,7 +1490,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         let merged = &v["layers"][0];
         // Duplicate labels → case, not match.
         assert_eq!(merged["filter"][0], "any");
@@ -1138,7 +1520,7 @@ mod tests {
                 {"id": "top", "type": "background", "paint": {"background-color": "#000"}}
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
 
         insta::assert_yaml_snapshot!(v, @r##"
         layers:
@@ -1217,7 +1599,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         insta::assert_yaml_snapshot!(v, @r##"
         layers:
           - filter:
@@ -1356,7 +1738,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         assert_eq!(v["layers"].as_array().unwrap().len(), 1);
         let merged = &v["layers"][0];
         // No maxzoom on merged (24 is default, omitted).
@@ -1389,7 +1771,7 @@ mod tests {
                 }
             ]
         });
-        layer_merge(&mut v, &mir);
+        layer_merge(&mut v, &mir, &KeepLayers::default(), None);
         assert_eq!(v["layers"].as_array().unwrap().len(), 1);
         let merged = &v["layers"][0];
         // Same zoom → match pattern still applies.
@@ -11,36 +11,69 @@
 //!
 //! - [`optimize_style_json_value`] / [`optimize_style_json_value_with_stats`]
 //!   run expression passes directly on the JSON (no typed round-trip, so all
-//!   optimizer-produced forms survive).  Structural passes then run in one
-//!   batch: one deserialize → all structural passes → one `sync_typed_to_json`.
+//!   optimizer-produced forms survive).  Consecutive structural passes run in
+//!   one batch: one deserialize → structural passes → one `sync_typed_to_json`.
+//!
+//! Both run the passes of a [`PassRegistry`]: each [`Pass`] declares its
+//! dependencies and the passes it invalidates, and the registry re-runs
+//! invalidated passes until nothing changes.  [`optimize_style`] and the
+//! JSON wrappers report passes that could not run as
+//! [`OptimizeError::Incomplete`].
 
+mod builtin;
 mod cleanup;
 mod color;
+mod cse;
 mod dead;
 mod defaults;
+mod error;
+pub(crate) mod explain;
 pub(crate) mod expr;
+mod keep;
+mod layer_map;
 mod legacy_filter;
+mod legacy_function;
+mod level;
 mod merge;
 mod metadata;
+mod overrides;
+mod pass;
 mod ramp;
 pub(crate) mod selectivity;
 pub(crate) mod source_util;
+mod split;
+mod state;
 mod strip;
 pub(crate) mod walk;
 mod zoom;
 
+use std::cell::RefCell;
+
 use cleanup::cleanup;
 use color::MinifyColorsVisitor;
+use cse::CommonSubexpressionVisitor;
 use dead::dead_elimination;
 use defaults::StripDefaultsVisitor;
-use expr::{NormalizeFoldVisitor, ReorderSelectivityVisitor, TypedNormalizeFoldVisitor};
+pub use error::{OptimizeError, OptimizeOutcome, SkippedPass};
+use explain::Recorder;
+pub use explain::{Change, ChangeLog, PassChanges};
+use expr::{
+    NormalizeFoldVisitor, ReorderSelectivityVisitor, TypedNormalizeFoldVisitor, layer_zoom_ranges,
+};
+pub use keep::KeepLayers;
+pub use layer_map::{LayerFate, LayerMap, LayerTarget};
+use legacy_function::LegacyFunctionVisitor;
+pub use level::{OPT_LEVEL_METADATA_KEY, OptLevel};
 use maplibre_style_spec::mir::MirSpec;
 use maplibre_style_spec::spec::MaplibreStyleSpecification;
 use metadata::metadata_refinement;
-use serde_json::Value;
+pub use overrides::{LayerOverride, SKIP_METADATA_KEY};
+pub use pass::{OptimizeReport, PASS_FIXPOINT_CAP, Pass, PassContext, PassData, PassRegistry};
+use serde_json::{Map, Value};
 use source_util::{
     precompute_vector_layer_info, precompute_vector_layer_info_typed, tighten_source_zoom_bounds,
 };
+use state::GlobalStateVisitor;
 use strip::strip_metadata;
 use walk::{walk_style_mut, walk_typed_filters};
 
@@ -48,7 +81,12 @@ use crate::stats::TileStatistics;
 
 const NORMALIZE_FOLD_FIXPOINT_CAP: usize = 8;
 
-/// Toggleable optimization passes.
+/// Switches for the built-in passes, plus options that tune them.
+///
+/// Fields that name a built-in [`Pass`] enable it; `normalize_fold` is enabled
+/// by any of its rule groups (`simplify_unary`, `expression_kind`,
+/// `constant_fold`, `simplify_expressions`).  The `*_stats` and
+/// `metadata_refinement_paint` fields only refine an enabled pass.
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct OptPasses {
@@ -65,10 +103,102 @@ pub struct OptPasses {
     pub strip_metadata: bool,
     pub strip_defaults: bool,
     pub simplify_expressions: bool,
+    pub prune_zoom_ramps: bool,
     pub minify_colors: bool,
     pub cleanup: bool,
     pub layer_merge: bool,
     pub source_zoom_tightening: bool,
+    pub common_subexpressions: bool,
+    /// Opt-in: split layers by low-cardinality `match` inputs.  Not part of
+    /// [`all`](Self::all), since it undoes `layer_merge`.
+    pub layer_split: bool,
+    /// Layers that must keep their id: never removed or merged.
+    pub keep_layers: KeepLayers,
+    /// Values to fold `["global-state", key]` lookups into; a non-empty map
+    /// enables the `global_state` pass.
+    pub global_state: Map<String, Value>,
+    /// Per-layer pass opt-outs, on top of the layers' own
+    /// [`SKIP_METADATA_KEY`] markers.
+    pub layer_overrides: Vec<LayerOverride>,
+    /// How far `simplify_expressions` lets a dropped `interpolate` stop stray
+    /// from the line through the stops it keeps.
+    pub stop_tolerance: StopTolerance,
+    /// `layer_merge` only merges a group whose estimated bytes saved outweigh
+    /// its added per-feature evaluation cost at this weight.
+    pub merge_cost_weight: MergeCostWeight,
+    /// Most distinct values a `match` input may take for `layer_split` to
+    /// split its layer.
+    pub split_cardinality: SplitCardinality,
+}
+
+/// A pass option validated once, on construction: `new` returns `None` for
+/// values the option does not accept, and `get` the accepted value.
+macro_rules! bounded_option {
+    (
+        $(#[$meta:meta])*
+        $name:ident($ty:ty) = $default:expr;
+        |$value:ident| $accepts:expr, $accepted:literal
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug)]
+        pub struct $name($ty);
+
+        impl $name {
+            #[doc = concat!("`None` unless `", stringify!($value), "` is ", $accepted, ".")]
+            #[must_use]
+            pub fn new($value: $ty) -> Option<Self> {
+                ($accepts).then_some(Self($value))
+            }
+
+            #[must_use]
+            pub const fn get(self) -> $ty {
+                self.0
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self($default)
+            }
+        }
+
+        // Bitwise, so that `Eq` holds for floats too.
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.to_ne_bytes() == other.0.to_ne_bytes()
+            }
+        }
+
+        impl Eq for $name {}
+    };
+}
+
+bounded_option! {
+    /// Largest deviation, in output units, of an interior `interpolate` stop
+    /// from the line through its neighbours for the stop to still be dropped.
+    ///
+    /// Zero (the default) drops only stops that are collinear up to rounding.
+    StopTolerance(f64) = 0.0;
+    |epsilon| epsilon.is_finite() && epsilon >= 0.0, "finite and non-negative"
+}
+
+bounded_option! {
+    /// Bytes of style JSON one extra expression node, evaluated once per tile
+    /// feature, is worth when `layer_merge` weighs a merge.
+    ///
+    /// The default, 0.01, trades a byte of style for a hundred node
+    /// evaluations: a style is fetched and parsed once, while every tile
+    /// evaluates the merged expressions for each of its features.  Zero
+    /// merges whenever the merged layer is smaller than its members.
+    MergeCostWeight(f64) = 0.01;
+    |weight| weight.is_finite() && weight >= 0.0, "finite and non-negative"
+}
+
+bounded_option! {
+    /// Largest number of distinct string values a `match` input may take in the
+    /// tile statistics for `layer_split` to split its layer, one layer per value.
+    SplitCardinality(u64) = 4;
+    |max| max > 0, "at least 1"
 }
 
 impl OptPasses {
@@ -88,12 +218,74 @@ impl OptPasses {
             strip_metadata: true,
             strip_defaults: true,
             simplify_expressions: true,
+            prune_zoom_ramps: true,
             minify_colors: true,
             cleanup: true,
             layer_merge: true,
             source_zoom_tightening: true,
+            common_subexpressions: true,
+            layer_split: false,
+            keep_layers: KeepLayers::default(),
+            global_state: Map::new(),
+            layer_overrides: Vec::new(),
+            stop_tolerance: StopTolerance::default(),
+            merge_cost_weight: MergeCostWeight::default(),
+            split_cardinality: SplitCardinality::default(),
         }
     }
+
+    /// Field names accepted by [`enable`](Self::enable).
+    pub const NAMES: &[&str] = &[
+        "simplify_unary",
+        "expression_kind",
+        "constant_fold",
+        "constant_fold_stats",
+        "dead_elimination",
+        "dead_elimination_stats",
+        "metadata_refinement",
+        "metadata_refinement_paint",
+        "metadata_refinement_stats",
+        "selectivity_reorder",
+        "strip_metadata",
+        "strip_defaults",
+        "simplify_expressions",
+        "prune_zoom_ramps",
+        "minify_colors",
+        "cleanup",
+        "layer_merge",
+        "source_zoom_tightening",
+        "common_subexpressions",
+        "layer_split",
+    ];
+
+    /// Set the field called `name`; returns `false` if there is none.
+    pub fn enable(&mut self, name: &str) -> bool {
+        let field = match name {
+            "simplify_unary" => &mut self.simplify_unary,
+            "expression_kind" => &mut self.expression_kind,
+            "constant_fold" => &mut self.constant_fold,
+            "constant_fold_stats" => &mut self.constant_fold_stats,
+            "dead_elimination" => &mut self.dead_elimination,
+            "dead_elimination_stats" => &mut self.dead_elimination_stats,
+            "metadata_refinement" => &mut self.metadata_refinement,
+            "metadata_refinement_paint" => &mut self.metadata_refinement_paint,
+            "metadata_refinement_stats" => &mut self.metadata_refinement_stats,
+            "selectivity_reorder" => &mut self.selectivity_reorder,
+            "strip_metadata" => &mut self.strip_metadata,
+            "strip_defaults" => &mut self.strip_defaults,
+            "simplify_expressions" => &mut self.simplify_expressions,
+            "prune_zoom_ramps" => &mut self.prune_zoom_ramps,
+            "minify_colors" => &mut self.minify_colors,
+            "cleanup" => &mut self.cleanup,
+            "layer_merge" => &mut self.layer_merge,
+            "source_zoom_tightening" => &mut self.source_zoom_tightening,
+            "common_subexpressions" => &mut self.common_subexpressions,
+            "layer_split" => &mut self.layer_split,
+            _ => return false,
+        };
+        *field = true;
+        true
+    }
 }
 
 fn wants_normalize_fold(passes: &OptPasses) -> bool {
@@ -116,37 +308,95 @@ fn wants_structural_passes(passes: &OptPasses) -> bool {
         || passes.metadata_refinement
         || passes.cleanup
         || passes.layer_merge
+        || passes.layer_split
         || passes.source_zoom_tightening
 }
 
 // ── Public entry points ─────────────────────────────────────────────────────
 
-/// Convenience wrapper (no stats).
-pub fn optimize_style_json_value(v: &mut Value, mir: &MirSpec, passes: &OptPasses) {
-    optimize_style_json_value_with_stats(v, mir, passes, None);
+/// Convenience wrapper (no stats) around
+/// [`optimize_style_json_value_with_stats`].
+///
+/// # Errors
+///
+/// See [`optimize_style_json_value_with_stats`].
+pub fn optimize_style_json_value(
+    v: &mut Value,
+    mir: &MirSpec,
+    passes: &OptPasses,
+) -> Result<OptimizeOutcome, OptimizeError> {
+    optimize_style_json_value_with_stats(v, mir, passes, None)
 }
 
 /// JSON entry point.  Expression passes run directly on the JSON so that all
 /// optimizer-produced forms (e.g. `["literal", 0.5]` from constant-folding a
-/// numeric paint property) are preserved verbatim.  Structural passes then run
-/// in a single deserialize → run → `sync_typed_to_json` cycle.
+/// numeric paint property) are preserved verbatim.  Consecutive structural
+/// passes share one deserialize → run → `sync_typed_to_json` cycle.
+///
+/// Runs the built-in passes enabled by `passes`; use
+/// [`PassRegistry::optimize_json`] to add passes of your own.
+///
+/// # Errors
+///
+/// Returns [`OptimizeError::Incomplete`] if a pass had to skip all or part of
+/// the style; `v` then holds the result of the passes that ran.
 pub fn optimize_style_json_value_with_stats(
     v: &mut Value,
     mir: &MirSpec,
     passes: &OptPasses,
     stats: Option<&TileStatistics>,
-) {
-    if !wants_expression_passes(passes) && !wants_structural_passes(passes) {
-        return;
+) -> Result<OptimizeOutcome, OptimizeError> {
+    PassRegistry::builtin().optimize_json(v, mir, passes, stats, &[])
+}
+
+/// Like [`optimize_style_json_value_with_stats`], but also collects into
+/// `changes` what every pass changed, as JSON pointer paths with before/after
+/// values.  `changes` is filled in even when the run is incomplete.
+///
+/// # Errors
+///
+/// See [`optimize_style_json_value_with_stats`].
+pub fn optimize_style_json_value_explained(
+    v: &mut Value,
+    mir: &MirSpec,
+    passes: &OptPasses,
+    stats: Option<&TileStatistics>,
+    changes: &mut ChangeLog,
+) -> Result<OptimizeOutcome, OptimizeError> {
+    let mut report = OptimizeReport {
+        changes: Some(std::mem::take(changes)),
+        ..OptimizeReport::default()
+    };
+    let result =
+        PassRegistry::builtin().optimize_json_reported(v, mir, passes, stats, &[], &mut report);
+    *changes = report.changes.unwrap_or_default();
+    result
+}
+
+fn optimize_json_recorded(
+    v: &mut Value,
+    cx: &PassContext<'_>,
+    registry: &PassRegistry,
+    extra: &[&str],
+    rec: &mut Recorder<'_>,
+) -> Result<(), OptimizeError> {
+    let schedule = registry.schedule(cx.options, extra)?;
+    if schedule.is_empty() {
+        return Ok(());
     }
 
     // Normalize legacy filter syntax to expressions up front. Expression
     // passes treat bare strings as literals (e.g. `["==", "class", "x"]`
     // folds to `false` because `"class" != "x"`), so styles using legacy
     // filters must be converted before any other pass runs.
-    legacy_filter::convert_legacy_filters_in_style(v);
-
-    run_optimization_pipeline(v, mir, passes, stats);
+    rec.json(
+        "legacy_filter",
+        v,
+        legacy_filter::convert_legacy_filters_in_style,
+    );
+
+    run_optimization_pipeline(v, cx, &schedule, rec);
+    Ok(())
 }
 
 /// Pipeline body shared by both entry points, *without* legacy filter
@@ -158,144 +408,60 @@ pub fn optimize_style_json_value_with_stats(
 /// idempotency.
 fn run_optimization_pipeline(
     v: &mut Value,
-    mir: &MirSpec,
-    passes: &OptPasses,
-    stats: Option<&TileStatistics>,
+    cx: &PassContext<'_>,
+    schedule: &[&dyn Pass],
+    rec: &mut Recorder<'_>,
 ) {
-    if !wants_expression_passes(passes) && !wants_structural_passes(passes) {
-        return;
-    }
-
-    // 1. Expression passes directly on JSON (no typed round-trip).
-    run_json_expression_passes(v, mir, passes, stats);
-
-    // 2. Structural passes — one deserialize + run all + one sync.
-    if wants_structural_passes(passes)
-        && let Ok(mut style) = serde_json::from_value::<MaplibreStyleSpecification>(v.clone())
-    {
-        run_structural_passes(&mut style, passes, stats);
-        sync_typed_to_json(&style, v);
-    }
-
-    // 3. Zoom-bounded optimizations — runs after structural passes have
-    //    tightened zoom bounds (metadata_refinement) and synced them to JSON.
-    if passes.simplify_expressions {
-        ramp::fold_zoom_comparisons(v);
-        ramp::prune_zoom_stops(v);
-    }
-
-    // 3b. Re-fold after structural changes: metadata_refinement may remove
-    //     zoom predicates leaving residual wrappers (e.g. `["all", x]`),
-    //     and ramp pruning may collapse ramps to bare literals.
-    if wants_normalize_fold(passes) && wants_structural_passes(passes) {
-        run_normalize_fold_only(v, mir, passes, stats);
-    }
-
-    // 4. Layer merging — runs on JSON after all other passes so that dead
-    //    layers are gone and expressions are simplified before grouping.
-    if passes.layer_merge {
-        merge::layer_merge(v, mir);
-
-        // 4b. Re-run expression passes on merge-generated expressions:
-        //     layer_merge synthesises new case/match/any expressions that
-        //     benefit from fold/simplify, default stripping, and
-        //     selectivity reordering.
-        run_json_expression_passes(v, mir, passes, stats);
-
-        // 4c. Fold zoom comparisons and prune zoom stops in newly-synthesised properties.
-        if passes.simplify_expressions {
-            ramp::fold_zoom_comparisons(v);
-            ramp::prune_zoom_stops(v);
-        }
-    }
-
-    // 5. Final cleanup. Late JSON-side passes (re-fold, ramp pruning, post-merge
-    //    expression re-run) can fold paint values to literal zero opacity, which
-    //    the earlier cleanup in step 2 (run_structural_passes) couldn't see.
-    //    Without this, the optimizer is not idempotent: a second call would
-    //    drop the now-invisible layer that the first call kept.
-    if passes.cleanup
-        && let Ok(mut style) = serde_json::from_value::<MaplibreStyleSpecification>(v.clone())
-    {
-        cleanup(&mut style);
-        sync_typed_to_json(&style, v);
-    }
+    // Convert legacy function objects (`{"stops": ...}`) to expressions so
+    // every pass can see them.  Unlike legacy filters these are unambiguous
+    // objects, so the typed entry point converts them too.
+    rec.json("legacy_function", v, |v| {
+        walk_style_mut(v, cx.mir, &mut LegacyFunctionVisitor);
+    });
+
+    pass::run_schedule(v, cx, schedule, rec);
 }
 
 /// Typed entry point.  Delegates to the JSON pipeline so that expression-pass
 /// results for all properties (paint, layout, and filter) are preserved.  The
 /// final JSON is deserialized back into a typed struct.
+///
+/// # Errors
+///
+/// Returns [`OptimizeError::Roundtrip`], leaving `style` unchanged, if the
+/// optimized style does not deserialize, and [`OptimizeError::Incomplete`] if
+/// a pass had to skip part of the style.
 pub fn optimize_style(
     style: &mut MaplibreStyleSpecification,
     mir: &MirSpec,
     passes: &OptPasses,
     stats: Option<&TileStatistics>,
-) {
-    let Ok(mut v) = serde_json::to_value(&*style) else {
-        return;
+) -> Result<OptimizeOutcome, OptimizeError> {
+    let registry = PassRegistry::builtin();
+    let schedule = registry.schedule(passes, &[])?;
+    if schedule.is_empty() {
+        return Ok(OptimizeOutcome::default());
+    }
+    let mut v = serde_json::to_value(&*style).map_err(|e| OptimizeError::Roundtrip {
+        path: String::new(),
+        reason: e.to_string(),
+    })?;
+    let outcome = RefCell::default();
+    let cx = PassContext {
+        mir,
+        options: passes,
+        stats,
+        layer_map: None,
+        layer_skips: None,
+        outcome: Some(&outcome),
     };
-    run_optimization_pipeline(&mut v, mir, passes, stats);
-    if let Ok(updated) = serde_json::from_value::<MaplibreStyleSpecification>(v) {
-        *style = updated;
-    }
-}
-
-/// Run structural passes in order.
-fn run_structural_passes(
-    style: &mut MaplibreStyleSpecification,
-    passes: &OptPasses,
-    stats: Option<&TileStatistics>,
-) {
-    if passes.strip_metadata {
-        strip_metadata(style);
-    }
-
-    if passes.dead_elimination {
-        let layer_info = stats.map(|_| precompute_vector_layer_info_typed(style));
-        dead_elimination(style, passes, stats, layer_info.as_deref());
-    }
-
-    if passes.metadata_refinement {
-        let layer_info = stats.map(|_| precompute_vector_layer_info_typed(style));
-        metadata_refinement(style, passes, stats, layer_info.as_deref());
-    }
-
-    if passes.cleanup {
-        cleanup(style);
-    }
-
-    // Typed filter expression passes — applied after structural passes so that
-    // residual filter simplification (e.g. after metadata_refinement removes
-    // zoom predicates) is caught without a JSON round-trip.
-    if wants_expression_passes(passes) {
-        let layer_info = stats.map(|_| precompute_vector_layer_info_typed(style));
-        let mut any_changed = false;
-        for _ in 0..NORMALIZE_FOLD_FIXPOINT_CAP {
-            let mut visitor = TypedNormalizeFoldVisitor {
-                passes,
-                stats,
-                layer_info: layer_info.as_deref(),
-                changed: false,
-            };
-            walk_typed_filters(style, &mut visitor);
-            if !visitor.changed {
-                break;
-            }
-            any_changed = true;
-        }
-        // Re-run cleanup after typed filter simplification may have produced
-        // trivially-true filters (e.g. geometry-type fold on typed layers).
-        if any_changed && passes.cleanup {
-            cleanup(style);
-        }
-    }
-
-    if passes.source_zoom_tightening {
-        tighten_source_zoom_bounds(style);
-    }
+    run_optimization_pipeline(&mut v, &cx, &schedule, &mut Recorder::disabled());
+    *style = error::deserialize_at_path(&v)
+        .map_err(|(path, reason)| OptimizeError::Roundtrip { path, reason })?;
+    outcome.into_inner().into_result()
 }
 
-// ── Sync helpers (JSON entry point only) ────────────────────────────────────
+// ── Sync helpers ────────────────────────────────────
 
 /// Sync typed struct → JSON.  Merges typed scalar fields into the JSON while
 /// preserving paint/layout from the JSON side (which has expression-pass results).
@@ -370,7 +536,7 @@ fn merge_layer_json(original: &Value, typed: &Value) -> Value {
             // Typed system dropped the section entirely but original may have
             // keys the type system doesn't model (e.g. *-transition overrides).
             // Only preserve those; any modeled key was intentionally removed.
-            let preserved: serde_json::Map<String, Value> = orig_obj
+            let preserved: Map<String, Value> = orig_obj
                 .iter()
                 .filter(|(k, _)| k.ends_with("-transition"))
                 .map(|(k, v)| (k.clone(), v.clone()))
@@ -386,58 +552,6 @@ fn merge_layer_json(original: &Value, typed: &Value) -> Value {
     result
 }
 
-/// Run expression-level passes on the JSON value.
-fn run_json_expression_passes(
-    v: &mut Value,
-    mir: &MirSpec,
-    passes: &OptPasses,
-    stats: Option<&TileStatistics>,
-) {
-    if !wants_expression_passes(passes) {
-        return;
-    }
-
-    let layer_info = stats.map(|_| precompute_vector_layer_info(v));
-
-    if wants_normalize_fold(passes) {
-        for _ in 0..NORMALIZE_FOLD_FIXPOINT_CAP {
-            let mut visitor = NormalizeFoldVisitor {
-                mir,
-                passes,
-                stats,
-                layer_info: layer_info.as_deref(),
-                changed: false,
-                filter_constraints: Vec::new(),
-            };
-            walk_style_mut(v, mir, &mut visitor);
-            if !visitor.changed {
-                break;
-            }
-        }
-    }
-
-    if passes.strip_defaults {
-        walk_style_mut(v, mir, &mut StripDefaultsVisitor { mir });
-    }
-
-    if passes.minify_colors {
-        walk_style_mut(v, mir, &mut MinifyColorsVisitor);
-    }
-
-    if passes.selectivity_reorder {
-        let layer_info = stats.map(|_| precompute_vector_layer_info(v));
-        walk_style_mut(
-            v,
-            mir,
-            &mut ReorderSelectivityVisitor {
-                mir,
-                stats,
-                layer_info: layer_info.as_deref(),
-            },
-        );
-    }
-}
-
 /// Run only the normalize/fold fixpoint — a lightweight cleanup pass for
 /// residual wrappers left by structural changes (e.g. `["all", x]` after a
 /// predicate was removed, or a ramp collapsed to a literal).
@@ -448,12 +562,14 @@ fn run_normalize_fold_only(
     stats: Option<&TileStatistics>,
 ) {
     let layer_info = stats.map(|_| precompute_vector_layer_info(v));
+    let zoom_ranges = layer_zoom_ranges(v);
     for _ in 0..NORMALIZE_FOLD_FIXPOINT_CAP {
         let mut visitor = NormalizeFoldVisitor {
             mir,
             passes,
             stats,
             layer_info: layer_info.as_deref(),
+            zoom_ranges: &zoom_ranges,
             changed: false,
             filter_constraints: Vec::new(),
         };
@@ -480,6 +596,19 @@ mod tests {
         load_intermediate_spec_from_v8_path(&path).expect("v8.json")
     }
 
+    #[test]
+    fn pass_options_reject_values_they_do_not_accept() {
+        assert_eq!(StopTolerance::new(-0.5), None);
+        assert_eq!(MergeCostWeight::new(f64::INFINITY), None);
+        assert_eq!(SplitCardinality::new(0), None);
+        assert_eq!(StopTolerance::new(0.0), Some(StopTolerance::default()));
+        assert_eq!(
+            OptPasses::default().merge_cost_weight,
+            MergeCostWeight::new(0.01).unwrap()
+        );
+        assert_eq!(SplitCardinality::default().get(), 4);
+    }
+
     fn passes_unary_only() -> OptPasses {
         OptPasses {
             simplify_unary: true,
@@ -491,7 +620,7 @@ mod tests {
     fn simplify_unary_any_in_filter() {
         let mir = sample_mir();
         let mut v = serde_json::json!({"version":8,"sources":{},"layers":[{"id":"x","type":"fill","filter":["any",["==",1,1]]}]});
-        optimize_style_json_value(&mut v, &mir, &passes_unary_only());
+        optimize_style_json_value(&mut v, &mir, &passes_unary_only()).unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r#"
         filter:
           - "=="
@@ -507,7 +636,7 @@ mod tests {
         let mir = sample_mir();
         let original = serde_json::json!({"version":8,"sources":{},"layers":[{"id":"x","type":"fill","filter":["any",["==",1,1]]}]});
         let mut v = original.clone();
-        optimize_style_json_value(&mut v, &mir, &OptPasses::default());
+        optimize_style_json_value(&mut v, &mir, &OptPasses::default()).unwrap();
         assert_eq!(v, original);
     }
 
@@ -515,7 +644,7 @@ mod tests {
     fn simplify_nested_unary_any() {
         let mir = sample_mir();
         let mut v = serde_json::json!({"version":8,"sources":{},"layers":[{"id":"x","type":"fill","filter":["any",["any",["==",1,1]]]}]});
-        optimize_style_json_value(&mut v, &mir, &passes_unary_only());
+        optimize_style_json_value(&mut v, &mir, &passes_unary_only()).unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r#"
         filter:
           - "=="
@@ -530,7 +659,7 @@ mod tests {
     fn simplify_unary_all() {
         let mir = sample_mir();
         let mut v = serde_json::json!({"version":8,"sources":{},"layers":[{"id":"x","type":"fill","filter":["all",["==",1,1]]}]});
-        optimize_style_json_value(&mut v, &mir, &passes_unary_only());
+        optimize_style_json_value(&mut v, &mir, &passes_unary_only()).unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r#"
         filter:
           - "=="
@@ -546,7 +675,7 @@ mod tests {
         let mir = sample_mir();
         let mut v = serde_json::json!({"version":8,"sources":{},"layers":[{"id":"x","type":"fill","filter":["any"]}]});
         let expected = v.clone();
-        optimize_style_json_value(&mut v, &mir, &passes_unary_only());
+        optimize_style_json_value(&mut v, &mir, &passes_unary_only()).unwrap();
         assert_eq!(v, expected);
     }
 
@@ -554,7 +683,7 @@ mod tests {
     fn simplify_double_not() {
         let mir = sample_mir();
         let mut v = serde_json::json!({"version":8,"sources":{},"layers":[{"id":"x","type":"fill","filter":["!",["!",["has","x"]]]}]});
-        optimize_style_json_value(&mut v, &mir, &passes_unary_only());
+        optimize_style_json_value(&mut v, &mir, &passes_unary_only()).unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         filter:
           - has
@@ -568,7 +697,7 @@ mod tests {
     fn simplify_triple_not() {
         let mir = sample_mir();
         let mut v = serde_json::json!({"version":8,"sources":{},"layers":[{"id":"x","type":"fill","filter":["!",["!",["!",["has","x"]]]]}]});
-        optimize_style_json_value(&mut v, &mir, &passes_unary_only());
+        optimize_style_json_value(&mut v, &mir, &passes_unary_only()).unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r#"
         filter:
           - "!"
@@ -590,7 +719,8 @@ mod tests {
                 expression_kind: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r#"
         filter:
           - "!="
@@ -613,7 +743,8 @@ mod tests {
                 constant_fold: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         filter: false
         id: x
@@ -632,7 +763,8 @@ mod tests {
                 dead_elimination: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v, @r#"
         layers:
           - filter:
@@ -662,7 +794,8 @@ mod tests {
                 metadata_refinement: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         filter: true
         id: x
@@ -682,7 +815,8 @@ mod tests {
                 selectivity_reorder: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r#"
         filter:
           - any
@@ -707,7 +841,8 @@ mod tests {
                 constant_fold: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         filter: 3
         id: x
@@ -726,7 +861,8 @@ mod tests {
                 constant_fold: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         filter: hello world
         id: x
@@ -745,7 +881,8 @@ mod tests {
                 simplify_expressions: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: x
         paint:
@@ -765,7 +902,8 @@ mod tests {
                 simplify_expressions: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: x
         paint:
@@ -785,7 +923,8 @@ mod tests {
                 simplify_expressions: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r##"
         id: x
         paint:
@@ -814,7 +953,8 @@ mod tests {
                 strip_metadata: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v, @"
         layers:
           - id: x
@@ -835,7 +975,8 @@ mod tests {
                 strip_defaults: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r##"
         id: x
         paint:
@@ -855,10 +996,109 @@ mod tests {
                 cleanup: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"], @"[]");
     }
 
+    #[test]
+    fn global_state_specializes_and_cascades() {
+        let mir = sample_mir();
+        let mut v = serde_json::json!({"version":8,"state":{"theme":{"default":"light"}},"sources":{"s":{"type":"vector","url":"x"}},"layers":[
+            {"id":"bg-light","type":"fill","source":"s","source-layer":"l","filter":["==",["global-state","theme"],"light"]},
+            {"id":"road","type":"line","source":"s","source-layer":"l","paint":{
+                "line-color":["match",["global-state","theme"],"dark","#fff","#000"]
+            }}
+        ]});
+        let mut passes = OptPasses {
+            constant_fold: true,
+            dead_elimination: true,
+            ..Default::default()
+        };
+        passes.global_state.insert("theme".into(), "dark".into());
+        optimize_style_json_value(&mut v, &mir, &passes).unwrap();
+        assert_eq!(v["state"]["theme"]["default"], "dark");
+        assert_yaml_snapshot!(v["layers"], @r##"
+        - id: road
+          paint:
+            line-color: "#fff"
+          source: s
+          source-layer: l
+          type: line
+        "##);
+    }
+
+    #[test]
+    fn explain_attributes_layer_removal_to_cleanup() {
+        let mir = sample_mir();
+        let mut v = serde_json::json!({"version":8,"sources":{"s":{"type":"vector","url":"x"}},"layers":[
+            {"id":"hidden","type":"fill","source":"s","source-layer":"l","layout":{"visibility":"none"}},
+            {"id":"kept","type":"fill","source":"s","source-layer":"l"}
+        ]});
+        let mut log = ChangeLog::default();
+        optimize_style_json_value_explained(
+            &mut v,
+            &mir,
+            &OptPasses {
+                cleanup: true,
+                ..Default::default()
+            },
+            None,
+            &mut log,
+        )
+        .unwrap();
+        let removed: Vec<&Change> = log
+            .changes_for("cleanup")
+            .filter(|c| c.after.is_none())
+            .collect();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].path, "/layers/0");
+        assert_eq!(removed[0].before.as_ref().unwrap()["id"], "hidden");
+        assert!(log.passes.iter().all(|p| p.pass == "cleanup"));
+    }
+
+    #[test]
+    fn legacy_functions_converted_before_expression_passes() {
+        let mir = sample_mir();
+        let mut v = serde_json::json!({"version":8,"sources":{"s":{"type":"vector","url":"x"}},"layers":[
+            {"id":"road","type":"line","source":"s","source-layer":"l","paint":{
+                "line-width":{"base":1.4,"stops":[[12,1],[20,8]]},
+                "line-color":{"property":"class","type":"categorical","stops":[["major","#f00"]],"default":"#000"}
+            }}
+        ]});
+        let mut log = ChangeLog::default();
+        optimize_style_json_value_explained(
+            &mut v,
+            &mir,
+            &OptPasses {
+                strip_defaults: true,
+                ..Default::default()
+            },
+            None,
+            &mut log,
+        )
+        .unwrap();
+        assert_eq!(log.changes_for("legacy_function").count(), 2);
+        assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"
+        line-color:
+          - match
+          - - get
+            - class
+          - major
+          - "#f00"
+          - "#000"
+        line-width:
+          - interpolate
+          - - exponential
+            - 1.4
+          - - zoom
+          - 12
+          - 1
+          - 20
+          - 8
+        "##);
+    }
+
     #[test]
     fn cleanup_removes_zero_opacity_background() {
         let mir = sample_mir();
@@ -872,7 +1112,8 @@ mod tests {
                 cleanup: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"], @"[]");
     }
 
@@ -889,7 +1130,8 @@ mod tests {
                 cleanup: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"], @"[]");
     }
 
@@ -897,7 +1139,7 @@ mod tests {
     fn cleanup_removes_zero_opacity_symbol() {
         let mir = sample_mir();
         let mut v = serde_json::json!({"version":8,"sources":{"s":{"type":"vector","url":"x"}},"layers":[
-            {"id":"sym","type":"symbol","source":"s","source-layer":"l","paint":{"icon-opacity":0,"text-opacity":0}}
+            {"id":"sym","type":"symbol","source":"s","source-layer":"l","layout":{"icon-image":"i","text-field":"x"},"paint":{"icon-opacity":0,"text-opacity":0}}
         ]});
         optimize_style_json_value(
             &mut v,
@@ -906,7 +1148,8 @@ mod tests {
                 cleanup: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"], @"[]");
     }
 
@@ -914,7 +1157,7 @@ mod tests {
     fn cleanup_keeps_symbol_with_one_nonzero_opacity() {
         let mir = sample_mir();
         let mut v = serde_json::json!({"version":8,"sources":{"s":{"type":"vector","url":"x"}},"layers":[
-            {"id":"sym","type":"symbol","source":"s","source-layer":"l","paint":{"icon-opacity":0,"text-opacity":1}}
+            {"id":"sym","type":"symbol","source":"s","source-layer":"l","layout":{"icon-image":"i","text-field":"x"},"paint":{"icon-opacity":0,"text-opacity":1}}
         ]});
         optimize_style_json_value(
             &mut v,
@@ -923,9 +1166,13 @@ mod tests {
                 cleanup: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"], @"
         - id: sym
+          layout:
+            icon-image: i
+            text-field: x
           paint:
             icon-opacity: 0
             text-opacity: 1
@@ -935,14 +1182,91 @@ mod tests {
         ");
     }
 
+    #[test]
+    fn cleanup_removes_layers_with_invisible_paint() {
+        let mir = sample_mir();
+        let mut v = serde_json::json!({"version":8,"sources":{"s":{"type":"vector","url":"x"}},"layers":[
+            {"id":"line","type":"line","source":"s","source-layer":"l","paint":{"line-width":0}},
+            {"id":"circle","type":"circle","source":"s","source-layer":"l","paint":{"circle-radius":0}},
+            {"id":"text","type":"symbol","source":"s","source-layer":"l","layout":{"text-field":"x","text-size":0}},
+            {"id":"fill","type":"fill","source":"s","source-layer":"l","paint":{"fill-color":"rgba(0, 0, 0, 0)"}},
+            {"id":"empty","type":"symbol","source":"s","source-layer":"l"}
+        ]});
+        optimize_style_json_value(
+            &mut v,
+            &mir,
+            &OptPasses {
+                cleanup: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_yaml_snapshot!(v["layers"], @"[]");
+    }
+
+    #[test]
+    fn cleanup_keeps_zero_radius_circle_with_stroke() {
+        let mir = sample_mir();
+        let mut v = serde_json::json!({"version":8,"sources":{"s":{"type":"vector","url":"x"}},"layers":[
+            {"id":"c","type":"circle","source":"s","source-layer":"l","paint":{"circle-radius":0,"circle-stroke-width":1}}
+        ]});
+        optimize_style_json_value(
+            &mut v,
+            &mir,
+            &OptPasses {
+                cleanup: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_yaml_snapshot!(v["layers"], @"
+        - id: c
+          paint:
+            circle-radius: 0
+            circle-stroke-width: 1
+          source: s
+          source-layer: l
+          type: circle
+        ");
+    }
+
+    #[test]
+    fn cleanup_raises_minzoom_past_transparent_zooms() {
+        let mir = sample_mir();
+        let mut v = serde_json::json!({"version":8,"sources":{},"layers":[
+            {"id":"x","type":"line","paint":{"line-color":["step",["zoom"],"rgba(0, 0, 0, 0)",10,"#000"]}}
+        ]});
+        optimize_style_json_value(
+            &mut v,
+            &mir,
+            &OptPasses {
+                cleanup: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_yaml_snapshot!(v["layers"][0], @r##"
+        id: x
+        minzoom: 10
+        paint:
+          line-color:
+            - step
+            - - zoom
+            - rgba(0, 0, 0, 0)
+            - 10
+            - "#000"
+        type: line
+        "##);
+    }
+
     #[test]
     fn all_passes_enabled_does_not_panic() {
         let mir = sample_mir();
         let mut v = serde_json::json!({"version":8,"metadata":{"editor":"maputnik"},"sources":{"openmaptiles":{"type":"vector","url":"https://example/tiles.json"}},"layers":[{"id":"background","type":"background","paint":{"background-color":"#f8f4f0","background-opacity":1}},{"id":"water","type":"fill","source":"openmaptiles","source-layer":"water","filter":["all",[">=",["zoom"],5],["==",["geometry-type"],"Polygon"]],"paint":{"fill-color":"#a0c8f0","fill-opacity":1}}]});
         let passes = OptPasses::all();
-        optimize_style_json_value(&mut v, &mir, &passes);
+        optimize_style_json_value(&mut v, &mir, &passes).unwrap();
         let mut again = v.clone();
-        optimize_style_json_value(&mut again, &mir, &passes);
+        optimize_style_json_value(&mut again, &mir, &passes).unwrap();
         assert_eq!(v, again);
     }
 
@@ -957,7 +1281,8 @@ mod tests {
                 metadata_refinement: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r#"
         filter:
           - "=="
@@ -986,7 +1311,8 @@ mod tests {
                 metadata_refinement_paint: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: x
         minzoom: 13.5
@@ -1017,7 +1343,8 @@ mod tests {
                 metadata_refinement_paint: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: x
         minzoom: 15
@@ -1046,7 +1373,8 @@ mod tests {
                 metadata_refinement_paint: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: x
         minzoom: 10
@@ -1085,7 +1413,8 @@ mod tests {
                 metadata_refinement_paint: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: x
         minzoom: 12
@@ -1123,7 +1452,8 @@ mod tests {
                 metadata_refinement_paint: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: x
         minzoom: 16
@@ -1154,7 +1484,8 @@ mod tests {
                 metadata_refinement_paint: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r#"
         id: x
         paint:
@@ -1182,7 +1513,8 @@ mod tests {
                 metadata_refinement_paint: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: x
         paint:
@@ -1207,7 +1539,7 @@ mod tests {
              "paint":{"line-width":["interpolate",["linear"],["zoom"],5,0,10,0,14,2.5]}}
         ]});
         let passes = OptPasses::all();
-        optimize_style_json_value(&mut v, &mir, &passes);
+        optimize_style_json_value(&mut v, &mir, &passes).unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r#"
         filter:
           - "=="
@@ -1274,7 +1606,8 @@ mod tests {
                 ..Default::default()
             },
             Some(&stats),
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"], @"[]");
     }
 
@@ -1304,7 +1637,8 @@ mod tests {
                 ..Default::default()
             },
             Some(&stats),
-        );
+        )
+        .unwrap();
         assert_eq!(v["layers"].as_array().unwrap().len(), 1);
     }
 
@@ -1333,7 +1667,8 @@ mod tests {
                 ..Default::default()
             },
             Some(&stats),
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: water-fill
         maxzoom: 14
@@ -1365,7 +1700,8 @@ mod tests {
                 ..Default::default()
             },
             Some(&stats),
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         filter: false
         id: water-fill
@@ -1405,7 +1741,8 @@ mod tests {
                 ..Default::default()
             },
             Some(&stats),
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         filter: true
         id: w
@@ -1445,7 +1782,8 @@ mod tests {
                 ..Default::default()
             },
             Some(&stats),
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         filter: true
         id: w
@@ -1487,7 +1825,8 @@ mod tests {
                 ..Default::default()
             },
             Some(&stats),
-        );
+        )
+        .unwrap();
         // Should NOT fold — property has two distinct values.
         assert_eq!(v["layers"][0]["filter"], original_filter);
     }
@@ -1521,7 +1860,8 @@ mod tests {
                 ..Default::default()
             },
             Some(&stats),
-        );
+        )
+        .unwrap();
         // Should NOT fold — property is not present on all features.
         assert_eq!(v["layers"][0]["filter"], original_filter);
     }
@@ -1557,7 +1897,8 @@ mod tests {
                 ..Default::default()
             },
             Some(&stats),
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: w
         paint:
@@ -1586,7 +1927,8 @@ mod tests {
                 simplify_expressions: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_eq!(
             v["layers"][0]["paint"]["fill-opacity"],
             serde_json::json!(["+", 1, ["get", "foo"]])
@@ -1607,7 +1949,8 @@ mod tests {
                 ..Default::default()
             },
             None,
-        );
+        )
+        .unwrap();
         assert!(style.metadata.is_none());
         assert!(style.layers[0].common().unwrap().metadata.is_none());
     }
@@ -1618,9 +1961,9 @@ mod tests {
         style: &mut MaplibreStyleSpecification,
     ) -> (Value, Value) {
         let passes = OptPasses::all();
-        optimize_style(style, mir, &passes, None);
+        optimize_style(style, mir, &passes, None).unwrap();
         let first = serde_json::to_value(&*style).unwrap();
-        optimize_style(style, mir, &passes, None);
+        optimize_style(style, mir, &passes, None).unwrap();
         let second = serde_json::to_value(&*style).unwrap();
         (first, second)
     }
@@ -1667,7 +2010,8 @@ mod tests {
                 cleanup: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         id: x
         type: fill
@@ -1687,7 +2031,8 @@ mod tests {
                 constant_fold: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @"
         filter: false
         id: x
@@ -1710,7 +2055,8 @@ mod tests {
                 minify_colors: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r##"
         id: x
         paint:
@@ -1732,7 +2078,8 @@ mod tests {
                 minify_colors: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r##"
         id: x
         paint:
@@ -1754,7 +2101,8 @@ mod tests {
                 minify_colors: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v["layers"][0], @r##"
         id: x
         paint:
@@ -1776,7 +2124,8 @@ mod tests {
                 cleanup: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v, @"
         layers: []
         sources: {}
@@ -1795,7 +2144,8 @@ mod tests {
                 cleanup: true,
                 ..Default::default()
             },
-        );
+        )
+        .unwrap();
         assert_yaml_snapshot!(v, @"
         bearing: 45
         layers: []
@@ -0,0 +1,191 @@
+//! Per-layer pass opt-outs.
+//!
+//! A layer skips a pass when an [`LayerOverride`] (from the config file)
+//! matches its id, or when its `metadata` carries [`SKIP_METADATA_KEY`]:
+//!
+//! ```json
+//! {"id": "poi-label", "metadata": {"maplibre-optimizer:skip": ["layer_merge"]}}
+//! ```
+//!
+//! `true` instead of a list skips every pass.  Opt-outs are resolved once per
+//! run from the input style, so a pass that strips `metadata` does not undo
+//! them.  Passes that remove or merge layers treat a skipping layer like a
+//! kept one; for all other passes the pass manager restores the layer as it
+//! was before the pass ran.
+
+use std::collections::HashMap;
+
+use glob::Pattern;
+use serde_json::Value;
+
+/// Layer `metadata` key holding a layer's own opt-outs.
+pub const SKIP_METADATA_KEY: &str = "maplibre-optimizer:skip";
+
+/// Passes that layers whose id matches a glob must skip.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayerOverride {
+    layers: Pattern,
+    skip: Vec<String>,
+}
+
+impl LayerOverride {
+    /// Layers matching the glob `layers` (e.g. `poi-*`) skip the passes named
+    /// in `skip`, or every pass if `skip` is empty.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `layers` is not a valid glob.
+    pub fn new(layers: &str, skip: Vec<String>) -> Result<Self, glob::PatternError> {
+        Ok(Self {
+            layers: Pattern::new(layers)?,
+            skip,
+        })
+    }
+
+    /// Pass names this override refers to.
+    pub fn passes(&self) -> impl Iterator<Item = &str> {
+        self.skip.iter().map(String::as_str)
+    }
+}
+
+/// The passes each layer of a style skips.
+#[derive(Debug, Default)]
+pub(crate) struct LayerSkips(HashMap<String, Skip>);
+
+#[derive(Debug)]
+enum Skip {
+    None,
+    All,
+    Passes(Vec<String>),
+}
+
+impl Skip {
+    fn add(&mut self, passes: &[String]) {
+        if passes.is_empty() {
+            *self = Self::All;
+            return;
+        }
+        match self {
+            Self::All => {}
+            Self::None => *self = Self::Passes(passes.to_vec()),
+            Self::Passes(own) => own.extend_from_slice(passes),
+        }
+    }
+
+    fn contains(&self, pass: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::None => false,
+            Self::Passes(passes) => passes.iter().any(|p| p == pass),
+        }
+    }
+}
+
+impl LayerSkips {
+    pub(crate) fn resolve(style: &Value, overrides: &[LayerOverride]) -> Self {
+        let mut skips = HashMap::new();
+        let layers = style.get("layers").and_then(Value::as_array);
+        for layer in layers.into_iter().flatten() {
+            let Some(id) = layer.get("id").and_then(Value::as_str) else {
+                continue;
+            };
+            let mut skip = Skip::None;
+            for o in overrides.iter().filter(|o| o.layers.matches(id)) {
+                skip.add(&o.skip);
+            }
+            match layer.get("metadata").and_then(|m| m.get(SKIP_METADATA_KEY)) {
+                Some(Value::Bool(true)) => skip.add(&[]),
+                Some(Value::Array(names)) => {
+                    let names: Vec<String> = names
+                        .iter()
+                        .filter_map(|n| n.as_str().map(str::to_owned))
+                        .collect();
+                    if !names.is_empty() {
+                        skip.add(&names);
+                    }
+                }
+                _ => {}
+            }
+            if !matches!(skip, Skip::None) {
+                skips.insert(id.to_owned(), skip);
+            }
+        }
+        Self(skips)
+    }
+
+    /// Whether layer `id` skips `pass`.
+    pub(crate) fn skips(&self, id: &str, pass: &str) -> bool {
+        self.0.get(id).is_some_and(|s| s.contains(pass))
+    }
+
+    /// Ids of the layers that skip `pass`.
+    pub(crate) fn skipping<'a>(&'a self, pass: &'a str) -> impl Iterator<Item = &'a str> {
+        self.0
+            .iter()
+            .filter(move |(_, s)| s.contains(pass))
+            .map(|(id, _)| id.as_str())
+    }
+}
+
+/// Put back the `before` version of every layer in `after` that skips `pass`.
+pub(crate) fn restore_skipped<T: Clone>(
+    skips: &LayerSkips,
+    pass: &str,
+    before: &[T],
+    after: &mut [T],
+    id: impl Fn(&T) -> &str,
+) {
+    if skips.skipping(pass).next().is_none() {
+        return;
+    }
+    for layer in after {
+        if skips.skips(id(layer), pass)
+            && let Some(old) = before.iter().find(|b| id(b) == id(layer))
+        {
+            layer.clone_from(old);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn resolves_globs_and_metadata_markers() {
+        let style = json!({"layers": [
+            {"id": "poi-bar"},
+            {"id": "road", "metadata": {"maplibre-optimizer:skip": ["strip_defaults"]}},
+            {"id": "water", "metadata": {"maplibre-optimizer:skip": true}},
+            {"id": "park", "metadata": {"maplibre-optimizer:skip": false}}
+        ]});
+        let overrides = [
+            LayerOverride::new("poi-*", vec!["layer_merge".into()]).unwrap(),
+            LayerOverride::new("road", vec!["cleanup".into()]).unwrap(),
+        ];
+        let skips = LayerSkips::resolve(&style, &overrides);
+        assert!(skips.skips("poi-bar", "layer_merge"));
+        assert!(!skips.skips("poi-bar", "cleanup"));
+        assert!(skips.skips("road", "strip_defaults"));
+        assert!(skips.skips("road", "cleanup"));
+        assert!(skips.skips("water", "normalize_fold"));
+        assert!(!skips.skips("park", "normalize_fold"));
+    }
+
+    #[test]
+    fn restores_only_skipping_layers() {
+        let style = json!({"layers": [{"id": "a", "metadata": {"maplibre-optimizer:skip": true}}]});
+        let skips = LayerSkips::resolve(&style, &[]);
+        let before = [json!({"id": "a", "x": 1}), json!({"id": "b", "x": 1})];
+        let mut after = [json!({"id": "b", "x": 2}), json!({"id": "a", "x": 2})];
+        restore_skipped(&skips, "cleanup", &before, &mut after, |l| {
+            l["id"].as_str().unwrap()
+        });
+        assert_eq!(
+            after,
+            [json!({"id": "b", "x": 2}), json!({"id": "a", "x": 1})]
+        );
+    }
+}
@@ -0,0 +1,678 @@
+//! Pass manager: named passes with declared ordering, run to a fixpoint.
+//!
+//! Every optimization is a [`Pass`].  A [`PassRegistry`] orders the enabled
+//! passes by their declared dependencies and runs them in sweeps.  A pass that
+//! changes the style marks the passes it invalidates as dirty; sweeps repeat
+//! until no pass is dirty or [`PASS_FIXPOINT_CAP`] sweeps have run.
+//!
+//! Passes run either on the style JSON or on the typed
+//! [`MaplibreStyleSpecification`].  Consecutive typed passes share one
+//! deserialization, which is synced back to the JSON before the next JSON pass
+//! (see `sync_typed_to_json`), so keys the typed model does not cover survive.
+//! A style the typed model rejects skips the typed passes; the run then ends
+//! in [`OptimizeError::Incomplete`] naming them and the offending path.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use maplibre_style_spec::mir::MirSpec;
+use maplibre_style_spec::spec::MaplibreStyleSpecification;
+use serde_json::Value;
+
+use super::error::{OptimizeError, OptimizeOutcome, SkippedPass, deserialize_at_path};
+use super::explain::{ChangeLog, Recorder};
+use super::keep::KeepLayers;
+use super::layer_map::{LayerMap, layer_ids};
+use super::overrides::{LayerSkips, restore_skipped};
+use super::{OptPasses, builtin, optimize_json_recorded, sync_typed_to_json};
+use crate::stats::TileStatistics;
+
+/// Maximum number of sweeps over the schedule before giving up on a fixpoint.
+pub const PASS_FIXPOINT_CAP: usize = 8;
+
+/// Representation a pass operates on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassData {
+    /// The style JSON, including keys the typed model does not cover.
+    Json,
+    /// The typed [`MaplibreStyleSpecification`].
+    Typed,
+}
+
+/// Inputs shared by every pass in a run.
+#[derive(Clone, Copy)]
+pub struct PassContext<'a> {
+    pub mir: &'a MirSpec,
+    /// Pass switches and per-pass options (e.g. `dead_elimination_stats`).
+    pub options: &'a OptPasses,
+    pub stats: Option<&'a TileStatistics>,
+    /// Set when the caller asked for a [`LayerMap`].
+    pub(crate) layer_map: Option<&'a RefCell<LayerMap>>,
+    /// Per-layer opt-outs, resolved when the schedule starts.
+    pub(crate) layer_skips: Option<&'a LayerSkips>,
+    /// Passes run and skipped so far.
+    pub(crate) outcome: Option<&'a RefCell<OptimizeOutcome>>,
+}
+
+impl<'a> PassContext<'a> {
+    /// Layers `pass` must neither remove nor merge: `options.keep_layers` plus
+    /// the layers that opted out of `pass`.
+    #[must_use]
+    pub fn keep_layers(&self, pass: &str) -> Cow<'a, KeepLayers> {
+        let keep = &self.options.keep_layers;
+        match self.layer_skips {
+            Some(skips) if skips.skipping(pass).next().is_some() => {
+                Cow::Owned(keep.with_ids(skips.skipping(pass)))
+            }
+            _ => Cow::Borrowed(keep),
+        }
+    }
+
+    /// Report that the layers in `members` now render as part of layer `into`,
+    /// each restricted to the features matching its filter.  Passes that fold
+    /// layers together call this so the layer map can follow them; layers that
+    /// simply disappear are recorded automatically.
+    pub fn record_merge(&self, into: &str, members: &[(String, Value)]) {
+        if let Some(map) = self.layer_map {
+            map.borrow_mut().merged(into, members);
+        }
+    }
+
+    /// Report that layer `from` is now drawn by the layers in `parts`, each
+    /// restricted to the features matching its filter.
+    pub fn record_split(&self, from: &str, parts: &[(String, Value)]) {
+        if let Some(map) = self.layer_map {
+            map.borrow_mut().split(from, parts);
+        }
+    }
+
+    /// Report that layer `id` draws the same features as the later layer `of`
+    /// but had to stay.
+    pub fn record_duplicate(&self, id: &str, of: &str) {
+        if let Some(map) = self.layer_map {
+            map.borrow_mut().duplicated(id, of);
+        }
+    }
+
+    /// Report that `pass` left the value at `path` (e.g.
+    /// `layers[3].paint.line-width`) alone because it did not deserialize.
+    /// The run then ends in [`OptimizeError::Incomplete`].
+    pub fn record_skip(&self, pass: &'static str, path: String, reason: String) {
+        if let Some(outcome) = self.outcome {
+            outcome
+                .borrow_mut()
+                .record_skip(SkippedPass { pass, path, reason });
+        }
+    }
+}
+
+/// Optional by-products of an optimization run.  Set a field to `Some` to
+/// collect it; it is replaced with the result.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OptimizeReport {
+    /// What each pass changed (`--explain`).
+    pub changes: Option<ChangeLog>,
+    /// Which optimized layer renders each input layer (`--layer-map`).
+    pub layer_map: Option<LayerMap>,
+}
+
+/// A single optimization pass.
+///
+/// Implement [`run_json`](Pass::run_json) or [`run_typed`](Pass::run_typed)
+/// according to [`data`](Pass::data); the other is never called.
+pub trait Pass: Send + Sync {
+    /// Unique name, used for `--passes`, scheduling, and the change log.
+    fn name(&self) -> &'static str;
+
+    fn data(&self) -> PassData;
+
+    /// Passes that must run before this one when both are enabled.
+    fn dependencies(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Passes to re-run after this one changed the style.
+    fn invalidates(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Whether `options` switch this pass on.  Passes that return `false` run
+    /// only when requested by name.
+    fn enabled(&self, options: &OptPasses) -> bool {
+        let _ = options;
+        false
+    }
+
+    fn run_json(&self, style: &mut Value, cx: &PassContext<'_>) {
+        let _ = (style, cx);
+    }
+
+    fn run_typed(&self, style: &mut MaplibreStyleSpecification, cx: &PassContext<'_>) {
+        let _ = (style, cx);
+    }
+}
+
+/// The set of known passes, in registration order.
+///
+/// Registration order breaks ties between passes whose dependencies do not
+/// order them.
+#[derive(Default)]
+pub struct PassRegistry {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassRegistry {
+    /// An empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry holding every built-in pass.
+    #[must_use]
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        builtin::register(&mut registry);
+        registry
+    }
+
+    /// Add `pass`, replacing a registered pass of the same name in place.
+    pub fn register(&mut self, pass: impl Pass + 'static) -> &mut Self {
+        let pass: Box<dyn Pass> = Box::new(pass);
+        match self.passes.iter().position(|p| p.name() == pass.name()) {
+            Some(i) => self.passes[i] = pass,
+            None => self.passes.push(pass),
+        }
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.passes.iter().map(|p| p.name())
+    }
+
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.passes.iter().any(|p| p.name() == name)
+    }
+
+    /// Optimize a style JSON with the passes enabled by `options` plus the
+    /// passes named in `extra`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `extra` names an unregistered pass or the dependencies of the
+    /// selected passes form a cycle, leaving `v` unchanged.  Returns
+    /// [`OptimizeError::Incomplete`] if a pass had to skip all or part of the
+    /// style; `v` then holds the result of the passes that ran.
+    pub fn optimize_json(
+        &self,
+        v: &mut Value,
+        mir: &MirSpec,
+        options: &OptPasses,
+        stats: Option<&TileStatistics>,
+        extra: &[&str],
+    ) -> Result<OptimizeOutcome, OptimizeError> {
+        let mut report = OptimizeReport::default();
+        self.optimize_json_reported(v, mir, options, stats, extra, &mut report)
+    }
+
+    /// Like [`optimize_json`](Self::optimize_json), but also returns what each
+    /// pass changed.
+    ///
+    /// # Errors
+    ///
+    /// See [`optimize_json`](Self::optimize_json).
+    pub fn optimize_json_explained(
+        &self,
+        v: &mut Value,
+        mir: &MirSpec,
+        options: &OptPasses,
+        stats: Option<&TileStatistics>,
+        extra: &[&str],
+    ) -> Result<ChangeLog, OptimizeError> {
+        let mut report = OptimizeReport {
+            changes: Some(ChangeLog::default()),
+            ..OptimizeReport::default()
+        };
+        self.optimize_json_reported(v, mir, options, stats, extra, &mut report)?;
+        Ok(report.changes.unwrap_or_default())
+    }
+
+    /// Like [`optimize_json`](Self::optimize_json), also collecting the parts
+    /// of `report` that are `Some`.
+    ///
+    /// # Errors
+    ///
+    /// See [`optimize_json`](Self::optimize_json).
+    pub fn optimize_json_reported(
+        &self,
+        v: &mut Value,
+        mir: &MirSpec,
+        options: &OptPasses,
+        stats: Option<&TileStatistics>,
+        extra: &[&str],
+        report: &mut OptimizeReport,
+    ) -> Result<OptimizeOutcome, OptimizeError> {
+        let layer_map = report
+            .layer_map
+            .is_some()
+            .then(|| RefCell::new(LayerMap::from_style(v)));
+        let outcome = RefCell::default();
+        let cx = PassContext {
+            mir,
+            options,
+            stats,
+            layer_map: layer_map.as_ref(),
+            layer_skips: None,
+            outcome: Some(&outcome),
+        };
+        let mut rec = match report.changes.as_mut() {
+            Some(log) => {
+                *log = ChangeLog::default();
+                Recorder::new(log)
+            }
+            None => Recorder::disabled(),
+        };
+        optimize_json_recorded(v, &cx, self, extra, &mut rec)?;
+        if let Some(map) = layer_map {
+            report.layer_map = Some(map.into_inner());
+        }
+        outcome.into_inner().into_result()
+    }
+
+    /// Select the passes to run and order them by their dependencies.
+    ///
+    /// Dependencies on passes that are not selected are ignored.
+    pub(super) fn schedule(
+        &self,
+        options: &OptPasses,
+        extra: &[&str],
+    ) -> Result<Vec<&dyn Pass>, OptimizeError> {
+        if let Some(name) = extra.iter().find(|name| !self.contains(name)) {
+            return Err(OptimizeError::UnknownPass {
+                name: (*name).to_owned(),
+                available: self.names().collect(),
+            });
+        }
+        let selected: Vec<&dyn Pass> = self
+            .passes
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|p| p.enabled(options) || extra.contains(&p.name()))
+            .collect();
+        let index: HashMap<&str, usize> = selected
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.name(), i))
+            .collect();
+
+        // Kahn's algorithm, always taking the earliest-registered ready pass.
+        let mut pending: Vec<usize> = selected
+            .iter()
+            .map(|p| {
+                p.dependencies()
+                    .iter()
+                    .filter(|d| index.contains_key(*d))
+                    .count()
+            })
+            .collect();
+        let mut done = vec![false; selected.len()];
+        let mut order = Vec::with_capacity(selected.len());
+        while order.len() < selected.len() {
+            let Some(next) = (0..selected.len()).find(|&i| !done[i] && pending[i] == 0) else {
+                return Err(OptimizeError::DependencyCycle(
+                    (0..selected.len())
+                        .filter(|&i| !done[i])
+                        .map(|i| selected[i].name())
+                        .collect(),
+                ));
+            };
+            done[next] = true;
+            order.push(selected[next]);
+            let name = selected[next].name();
+            for (i, p) in selected.iter().enumerate() {
+                if !done[i] && p.dependencies().contains(&name) {
+                    pending[i] -= 1;
+                }
+            }
+        }
+        Ok(order)
+    }
+}
+
+/// Run `schedule` in sweeps until no pass is invalidated.
+pub(super) fn run_schedule(
+    v: &mut Value,
+    cx: &PassContext<'_>,
+    schedule: &[&dyn Pass],
+    rec: &mut Recorder<'_>,
+) {
+    let index: HashMap<&str, usize> = schedule
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.name(), i))
+        .collect();
+    let mut dirty = vec![true; schedule.len()];
+    let mut typed: Option<MaplibreStyleSpecification> = None;
+    let skips = LayerSkips::resolve(v, &cx.options.layer_overrides);
+    let cx = &PassContext {
+        layer_skips: Some(&skips),
+        ..*cx
+    };
+
+    for _ in 0..PASS_FIXPOINT_CAP {
+        if !dirty.contains(&true) {
+            break;
+        }
+        for (i, pass) in schedule.iter().enumerate() {
+            if !std::mem::take(&mut dirty[i]) {
+                continue;
+            }
+            let changed = match pass.data() {
+                PassData::Json => {
+                    if let Some(style) = typed.take() {
+                        sync_typed_to_json(&style, v);
+                    }
+                    let before = v.clone();
+                    rec.json(pass.name(), v, |v| {
+                        pass.run_json(v, cx);
+                        if let (Some(old), Some(new)) = (
+                            before.get("layers").and_then(Value::as_array),
+                            v.get_mut("layers").and_then(Value::as_array_mut),
+                        ) {
+                            restore_skipped(&skips, pass.name(), old, new, |l| {
+                                l.get("id").and_then(Value::as_str).unwrap_or_default()
+                            });
+                        }
+                    });
+                    record_removals(cx, pass.name(), layer_ids(&before), layer_ids(v));
+                    record_run(cx, pass.name());
+                    *v != before
+                }
+                PassData::Typed => {
+                    if typed.is_none() {
+                        // A style the typed model rejects skips typed passes.
+                        match deserialize_at_path(v) {
+                            Ok(style) => typed = Some(style),
+                            Err((path, reason)) => {
+                                cx.record_skip(pass.name(), path, reason);
+                                continue;
+                            }
+                        }
+                    }
+                    let Some(style) = typed.as_mut() else {
+                        continue;
+                    };
+                    let before = style.clone();
+                    rec.typed(pass.name(), style, |style| {
+                        pass.run_typed(style, cx);
+                        restore_skipped(
+                            &skips,
+                            pass.name(),
+                            &before.layers,
+                            &mut style.layers,
+                            |l| l.id().as_str(),
+                        );
+                    });
+                    let ids = |s: &MaplibreStyleSpecification| {
+                        s.layers
+                            .iter()
+                            .map(|l| l.id().as_str().to_owned())
+                            .collect::<Vec<_>>()
+                    };
+                    record_removals(cx, pass.name(), ids(&before), ids(style));
+                    record_run(cx, pass.name());
+                    *style != before
+                }
+            };
+            if changed {
+                for name in pass.invalidates() {
+                    if let Some(&j) = index.get(name) {
+                        dirty[j] = true;
+                    }
+                }
+            }
+        }
+    }
+    if let Some(style) = typed {
+        sync_typed_to_json(&style, v);
+    }
+}
+
+fn record_run(cx: &PassContext<'_>, pass: &'static str) {
+    if let Some(outcome) = cx.outcome {
+        outcome.borrow_mut().record_run(pass);
+    }
+}
+
+/// Record layers present `before` a pass but not after it as removed by it.
+fn record_removals<B, A>(cx: &PassContext<'_>, pass: &'static str, before: B, after: A)
+where
+    B: IntoIterator<Item: AsRef<str>>,
+    A: IntoIterator<Item: AsRef<str>>,
+{
+    let Some(map) = cx.layer_map else {
+        return;
+    };
+    let after: HashSet<String> = after.into_iter().map(|id| id.as_ref().to_owned()).collect();
+    let mut map = map.borrow_mut();
+    for id in before {
+        if !after.contains(id.as_ref()) {
+            map.removed(id.as_ref(), pass);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::load_intermediate_spec_from_v8_path;
+
+    fn sample_mir() -> MirSpec {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../upstream/src/reference/v8.json");
+        load_intermediate_spec_from_v8_path(&path).expect("v8.json")
+    }
+
+    struct Named {
+        name: &'static str,
+        deps: &'static [&'static str],
+    }
+
+    impl Pass for Named {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn data(&self) -> PassData {
+            PassData::Json
+        }
+        fn dependencies(&self) -> &[&'static str] {
+            self.deps
+        }
+    }
+
+    fn names(schedule: &[&dyn Pass]) -> Vec<&'static str> {
+        schedule.iter().map(|p| p.name()).collect()
+    }
+
+    #[test]
+    fn schedule_respects_dependencies_then_registration_order() {
+        let mut registry = PassRegistry::new();
+        registry
+            .register(Named {
+                name: "c",
+                deps: &["b"],
+            })
+            .register(Named {
+                name: "a",
+                deps: &[],
+            })
+            .register(Named {
+                name: "b",
+                deps: &[],
+            });
+        let schedule = registry
+            .schedule(&OptPasses::default(), &["a", "b", "c"])
+            .unwrap();
+        assert_eq!(names(&schedule), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn schedule_ignores_unselected_dependencies() {
+        let mut registry = PassRegistry::new();
+        registry
+            .register(Named {
+                name: "a",
+                deps: &[],
+            })
+            .register(Named {
+                name: "b",
+                deps: &["a"],
+            });
+        let schedule = registry.schedule(&OptPasses::default(), &["b"]).unwrap();
+        assert_eq!(names(&schedule), ["b"]);
+    }
+
+    #[test]
+    fn schedule_rejects_unknown_names_and_cycles() {
+        let mut registry = PassRegistry::new();
+        registry
+            .register(Named {
+                name: "a",
+                deps: &["b"],
+            })
+            .register(Named {
+                name: "b",
+                deps: &["a"],
+            });
+        let err = registry
+            .schedule(&OptPasses::default(), &["nope"])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("unknown pass `nope`"));
+        let err = registry
+            .schedule(&OptPasses::default(), &["a", "b"])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("cycle"));
+    }
+
+    #[test]
+    fn register_replaces_same_name() {
+        let mut registry = PassRegistry::new();
+        registry
+            .register(Named {
+                name: "a",
+                deps: &[],
+            })
+            .register(Named {
+                name: "a",
+                deps: &["x"],
+            });
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["a"]);
+    }
+
+    #[test]
+    fn builtin_passes_schedule_for_all_options() {
+        let registry = PassRegistry::builtin();
+        let schedule = registry.schedule(&OptPasses::all(), &[]).unwrap();
+        // `global_state` only runs when given state values; `layer_split` is
+        // opt-in.
+        assert_eq!(
+            names(&schedule),
+            registry
+                .names()
+                .filter(|&n| n != "global_state" && n != "layer_split")
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(schedule.last().unwrap().name(), "common_subexpressions");
+        assert!(
+            registry
+                .schedule(&OptPasses::default(), &[])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    /// Decrements `remaining` once per run and asks to run again.
+    struct Countdown;
+
+    impl Pass for Countdown {
+        fn name(&self) -> &'static str {
+            "countdown"
+        }
+        fn data(&self) -> PassData {
+            PassData::Json
+        }
+        fn invalidates(&self) -> &[&'static str] {
+            &["countdown"]
+        }
+        fn run_json(&self, style: &mut Value, _: &PassContext<'_>) {
+            if let Some(n) = style["remaining"].as_u64()
+                && n > 0
+            {
+                style["remaining"] = json!(n - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn invalidated_passes_rerun_until_fixpoint_or_cap() {
+        let mir = sample_mir();
+        let mut registry = PassRegistry::new();
+        registry.register(Countdown);
+        let options = OptPasses::default();
+        let cx = PassContext {
+            mir: &mir,
+            options: &options,
+            stats: None,
+            layer_map: None,
+            layer_skips: None,
+            outcome: None,
+        };
+        let schedule = registry.schedule(&options, &["countdown"]).unwrap();
+
+        let mut v = json!({"remaining": 3});
+        run_schedule(&mut v, &cx, &schedule, &mut Recorder::disabled());
+        assert_eq!(v["remaining"], json!(0));
+
+        let mut v = json!({"remaining": 100});
+        run_schedule(&mut v, &cx, &schedule, &mut Recorder::disabled());
+        assert_eq!(v["remaining"], json!(100 - PASS_FIXPOINT_CAP));
+    }
+
+    struct TypedNoop;
+
+    impl Pass for TypedNoop {
+        fn name(&self) -> &'static str {
+            "typed"
+        }
+        fn data(&self) -> PassData {
+            PassData::Typed
+        }
+    }
+
+    #[test]
+    fn typed_passes_on_rejected_styles_are_reported() {
+        let mir = sample_mir();
+        let mut registry = PassRegistry::new();
+        registry.register(TypedNoop);
+        let mut v = json!({"version": 8, "sources": {}, "layers": [
+            {"id": "bad", "type": "line", "source": "s", "paint": {"line-width": {"oops": 1}}}
+        ]});
+        let err = registry
+            .optimize_json(&mut v, &mir, &OptPasses::default(), None, &["typed"])
+            .unwrap_err();
+        let OptimizeError::Incomplete(outcome) = err else {
+            panic!("unexpected error {err}");
+        };
+        assert!(outcome.ran.is_empty());
+        assert_eq!(outcome.skipped.len(), 1);
+        assert_eq!(outcome.skipped[0].pass, "typed");
+        assert!(outcome.skipped[0].path.starts_with("layers[0]"));
+    }
+}
@@ -10,20 +10,25 @@ use maplibre_style_spec::spec::{
     Any, Color, ColorOrArrayOfColor, Number, NumberLiteral,
     NumberOrArrayOfNumberOrColorOrArrayOfColorOrProjection,
 };
-use serde_json::Value;
+use serde_json::{Value, json};
 
 /// Walk all layers and prune out-of-range stops from zoom-driven
 /// `step`/`interpolate` expressions.
-pub(super) fn prune_zoom_stops(style: &mut Value) {
+///
+/// Returns the path and deserialization error of every numeric or colour
+/// zoom ramp that could not be pruned because the typed expression model
+/// rejected it.
+pub(super) fn prune_zoom_stops(style: &mut Value) -> Vec<(String, String)> {
+    let mut skipped = Vec::new();
     let Some(layers) = style
         .as_object_mut()
         .and_then(|o| o.get_mut("layers"))
         .and_then(Value::as_array_mut)
     else {
-        return;
+        return skipped;
     };
 
-    for layer in layers.iter_mut() {
+    for (i, layer) in layers.iter_mut().enumerate() {
         let Some(obj) = layer.as_object_mut() else {
             continue;
         };
@@ -38,37 +43,82 @@ pub(super) fn prune_zoom_stops(style: &mut Value) {
             let Some(props) = obj.get_mut(section).and_then(Value::as_object_mut) else {
                 continue;
             };
-            for value in props.values_mut() {
-                try_prune_property(value, minzoom, maxzoom);
+            for (key, value) in props.iter_mut() {
+                if let Err(reason) = try_prune_property(value, minzoom, maxzoom) {
+                    skipped.push((format!("layers[{i}].{section}.{key}"), reason));
+                }
             }
         }
     }
+    skipped
 }
 
 /// Try to deserialize a property value as a typed expression, prune it,
 /// and serialize back if changed.  After serialization, collapse trivial
 /// ramps (zero stops for step, single/identical stops for interpolate)
 /// to their bare output value.
-fn try_prune_property(value: &mut Value, minzoom: Option<f64>, maxzoom: Option<f64>) {
+///
+/// Fails with the deserialization error if `value` is a zoom ramp over
+/// numbers or colours that neither typed expression accepts.
+fn try_prune_property(
+    value: &mut Value,
+    minzoom: Option<f64>,
+    maxzoom: Option<f64>,
+) -> Result<(), String> {
     // Try numeric first (most common for zoom-driven ramps like opacity, width).
-    if let Ok(mut expr) = serde_json::from_value::<NumericExpression>(value.clone())
-        && prune_numeric(&mut expr, minzoom, maxzoom)
-        && let Ok(mut v) = serde_json::to_value(&expr)
-    {
-        collapse_trivial_ramp(&mut v);
-        *value = v;
-        return;
-    }
+    let numeric_err = match serde_json::from_value::<NumericExpression>(value.clone()) {
+        Ok(mut expr) => {
+            if prune_numeric(&mut expr, minzoom, maxzoom)
+                && let Ok(mut v) = serde_json::to_value(&expr)
+            {
+                collapse_trivial_ramp(&mut v);
+                *value = v;
+                return Ok(());
+            }
+            None
+        }
+        Err(e) => Some(e),
+    };
     // Then color (e.g. fill-color, line-color).
-    if let Ok(mut expr) = serde_json::from_value::<ColorExpression>(value.clone())
-        && prune_color(&mut expr, minzoom, maxzoom)
-        && let Ok(mut v) = serde_json::to_value(&expr)
-    {
-        collapse_trivial_ramp(&mut v);
-        *value = v;
+    match serde_json::from_value::<ColorExpression>(value.clone()) {
+        Ok(mut expr) => {
+            if prune_color(&mut expr, minzoom, maxzoom)
+                && let Ok(mut v) = serde_json::to_value(&expr)
+            {
+                collapse_trivial_ramp(&mut v);
+                *value = v;
+            }
+            Ok(())
+        }
+        Err(color_err) => match numeric_err {
+            Some(numeric_err) if is_scalar_zoom_ramp(value) => Err(format!(
+                "not a numeric ({numeric_err}) or color ({color_err}) expression"
+            )),
+            _ => Ok(()),
+        },
     }
 }
 
+/// Whether `v` is a `step`/`interpolate` over `["zoom"]` whose outputs are
+/// all numbers, or (for `interpolate`) all strings, i.e. a ramp
+/// [`NumericExpression`] or [`ColorExpression`] should accept.  Ramps over
+/// other outputs (`text-field` steps, arrays) legitimately fail both.
+fn is_scalar_zoom_ramp(v: &Value) -> bool {
+    let Some(arr) = v.as_array() else {
+        return false;
+    };
+    let (input, first_output, interpolate) = match arr.first().and_then(Value::as_str) {
+        Some("step") => (1, 2, false),
+        Some("interpolate" | "interpolate-hcl" | "interpolate-lab") => (2, 4, true),
+        _ => return false,
+    };
+    let outputs: Vec<&Value> = arr.iter().skip(first_output).step_by(2).collect();
+    !outputs.is_empty()
+        && arr.get(input) == Some(&json!(["zoom"]))
+        && (outputs.iter().all(|o| o.is_number())
+            || (interpolate && outputs.iter().all(|o| o.is_string())))
+}
+
 /// Collapse a serialized ramp expression to its bare value when all stops
 /// have been pruned away or all remaining outputs are identical.
 fn collapse_trivial_ramp(v: &mut Value) {
@@ -585,4 +635,26 @@ mod tests {
         prune_zoom_stops(&mut style);
         assert_eq!(style, original);
     }
+
+    #[test]
+    fn rejected_scalar_ramps_are_reported() {
+        let mut style = make_style(
+            Some(10.0),
+            None,
+            json!(["interpolate", ["bogus"], ["zoom"], 5, 0, 15, 1]),
+        );
+        let original = style.clone();
+        let skipped = prune_zoom_stops(&mut style);
+        assert_eq!(style, original);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, "layers[0].paint.fill-opacity");
+
+        // Text steps are not numeric or colour ramps to begin with.
+        let mut style = make_style(
+            Some(10.0),
+            None,
+            json!(["step", ["zoom"], "", 12, ["get", "name"]]),
+        );
+        assert!(prune_zoom_stops(&mut style).is_empty());
+    }
 }
@@ -0,0 +1,494 @@
+//! Layer splitting: the inverse of [`layer_merge`](super::merge::layer_merge).
+//!
+//! A layer whose paint/layout values `match` on `["get", P]`, where tile
+//! statistics show `P` is a string with only a few distinct values, becomes one
+//! layer per distinct combination of `match` outputs, each with constant values
+//! and an equality filter on `P`, plus a last layer with the `match` fallbacks
+//! for every other value.  Constant paint needs no data-driven shader
+//! attributes and no per-feature evaluation.
+//!
+//! Only complete statistics (`sample_rate == 1`) are used, so every split-off
+//! layer draws at least one feature: the fallback layer is only added when
+//! some feature lacks `P` or has a value that falls back.  Features of
+//! different parts no longer interleave in tile order, which is why the pass
+//! is opt-in.
+
+use std::collections::HashSet;
+
+use indexmap::IndexMap;
+use serde_json::{Value, json};
+
+use super::SplitCardinality;
+use super::keep::KeepLayers;
+use crate::stats::{LayerStats, PropertyStats, TileStatistics};
+
+/// A layer replaced by [`layer_split`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitLayer {
+    /// Id of the original layer.
+    pub from: String,
+    /// Each new layer id, with the filter selecting its share of the original
+    /// layer's features.
+    pub parts: Vec<(String, Value)>,
+}
+
+/// Split layers whose `match` inputs have at most `max_cardinality` values.
+///
+/// Layers in `keep`, targets of a `ref`, symbol layers (splitting changes
+/// label placement order) and layers with a sort-key are left alone.
+pub fn layer_split(
+    v: &mut Value,
+    stats: &TileStatistics,
+    keep: &KeepLayers,
+    max_cardinality: SplitCardinality,
+) -> Vec<SplitLayer> {
+    if (stats.sample_rate - 1.0).abs() > f64::EPSILON {
+        return Vec::new();
+    }
+    let Some(layers) = v.get_mut("layers").and_then(Value::as_array_mut) else {
+        return Vec::new();
+    };
+
+    let mut ids: Vec<String> = layers.iter().map(|l| layer_id(l).to_owned()).collect();
+    // Splitting a `ref` target would leave the layers referencing it dangling.
+    let referenced: HashSet<String> = layers
+        .iter()
+        .filter_map(|l| l.get("ref").and_then(Value::as_str))
+        .map(str::to_owned)
+        .collect();
+    let old_layers = std::mem::take(layers);
+    let mut splits = Vec::new();
+
+    for layer in old_layers {
+        let parts = if keep.contains(layer_id(&layer)) || referenced.contains(layer_id(&layer)) {
+            None
+        } else {
+            split_layer(&layer, stats, max_cardinality).filter(|parts| {
+                parts
+                    .iter()
+                    .all(|(l, _)| ids.iter().all(|id| id != layer_id(l)))
+            })
+        };
+        let Some(parts) = parts else {
+            layers.push(layer);
+            continue;
+        };
+        splits.push(SplitLayer {
+            from: layer_id(&layer).to_owned(),
+            parts: parts
+                .iter()
+                .map(|(l, filter)| (layer_id(l).to_owned(), filter.clone()))
+                .collect(),
+        });
+        for (part, _) in parts {
+            ids.push(layer_id(&part).to_owned());
+            layers.push(part);
+        }
+    }
+    splits
+}
+
+fn layer_id(layer: &Value) -> &str {
+    layer.get("id").and_then(Value::as_str).unwrap_or_default()
+}
+
+/// The layers replacing `layer`, each with the filter on the `match` input
+/// that selects its features; `None` when the layer does not qualify.
+fn split_layer(
+    layer: &Value,
+    stats: &TileStatistics,
+    max_cardinality: SplitCardinality,
+) -> Option<Vec<(Value, Value)>> {
+    let layer_type = layer.get("type")?.as_str()?;
+    if layer_type == "symbol" || has_sort_key(layer) {
+        return None;
+    }
+    let source = layer.get("source")?.as_str()?;
+    let source_layer = layer.get("source-layer")?.as_str()?;
+    let layer_stats = stats.layer_stats(source, source_layer)?;
+    if layer_stats.total_features == 0 {
+        return None;
+    }
+
+    let (input, observed) = split_input(layer, layer_stats, max_cardinality)?;
+    let props = match_properties(layer, input);
+
+    // Group the observed values by the outputs they select.  The last part
+    // takes every fallback: features without the property, and values the
+    // statistics have not seen.  It is left out when every feature has the
+    // property and none of its values falls back.
+    let fallbacks: Vec<&Value> = props.iter().map(|(_, _, m)| m.fallback).collect();
+    let mut groups: IndexMap<Vec<&Value>, Vec<&str>> = IndexMap::new();
+    for &value in &observed {
+        let outputs = props.iter().map(|(_, _, m)| m.output(value)).collect();
+        groups.entry(outputs).or_default().push(value);
+    }
+    let fallback_values = groups.shift_remove(&fallbacks).unwrap_or_default();
+    let always_present = layer_stats
+        .properties
+        .get(input)
+        .is_some_and(|p| p.present_count() == layer_stats.total_features);
+    let has_fallback = !(always_present && fallback_values.is_empty());
+    if has_fallback {
+        groups.insert(fallbacks, fallback_values);
+    }
+    if groups.len() < 2 {
+        return None;
+    }
+
+    let get = json!(["get", input]);
+    let mut ids: Vec<String> = Vec::new();
+    let last = groups.len() - 1;
+    let parts = groups
+        .iter()
+        .enumerate()
+        .map(|(i, (outputs, values))| {
+            let selector = if has_fallback && i == last {
+                let others: Vec<&str> = observed
+                    .iter()
+                    .copied()
+                    .filter(|v| !values.contains(v))
+                    .collect();
+                json!(["!", ["in", get, ["literal", others]]])
+            } else if let [value] = values.as_slice() {
+                json!(["==", get, value])
+            } else {
+                json!(["in", get, ["literal", values]])
+            };
+            let suffix = values.first().copied().unwrap_or("other");
+            let base = format!("{}-{suffix}", layer_id(layer));
+            let mut id = base.clone();
+            // A value may itself be called "other".
+            for n in 2.. {
+                if !ids.contains(&id) {
+                    break;
+                }
+                id = format!("{base}-{n}");
+            }
+            ids.push(id.clone());
+            let mut part = layer.clone();
+            let obj = part.as_object_mut().expect("layer is an object");
+            obj.insert("id".into(), json!(id));
+            let filter = match obj.remove("filter") {
+                Some(filter) => json!(["all", filter, selector]),
+                None => selector.clone(),
+            };
+            obj.insert("filter".into(), filter);
+            for ((section, prop, _), output) in props.iter().zip(outputs) {
+                obj[*section][*prop] = (*output).clone();
+            }
+            (part, selector)
+        })
+        .collect();
+    Some(parts)
+}
+
+fn has_sort_key(layer: &Value) -> bool {
+    layer
+        .get("layout")
+        .and_then(Value::as_object)
+        .is_some_and(|o| o.keys().any(|k| k.ends_with("-sort-key")))
+}
+
+/// The first `match` input property whose stats qualify, with its observed values.
+fn split_input<'a>(
+    layer: &'a Value,
+    layer_stats: &'a LayerStats,
+    max_cardinality: SplitCardinality,
+) -> Option<(&'a str, Vec<&'a str>)> {
+    let mut inputs = ["paint", "layout"]
+        .into_iter()
+        .filter_map(|section| layer.get(section).and_then(Value::as_object))
+        .flat_map(serde_json::Map::values)
+        .filter_map(|v| StringMatch::parse(v).map(|m| m.input));
+    inputs.find_map(|input| {
+        let PropertyStats::String {
+            cardinality,
+            value_counts: Some(counts),
+            ..
+        } = layer_stats.properties.get(input)?
+        else {
+            return None;
+        };
+        if *cardinality > max_cardinality.get() {
+            return None;
+        }
+        let observed = counts
+            .iter()
+            .filter(|&(_, &n)| n > 0)
+            .map(|(v, _)| v.as_str())
+            .collect();
+        Some((input, observed))
+    })
+}
+
+/// Every paint/layout value that is a string `match` on `["get", input]`.
+fn match_properties<'a>(
+    layer: &'a Value,
+    input: &str,
+) -> Vec<(&'static str, &'a str, StringMatch<'a>)> {
+    let mut props = Vec::new();
+    for section in ["paint", "layout"] {
+        let Some(obj) = layer.get(section).and_then(Value::as_object) else {
+            continue;
+        };
+        for (prop, value) in obj {
+            if let Some(m) = StringMatch::parse(value).filter(|m| m.input == input) {
+                props.push((section, prop.as_str(), m));
+            }
+        }
+    }
+    props
+}
+
+/// `["match", ["get", input], labels, output, ..., fallback]` with string labels.
+struct StringMatch<'a> {
+    input: &'a str,
+    arms: Vec<(Vec<&'a str>, &'a Value)>,
+    fallback: &'a Value,
+}
+
+impl<'a> StringMatch<'a> {
+    fn parse(v: &'a Value) -> Option<Self> {
+        let arr = v.as_array()?;
+        if arr.first()?.as_str()? != "match" || arr.len() < 5 || arr.len() % 2 != 1 {
+            return None;
+        }
+        let get = arr[1].as_array()?;
+        if get.len() != 2 || get[0].as_str()? != "get" {
+            return None;
+        }
+        let input = get[1].as_str()?;
+        let arms = arr[2..arr.len() - 1]
+            .chunks_exact(2)
+            .map(|pair| {
+                let labels = match &pair[0] {
+                    Value::String(s) => vec![s.as_str()],
+                    Value::Array(labels) => {
+                        labels.iter().map(Value::as_str).collect::<Option<_>>()?
+                    }
+                    _ => return None,
+                };
+                Some((labels, &pair[1]))
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            input,
+            arms,
+            fallback: arr.last()?,
+        })
+    }
+
+    fn output(&self, value: &str) -> &'a Value {
+        self.arms
+            .iter()
+            .find(|(labels, _)| labels.contains(&value))
+            .map_or(self.fallback, |(_, out)| out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::stats::SourceStats;
+
+    fn class_stats(present: u64, total: u64) -> TileStatistics {
+        let layer = LayerStats {
+            total_features: total,
+            properties: BTreeMap::from([(
+                "class".to_string(),
+                PropertyStats::String {
+                    present_count: present,
+                    cardinality: 3,
+                    value_counts: Some(IndexMap::from([
+                        ("grass".to_string(), present - 30),
+                        ("wood".to_string(), 20),
+                        ("farm".to_string(), 10),
+                    ])),
+                },
+            )]),
+            ..Default::default()
+        };
+        TileStatistics {
+            sources: BTreeMap::from([(
+                "s".to_string(),
+                SourceStats {
+                    layers: BTreeMap::from([("landcover".to_string(), layer)]),
+                },
+            )]),
+            sample_rate: 1.0,
+        }
+    }
+
+    fn style(paint: &Value) -> Value {
+        json!({
+            "version": 8,
+            "sources": {"s": {"type": "vector"}},
+            "layers": [{
+                "id": "lc", "type": "fill", "source": "s", "source-layer": "landcover",
+                "filter": ["has", "class"],
+                "paint": paint
+            }]
+        })
+    }
+
+    fn split(v: &mut Value, stats: &TileStatistics, max: u64) -> Vec<SplitLayer> {
+        let max = SplitCardinality::new(max).unwrap();
+        layer_split(v, stats, &KeepLayers::default(), max)
+    }
+
+    #[test]
+    fn splits_by_match_outputs() {
+        let mut v = style(&json!({
+            "fill-color": ["match", ["get", "class"], "wood", "#080", ["farm", "grass"], "#8c4", "#eee"],
+            "fill-opacity": ["match", ["get", "class"], "farm", 0.5, 1]
+        }));
+        let splits = split(&mut v, &class_stats(100, 100), 4);
+        insta::assert_yaml_snapshot!(v["layers"], @r##"
+        - filter:
+            - all
+            - - has
+              - class
+            - - "=="
+              - - get
+                - class
+              - grass
+          id: lc-grass
+          paint:
+            fill-color: "#8c4"
+            fill-opacity: 1
+          source: s
+          source-layer: landcover
+          type: fill
+        - filter:
+            - all
+            - - has
+              - class
+            - - "=="
+              - - get
+                - class
+              - wood
+          id: lc-wood
+          paint:
+            fill-color: "#080"
+            fill-opacity: 1
+          source: s
+          source-layer: landcover
+          type: fill
+        - filter:
+            - all
+            - - has
+              - class
+            - - "=="
+              - - get
+                - class
+              - farm
+          id: lc-farm
+          paint:
+            fill-color: "#8c4"
+            fill-opacity: 0.5
+          source: s
+          source-layer: landcover
+          type: fill
+        "##);
+        assert_eq!(splits[0].from, "lc");
+        assert_eq!(splits[0].parts.len(), 3);
+    }
+
+    #[test]
+    fn values_that_fall_back_keep_the_fallback_part() {
+        let mut v = style(&json!({
+            "fill-color": ["match", ["get", "class"], "wood", "#080", "grass", "#8c4", "#eee"]
+        }));
+        split(&mut v, &class_stats(100, 100), 4);
+        assert_eq!(v["layers"].as_array().unwrap().len(), 3);
+        assert_eq!(v["layers"][2]["id"], "lc-farm");
+        assert_eq!(
+            v["layers"][2]["filter"][2],
+            json!([
+                "!",
+                ["in", ["get", "class"], ["literal", ["grass", "wood"]]]
+            ])
+        );
+        assert_eq!(v["layers"][2]["paint"]["fill-color"], "#eee");
+    }
+
+    #[test]
+    fn features_without_the_property_take_the_fallback() {
+        let mut v = style(&json!({
+            "fill-color": ["match", ["get", "class"], "wood", "#080", "#eee"]
+        }));
+        split(&mut v, &class_stats(90, 100), 4);
+        assert_eq!(
+            v["layers"][0]["filter"][2],
+            json!(["==", ["get", "class"], "wood"])
+        );
+        assert_eq!(
+            v["layers"][1]["filter"][2],
+            json!(["!", ["in", ["get", "class"], ["literal", ["wood"]]]])
+        );
+        assert_eq!(v["layers"][1]["paint"]["fill-color"], "#eee");
+    }
+
+    #[test]
+    fn part_ids_stay_unique_when_a_value_is_called_other() {
+        let mut stats = class_stats(90, 100);
+        let layer = stats
+            .sources
+            .get_mut("s")
+            .unwrap()
+            .layers
+            .get_mut("landcover");
+        let Some(PropertyStats::String {
+            value_counts: Some(counts),
+            ..
+        }) = layer.unwrap().properties.get_mut("class")
+        else {
+            unreachable!("string stats");
+        };
+        counts.swap_remove("farm");
+        counts.insert("other".to_string(), 10);
+        let mut v = style(&json!({
+            "fill-color": [
+                "match", ["get", "class"], "wood", "#080", "other", "#888", "grass", "#8c4", "#eee"
+            ]
+        }));
+        split(&mut v, &stats, 4);
+        let ids: Vec<&str> = v["layers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|l| l["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["lc-grass", "lc-wood", "lc-other", "lc-other-2"]);
+    }
+
+    #[test]
+    fn ref_targets_are_left_alone() {
+        let paint = json!({"fill-color": ["match", ["get", "class"], "wood", "#080", "#eee"]});
+        let mut v = style(&paint);
+        v["layers"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({"id": "lc-outline", "ref": "lc"}));
+        let expected = v.clone();
+        assert!(split(&mut v, &class_stats(100, 100), 4).is_empty());
+        assert_eq!(v, expected);
+    }
+
+    #[test]
+    fn high_cardinality_and_sampled_stats_are_left_alone() {
+        let paint = json!({"fill-color": ["match", ["get", "class"], "wood", "#080", "#eee"]});
+        let mut v = style(&paint);
+        split(&mut v, &class_stats(100, 100), 2);
+        assert_eq!(v, style(&paint));
+
+        let mut stats = class_stats(100, 100);
+        stats.sample_rate = 0.5;
+        let mut v = style(&paint);
+        split(&mut v, &stats, 4);
+        assert_eq!(v, style(&paint));
+    }
+}
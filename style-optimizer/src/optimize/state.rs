@@ -0,0 +1,124 @@
+//! Global-state specialization: replace `["global-state", key]` lookups with
+//! caller-supplied values (`--state key=value`).
+//!
+//! The result is a style specialized to one state (e.g. a dark variant of a
+//! style that branches on `["global-state", "theme"]`); `constant_fold`,
+//! `dead_elimination` and ramp pruning then remove the branches it no longer
+//! takes.  Keys without a supplied value stay runtime lookups.
+
+use serde_json::{Map, Value};
+
+use super::legacy_function::literal;
+use super::walk::{PropertyContext, StyleVisitor};
+
+pub(crate) struct GlobalStateVisitor<'a> {
+    pub(crate) state: &'a Map<String, Value>,
+}
+
+impl StyleVisitor for GlobalStateVisitor<'_> {
+    fn visit_filter(&mut self, _: usize, _: &str, filter: &mut Value) {
+        fold_global_state(filter, self.state);
+    }
+
+    fn visit_property(&mut self, _: &PropertyContext<'_>, value: &mut Value) {
+        fold_global_state(value, self.state);
+    }
+
+    /// Keep the root `state` defaults in line with the folded values, so the
+    /// specialized style reports the state it was built for.
+    fn visit_root(&mut self, root: &mut Value) {
+        let Some(defaults) = root.get_mut("state").and_then(Value::as_object_mut) else {
+            return;
+        };
+        for (key, entry) in defaults {
+            if let Some(value) = self.state.get(key)
+                && let Some(entry) = entry.as_object_mut()
+            {
+                entry.insert("default".into(), value.clone());
+            }
+        }
+    }
+}
+
+fn fold_global_state(v: &mut Value, state: &Map<String, Value>) {
+    let Value::Array(arr) = v else {
+        return;
+    };
+    match arr.first().and_then(Value::as_str) {
+        Some("literal") => return,
+        Some("global-state") => {
+            if let [_, Value::String(key)] = arr.as_slice()
+                && let Some(value) = state.get(key)
+            {
+                *v = literal(value);
+            }
+            return;
+        }
+        _ => {}
+    }
+    for child in arr {
+        fold_global_state(child, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn state() -> Map<String, Value> {
+        let Value::Object(map) = json!({"theme": "dark", "offset": [0, 2]}) else {
+            unreachable!()
+        };
+        map
+    }
+
+    #[test]
+    fn folds_known_keys_only() {
+        let mut v = json!([
+            "case",
+            ["==", ["global-state", "theme"], "dark"],
+            "#000",
+            ["global-state", "accent"]
+        ]);
+        fold_global_state(&mut v, &state());
+        assert_eq!(
+            v,
+            json!([
+                "case",
+                ["==", "dark", "dark"],
+                "#000",
+                ["global-state", "accent"]
+            ])
+        );
+    }
+
+    #[test]
+    fn wraps_array_values_and_skips_literals() {
+        let mut v = json!([
+            "coalesce",
+            ["global-state", "offset"],
+            ["literal", ["global-state", "theme"]]
+        ]);
+        fold_global_state(&mut v, &state());
+        assert_eq!(
+            v,
+            json!([
+                "coalesce",
+                ["literal", [0, 2]],
+                ["literal", ["global-state", "theme"]]
+            ])
+        );
+    }
+
+    #[test]
+    fn updates_root_defaults() {
+        let mut root = json!({"state": {"theme": {"default": "light"}, "x": {"default": 1}}});
+        GlobalStateVisitor { state: &state() }.visit_root(&mut root);
+        assert_eq!(
+            root["state"],
+            json!({"theme": {"default": "dark"}, "x": {"default": 1}})
+        );
+    }
+}
@@ -142,38 +142,34 @@ fn prev_zoom(n: f64) -> f64 {
 /// Returns `Some(f64::INFINITY)` when the value is statically zero at all zooms
 /// (caller should let cleanup handle that).  Returns `None` when no constraint
 /// can be derived (non-zoom expression, data-driven, non-numeric stops, etc.).
-#[allow(clippy::float_cmp)]
 pub(super) fn visibility_minzoom_from_value(value: &Value) -> Option<f64> {
-    match value {
-        // Scalar literal (bare or wrapped in ["literal", n]).
-        Value::Number(n) => {
-            if n.as_f64() == Some(0.0) {
-                Some(f64::INFINITY)
-            } else {
-                None
-            }
-        }
-        Value::Array(arr) if arr.len() == 2 && arr[0].as_str() == Some("literal") => {
-            let n = arr[1].as_f64()?;
-            if n == 0.0 { Some(f64::INFINITY) } else { None }
-        }
-        Value::Array(arr) if arr.len() >= 4 => {
-            let head = arr[0].as_str()?;
-            match head {
-                "interpolate" | "interpolate-hcl" | "interpolate-lab" => {
-                    visibility_minzoom_interpolate(arr)
-                }
-                "step" => visibility_minzoom_step(arr),
-                _ => None,
-            }
+    hidden_minzoom_from_value(value, &|v| v.as_f64().map(|n| n == 0.0))
+}
+
+/// Like [`visibility_minzoom_from_value`], for any property whose literal
+/// values `is_hidden` classifies (`None` for values it cannot judge).
+pub(super) fn hidden_minzoom_from_value(
+    value: &Value,
+    is_hidden: &dyn Fn(&Value) -> Option<bool>,
+) -> Option<f64> {
+    if let Some(lit) = extract_json_literal(value) {
+        return is_hidden(&lit)?.then_some(f64::INFINITY);
+    }
+    let arr = value.as_array().filter(|arr| arr.len() >= 4)?;
+    match arr[0].as_str()? {
+        "interpolate" | "interpolate-hcl" | "interpolate-lab" => {
+            hidden_minzoom_interpolate(arr, is_hidden)
         }
+        "step" => hidden_minzoom_step(arr, is_hidden),
         _ => None,
     }
 }
 
 /// `["interpolate", curve, ["zoom"], z1, v1, z2, v2, ...]`
-#[allow(clippy::float_cmp)]
-fn visibility_minzoom_interpolate(arr: &[Value]) -> Option<f64> {
+fn hidden_minzoom_interpolate(
+    arr: &[Value],
+    is_hidden: &dyn Fn(&Value) -> Option<bool>,
+) -> Option<f64> {
     // arr[1] = curve, arr[2] = input (must be ["zoom"]), then pairs.
     if arr.len() < 5 {
         return None;
@@ -186,28 +182,25 @@ fn visibility_minzoom_interpolate(arr: &[Value]) -> Option<f64> {
         return None;
     }
 
-    let mut last_zero_stop = None;
-    let mut all_zero = true;
-
+    // Values hold the first stop's output below it, so the layer is hidden up
+    // to the last stop of the leading run of hidden outputs.
+    let mut last_hidden_stop = None;
     for chunk in pairs.chunks_exact(2) {
         let z = chunk[0].as_f64()?;
-        let v = extract_json_literal(&chunk[1]).and_then(|v| v.as_f64())?;
-        if v == 0.0 {
-            last_zero_stop = Some(z);
+        let v = extract_json_literal(&chunk[1])?;
+        if is_hidden(&v)? {
+            last_hidden_stop = Some(z);
         } else {
-            all_zero = false;
+            return last_hidden_stop;
         }
     }
 
-    if all_zero {
-        return Some(f64::INFINITY);
-    }
-    last_zero_stop
+    // All stops are hidden.
+    Some(f64::INFINITY)
 }
 
 /// `["step", ["zoom"], default, z1, v1, z2, v2, ...]`
-#[allow(clippy::float_cmp)]
-fn visibility_minzoom_step(arr: &[Value]) -> Option<f64> {
+fn hidden_minzoom_step(arr: &[Value], is_hidden: &dyn Fn(&Value) -> Option<bool>) -> Option<f64> {
     if arr.len() < 4 {
         return None;
     }
@@ -216,27 +209,27 @@ fn visibility_minzoom_step(arr: &[Value]) -> Option<f64> {
     }
 
     // Default output (before first stop).
-    let default_val = extract_json_literal(&arr[2]).and_then(|v| v.as_f64())?;
+    let default_val = extract_json_literal(&arr[2])?;
     let pairs = &arr[3..];
     if !pairs.len().is_multiple_of(2) {
         return None;
     }
 
-    if default_val != 0.0 {
-        // Default is non-zero → visible from the start.
+    if !is_hidden(&default_val)? {
+        // Default is visible → visible from the start.
         return None;
     }
 
-    // Walk stops to find the first non-zero value.
+    // Walk stops to find the first visible value.
     for chunk in pairs.chunks_exact(2) {
         let z = chunk[0].as_f64()?;
-        let v = extract_json_literal(&chunk[1]).and_then(|v| v.as_f64())?;
-        if v != 0.0 {
+        let v = extract_json_literal(&chunk[1])?;
+        if !is_hidden(&v)? {
             return Some(z);
         }
     }
 
-    // All stops are zero.
+    // All stops are hidden.
     Some(f64::INFINITY)
 }
 
@@ -5,7 +5,8 @@
 
 use std::collections::HashSet;
 
-use maplibre_style_spec::spec::expressions::{Any, Boolean, ExprOrLiteral, String as StringExpr};
+use maplibre_style_spec::expression_eval::{EvalValue, EvaluationContext, Feature, evaluate};
+use maplibre_style_spec::spec::expressions::Boolean;
 use serde_json::Value;
 
 use crate::advisory::{GeometryType, SourceAdvisory, SourceLayerAdvisory, UnusedValues};
@@ -61,7 +62,7 @@ fn prune_layer(layer: &mut mvt::tile::Layer, advisory: &SourceLayerAdvisory, zoo
     filter_by_property_values(layer, &advisory.unused_property_values);
 
     // Reorder features by priority: features matching more style-layer filters come first.
-    reorder_features_by_priority(layer, &advisory.layer_filters);
+    reorder_features_by_priority(layer, &advisory.layer_filters, zoom);
 
     // Strip feature IDs when no targeting layer uses them.
     if !advisory.feature_ids_needed {
@@ -346,256 +347,89 @@ fn mvt_value_matches_json(mvt_val: &mvt::tile::Value, json_val: &Value) -> bool
 
 // ── Feature priority reordering ──────────────────────────────────────────────
 
-/// A resolved value from evaluating an expression against a feature.
-enum Resolved<'a> {
-    Null,
-    Bool(bool),
-    Number(f64),
-    Str(&'a str),
-}
-
-impl Resolved<'_> {
-    fn as_f64(&self) -> Option<f64> {
-        match self {
-            Self::Number(n) => Some(*n),
-            _ => None,
-        }
-    }
-}
-
-impl PartialEq for Resolved<'_> {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::Null, Self::Null) => true,
-            (Self::Bool(a), Self::Bool(b)) => a == b,
-            (Self::Number(a), Self::Number(b)) => a == b,
-            (Self::Str(a), Self::Str(b)) => a == b,
-            _ => false,
-        }
-    }
-}
-
-/// Look up a property value in an MVT feature's tag pairs.
-fn lookup_property<'a>(
-    prop_name: &str,
-    feature: &mvt::tile::Feature,
-    keys: &[String],
-    values: &'a [mvt::tile::Value],
-) -> Option<&'a mvt::tile::Value> {
-    let mut i = 0;
-    while i + 1 < feature.tags.len() {
-        let ki = feature.tags[i] as usize;
-        let vi = feature.tags[i + 1] as usize;
-        if keys.get(ki).is_some_and(|k| k == prop_name) {
-            return values.get(vi);
-        }
-        i += 2;
-    }
-    None
-}
-
-/// Convert an MVT value to a `Resolved`.
-fn mvt_value_to_resolved(v: &mvt::tile::Value) -> Resolved<'_> {
+/// Convert an MVT value to the JSON value GL JS decodes it to.
+fn mvt_value_to_json(v: &mvt::tile::Value) -> Value {
     if let Some(s) = v.string_value.as_deref() {
-        Resolved::Str(s)
+        Value::from(s)
     } else if let Some(b) = v.bool_value {
-        Resolved::Bool(b)
+        Value::from(b)
     } else if let Some(n) = v.double_value {
-        Resolved::Number(n)
+        Value::from(n)
     } else if let Some(n) = v.float_value {
-        Resolved::Number(f64::from(n))
-    } else if let Some(n) = v.int_value {
-        #[expect(clippy::cast_precision_loss)]
-        Resolved::Number(n as f64)
+        Value::from(f64::from(n))
+    } else if let Some(n) = v.int_value.or(v.sint_value) {
+        Value::from(n)
     } else if let Some(n) = v.uint_value {
-        #[expect(clippy::cast_precision_loss)]
-        Resolved::Number(n as f64)
-    } else if let Some(n) = v.sint_value {
-        #[expect(clippy::cast_precision_loss)]
-        Resolved::Number(n as f64)
+        Value::from(n)
     } else {
-        Resolved::Null
+        Value::Null
     }
 }
 
-/// Resolve an `ExprOrLiteral` to a comparable value using feature properties.
-fn resolve_expr<'a>(
-    expr: &'a ExprOrLiteral,
-    feature: &mvt::tile::Feature,
-    keys: &[String],
-    values: &'a [mvt::tile::Value],
-) -> Resolved<'a> {
-    match expr {
-        ExprOrLiteral::Bool(b) => Resolved::Bool(*b),
-        ExprOrLiteral::NumberLiteral(n) => Resolved::Number(n.as_f64().unwrap_or(f64::NAN)),
-        ExprOrLiteral::StringLiteral(s) => Resolved::Str(s.as_str()),
-        ExprOrLiteral::AnyExpr(any) => match any.as_ref() {
-            Any::Get(prop, None) => {
-                // Resolve the property name from the String expression.
-                let prop_name = resolve_string_expr(prop);
-                match prop_name {
-                    Some(name) => lookup_property(name, feature, keys, values)
-                        .map_or(Resolved::Null, mvt_value_to_resolved),
-                    None => Resolved::Null,
-                }
-            }
-            Any::Id => feature.id.map_or(Resolved::Null, |id| {
-                #[expect(clippy::cast_precision_loss)]
-                Resolved::Number(id as f64)
-            }),
-            _ => Resolved::Null,
-        },
-        ExprOrLiteral::StringExpr(s) => match s.as_ref() {
-            StringExpr::GeometryType => {
-                let gt = feature.r#type.unwrap_or(0);
-                let name = match mvt::tile::GeomType::try_from(gt) {
-                    Ok(mvt::tile::GeomType::Point) => "Point",
-                    Ok(mvt::tile::GeomType::Linestring) => "LineString",
-                    Ok(mvt::tile::GeomType::Polygon) => "Polygon",
-                    _ => "Unknown",
-                };
-                Resolved::Str(name)
-            }
-            StringExpr::Literal(s) => Resolved::Str(s.as_str()),
-            _ => Resolved::Null,
-        },
-        _ => Resolved::Null,
-    }
-}
-
-/// Extract a literal string from a `String` expression (the property name in `["get", prop]`).
-fn resolve_string_expr(expr: &StringExpr) -> Option<&str> {
-    match expr {
-        StringExpr::Literal(s) => Some(s.as_str()),
-        _ => None,
-    }
-}
-
-/// Check if a feature has a property by name.
-fn feature_has_property(prop_name: &str, feature: &mvt::tile::Feature, keys: &[String]) -> bool {
-    let mut i = 0;
-    while i + 1 < feature.tags.len() {
-        let ki = feature.tags[i] as usize;
-        if keys.get(ki).is_some_and(|k| k == prop_name) {
-            return true;
-        }
-        i += 2;
-    }
-    false
-}
-
-/// Evaluate a typed `Boolean` filter expression against an MVT feature.
-/// Returns `true` if the feature matches the filter.
-fn eval_filter(
-    filter: &Boolean,
+/// The expression interpreter's view of an MVT feature: its tags, id and
+/// geometry type.
+fn eval_feature(
     feature: &mvt::tile::Feature,
     keys: &[String],
     values: &[mvt::tile::Value],
-) -> bool {
-    match filter {
-        Boolean::Literal(b) => *b,
-        Boolean::Not(inner) => !eval_filter(inner, feature, keys, values),
-        Boolean::All(conditions) => conditions
-            .iter()
-            .all(|c| eval_filter(c, feature, keys, values)),
-        Boolean::Any(conditions) => conditions
-            .iter()
-            .any(|c| eval_filter(c, feature, keys, values)),
-        Boolean::EqualEqual(a, b, _) => {
-            let ra = resolve_expr(a, feature, keys, values);
-            let rb = resolve_expr(b, feature, keys, values);
-            ra == rb
-        }
-        Boolean::NotEqual(a, b, _) => {
-            let ra = resolve_expr(a, feature, keys, values);
-            let rb = resolve_expr(b, feature, keys, values);
-            ra != rb
-        }
-        Boolean::Less(a, b, _) => {
-            let ra = resolve_expr(a, feature, keys, values);
-            let rb = resolve_expr(b, feature, keys, values);
-            matches!((ra.as_f64(), rb.as_f64()), (Some(x), Some(y)) if x < y)
-        }
-        Boolean::LessEqual(a, b, _) => {
-            let ra = resolve_expr(a, feature, keys, values);
-            let rb = resolve_expr(b, feature, keys, values);
-            matches!((ra.as_f64(), rb.as_f64()), (Some(x), Some(y)) if x <= y)
-        }
-        Boolean::Greater(a, b, _) => {
-            let ra = resolve_expr(a, feature, keys, values);
-            let rb = resolve_expr(b, feature, keys, values);
-            matches!((ra.as_f64(), rb.as_f64()), (Some(x), Some(y)) if x > y)
-        }
-        Boolean::GreaterEqual(a, b, _) => {
-            let ra = resolve_expr(a, feature, keys, values);
-            let rb = resolve_expr(b, feature, keys, values);
-            matches!((ra.as_f64(), rb.as_f64()), (Some(x), Some(y)) if x >= y)
-        }
-        Boolean::Has(prop, None) => {
-            resolve_string_expr(prop).is_some_and(|name| feature_has_property(name, feature, keys))
-        }
-        Boolean::In(needle, haystack) => {
-            let rn = resolve_expr(needle, feature, keys, values);
-            // If haystack is a literal array, check membership.
-            if let ExprOrLiteral::AnyExpr(any) = haystack
-                && let Any::Get(prop, None) = any.as_ref()
-            {
-                // ["in", needle, ["get", prop]] — check if needle is substring
-                if let (Some(name), Resolved::Str(needle_str)) = (resolve_string_expr(prop), &rn) {
-                    return lookup_property(name, feature, keys, values)
-                        .and_then(|v| v.string_value.as_deref())
-                        .is_some_and(|s| s.contains(needle_str));
-                }
-            }
-            // For literal arrays (not common in filter context), conservative false.
-            false
-        }
-        Boolean::AnyExpr(any) => match any.as_ref() {
-            Any::Case(arms) => {
-                let (cases, fallback) = arms;
-                for (cond, result) in cases {
-                    if eval_filter(cond, feature, keys, values) {
-                        return resolve_expr(result, feature, keys, values) != Resolved::Null
-                            && resolve_expr(result, feature, keys, values)
-                                != Resolved::Bool(false);
-                    }
-                }
-                let r = resolve_expr(fallback, feature, keys, values);
-                r != Resolved::Null && r != Resolved::Bool(false)
-            }
-            _ => false, // Conservative: unsupported expression → no match.
-        },
-        // Unsupported variants → conservative false.
-        _ => false,
+) -> Feature {
+    let properties = feature
+        .tags
+        .chunks_exact(2)
+        .filter_map(|pair| {
+            let key = keys.get(pair[0] as usize)?;
+            let value = values.get(pair[1] as usize)?;
+            Some((key.clone(), mvt_value_to_json(value)))
+        })
+        .collect();
+    let geometry_type = match mvt::tile::GeomType::try_from(feature.r#type.unwrap_or(0)) {
+        Ok(mvt::tile::GeomType::Point) => Some("Point"),
+        Ok(mvt::tile::GeomType::Linestring) => Some("LineString"),
+        Ok(mvt::tile::GeomType::Polygon) => Some("Polygon"),
+        _ => None,
+    };
+    Feature {
+        id: feature.id.map(Value::from),
+        properties,
+        geometry: None,
+        geometry_type: geometry_type.map(str::to_owned),
     }
 }
 
-/// Count how many of the given filters a feature matches.
-fn count_matching_filters(
-    feature: &mvt::tile::Feature,
-    keys: &[String],
-    values: &[mvt::tile::Value],
-    filters: &[Boolean],
-) -> usize {
-    filters
-        .iter()
-        .filter(|f| eval_filter(f, feature, keys, values))
-        .count()
+/// Whether a filter expression matches `feature` at `zoom`.  As in GL JS,
+/// only `true` matches; evaluation errors do not.
+fn filter_matches(filter: &Value, feature: &Feature, zoom: u8) -> bool {
+    let ctx = EvaluationContext {
+        zoom: Some(f64::from(zoom)),
+        feature: Some(feature),
+        ..EvaluationContext::default()
+    };
+    matches!(evaluate(filter, &ctx), Ok(EvalValue::Boolean(true)))
 }
 
 /// Reorder features within a layer by the number of style-layer filters they match.
 /// Features matching more filters are placed first (higher priority).
 /// Uses stable sort to preserve original order for ties.
-fn reorder_features_by_priority(layer: &mut mvt::tile::Layer, layer_filters: &[Boolean]) {
+fn reorder_features_by_priority(layer: &mut mvt::tile::Layer, layer_filters: &[Boolean], zoom: u8) {
     if layer_filters.is_empty() {
         return;
     }
+    let filters: Vec<Value> = layer_filters
+        .iter()
+        .filter_map(|f| serde_json::to_value(f).ok())
+        .collect();
 
     // Pre-compute scores to avoid O(n*m) comparisons during sort.
     let scores: Vec<usize> = layer
         .features
         .iter()
-        .map(|f| count_matching_filters(f, &layer.keys, &layer.values, layer_filters))
+        .map(|f| {
+            let feature = eval_feature(f, &layer.keys, &layer.values);
+            filters
+                .iter()
+                .filter(|filter| filter_matches(filter, &feature, zoom))
+                .count()
+        })
         .collect();
 
     // Build index array and sort by score descending.
@@ -1240,4 +1074,39 @@ mod tests {
         // So Point should come first.
         assert_eq!(ids, vec![2, 1]);
     }
+
+    #[test]
+    fn reorder_evaluates_full_expressions() {
+        let mut tile = make_reorder_tile();
+
+        // `match` and `zoom` were beyond the old filter subset.
+        let f1 = parse_filter(serde_json::json!([
+            "all",
+            ["match", ["get", "class"], ["path"], true, false],
+            [">=", ["zoom"], 10]
+        ]));
+
+        let advisory = SourceAdvisory {
+            unused_source_layers: vec![],
+            layers: BTreeMap::from([(
+                "transport".to_string(),
+                SourceLayerAdvisory {
+                    used_properties: BTreeMap::from([("class".to_string(), ZoomRange::All)]),
+                    used_geometry_types: BTreeMap::new(),
+                    unused_zoom_levels: vec![],
+                    unused_property_values: BTreeMap::new(),
+                    interned_properties: BTreeMap::new(),
+                    feature_ids_needed: true,
+                    combined_filter: None,
+                    layer_filters: vec![f1],
+                },
+            )]),
+        };
+
+        prune_tile(&mut tile, &advisory, 10);
+
+        let transport = tile.layers.iter().find(|l| l.name == "transport").unwrap();
+        let ids: Vec<u64> = transport.features.iter().map(|f| f.id.unwrap()).collect();
+        assert_eq!(ids, vec![3, 1, 2]);
+    }
 }
@@ -98,7 +98,7 @@ fn fold_get_with_redundant_properties() {
         ["get", "name", ["properties"]],
         "v"
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0], @r#"
     filter:
       - "=="
@@ -116,7 +116,7 @@ fn fold_get_with_redundant_properties() {
 fn fold_has_with_redundant_properties() {
     let mir = sample_mir();
     let mut v = style_with_filter(serde_json::json!(["has", "name", ["properties"]]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0], @"
     filter:
       - has
@@ -134,7 +134,7 @@ fn fold_has_with_redundant_properties() {
 fn fold_empty_all_to_true() {
     let mir = sample_mir();
     let mut v = style_with_filter(serde_json::json!(["all"]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"true");
 }
 
@@ -142,7 +142,7 @@ fn fold_empty_all_to_true() {
 fn fold_empty_any_to_false() {
     let mir = sample_mir();
     let mut v = style_with_filter(serde_json::json!(["any"]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"false");
 }
 
@@ -150,7 +150,7 @@ fn fold_empty_any_to_false() {
 fn fold_literal_comparison() {
     let mir = sample_mir();
     let mut v = style_with_filter(serde_json::json!(["==", 2, 3]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"false");
 }
 
@@ -158,7 +158,7 @@ fn fold_literal_comparison() {
 fn fold_pure_arithmetic() {
     let mir = sample_mir();
     let mut v = style_with_filter(serde_json::json!(["+", 1, 2]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"3");
 }
 
@@ -166,10 +166,39 @@ fn fold_pure_arithmetic() {
 fn fold_string_concat() {
     let mir = sample_mir();
     let mut v = style_with_filter(serde_json::json!(["concat", "hello", " ", "world"]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"hello world");
 }
 
+#[test]
+fn fold_colours_only_where_a_colour_is_read() {
+    let mir = sample_mir();
+    let mut v = style_with_paint(
+        "fill-color",
+        serde_json::json!([
+            "match",
+            ["get", "k"],
+            "a",
+            ["rgb", 255, 128, 0],
+            ["rgb", 0, 0, 0]
+        ]),
+    );
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
+    assert_eq!(
+        v["layers"][0]["paint"]["fill-color"],
+        serde_json::json!(["match", ["get", "k"], "a", "#ff8000", "#000"])
+    );
+
+    // GL JS prints colours as `rgba(...)`, and `typeof` still sees a colour.
+    let mut v = style_with_filter(serde_json::json!([
+        "all",
+        ["==", ["concat", ["rgb", 255, 128, 0]], "rgba(255,128,0,1)"],
+        ["==", ["typeof", ["to-color", "#f80"]], "color"]
+    ]));
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
+    assert_eq!(v["layers"][0]["filter"], serde_json::json!(true));
+}
+
 // ── Stats: has fold ──────────────────────────────────────────────────────
 
 #[test]
@@ -184,7 +213,7 @@ fn has_folds_false_when_property_absent() {
         },
     );
     let mut v = style_with_filter(serde_json::json!(["has", "missing"]));
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"false");
 }
 
@@ -207,7 +236,7 @@ fn has_folds_true_when_always_present() {
         },
     );
     let mut v = style_with_filter(serde_json::json!(["has", "name"]));
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"true");
 }
 
@@ -231,7 +260,7 @@ fn has_unchanged_when_partially_present() {
     );
     let mut v = style_with_filter(serde_json::json!(["has", "name"]));
     let original = v["layers"][0]["filter"].clone();
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_eq!(v["layers"][0]["filter"], original);
 }
 
@@ -260,7 +289,7 @@ fn geometry_type_eq_folds_true_when_only_type() {
         "layers": [{"id": "l", "type": "symbol", "source": "src", "source-layer": "lyr",
                      "filter": ["==", ["geometry-type"], "Point"]}]
     });
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"true");
 }
 
@@ -287,7 +316,7 @@ fn geometry_type_eq_folds_false_when_absent() {
         "layers": [{"id": "l", "type": "symbol", "source": "src", "source-layer": "lyr",
                      "filter": ["==", ["geometry-type"], "Polygon"]}]
     });
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"false");
 }
 
@@ -318,7 +347,7 @@ fn comparison_lt_folds_false_when_min_ge_n() {
     let mir = sample_mir();
     let stats = rank_stats(5, 10);
     let mut v = style_with_filter(serde_json::json!(["<", ["get", "rank"], 3]));
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"false");
 }
 
@@ -327,7 +356,7 @@ fn comparison_gte_folds_true_when_min_ge_n() {
     let mir = sample_mir();
     let stats = rank_stats(5, 10);
     let mut v = style_with_filter(serde_json::json!([">=", ["get", "rank"], 3]));
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"true");
 }
 
@@ -336,7 +365,7 @@ fn comparison_eq_folds_false_when_out_of_range() {
     let mir = sample_mir();
     let stats = rank_stats(5, 10);
     let mut v = style_with_filter(serde_json::json!(["==", ["get", "rank"], 99]));
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"false");
 }
 
@@ -370,7 +399,7 @@ fn in_prune_removes_dead_values() {
         ["get", "class"],
         ["literal", ["lake", "ocean", "sea"]]
     ]));
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @r#"
     - "=="
     - - get
@@ -388,7 +417,7 @@ fn in_prune_all_dead_folds_to_false() {
         ["get", "class"],
         ["literal", ["ocean", "sea"]]
     ]));
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"false");
 }
 
@@ -410,7 +439,7 @@ fn match_prune_removes_dead_arm() {
             "#ccc"
         ]),
     );
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"
     fill-color:
       - match
@@ -446,7 +475,7 @@ fn coalesce_truncates_after_always_present_arm() {
         "text-field",
         serde_json::json!(["coalesce", ["get", "name"], ["get", "name_en"], "fallback"]),
     );
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["layout"], @"
     text-field:
       - get
@@ -464,7 +493,7 @@ fn step_prune_above_max() {
         "fill-opacity",
         serde_json::json!(["step", ["get", "rank"], 0.1, 3, 0.5, 7, 0.9]),
     );
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @"
     fill-opacity:
       - step
@@ -498,7 +527,7 @@ fn interpolate_prune_above_max() {
             5.0
         ]),
     );
-    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &fold_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @"
     fill-opacity:
       - interpolate
@@ -528,7 +557,7 @@ fn sccp_case_substitutes_get_in_output() {
             "other"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r#"
     fill-color:
       - case
@@ -553,7 +582,7 @@ fn sccp_case_commuted_operands() {
             "other"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r#"
     fill-color:
       - case
@@ -578,7 +607,7 @@ fn sccp_case_enables_concat_fold() {
             "other"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r#"
     fill-color:
       - case
@@ -604,7 +633,7 @@ fn sccp_match_substitutes_input() {
             "default"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @"
     fill-color:
       - match
@@ -629,7 +658,7 @@ fn sccp_match_enables_concat_fold() {
             "default"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @"
     fill-color:
       - match
@@ -654,7 +683,7 @@ fn sccp_match_skips_multi_label_arms() {
             "default"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     // Output should be unchanged — multi-label arm is not substituted
     assert_yaml_snapshot!(v["layers"][0]["paint"], @"
     fill-color:
@@ -681,7 +710,7 @@ fn sccp_case_skips_not_equal_condition() {
             "other"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     // Output should be unchanged — != doesn't constrain the value
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r#"
     fill-color:
@@ -714,7 +743,7 @@ fn filter_propagation_match_folds_to_literal() {
             "#999"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"fill-color: "#333""##);
 }
 
@@ -726,7 +755,7 @@ fn filter_propagation_case_folds_true_branch() {
         "fill-color",
         serde_json::json!(["case", ["==", ["get", "kind"], "park"], "#0f0", "#ccc"]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"fill-color: "#0f0""##);
 }
 
@@ -750,7 +779,7 @@ fn filter_propagation_all_extracts_multiple_constraints() {
             "#999"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"fill-color: "#333""##);
 }
 
@@ -770,7 +799,7 @@ fn filter_propagation_commuted_equality() {
             "#999"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"fill-color: "#333""##);
 }
 
@@ -793,7 +822,7 @@ fn filter_propagation_no_leak_across_layers() {
             }
         ]
     });
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     // First layer should be folded.
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"fill-color: "#333""##);
     // Second layer should remain data-driven (no filter constraint).
@@ -819,7 +848,7 @@ fn filter_propagation_has_eliminates_coalesce_fallback() {
         serde_json::json!(["coalesce", ["get", "name"], "unnamed"]),
     );
     // simplify_passes needed so try_simplify_coalesce unwraps single-arg coalesce.
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @"
     fill-color:
       - get
@@ -837,7 +866,7 @@ fn filter_propagation_has_coalesce_not_first_arg() {
         "fill-color",
         serde_json::json!(["coalesce", ["get", "name"], ["get", "alt_name"], "fallback"]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @"
     fill-color:
       - coalesce
@@ -862,7 +891,7 @@ fn filter_propagation_has_nested() {
             "#ccc"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"
     fill-color:
       - case
@@ -896,7 +925,7 @@ fn filter_propagation_in_prunes_match_arms() {
             "#999"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"
     fill-color:
       - match
@@ -927,7 +956,7 @@ fn filter_propagation_in_collapses_to_fallback() {
             "#999"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"fill-color: "#999""##);
 }
 
@@ -949,7 +978,7 @@ fn filter_propagation_in_with_array_labels() {
             "#999"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"
     fill-color:
       - match
@@ -974,7 +1003,7 @@ fn filter_propagation_range_folds_ge_to_true() {
         "fill-color",
         serde_json::json!(["case", [">=", ["get", "scalerank"], 1], "#0f0", "#ccc"]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"fill-color: "#0f0""##);
 }
 
@@ -987,7 +1016,7 @@ fn filter_propagation_range_folds_lt_to_false() {
         "fill-color",
         serde_json::json!(["case", ["<", ["get", "scalerank"], 2], "#0f0", "#ccc"]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"fill-color: "#ccc""##);
 }
 
@@ -1000,7 +1029,7 @@ fn filter_propagation_range_no_fold_ambiguous() {
         "fill-color",
         serde_json::json!(["case", [">=", ["get", "scalerank"], 5], "#0f0", "#ccc"]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"
     fill-color:
       - case
@@ -1023,7 +1052,7 @@ fn filter_propagation_range_commuted() {
         "fill-color",
         serde_json::json!(["case", [">=", ["get", "scalerank"], 1], "#0f0", "#ccc"]),
     );
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @r##"fill-color: "#0f0""##);
 }
 
@@ -1044,7 +1073,7 @@ fn filter_propagation_all_with_mixed_constraints() {
             "#ccc"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"], @"
     fill-color:
       - get
@@ -1063,7 +1092,7 @@ fn distributive_factor_any_of_alls() {
         ["all", ["has", "name"], ["==", ["get", "class"], "road"]],
         ["all", ["has", "name"], ["==", ["get", "class"], "rail"]]
     ]));
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     // Factoring produces ["all", ["has","name"], ["any", ==road, ==rail]]
     // then any_to_in rewrites the inner any to ["in", ...].
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"
@@ -1088,7 +1117,7 @@ fn distributive_factor_all_of_anys() {
         ["any", ["has", "name"], ["==", ["get", "class"], "road"]],
         ["any", ["has", "name"], ["==", ["get", "class"], "rail"]]
     ]));
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     // Factoring extracts ["has","name"], absorption may also interact.
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"
     - has
@@ -1115,7 +1144,7 @@ fn distributive_factor_multiple_common() {
             ["==", ["get", "class"], "rail"]
         ]
     ]));
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     // Factoring then any_to_in on the remainder.
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"
     - all
@@ -1142,7 +1171,7 @@ fn distributive_factor_no_common_unchanged() {
         ["all", ["has", "rank"], ["==", ["get", "class"], "rail"]]
     ]));
     let original = v["layers"][0]["filter"].clone();
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_eq!(v["layers"][0]["filter"], original);
 }
 
@@ -1154,7 +1183,7 @@ fn distributive_factor_single_child_unchanged() {
         "any",
         ["all", ["has", "name"], ["==", ["get", "class"], "road"]]
     ]));
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     // simplify_unary unwraps: ["any", X] → X
     assert_yaml_snapshot!(v["layers"][0]["filter"], @r#"
     - all
@@ -1204,6 +1233,7 @@ fn zoom_fold_passes() -> OptPasses {
         constant_fold: true,
         simplify_unary: true,
         simplify_expressions: true,
+        prune_zoom_ramps: true,
         // Need a structural pass to trigger the re-fold (step 3b).
         cleanup: true,
         ..Default::default()
@@ -1219,7 +1249,7 @@ fn zoom_fold_gte_true() {
         serde_json::json!(["case", [">=", ["zoom"], 5], 0.8, 0.2]),
         10.0,
     );
-    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes());
+    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes()).unwrap();
     // case folds to true branch → 0.8
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @"0.8");
 }
@@ -1233,7 +1263,7 @@ fn zoom_fold_lt_false() {
         serde_json::json!(["case", ["<", ["zoom"], 5], 0.8, 0.2]),
         8.0,
     );
-    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes());
+    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes()).unwrap();
     // case folds to false (fallback) → 0.2
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @"0.2");
 }
@@ -1252,7 +1282,7 @@ fn zoom_fold_inside_all() {
         ]),
         10.0,
     );
-    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes());
+    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes()).unwrap();
     // [">=", ["zoom"], 5] → true, ["all", true, ["has", "name"]] → ["has", "name"]
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @"
     - case
@@ -1272,7 +1302,7 @@ fn zoom_fold_eq_false() {
         serde_json::json!(["case", ["==", ["zoom"], 3], 0.8, 0.2]),
         5.0,
     );
-    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes());
+    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @"0.2");
 }
 
@@ -1285,7 +1315,7 @@ fn zoom_fold_commuted_lte_false() {
         serde_json::json!(["case", [">=", 5, ["zoom"]], 0.8, 0.2]),
         8.0,
     );
-    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes());
+    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @"0.2");
 }
 
@@ -1298,7 +1328,7 @@ fn zoom_fold_undetermined_no_change() {
         serde_json::json!(["case", [">=", ["zoom"], 15], 0.8, 0.2]),
         10.0,
     );
-    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes());
+    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @r#"
     - case
     - - ">="
@@ -1317,7 +1347,7 @@ fn zoom_fold_no_minzoom_no_change() {
         "fill-opacity",
         serde_json::json!(["case", [">=", ["zoom"], 5], 0.8, 0.2]),
     );
-    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes());
+    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @r#"
     - case
     - - ">="
@@ -1336,7 +1366,7 @@ fn zoom_fold_in_filter() {
         serde_json::json!(["all", [">=", ["zoom"], 5], ["has", "name"]]),
         10.0,
     );
-    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes());
+    optimize_style_json_value(&mut v, &mir, &zoom_fold_passes()).unwrap();
     // [">=", ["zoom"], 5] → true, ["all", true, ["has", "name"]] → ["has", "name"]
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"
     - has
@@ -1384,7 +1414,7 @@ fn match_arms_reordered_by_frequency() {
             "black"
         ]),
     );
-    optimize_style_json_value_with_stats(&mut v, &mir, &simplify_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &simplify_passes(), Some(&stats)).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-color"], @"
     - match
     - - get
@@ -1425,7 +1455,7 @@ fn match_arms_unchanged_when_already_ordered() {
         serde_json::json!(["match", ["get", "class"], "a", "red", "b", "blue", "black"]),
     );
     let original = v["layers"][0]["paint"]["fill-color"].clone();
-    optimize_style_json_value_with_stats(&mut v, &mir, &simplify_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &simplify_passes(), Some(&stats)).unwrap();
     assert_eq!(v["layers"][0]["paint"]["fill-color"], original);
 }
 
@@ -1452,7 +1482,7 @@ fn match_arms_not_reordered_without_value_counts() {
         serde_json::json!(["match", ["get", "class"], "b", "red", "a", "blue", "black"]),
     );
     let original = v["layers"][0]["paint"]["fill-color"].clone();
-    optimize_style_json_value_with_stats(&mut v, &mir, &simplify_passes(), Some(&stats));
+    optimize_style_json_value_with_stats(&mut v, &mir, &simplify_passes(), Some(&stats)).unwrap();
     assert_eq!(v["layers"][0]["paint"]["fill-color"], original);
 }
 
@@ -1470,7 +1500,7 @@ fn equivalence_substitution_in_subsumption() {
             ["literal", ["road", "rail", "path"]]
         ]
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"], @r#"
     - filter:
         - all
@@ -1493,7 +1523,7 @@ fn equivalence_substitution_comparison() {
         ["==", ["get", "x"], 5],
         [">=", ["get", "x"], 3]
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"], @r#"
     - filter:
         - all
@@ -1517,7 +1547,7 @@ fn equivalence_substitution_no_binding_without_equality() {
         ["in", ["get", "x"], ["literal", [5, 6, 7]]]
     ]));
     let original_filter = v["layers"][0]["filter"].clone();
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_eq!(v["layers"][0]["filter"], original_filter);
 }
 
@@ -1534,7 +1564,7 @@ fn equivalence_substitution_multiple_equalities() {
             ["literal", ["road", "rail", "path"]]
         ]
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"], @r#"
     - filter:
         - all
@@ -1565,7 +1595,7 @@ fn equivalence_substitution_commuted_equality() {
             ["literal", ["road", "rail", "path"]]
         ]
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"], @r#"
     - filter:
         - all
@@ -1592,7 +1622,7 @@ fn equivalence_substitution_flat_walk_only() {
             ["==", ["get", "class"], "path"]
         ]
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"], @r#"
     - filter:
         - all
@@ -1615,7 +1645,7 @@ fn equivalence_substitution_no_self_modification() {
         ["==", ["get", "class"], "road"],
         ["!=", ["get", "class"], "rail"]
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"], @r#"
     - filter:
         - all
@@ -1644,7 +1674,7 @@ fn case_flatten_two_level() {
             ["case", ["==", ["get", "kind"], "water"], 0.6, 0.2]
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @"
     - match
     - - get
@@ -1674,7 +1704,7 @@ fn case_flatten_three_level() {
             ]
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @r#"
     - case
     - - "=="
@@ -1700,7 +1730,7 @@ fn case_flatten_noop_non_case_fallback() {
         "fill-opacity",
         serde_json::json!(["case", ["==", ["get", "kind"], "park"], 0.8, 0.2]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @r#"
     - case
     - - "=="
@@ -1721,7 +1751,7 @@ fn canonicalize_exponential_1_to_linear() {
         "fill-opacity",
         serde_json::json!(["interpolate", ["exponential", 1], ["zoom"], 0, 0.0, 10, 1.0]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-opacity"], @"
     - interpolate
     - - linear
@@ -1748,7 +1778,7 @@ fn canonicalize_exponential_1_hcl() {
             "#fff"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-color"], @r##"
     - interpolate-hcl
     - - linear
@@ -1776,7 +1806,7 @@ fn case_to_match_string_labels() {
             "gray"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-color"], @"
     - match
     - - get
@@ -1803,7 +1833,7 @@ fn case_to_match_numeric_labels() {
             "c"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-color"], @"
     - match
     - - get
@@ -1830,7 +1860,7 @@ fn case_to_match_commuted_equality() {
             "gray"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-color"], @"
     - match
     - - get
@@ -1850,7 +1880,7 @@ fn case_to_match_single_arm_no_conversion() {
         "fill-color",
         serde_json::json!(["case", ["==", ["get", "class"], "road"], "red", "gray"]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     // Single arm stays as case — not worth converting.
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-color"], @r#"
     - case
@@ -1877,7 +1907,7 @@ fn case_to_match_non_uniform_expr_no_conversion() {
             "gray"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     // Different get expressions — stays as case.
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-color"], @r#"
     - case
@@ -1909,7 +1939,7 @@ fn case_to_match_bool_labels_no_conversion() {
             "c"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     // Bool labels are rejected by match spec — stays as case.
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-color"], @r#"
     - case
@@ -1939,7 +1969,7 @@ fn case_to_match_collator_no_conversion() {
             "gray"
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     // Collator (4-element ==) is rejected by extract_eq_chain — stays as case.
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-color"], @r#"
     - case
@@ -1970,7 +2000,7 @@ fn case_to_match_in_filter() {
         true,
         false
     ]));
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"
     - in
     - - get
@@ -2005,7 +2035,7 @@ fn nested_case_flatten_then_match() {
             ]
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     // Both outer and inner are independently converted to match.
     assert_yaml_snapshot!(v["layers"][0]["paint"]["fill-color"], @"
     - match
@@ -2042,7 +2072,7 @@ fn no_canonicalize_exponential_non_1() {
         ]),
     );
     let original = v["layers"][0]["paint"]["fill-opacity"].clone();
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_eq!(v["layers"][0]["paint"]["fill-opacity"], original);
 }
 
@@ -2055,7 +2085,7 @@ fn strip_coalesce_default_in_to_string() {
         "text-field",
         serde_json::json!(["to-string", ["coalesce", ["get", "name"], ""]]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["layout"], @"
     text-field:
       - to-string
@@ -2071,7 +2101,7 @@ fn strip_coalesce_default_in_to_number() {
         "text-size",
         serde_json::json!(["to-number", ["coalesce", ["get", "size"], 0]]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["layout"], @"
     text-size:
       - to-number
@@ -2087,7 +2117,7 @@ fn strip_coalesce_default_in_to_boolean() {
         "to-boolean",
         ["coalesce", ["get", "active"], false]
     ]));
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"
     - to-boolean
     - - get
@@ -2103,7 +2133,7 @@ fn no_strip_coalesce_nondefault_in_to_string() {
         serde_json::json!(["to-string", ["coalesce", ["get", "name"], "fallback"]]),
     );
     let original = v["layers"][0]["layout"]["text-field"].clone();
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_eq!(v["layers"][0]["layout"]["text-field"], original);
 }
 
@@ -2120,7 +2150,7 @@ fn flatten_nested_coalesce() {
             ["get", "ref"]
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["layout"], @"
     text-field:
       - coalesce
@@ -2148,7 +2178,7 @@ fn flatten_deeply_nested_coalesce() {
             ["get", "d"]
         ]),
     );
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["layout"], @"
     text-field:
       - coalesce
@@ -2173,7 +2203,7 @@ fn strip_coalesce_in_in_expression() {
         ["coalesce", ["get", "class"], "other"],
         ["literal", ["lake", "river", "pond"]]
     ]));
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"
     - in
     - - get
@@ -2196,7 +2226,7 @@ fn strip_typeof_guard_eq_string() {
         ["==", ["get", "klasse"], "Eisenbahn"],
         false
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @r#"
     - "=="
     - - get
@@ -2214,7 +2244,7 @@ fn strip_typeof_guard_neq_string() {
         ["!=", ["get", "klasse"], "Eisenbahn"],
         true
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @r#"
     - "!="
     - - get
@@ -2232,7 +2262,7 @@ fn strip_typeof_guard_eq_number() {
         ["==", ["get", "level"], 5],
         false
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @r#"
     - "=="
     - - get
@@ -2250,7 +2280,7 @@ fn no_strip_typeof_guard_relational_op() {
         [">=", ["get", "level"], 5],
         false
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     // The guard wrapping >= must NOT be stripped (relational ops error on type mismatch).
     let filter_str = serde_json::to_string(&v["layers"][0]["filter"]).unwrap();
     assert!(
@@ -2268,7 +2298,7 @@ fn no_strip_typeof_guard_wrong_fallback() {
         ["==", ["get", "klasse"], "Eisenbahn"],
         "unknown"
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     // Should NOT strip — fallback is "unknown", not false.
     let filter_str = serde_json::to_string(&v["layers"][0]["filter"]).unwrap();
     assert!(
@@ -2286,7 +2316,7 @@ fn no_strip_typeof_guard_type_mismatch() {
         ["==", ["get", "klasse"], 42],
         false
     ]));
-    optimize_style_json_value(&mut v, &mir, &fold_passes());
+    optimize_style_json_value(&mut v, &mir, &fold_passes()).unwrap();
     // Should NOT strip — literal (42) is a number but typeof checks for "string".
     let filter_str = serde_json::to_string(&v["layers"][0]["filter"]).unwrap();
     assert!(
@@ -2319,7 +2349,7 @@ fn strip_typeof_guard_any_to_in_end_to_end() {
             false
         ]
     ]));
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_yaml_snapshot!(v["layers"][0]["filter"], @"
     - in
     - - get
@@ -2340,6 +2370,6 @@ fn no_strip_coalesce_in_in_when_default_in_haystack() {
         ["literal", ["lake", "river", "pond"]]
     ]));
     let original = v["layers"][0]["filter"].clone();
-    optimize_style_json_value(&mut v, &mir, &simplify_passes());
+    optimize_style_json_value(&mut v, &mir, &simplify_passes()).unwrap();
     assert_eq!(v["layers"][0]["filter"], original);
 }
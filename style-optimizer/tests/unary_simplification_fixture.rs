@@ -32,7 +32,8 @@ fn unary_simplification_fixture_matches_expected() {
             simplify_unary: true,
             ..Default::default()
         },
-    );
+    )
+    .unwrap();
 
     assert_eq!(
         value, expected,
@@ -47,7 +48,7 @@ fn unary_simplification_fixture_noop_when_disabled() {
         serde_json::from_str(&std::fs::read_to_string(&before_path).unwrap()).unwrap();
     let original = value.clone();
 
-    optimize_style_json_value(&mut value, &sample_mir(), &OptPasses::default());
+    optimize_style_json_value(&mut value, &sample_mir(), &OptPasses::default()).unwrap();
 
     assert_eq!(value, original);
 }
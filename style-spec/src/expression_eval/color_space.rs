@@ -0,0 +1,111 @@
+//! CIELAB and HCL conversions for `interpolate-lab` / `interpolate-hcl`.
+//!
+//! Same constants as GL JS (`color_spaces.ts`, after d3-color): D50 white
+//! point with Bradford-adapted sRGB matrices.
+
+use super::value::Rgba;
+
+const XN: f64 = 0.964_22;
+const YN: f64 = 1.0;
+const ZN: f64 = 0.825_21;
+const T0: f64 = 4.0 / 29.0;
+const T1: f64 = 6.0 / 29.0;
+const T2: f64 = 3.0 * T1 * T1;
+const T3: f64 = T1 * T1 * T1;
+
+fn xyz_to_lab(t: f64) -> f64 {
+    if t > T3 { t.cbrt() } else { t / T2 + T0 }
+}
+
+fn lab_to_xyz(t: f64) -> f64 {
+    if t > T1 { t * t * t } else { T2 * (t - T0) }
+}
+
+fn xyz_to_rgb(x: f64) -> f64 {
+    if x <= 0.003_04 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn rgb_to_xyz(x: f64) -> f64 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// `[l, a, b, alpha]`.
+pub(super) fn rgb_to_lab(c: Rgba) -> [f64; 4] {
+    let (r, g, b) = (rgb_to_xyz(c.r), rgb_to_xyz(c.g), rgb_to_xyz(c.b));
+    let y = xyz_to_lab((0.222_504_5 * r + 0.716_878_6 * g + 0.060_616_9 * b) / YN);
+    let (x, z) = if (r - g).abs() < f64::EPSILON && (g - b).abs() < f64::EPSILON {
+        (y, y)
+    } else {
+        (
+            xyz_to_lab((0.436_074_7 * r + 0.385_064_9 * g + 0.143_080_4 * b) / XN),
+            xyz_to_lab((0.013_932_2 * r + 0.097_104_5 * g + 0.714_173_3 * b) / ZN),
+        )
+    };
+    let l = 116.0 * y - 16.0;
+    [l.max(0.0), 500.0 * (x - y), 200.0 * (y - z), c.a]
+}
+
+pub(super) fn lab_to_rgb([l, a, b, alpha]: [f64; 4]) -> Rgba {
+    let y = (l + 16.0) / 116.0;
+    let x = if a.is_nan() { y } else { y + a / 500.0 };
+    let z = if b.is_nan() { y } else { y - b / 200.0 };
+    let (x, y, z) = (XN * lab_to_xyz(x), YN * lab_to_xyz(y), ZN * lab_to_xyz(z));
+    Rgba {
+        r: xyz_to_rgb(3.133_856_1 * x - 1.616_866_7 * y - 0.490_614_6 * z),
+        g: xyz_to_rgb(-0.978_768_4 * x + 1.916_141_5 * y + 0.033_454_0 * z),
+        b: xyz_to_rgb(0.071_945_3 * x - 0.228_991_4 * y + 1.405_242_7 * z),
+        a: alpha,
+    }
+}
+
+/// `[hue, chroma, luminance, alpha]`; the hue of a grey is `NaN`.
+pub(super) fn rgb_to_hcl(c: Rgba) -> [f64; 4] {
+    let [l, a, b, alpha] = rgb_to_lab(c);
+    let chroma = a.hypot(b);
+    let hue = if (chroma * 10_000.0).round() == 0.0 {
+        f64::NAN
+    } else {
+        b.atan2(a).to_degrees()
+    };
+    [if hue < 0.0 { hue + 360.0 } else { hue }, chroma, l, alpha]
+}
+
+pub(super) fn hcl_to_rgb([h, c, l, alpha]: [f64; 4]) -> Rgba {
+    let h = if h.is_nan() { 0.0 } else { h.to_radians() };
+    lab_to_rgb([l, h.cos() * c, h.sin() * c, alpha])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lab_round_trips() {
+        let c = Rgba {
+            r: 0.2,
+            g: 0.5,
+            b: 0.8,
+            a: 0.5,
+        };
+        let back = lab_to_rgb(rgb_to_lab(c));
+        for (x, y) in [(c.r, back.r), (c.g, back.g), (c.b, back.b), (c.a, back.a)] {
+            assert!((x - y).abs() < 1e-4, "{c:?} vs {back:?}");
+        }
+        let white = rgb_to_lab(Rgba {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 1.0,
+        });
+        assert!((white[0] - 100.0).abs() < 0.01);
+        assert!(rgb_to_hcl(Rgba::TRANSPARENT)[0].is_nan());
+    }
+}
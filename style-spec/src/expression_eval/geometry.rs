@@ -0,0 +1,343 @@
+//! `within` and `distance`.
+//!
+//! Feature geometries are in longitude/latitude.  `within` tests in Web
+//! Mercator, where GL JS tests tile coordinates; `distance` measures in
+//! metres with the cheap-ruler approximation GL JS uses, at the latitude of
+//! the feature's first coordinate.
+
+use geojson::{GeoJson, Geometry, Value as Geo};
+
+type Point = [f64; 2];
+
+/// The parts of a geometry: points, line strings and polygons (rings).
+#[derive(Debug, Default)]
+struct Parts {
+    points: Vec<Point>,
+    lines: Vec<Vec<Point>>,
+    polygons: Vec<Vec<Vec<Point>>>,
+}
+
+fn point(p: &[f64]) -> Point {
+    [
+        p.first().copied().unwrap_or(f64::NAN),
+        p.get(1).copied().unwrap_or(f64::NAN),
+    ]
+}
+
+fn line(l: &[Vec<f64>]) -> Vec<Point> {
+    l.iter().map(|p| point(p)).collect()
+}
+
+fn polygon(rings: &[Vec<Vec<f64>>]) -> Vec<Vec<Point>> {
+    rings.iter().map(|r| line(r)).collect()
+}
+
+impl Parts {
+    fn add(&mut self, geometry: &Geo) {
+        match geometry {
+            Geo::Point(p) => self.points.push(point(p)),
+            Geo::MultiPoint(ps) => self.points.extend(ps.iter().map(|p| point(p))),
+            Geo::LineString(l) => self.lines.push(line(l)),
+            Geo::MultiLineString(ls) => self.lines.extend(ls.iter().map(|l| line(l))),
+            Geo::Polygon(p) => self.polygons.push(polygon(p)),
+            Geo::MultiPolygon(ps) => self.polygons.extend(ps.iter().map(|p| polygon(p))),
+            Geo::GeometryCollection(gs) => {
+                for g in gs {
+                    self.add(&g.value);
+                }
+            }
+        }
+    }
+
+    fn from_geojson(value: &serde_json::Value) -> Option<Self> {
+        let mut parts = Self::default();
+        let mut add = |g: Option<&Geometry>| {
+            if let Some(g) = g {
+                parts.add(&g.value);
+            }
+        };
+        match GeoJson::from_json_value(value.clone()).ok()? {
+            GeoJson::Geometry(g) => add(Some(&g)),
+            GeoJson::Feature(f) => add(f.geometry.as_ref()),
+            GeoJson::FeatureCollection(fc) => {
+                for f in &fc.features {
+                    add(f.geometry.as_ref());
+                }
+            }
+        }
+        Some(parts)
+    }
+
+    fn first(&self) -> Option<Point> {
+        self.points
+            .first()
+            .or_else(|| self.lines.iter().flatten().next())
+            .or_else(|| self.polygons.iter().flatten().flatten().next())
+            .copied()
+    }
+}
+
+// ── within ──────────────────────────────────────────────────────────────────
+
+fn mercator([lng, lat]: Point) -> Point {
+    let y = (std::f64::consts::FRAC_PI_4 + lat.to_radians() / 2.0)
+        .tan()
+        .ln();
+    [
+        (lng + 180.0) / 360.0,
+        0.5 - y / (2.0 * std::f64::consts::PI),
+    ]
+}
+
+fn cross(o: Point, a: Point, b: Point) -> f64 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+fn on_segment(p: Point, a: Point, b: Point) -> bool {
+    cross(p, a, b) == 0.0
+        && (a[0] - p[0]) * (b[0] - p[0]) <= 0.0
+        && (a[1] - p[1]) * (b[1] - p[1]) <= 0.0
+}
+
+/// Strictly inside (boundary points are not); holes by the even-odd rule.
+fn point_in_polygon(p: Point, rings: &[Vec<Point>]) -> bool {
+    let mut inside = false;
+    for ring in rings {
+        for (a, b) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+            if on_segment(p, *a, *b) {
+                return false;
+            }
+            if (a[1] > p[1]) != (b[1] > p[1])
+                && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+            {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Whether segments `p1p2` and `q1q2` cross at a point interior to both.
+fn segments_cross(p1: Point, p2: Point, q1: Point, q2: Point) -> bool {
+    let d1 = cross(q1, q2, p1);
+    let d2 = cross(q1, q2, p2);
+    let d3 = cross(p1, p2, q1);
+    let d4 = cross(p1, p2, q2);
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+fn line_in_polygon(line: &[Point], rings: &[Vec<Point>]) -> bool {
+    line.iter().all(|p| point_in_polygon(*p, rings))
+        && line.windows(2).all(|seg| {
+            rings.iter().all(|ring| {
+                ring.windows(2)
+                    .all(|edge| !segments_cross(seg[0], seg[1], edge[0], edge[1]))
+            })
+        })
+}
+
+/// `["within", geojson]`: whether every point or line of `feature` lies
+/// inside one of the polygons of `geojson`.  Other geometries are never
+/// within.
+pub(super) fn within(feature: &Geo, geojson: &serde_json::Value) -> bool {
+    let Some(area) = Parts::from_geojson(geojson) else {
+        return false;
+    };
+    let polygons: Vec<Vec<Vec<Point>>> = area
+        .polygons
+        .iter()
+        .map(|rings| {
+            rings
+                .iter()
+                .map(|r| r.iter().copied().map(mercator).collect())
+                .collect()
+        })
+        .collect();
+    if polygons.is_empty() {
+        return false;
+    }
+    let mut parts = Parts::default();
+    parts.add(feature);
+    if !parts.polygons.is_empty() || (parts.points.is_empty() && parts.lines.is_empty()) {
+        return false;
+    }
+    parts.points.iter().all(|p| {
+        polygons
+            .iter()
+            .any(|poly| point_in_polygon(mercator(*p), poly))
+    }) && parts.lines.iter().all(|l| {
+        let l: Vec<Point> = l.iter().copied().map(mercator).collect();
+        polygons.iter().any(|poly| line_in_polygon(&l, poly))
+    })
+}
+
+// ── distance ────────────────────────────────────────────────────────────────
+
+/// Planar metres around a reference latitude (cheap-ruler).
+struct Ruler {
+    kx: f64,
+    ky: f64,
+    origin: Point,
+}
+
+impl Ruler {
+    fn new(origin: Point) -> Self {
+        const RE: f64 = 6378.137;
+        const FE: f64 = 1.0 / 298.257_223_563;
+        const E2: f64 = FE * (2.0 - FE);
+        let m = 1.0_f64.to_radians() * RE * 1000.0;
+        let coslat = origin[1].to_radians().cos();
+        let w2 = 1.0 / (1.0 - E2 * (1.0 - coslat * coslat));
+        let w = w2.sqrt();
+        Self {
+            kx: m * w * coslat,
+            ky: m * w * w2 * (1.0 - E2),
+            origin,
+        }
+    }
+
+    fn project(&self, [lng, lat]: Point) -> Point {
+        let mut dx = lng - self.origin[0];
+        while dx < -180.0 {
+            dx += 360.0;
+        }
+        while dx > 180.0 {
+            dx -= 360.0;
+        }
+        [dx * self.kx, (lat - self.origin[1]) * self.ky]
+    }
+}
+
+fn point_segment_distance(p: Point, a: Point, b: Point) -> f64 {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 == 0.0 {
+        0.0
+    } else {
+        (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / len2).clamp(0.0, 1.0)
+    };
+    (p[0] - a[0] - t * dx).hypot(p[1] - a[1] - t * dy)
+}
+
+fn segment_distance(p1: Point, p2: Point, q1: Point, q2: Point) -> f64 {
+    if segments_cross(p1, p2, q1, q2) {
+        return 0.0;
+    }
+    point_segment_distance(p1, q1, q2)
+        .min(point_segment_distance(p2, q1, q2))
+        .min(point_segment_distance(q1, p1, p2))
+        .min(point_segment_distance(q2, p1, p2))
+}
+
+/// Segments of a part list: each line's segments, each polygon ring's edges,
+/// and each point as a degenerate segment.
+fn segments(parts: &Parts) -> Vec<(Point, Point)> {
+    let mut out: Vec<(Point, Point)> = parts.points.iter().map(|p| (*p, *p)).collect();
+    for l in &parts.lines {
+        if l.len() == 1 {
+            out.push((l[0], l[0]));
+        }
+        out.extend(l.windows(2).map(|w| (w[0], w[1])));
+    }
+    for ring in parts.polygons.iter().flatten() {
+        out.extend(ring.windows(2).map(|w| (w[0], w[1])));
+    }
+    out
+}
+
+/// Whether any vertex of `a` lies inside (or on) a polygon of `b`.
+fn overlaps(a: &Parts, b: &Parts) -> bool {
+    let vertices = a
+        .points
+        .iter()
+        .chain(a.lines.iter().flatten())
+        .chain(a.polygons.iter().flatten().flatten());
+    vertices
+        .copied()
+        .any(|p| b.polygons.iter().any(|poly| point_in_polygon(p, poly)))
+}
+
+/// `["distance", geojson]`: the shortest distance in metres between
+/// `feature` and `geojson`, 0 when they overlap.  `None` if either has no
+/// coordinates.
+pub(super) fn distance(feature: &Geo, geojson: &serde_json::Value) -> Option<f64> {
+    let target = Parts::from_geojson(geojson)?;
+    let mut source = Parts::default();
+    source.add(feature);
+    let ruler = Ruler::new(source.first()?);
+    let project = |parts: &Parts| Parts {
+        points: parts.points.iter().map(|p| ruler.project(*p)).collect(),
+        lines: parts
+            .lines
+            .iter()
+            .map(|l| l.iter().map(|p| ruler.project(*p)).collect())
+            .collect(),
+        polygons: parts
+            .polygons
+            .iter()
+            .map(|rings| {
+                rings
+                    .iter()
+                    .map(|r| r.iter().map(|p| ruler.project(*p)).collect())
+                    .collect()
+            })
+            .collect(),
+    };
+    let (source, target) = (project(&source), project(&target));
+    target.first()?;
+    if overlaps(&source, &target) || overlaps(&target, &source) {
+        return Some(0.0);
+    }
+    let targets = segments(&target);
+    segments(&source)
+        .iter()
+        .flat_map(|&(p1, p2)| {
+            targets
+                .iter()
+                .map(move |&(q1, q2)| segment_distance(p1, p2, q1, q2))
+        })
+        .reduce(f64::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn square() -> serde_json::Value {
+        json!({"type": "Polygon", "coordinates": [[[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]]]})
+    }
+
+    #[test]
+    fn points_and_lines_within_polygons() {
+        assert!(within(&Geo::Point(vec![5.0, 5.0]), &square()));
+        assert!(!within(&Geo::Point(vec![15.0, 5.0]), &square()));
+        assert!(!within(&Geo::Point(vec![0.0, 5.0]), &square()));
+        assert!(within(
+            &Geo::LineString(vec![vec![1.0, 1.0], vec![9.0, 9.0]]),
+            &square()
+        ));
+        let feature = json!({"type": "Feature", "properties": {}, "geometry": square()});
+        assert!(!within(
+            &Geo::LineString(vec![vec![1.0, 1.0], vec![19.0, 9.0]]),
+            &feature
+        ));
+    }
+
+    #[test]
+    fn distances_in_metres() {
+        // One degree of latitude is about 111 km.
+        let d = distance(
+            &Geo::Point(vec![0.0, 0.0]),
+            &json!({"type": "Point", "coordinates": [0, 1]}),
+        )
+        .unwrap();
+        assert!((d - 110_574.0).abs() < 100.0, "{d}");
+        let inside = distance(&Geo::Point(vec![5.0, 5.0]), &square()).unwrap();
+        assert!(inside.abs() < f64::EPSILON);
+        let line = json!({"type": "LineString", "coordinates": [[-1, -1], [-1, 1]]});
+        let d = distance(&Geo::Point(vec![0.0, 0.0]), &line).unwrap();
+        assert!((d - 111_319.0).abs() < 200.0, "{d}");
+    }
+}
@@ -0,0 +1,1614 @@
+//! Reference interpreter for the v8 expression language.
+//!
+//! [`evaluate`] runs an expression, as style JSON, against an
+//! [`EvaluationContext`]: zoom, the feature being styled, feature-state and
+//! global-state.  It follows GL JS runtime semantics — JavaScript number
+//! formatting and string conversions, runtime type assertions, lazy `case` /
+//! `match` / `coalesce` branches — so that tools folding or simulating
+//! expressions share one definition of what an expression means.
+//!
+//! Expressions are evaluated untyped: parse-time coercions GL JS inserts
+//! (e.g. a colour string in a colour-typed output) are applied where the
+//! operator makes the expected type clear, and are otherwise left to the
+//! caller.
+
+mod color_space;
+mod geometry;
+mod number_format;
+mod ramp;
+mod value;
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use serde_json::{Map, Value};
+use unicode_normalization::UnicodeNormalization;
+
+use number_format::NumberFormat;
+use ramp::{ColorSpace, Interpolation, interpolate_values, stop_index};
+use value::js_string_to_number;
+pub use value::{
+    Collator, EvalValue, Formatted, FormattedSection, ResolvedImage, Rgba, js_number_to_string,
+};
+
+/// A runtime error, with GL JS's message where it has one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalError {
+    message: String,
+}
+
+impl EvalError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    fn type_mismatch(expected: &str, found: &EvalValue) -> Self {
+        Self::new(format!(
+            "Expected value to be of type {expected}, but found {} instead.",
+            found.type_name()
+        ))
+    }
+
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// The feature an expression is evaluated for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Feature {
+    pub id: Option<Value>,
+    pub properties: Map<String, Value>,
+    /// Longitude/latitude geometry, for `within` and `distance`.
+    pub geometry: Option<geojson::Value>,
+    /// `Point`, `LineString` or `Polygon` (or a `Multi*` name).  Derived from
+    /// `geometry` when unset.
+    pub geometry_type: Option<String>,
+}
+
+impl Feature {
+    /// The `geometry-type` of the feature, if known.
+    #[must_use]
+    pub fn geometry_type(&self) -> Option<&str> {
+        if let Some(t) = &self.geometry_type {
+            return Some(t);
+        }
+        Some(match self.geometry.as_ref()? {
+            geojson::Value::Point(_) => "Point",
+            geojson::Value::MultiPoint(_) => "MultiPoint",
+            geojson::Value::LineString(_) => "LineString",
+            geojson::Value::MultiLineString(_) => "MultiLineString",
+            geojson::Value::Polygon(_) => "Polygon",
+            geojson::Value::MultiPolygon(_) => "MultiPolygon",
+            geojson::Value::GeometryCollection(_) => return None,
+        })
+    }
+}
+
+/// Everything an expression may read besides its own arguments.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EvaluationContext<'a> {
+    pub zoom: Option<f64>,
+    pub feature: Option<&'a Feature>,
+    pub feature_state: Option<&'a Map<String, Value>>,
+    pub global_state: Option<&'a Map<String, Value>>,
+    pub heatmap_density: Option<f64>,
+    pub line_progress: Option<f64>,
+    pub accumulated: Option<f64>,
+    pub elevation: Option<f64>,
+    /// Images the map has; `None` treats every image as available.
+    pub available_images: Option<&'a [String]>,
+    /// The locale `resolved-locale` reports for collators without one.
+    pub locale: Option<&'a str>,
+    /// Whether the RTL text plugin is loaded, for `is-supported-script`.
+    pub rtl_text_plugin: bool,
+}
+
+/// Every operator [`evaluate`] implements.
+pub const OPERATORS: &[&str] = &[
+    "!",
+    "!=",
+    "%",
+    "*",
+    "+",
+    "-",
+    "/",
+    "<",
+    "<=",
+    "==",
+    ">",
+    ">=",
+    "^",
+    "abs",
+    "accumulated",
+    "acos",
+    "all",
+    "any",
+    "array",
+    "asin",
+    "at",
+    "atan",
+    "boolean",
+    "case",
+    "ceil",
+    "coalesce",
+    "collator",
+    "concat",
+    "cos",
+    "distance",
+    "downcase",
+    "e",
+    "elevation",
+    "feature-state",
+    "floor",
+    "format",
+    "geometry-type",
+    "get",
+    "global-state",
+    "has",
+    "heatmap-density",
+    "id",
+    "image",
+    "in",
+    "index-of",
+    "interpolate",
+    "interpolate-hcl",
+    "interpolate-lab",
+    "is-supported-script",
+    "join",
+    "length",
+    "let",
+    "line-progress",
+    "literal",
+    "ln",
+    "ln2",
+    "log10",
+    "log2",
+    "match",
+    "max",
+    "min",
+    "number",
+    "number-format",
+    "object",
+    "pi",
+    "properties",
+    "resolved-locale",
+    "rgb",
+    "rgba",
+    "round",
+    "sin",
+    "slice",
+    "split",
+    "sqrt",
+    "step",
+    "string",
+    "tan",
+    "to-boolean",
+    "to-color",
+    "to-number",
+    "to-rgba",
+    "to-string",
+    "typeof",
+    "upcase",
+    "var",
+    "within",
+    "zoom",
+];
+
+/// Whether [`evaluate`] implements `operator`.
+#[must_use]
+pub fn is_supported(operator: &str) -> bool {
+    OPERATORS.contains(&operator)
+}
+
+/// Evaluate `expr` in `ctx`.
+///
+/// # Errors
+///
+/// Returns the runtime error GL JS would raise: a failed type assertion or
+/// conversion, an out-of-range argument, or an expression that does not
+/// parse (unknown operator, bare array or object).
+pub fn evaluate(expr: &Value, ctx: &EvaluationContext<'_>) -> Result<EvalValue, EvalError> {
+    Evaluator {
+        ctx,
+        scope: Vec::new(),
+    }
+    .eval(expr)
+}
+
+struct Evaluator<'c, 'a> {
+    ctx: &'c EvaluationContext<'a>,
+    /// `let` bindings, innermost last.
+    scope: Vec<(String, EvalValue)>,
+}
+
+fn arg<'v>(op: &str, args: &'v [Value], i: usize) -> Result<&'v Value, EvalError> {
+    args.get(i).ok_or_else(|| {
+        EvalError::new(format!(
+            "\"{op}\" expects at least {} arguments, but found {} instead.",
+            i + 1,
+            args.len()
+        ))
+    })
+}
+
+fn literal_number(v: &Value) -> Result<f64, EvalError> {
+    v.as_f64().ok_or_else(|| {
+        EvalError::new("Input/output pairs for ramps must be defined using literal numeric values.")
+    })
+}
+
+/// Clamp a fraction-digit option to what `Intl` accepts.
+#[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn fraction_digits(n: f64) -> usize {
+    n.clamp(0.0, 20.0) as usize
+}
+
+/// Exact small integer, for indices.
+#[expect(clippy::cast_possible_truncation)]
+fn as_index(n: f64) -> Option<i64> {
+    (n.fract() == 0.0 && n.abs() < 9_007_199_254_740_992.0).then_some(n as i64)
+}
+
+/// JavaScript `slice` bounds: negative indices count from the end.
+fn slice_bounds(len: usize, start: f64, end: Option<f64>) -> (usize, usize) {
+    #[expect(clippy::cast_precision_loss)]
+    let len_f = len as f64;
+    let resolve = |i: f64| {
+        let i = if i.is_nan() { 0.0 } else { i.trunc() };
+        let i = if i < 0.0 {
+            (len_f + i).max(0.0)
+        } else {
+            i.min(len_f)
+        };
+        #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let i = i as usize;
+        i
+    };
+    let start = resolve(start);
+    let end = end.map_or(len, resolve);
+    (start, end.max(start))
+}
+
+#[expect(clippy::cast_precision_loss)]
+fn index_number(i: Option<usize>) -> EvalValue {
+    EvalValue::Number(i.map_or(-1.0, |i| i as f64))
+}
+
+fn is_rtl(c: u32) -> bool {
+    matches!(c, 0x0590..=0x08FF | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF)
+}
+
+/// Complex-shaping scripts GL JS cannot lay out (Indic, Tibetan, Myanmar, Khmer).
+fn is_unsupported_complex(c: u32) -> bool {
+    matches!(c, 0x0900..=0x0DFF | 0x0F00..=0x109F | 0x1780..=0x17FF)
+}
+
+/// Fold a string for collator comparison.
+fn collation_key(s: &str, case_sensitive: bool, diacritic_sensitive: bool) -> String {
+    let s = if case_sensitive {
+        s.to_owned()
+    } else {
+        s.to_lowercase()
+    };
+    if diacritic_sensitive {
+        s
+    } else {
+        s.nfd()
+            .filter(|c| !matches!(u32::from(*c), 0x0300..=0x036F))
+            .nfc()
+            .collect()
+    }
+}
+
+/// An approximation of `Intl.Collator.compare`: letters compare ignoring case
+/// and accents first; the sensitivities decide ties, lower case first.
+fn collate(a: &str, b: &str, collator: &Collator) -> Ordering {
+    let primary = collation_key(a, false, false).cmp(&collation_key(b, false, false));
+    let accents = || {
+        if collator.diacritic_sensitive {
+            collation_key(a, false, true).cmp(&collation_key(b, false, true))
+        } else {
+            Ordering::Equal
+        }
+    };
+    let case = || {
+        if collator.case_sensitive {
+            // Swap case so that lower case sorts first, as in English locales.
+            let flip = |s: &str| -> String {
+                s.chars()
+                    .map(|c| {
+                        if c.is_lowercase() {
+                            c.to_uppercase().next().unwrap_or(c)
+                        } else {
+                            c.to_lowercase().next().unwrap_or(c)
+                        }
+                    })
+                    .collect()
+            };
+            flip(a).cmp(&flip(b))
+        } else {
+            Ordering::Equal
+        }
+    };
+    primary.then_with(accents).then_with(case)
+}
+
+fn check_rgba(r: f64, g: f64, b: f64, a: Option<f64>) -> Result<Rgba, EvalError> {
+    let shown = || {
+        let mut parts = vec![r, g, b];
+        parts.extend(a);
+        parts
+            .iter()
+            .map(|n| js_number_to_string(*n))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if ![r, g, b].iter().all(|c| (0.0..=255.0).contains(c)) {
+        return Err(EvalError::new(format!(
+            "Invalid rgba value [{}]: 'r', 'g', and 'b' must be between 0 and 255.",
+            shown()
+        )));
+    }
+    if a.is_some_and(|a| !(0.0..=1.0).contains(&a)) {
+        return Err(EvalError::new(format!(
+            "Invalid rgba value [{}]: 'a' must be between 0 and 1.",
+            shown()
+        )));
+    }
+    Ok(Rgba {
+        r: r / 255.0,
+        g: g / 255.0,
+        b: b / 255.0,
+        a: a.unwrap_or(1.0),
+    })
+}
+
+/// `to-color` of one value: a colour, a colour string or an `[r, g, b(, a)]`
+/// array.
+fn to_color(v: &EvalValue) -> Result<Option<Rgba>, EvalError> {
+    Ok(match v {
+        EvalValue::Color(c) => Some(*c),
+        EvalValue::String(s) => Rgba::parse(s),
+        EvalValue::Array(items) if matches!(items.len(), 3 | 4) => {
+            let channels: Option<Vec<f64>> = items
+                .iter()
+                .map(|i| match i {
+                    EvalValue::Number(n) => Some(*n),
+                    _ => None,
+                })
+                .collect();
+            match channels.as_deref() {
+                Some(&[r, g, b]) => Some(check_rgba(r, g, b, None)?),
+                Some(&[r, g, b, a]) => Some(check_rgba(r, g, b, Some(a))?),
+                _ => None,
+            }
+        }
+        _ => None,
+    })
+}
+
+impl Evaluator<'_, '_> {
+    fn eval(&mut self, expr: &Value) -> Result<EvalValue, EvalError> {
+        match expr {
+            Value::Array(items) => match items.split_first() {
+                Some((Value::String(op), args)) => self.call(op, args),
+                Some((first, _)) => Err(EvalError::new(format!(
+                    "Expression name must be a string, but found {} instead. If you wanted a literal array, use [\"literal\", [...]].",
+                    EvalValue::from_json(first).kind()
+                ))),
+                None => Err(EvalError::new(
+                    "Expected an array with at least one element. If you wanted a literal array, use [\"literal\", []].",
+                )),
+            },
+            Value::Object(_) => Err(EvalError::new(
+                "Bare objects invalid. Use [\"literal\", {...}] instead.",
+            )),
+            scalar => Ok(EvalValue::from_json(scalar)),
+        }
+    }
+
+    fn number(&mut self, expr: &Value) -> Result<f64, EvalError> {
+        self.eval(expr)?.expect_number()
+    }
+
+    fn boolean(&mut self, expr: &Value) -> Result<bool, EvalError> {
+        self.eval(expr)?.expect_bool()
+    }
+
+    fn string(&mut self, expr: &Value) -> Result<String, EvalError> {
+        match self.eval(expr)? {
+            EvalValue::String(s) => Ok(s),
+            other => Err(EvalError::type_mismatch("string", &other)),
+        }
+    }
+
+    fn color(&mut self, expr: &Value) -> Result<Rgba, EvalError> {
+        let v = self.eval(expr)?;
+        to_color(&v)?.ok_or_else(|| EvalError::type_mismatch("color", &v))
+    }
+
+    fn feature(&self) -> Option<&Feature> {
+        self.ctx.feature
+    }
+
+    fn properties(&self) -> Option<&Map<String, Value>> {
+        self.feature().map(|f| &f.properties)
+    }
+
+    /// The object `get`/`has` look in: the optional second argument, or the
+    /// feature's properties.
+    fn lookup_object(&mut self, args: &[Value]) -> Result<Option<Map<String, Value>>, EvalError> {
+        match args.get(1) {
+            Some(obj) => match self.eval(obj)? {
+                EvalValue::Object(o) => Ok(Some(o)),
+                other => Err(EvalError::type_mismatch("object", &other)),
+            },
+            None => Ok(self.properties().cloned()),
+        }
+    }
+
+    #[expect(clippy::too_many_lines)]
+    fn call(&mut self, op: &str, args: &[Value]) -> Result<EvalValue, EvalError> {
+        use EvalValue as V;
+        Ok(match op {
+            // ── Types ──────────────────────────────────────────────────────
+            "literal" => V::from_json(arg(op, args, 0)?),
+            "array" => self.assert_array(args)?,
+            "string" | "number" | "boolean" | "object" => {
+                let mut last = V::Null;
+                for a in args {
+                    last = self.eval(a)?;
+                    if last.kind() == op {
+                        return Ok(last);
+                    }
+                }
+                return Err(EvalError::type_mismatch(op, &last));
+            }
+            "to-boolean" => V::Boolean(self.eval(arg(op, args, 0)?)?.is_truthy()),
+            "to-number" => {
+                let mut last = V::Null;
+                for a in args {
+                    last = self.eval(a)?;
+                    match &last {
+                        V::Null => return Ok(V::Number(0.0)),
+                        V::Boolean(b) => return Ok(V::Number(f64::from(u8::from(*b)))),
+                        V::Number(_) => return Ok(last),
+                        V::String(s) => {
+                            let n = js_string_to_number(s);
+                            if !n.is_nan() {
+                                return Ok(V::Number(n));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                return Err(EvalError::new(format!(
+                    "Could not convert {} to number.",
+                    serde_json::to_string(&last.to_json()).unwrap_or_default()
+                )));
+            }
+            "to-string" => V::String(self.eval(arg(op, args, 0)?)?.to_js_string()),
+            "to-color" => {
+                let mut last = V::Null;
+                for a in args {
+                    last = self.eval(a)?;
+                    if let Some(c) = to_color(&last)? {
+                        return Ok(V::Color(c));
+                    }
+                }
+                return Err(EvalError::new(format!(
+                    "Could not parse color from value '{}'",
+                    match &last {
+                        V::String(s) => s.clone(),
+                        other => serde_json::to_string(&other.to_json()).unwrap_or_default(),
+                    }
+                )));
+            }
+            "typeof" => V::String(self.eval(arg(op, args, 0)?)?.type_name()),
+            "collator" => {
+                let Some(Value::Object(options)) = args.first() else {
+                    return Err(EvalError::new(
+                        "Collator options argument must be an object.",
+                    ));
+                };
+                let mut flag = |key: &str| -> Result<bool, EvalError> {
+                    options.get(key).map_or(Ok(false), |e| self.boolean(e))
+                };
+                let case_sensitive = flag("case-sensitive")?;
+                let diacritic_sensitive = flag("diacritic-sensitive")?;
+                let locale = match options.get("locale") {
+                    Some(e) => Some(self.string(e)?),
+                    None => None,
+                };
+                V::Collator(Collator {
+                    case_sensitive,
+                    diacritic_sensitive,
+                    locale,
+                })
+            }
+            "format" => self.format(args)?,
+            "image" => {
+                let name = self.string(arg(op, args, 0)?)?;
+                let available = self
+                    .ctx
+                    .available_images
+                    .is_none_or(|images| images.contains(&name));
+                V::Image(ResolvedImage { name, available })
+            }
+            "number-format" => {
+                let n = self.number(arg(op, args, 0)?)?;
+                let options = match args.get(1) {
+                    Some(Value::Object(o)) => o.clone(),
+                    Some(_) => {
+                        return Err(EvalError::new(
+                            "NumberFormat options argument must be an object.",
+                        ));
+                    }
+                    None => Map::new(),
+                };
+                let mut text = |key: &str| -> Result<Option<String>, EvalError> {
+                    options.get(key).map(|e| self.string(e)).transpose()
+                };
+                let locale = text("locale")?;
+                let currency = text("currency")?;
+                let mut digits = |key: &str| -> Result<Option<usize>, EvalError> {
+                    options
+                        .get(key)
+                        .map(|e| self.number(e).map(fraction_digits))
+                        .transpose()
+                };
+                let format = NumberFormat {
+                    locale: locale.as_deref(),
+                    currency: currency.as_deref(),
+                    min_fraction_digits: digits("min-fraction-digits")?,
+                    max_fraction_digits: digits("max-fraction-digits")?,
+                };
+                V::String(format.format(n))
+            }
+
+            // ── Feature data ───────────────────────────────────────────────
+            "accumulated" => V::Number(self.ctx.accumulated.unwrap_or(0.0)),
+            "line-progress" => V::Number(self.ctx.line_progress.unwrap_or(0.0)),
+            "heatmap-density" => V::Number(self.ctx.heatmap_density.unwrap_or(0.0)),
+            "elevation" => V::Number(self.ctx.elevation.unwrap_or(0.0)),
+            "zoom" => V::Number(self.ctx.zoom.ok_or_else(|| {
+                EvalError::new("The zoom level is not available in this context.")
+            })?),
+            "feature-state" => {
+                let key = self.string(arg(op, args, 0)?)?;
+                self.ctx
+                    .feature_state
+                    .and_then(|s| s.get(&key))
+                    .map_or(V::Null, V::from_json)
+            }
+            "global-state" => {
+                let key = self.string(arg(op, args, 0)?)?;
+                self.ctx
+                    .global_state
+                    .and_then(|s| s.get(&key))
+                    .map_or(V::Null, V::from_json)
+            }
+            "geometry-type" => self
+                .feature()
+                .and_then(Feature::geometry_type)
+                .map_or(V::Null, |t| V::String(t.to_owned())),
+            "id" => self
+                .feature()
+                .and_then(|f| f.id.as_ref())
+                .map_or(V::Null, V::from_json),
+            "properties" => V::Object(self.properties().cloned().unwrap_or_default()),
+
+            // ── Lookup ─────────────────────────────────────────────────────
+            "get" => {
+                let key = self.string(arg(op, args, 0)?)?;
+                self.lookup_object(args)?
+                    .and_then(|o| o.get(&key).map(V::from_json))
+                    .unwrap_or(V::Null)
+            }
+            "has" => {
+                let key = self.string(arg(op, args, 0)?)?;
+                V::Boolean(
+                    self.lookup_object(args)?
+                        .is_some_and(|o| o.contains_key(&key)),
+                )
+            }
+            "at" => {
+                let index = self.number(arg(op, args, 0)?)?;
+                let array = match self.eval(arg(op, args, 1)?)? {
+                    V::Array(items) => items,
+                    other => return Err(EvalError::type_mismatch("array", &other)),
+                };
+                let shown = js_number_to_string(index);
+                if index < 0.0 {
+                    return Err(EvalError::new(format!(
+                        "Array index out of bounds: {shown} < 0."
+                    )));
+                }
+                let Some(i) = as_index(index) else {
+                    return Err(EvalError::new(format!(
+                        "Array index must be an integer, but found {shown} instead."
+                    )));
+                };
+                match usize::try_from(i).ok().and_then(|i| array.get(i)) {
+                    Some(item) => item.clone(),
+                    None => {
+                        return Err(EvalError::new(format!(
+                            "Array index out of bounds: {shown} > {}.",
+                            array.len().saturating_sub(1)
+                        )));
+                    }
+                }
+            }
+            "in" | "index-of" => {
+                let needle = self.eval(arg(op, args, 0)?)?;
+                if !matches!(
+                    needle,
+                    V::Null | V::Boolean(_) | V::String(_) | V::Number(_)
+                ) {
+                    return Err(EvalError::new(format!(
+                        "Expected first argument to be of type boolean, string, number or null, but found {} instead.",
+                        needle.type_name()
+                    )));
+                }
+                let haystack = self.eval(arg(op, args, 1)?)?;
+                let from = match args.get(2) {
+                    Some(e) => self.number(e)?,
+                    None => 0.0,
+                };
+                let index = match &haystack {
+                    V::String(s) => {
+                        let units: Vec<u16> = s.encode_utf16().collect();
+                        let needle: Vec<u16> = needle.to_js_string().encode_utf16().collect();
+                        let (start, _) = slice_bounds(units.len(), from.max(0.0), None);
+                        if needle.is_empty() {
+                            Some(start)
+                        } else {
+                            units[start..]
+                                .windows(needle.len())
+                                .position(|w| w == needle)
+                                .map(|i| i + start)
+                        }
+                    }
+                    V::Array(items) => {
+                        let (start, _) = slice_bounds(items.len(), from, None);
+                        items[start..]
+                            .iter()
+                            .position(|item| *item == needle)
+                            .map(|i| i + start)
+                    }
+                    other => {
+                        return Err(EvalError::new(format!(
+                            "Expected second argument to be of type array or string, but found {} instead.",
+                            other.type_name()
+                        )));
+                    }
+                };
+                if op == "in" {
+                    V::Boolean(index.is_some())
+                } else {
+                    index_number(index)
+                }
+            }
+            "slice" => {
+                let input = self.eval(arg(op, args, 0)?)?;
+                let start = self.number(arg(op, args, 1)?)?;
+                let end = args.get(2).map(|e| self.number(e)).transpose()?;
+                match input {
+                    V::String(s) => {
+                        let units: Vec<u16> = s.encode_utf16().collect();
+                        let (a, b) = slice_bounds(units.len(), start, end);
+                        V::String(String::from_utf16_lossy(&units[a..b]))
+                    }
+                    V::Array(items) => {
+                        let (a, b) = slice_bounds(items.len(), start, end);
+                        V::Array(items[a..b].to_vec())
+                    }
+                    other => {
+                        return Err(EvalError::new(format!(
+                            "Expected first argument to be of type array or string, but found {} instead.",
+                            other.type_name()
+                        )));
+                    }
+                }
+            }
+            "length" => match self.eval(arg(op, args, 0)?)? {
+                V::String(s) => index_number(Some(s.encode_utf16().count())),
+                V::Array(items) => index_number(Some(items.len())),
+                other => {
+                    return Err(EvalError::new(format!(
+                        "Expected value to be of type string or array, but found {} instead.",
+                        other.type_name()
+                    )));
+                }
+            },
+
+            // ── Decision ───────────────────────────────────────────────────
+            "!" => V::Boolean(!self.boolean(arg(op, args, 0)?)?),
+            "==" | "!=" | "<" | "<=" | ">" | ">=" => self.compare(op, args)?,
+            "all" => {
+                for a in args {
+                    if !self.boolean(a)? {
+                        return Ok(V::Boolean(false));
+                    }
+                }
+                V::Boolean(true)
+            }
+            "any" => {
+                for a in args {
+                    if self.boolean(a)? {
+                        return Ok(V::Boolean(true));
+                    }
+                }
+                V::Boolean(false)
+            }
+            "case" => {
+                let (fallback, branches) = args
+                    .split_last()
+                    .ok_or_else(|| EvalError::new("Expected an odd number of arguments."))?;
+                for pair in branches.chunks(2) {
+                    let [condition, output] = pair else {
+                        return Err(EvalError::new("Expected an odd number of arguments."));
+                    };
+                    if self.boolean(condition)? {
+                        return self.eval(output);
+                    }
+                }
+                self.eval(fallback)?
+            }
+            "match" => {
+                let input = self.eval(arg(op, args, 0)?)?;
+                let (fallback, branches) = args[1..]
+                    .split_last()
+                    .ok_or_else(|| EvalError::new("Expected an even number of arguments."))?;
+                for pair in branches.chunks(2) {
+                    let [labels, output] = pair else {
+                        return Err(EvalError::new("Expected an even number of arguments."));
+                    };
+                    let hit = match labels {
+                        Value::Array(labels) => labels.iter().any(|l| match_label(&input, l)),
+                        label => match_label(&input, label),
+                    };
+                    if hit {
+                        return self.eval(output);
+                    }
+                }
+                self.eval(fallback)?
+            }
+            "coalesce" => {
+                let mut requested: Option<String> = None;
+                for (i, a) in args.iter().enumerate() {
+                    match self.eval(a)? {
+                        V::Null => {}
+                        V::Image(image) if !image.available => {
+                            let name = requested.get_or_insert(image.name);
+                            if i + 1 == args.len() {
+                                return Ok(V::Image(ResolvedImage {
+                                    name: name.clone(),
+                                    available: false,
+                                }));
+                            }
+                        }
+                        v => return Ok(v),
+                    }
+                }
+                V::Null
+            }
+            "within" => {
+                let area = arg(op, args, 0)?;
+                V::Boolean(
+                    self.feature()
+                        .and_then(|f| f.geometry.as_ref())
+                        .is_some_and(|g| geometry::within(g, area)),
+                )
+            }
+            "distance" => {
+                let target = arg(op, args, 0)?;
+                V::Number(
+                    self.feature()
+                        .and_then(|f| f.geometry.as_ref())
+                        .and_then(|g| geometry::distance(g, target))
+                        .unwrap_or(f64::NAN),
+                )
+            }
+
+            // ── Ramps, scales, curves ──────────────────────────────────────
+            "step" => {
+                let input = self.number(arg(op, args, 0)?)?;
+                let first = arg(op, args, 1)?;
+                let stops = &args[2..];
+                let inputs = stops
+                    .chunks(2)
+                    .map(|pair| literal_number(&pair[0]))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if inputs.first().is_none_or(|&s| input < s) {
+                    self.eval(first)?
+                } else {
+                    let i = stop_index(&inputs, input);
+                    let output = stops
+                        .get(2 * i + 1)
+                        .ok_or_else(|| EvalError::new("Expected an even number of arguments."))?;
+                    self.eval(output)?
+                }
+            }
+            "interpolate" | "interpolate-hcl" | "interpolate-lab" => {
+                let space = match op {
+                    "interpolate-hcl" => ColorSpace::Hcl,
+                    "interpolate-lab" => ColorSpace::Lab,
+                    _ => ColorSpace::Rgb,
+                };
+                self.interpolate(op, args, space)?
+            }
+
+            // ── Variable binding ───────────────────────────────────────────
+            "let" => {
+                let (body, bindings) = args
+                    .split_last()
+                    .ok_or_else(|| EvalError::new("Expected at least 3 arguments."))?;
+                let depth = self.scope.len();
+                for pair in bindings.chunks(2) {
+                    let [Value::String(name), value] = pair else {
+                        self.scope.truncate(depth);
+                        return Err(EvalError::new(
+                            "Expected string for variable name in \"let\" binding.",
+                        ));
+                    };
+                    let value = self.eval(value);
+                    match value {
+                        Ok(v) => self.scope.push((name.clone(), v)),
+                        Err(e) => {
+                            self.scope.truncate(depth);
+                            return Err(e);
+                        }
+                    }
+                }
+                let result = self.eval(body);
+                self.scope.truncate(depth);
+                result?
+            }
+            "var" => {
+                let Some(Value::String(name)) = args.first() else {
+                    return Err(EvalError::new(
+                        "'var' expression requires exactly one string literal argument.",
+                    ));
+                };
+                self.scope
+                    .iter()
+                    .rev()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, v)| v.clone())
+                    .ok_or_else(|| {
+                        EvalError::new(format!(
+                            "Unknown variable \"{name}\". Make sure \"{name}\" has been bound in an enclosing \"let\" expression before using it."
+                        ))
+                    })?
+            }
+
+            // ── String ─────────────────────────────────────────────────────
+            "concat" => {
+                let mut out = String::new();
+                for a in args {
+                    out.push_str(&self.eval(a)?.to_js_string());
+                }
+                V::String(out)
+            }
+            "downcase" => V::String(self.string(arg(op, args, 0)?)?.to_lowercase()),
+            "upcase" => V::String(self.string(arg(op, args, 0)?)?.to_uppercase()),
+            "split" => {
+                let input = self.string(arg(op, args, 0)?)?;
+                let separator = self.string(arg(op, args, 1)?)?;
+                let parts: Vec<V> = if separator.is_empty() {
+                    input.chars().map(|c| V::String(c.to_string())).collect()
+                } else {
+                    input
+                        .split(separator.as_str())
+                        .map(|part| V::String(part.to_owned()))
+                        .collect()
+                };
+                V::Array(parts)
+            }
+            "join" => {
+                let items = match self.eval(arg(op, args, 0)?)? {
+                    V::Array(items) => items,
+                    other => return Err(EvalError::type_mismatch("array<string>", &other)),
+                };
+                let separator = self.string(arg(op, args, 1)?)?;
+                V::String(
+                    items
+                        .iter()
+                        .map(V::to_js_string)
+                        .collect::<Vec<_>>()
+                        .join(&separator),
+                )
+            }
+            "is-supported-script" => {
+                let text = self.string(arg(op, args, 0)?)?;
+                let rtl_ok = self.ctx.rtl_text_plugin;
+                V::Boolean(
+                    text.chars()
+                        .map(u32::from)
+                        .all(|c| !is_unsupported_complex(c) && (rtl_ok || !is_rtl(c))),
+                )
+            }
+            "resolved-locale" => match self.eval(arg(op, args, 0)?)? {
+                V::Collator(c) => V::String(
+                    c.locale
+                        .or_else(|| self.ctx.locale.map(str::to_owned))
+                        .unwrap_or_else(|| "en".into()),
+                ),
+                other => return Err(EvalError::type_mismatch("collator", &other)),
+            },
+
+            // ── Color ──────────────────────────────────────────────────────
+            "rgb" | "rgba" => {
+                let mut channels = Vec::with_capacity(args.len());
+                for a in args {
+                    channels.push(self.number(a)?);
+                }
+                match *channels.as_slice() {
+                    [r, g, b] => V::Color(check_rgba(r, g, b, None)?),
+                    [r, g, b, a] => V::Color(check_rgba(r, g, b, Some(a))?),
+                    _ => {
+                        return Err(EvalError::new(format!(
+                            "Expected {} arguments, but found {} instead.",
+                            if op == "rgb" { 3 } else { 4 },
+                            args.len()
+                        )));
+                    }
+                }
+            }
+            "to-rgba" => {
+                let c = self.color(arg(op, args, 0)?)?;
+                V::Array(c.to_rgba_array().map(V::Number).to_vec())
+            }
+
+            // ── Math ───────────────────────────────────────────────────────
+            "e" => V::Number(std::f64::consts::E),
+            "pi" => V::Number(std::f64::consts::PI),
+            "ln2" => V::Number(std::f64::consts::LN_2),
+            "+" | "*" | "min" | "max" => {
+                let (init, f): (f64, fn(f64, f64) -> f64) = match op {
+                    "+" => (0.0, |a, b| a + b),
+                    "*" => (1.0, |a, b| a * b),
+                    "min" => (f64::INFINITY, js_min),
+                    _ => (f64::NEG_INFINITY, js_max),
+                };
+                let mut acc = init;
+                for a in args {
+                    acc = f(acc, self.number(a)?);
+                }
+                V::Number(acc)
+            }
+            "-" => {
+                let a = self.number(arg(op, args, 0)?)?;
+                match args.get(1) {
+                    Some(b) => V::Number(a - self.number(b)?),
+                    None => V::Number(-a),
+                }
+            }
+            "/" | "%" | "^" => {
+                let a = self.number(arg(op, args, 0)?)?;
+                let b = self.number(arg(op, args, 1)?)?;
+                V::Number(match op {
+                    "/" => a / b,
+                    "%" => a % b,
+                    _ => a.powf(b),
+                })
+            }
+            "sqrt" | "log10" | "ln" | "log2" | "sin" | "cos" | "tan" | "asin" | "acos" | "atan"
+            | "abs" | "ceil" | "floor" | "round" => {
+                let n = self.number(arg(op, args, 0)?)?;
+                V::Number(match op {
+                    "sqrt" => n.sqrt(),
+                    "log10" => n.log10(),
+                    "ln" => n.ln(),
+                    "log2" => n.log2(),
+                    "sin" => n.sin(),
+                    "cos" => n.cos(),
+                    "tan" => n.tan(),
+                    "asin" => n.asin(),
+                    "acos" => n.acos(),
+                    "atan" => n.atan(),
+                    "abs" => n.abs(),
+                    "ceil" => n.ceil(),
+                    "floor" => n.floor(),
+                    // GL JS rounds half away from zero.
+                    _ => n.round(),
+                })
+            }
+
+            _ => {
+                return Err(EvalError::new(format!(
+                    "Unknown expression \"{op}\". If you wanted a literal array, use [\"literal\", [...]]."
+                )));
+            }
+        })
+    }
+
+    /// `["array", value]`, `["array", type, value]`, `["array", type, N, value]`.
+    fn assert_array(&mut self, args: &[Value]) -> Result<EvalValue, EvalError> {
+        let (item_type, length, input) = match args {
+            [input] => (None, None, input),
+            [Value::String(t), input] => (Some(t.as_str()), None, input),
+            [Value::String(t), n, input] => (
+                Some(t.as_str()),
+                Some(n.as_u64().ok_or_else(|| {
+                    EvalError::new(
+                        "The length argument to \"array\" must be a positive integer literal",
+                    )
+                })?),
+                input,
+            ),
+            _ => {
+                return Err(EvalError::new(format!(
+                    "Expected 1, 2, or 3 arguments, but found {} instead.",
+                    args.len()
+                )));
+            }
+        };
+        let expected = match (item_type, length) {
+            (None, _) => "array".to_owned(),
+            (Some(t), None) => format!("array<{t}>"),
+            (Some(t), Some(n)) => format!("array<{t}, {n}>"),
+        };
+        let v = self.eval(input)?;
+        let ok = match &v {
+            EvalValue::Array(items) => {
+                item_type.is_none_or(|t| t == "value" || items.iter().all(|i| i.kind() == t))
+                    && length.is_none_or(|n| u64::try_from(items.len()).is_ok_and(|len| len == n))
+            }
+            _ => false,
+        };
+        if ok {
+            Ok(v)
+        } else {
+            Err(EvalError::type_mismatch(&expected, &v))
+        }
+    }
+
+    fn format(&mut self, args: &[Value]) -> Result<EvalValue, EvalError> {
+        let mut sections = Vec::new();
+        let mut i = 0;
+        while i < args.len() {
+            let content = self.eval(&args[i])?;
+            i += 1;
+            let options = match args.get(i) {
+                Some(Value::Object(o)) => {
+                    i += 1;
+                    Some(o)
+                }
+                _ => None,
+            };
+            let mut section = match content {
+                EvalValue::Image(image) => FormattedSection {
+                    text: String::new(),
+                    image: Some(image),
+                    scale: None,
+                    font_stack: None,
+                    text_color: None,
+                    vertical_align: None,
+                },
+                other => FormattedSection {
+                    text: other.to_js_string(),
+                    image: None,
+                    scale: None,
+                    font_stack: None,
+                    text_color: None,
+                    vertical_align: None,
+                },
+            };
+            if let Some(options) = options {
+                if let Some(e) = options.get("font-scale") {
+                    section.scale = Some(self.number(e)?);
+                }
+                if let Some(e) = options.get("text-font") {
+                    section.font_stack = Some(match self.eval(e)? {
+                        EvalValue::Array(fonts) => fonts
+                            .iter()
+                            .map(EvalValue::to_js_string)
+                            .collect::<Vec<_>>()
+                            .join(","),
+                        other => return Err(EvalError::type_mismatch("array<string>", &other)),
+                    });
+                }
+                if let Some(e) = options.get("text-color") {
+                    section.text_color = Some(self.color(e)?);
+                }
+                if let Some(e) = options.get("vertical-align") {
+                    section.vertical_align = Some(self.string(e)?);
+                }
+            }
+            sections.push(section);
+        }
+        Ok(EvalValue::Formatted(Formatted { sections }))
+    }
+
+    fn compare(&mut self, op: &str, args: &[Value]) -> Result<EvalValue, EvalError> {
+        let lhs = self.eval(arg(op, args, 0)?)?;
+        let rhs = self.eval(arg(op, args, 1)?)?;
+        let collator = match args.get(2) {
+            Some(e) => match self.eval(e)? {
+                EvalValue::Collator(c) => Some(c),
+                other => return Err(EvalError::type_mismatch("collator", &other)),
+            },
+            None => None,
+        };
+        let ordering = match (&lhs, &rhs, &collator) {
+            (EvalValue::String(a), EvalValue::String(b), Some(c)) => Some(collate(a, b, c)),
+            (EvalValue::String(a), EvalValue::String(b), None) => {
+                Some(a.encode_utf16().cmp(b.encode_utf16()))
+            }
+            (EvalValue::Number(a), EvalValue::Number(b), _) => a.partial_cmp(b),
+            _ => None,
+        };
+        let orderable = matches!(
+            (&lhs, &rhs),
+            (EvalValue::String(_), EvalValue::String(_))
+                | (EvalValue::Number(_), EvalValue::Number(_))
+        );
+        let result = match op {
+            "==" | "!=" => {
+                let equal = if orderable {
+                    ordering == Some(Ordering::Equal)
+                } else {
+                    lhs == rhs
+                };
+                equal == (op == "==")
+            }
+            _ => {
+                if !orderable {
+                    return Err(EvalError::new(format!(
+                        "Expected arguments for \"{op}\" to be (string, string) or (number, number), but found ({}, {}) instead.",
+                        lhs.kind(),
+                        rhs.kind()
+                    )));
+                }
+                ordering.is_some_and(|o| match op {
+                    "<" => o.is_lt(),
+                    "<=" => o.is_le(),
+                    ">" => o.is_gt(),
+                    _ => o.is_ge(),
+                })
+            }
+        };
+        Ok(EvalValue::Boolean(result))
+    }
+
+    fn interpolate(
+        &mut self,
+        op: &str,
+        args: &[Value],
+        space: ColorSpace,
+    ) -> Result<EvalValue, EvalError> {
+        let Some(Value::Array(curve)) = args.first() else {
+            return Err(EvalError::new("Expected an interpolation type expression."));
+        };
+        let interpolation = match curve.first().and_then(Value::as_str) {
+            Some("linear") => Interpolation::Linear,
+            Some("exponential") => {
+                Interpolation::Exponential(literal_number(arg("exponential", &curve[1..], 0)?)?)
+            }
+            Some("cubic-bezier") => {
+                let mut points = [0.0; 4];
+                for (i, p) in points.iter_mut().enumerate() {
+                    *p = literal_number(arg("cubic-bezier", &curve[1..], i)?)?;
+                }
+                Interpolation::CubicBezier(points)
+            }
+            _ => {
+                return Err(EvalError::new(format!(
+                    "Unknown interpolation type {}",
+                    curve.first().map(Value::to_string).unwrap_or_default()
+                )));
+            }
+        };
+        let input = self.number(arg(op, args, 1)?)?;
+        let stops = &args[2..];
+        if stops.is_empty() || !stops.len().is_multiple_of(2) {
+            return Err(EvalError::new("Expected an even number of arguments."));
+        }
+        let inputs = stops
+            .chunks(2)
+            .map(|pair| literal_number(&pair[0]))
+            .collect::<Result<Vec<_>, _>>()?;
+        let last = inputs.len() - 1;
+        if input <= inputs[0] {
+            return self.eval(&stops[1]);
+        }
+        if input >= inputs[last] {
+            return self.eval(&stops[2 * last + 1]);
+        }
+        let i = stop_index(&inputs, input);
+        let t = interpolation.factor(input, inputs[i], inputs[i + 1]);
+        let lower = self.eval(&stops[2 * i + 1])?;
+        let upper = self.eval(&stops[2 * i + 3])?;
+        interpolate_values(&lower, &upper, t, space)
+    }
+}
+
+/// Whether a `match` label selects `input`; labels of another type never do.
+fn match_label(input: &EvalValue, label: &Value) -> bool {
+    match (input, label) {
+        (EvalValue::String(s), Value::String(l)) => s == l,
+        (EvalValue::Number(n), Value::Number(l)) => l.as_f64() == Some(*n),
+        _ => false,
+    }
+}
+
+/// `Math.min`: `NaN` wins.
+fn js_min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        a.min(b)
+    }
+}
+
+/// `Math.max`: `NaN` wins.
+fn js_max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        a.max(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::decoder::StyleReference;
+    use crate::mir::MirSpec;
+
+    fn eval(expr: &Value) -> Result<EvalValue, EvalError> {
+        evaluate(expr, &EvaluationContext::default())
+    }
+
+    fn eval_json(expr: &Value) -> Value {
+        eval(expr).unwrap().to_json()
+    }
+
+    fn feature(properties: &Value) -> Feature {
+        Feature {
+            id: Some(json!(7)),
+            properties: properties.as_object().cloned().unwrap_or_default(),
+            geometry: Some(geojson::Value::Point(vec![5.0, 5.0])),
+            geometry_type: None,
+        }
+    }
+
+    #[test]
+    fn math_follows_javascript() {
+        assert_eq!(eval_json(&json!(["+", 1, 2, 3.5])), json!(6.5));
+        assert_eq!(eval_json(&json!(["-", 4])), json!(-4));
+        assert_eq!(eval_json(&json!(["%", -7, 3])), json!(-1));
+        assert_eq!(eval_json(&json!(["round", -2.5])), json!(-3));
+        assert_eq!(eval_json(&json!(["max", 1, ["^", 2, 3]])), json!(8));
+        assert_eq!(eval_json(&json!(["/", 1, 0])), Value::Null);
+        assert_eq!(
+            eval(&json!(["+", 1, "2"])).unwrap_err().message(),
+            "Expected value to be of type number, but found string instead."
+        );
+    }
+
+    #[test]
+    fn feature_data_and_lookup() {
+        let f = feature(&json!({"name": "Main St", "lanes": 2, "tags": ["a", "b"]}));
+        let state = json!({"hover": true});
+        let ctx = EvaluationContext {
+            zoom: Some(12.0),
+            feature: Some(&f),
+            feature_state: state.as_object(),
+            ..EvaluationContext::default()
+        };
+        let run = |e: Value| evaluate(&e, &ctx).unwrap().to_json();
+        assert_eq!(run(json!(["get", "lanes"])), json!(2));
+        assert_eq!(run(json!(["get", "missing"])), Value::Null);
+        assert_eq!(run(json!(["has", "name"])), json!(true));
+        assert_eq!(run(json!(["id"])), json!(7));
+        assert_eq!(run(json!(["geometry-type"])), json!("Point"));
+        assert_eq!(run(json!(["feature-state", "hover"])), json!(true));
+        assert_eq!(run(json!(["zoom"])), json!(12));
+        assert_eq!(run(json!(["at", 1, ["get", "tags"]])), json!("b"));
+        assert_eq!(run(json!(["in", "Main", ["get", "name"]])), json!(true));
+        assert_eq!(run(json!(["index-of", "b", ["get", "tags"]])), json!(1));
+        assert_eq!(run(json!(["slice", ["get", "name"], -2])), json!("St"));
+        assert_eq!(run(json!(["length", ["get", "name"]])), json!(7));
+        assert_eq!(
+            evaluate(&json!(["at", 2, ["get", "tags"]]), &ctx)
+                .unwrap_err()
+                .message(),
+            "Array index out of bounds: 2 > 1."
+        );
+    }
+
+    #[test]
+    fn decisions_and_bindings() {
+        assert_eq!(
+            eval_json(&json!(["match", "b", ["a", "b"], 1, "c", 2, 0])),
+            json!(1)
+        );
+        assert_eq!(
+            eval_json(&json!(["match", 1, "1", "string", "other"])),
+            json!("other")
+        );
+        assert_eq!(
+            eval_json(&json!([
+                "case",
+                false,
+                ["to-number", "x"],
+                true,
+                "yes",
+                "no"
+            ])),
+            json!("yes")
+        );
+        assert_eq!(
+            eval_json(&json!(["coalesce", null, ["literal", [1]]])),
+            json!([1])
+        );
+        assert_eq!(
+            eval_json(&json!([
+                "let",
+                "a",
+                2,
+                ["let", "b", ["*", ["var", "a"], 3], ["var", "b"]]
+            ])),
+            json!(6)
+        );
+        assert!(eval(&json!(["var", "a"])).is_err());
+        assert_eq!(
+            eval_json(&json!(["all", true, ["<", 1, 2], [">=", "b", "a"]])),
+            json!(true)
+        );
+        assert_eq!(
+            eval_json(&json!(["any", false, ["==", 1, "1"]])),
+            json!(false)
+        );
+        assert_eq!(
+            eval(&json!(["<", 1, "1"])).unwrap_err().message(),
+            "Expected arguments for \"<\" to be (string, string) or (number, number), but found (number, string) instead."
+        );
+        let collator = json!(["collator", {"case-sensitive": false, "diacritic-sensitive": false}]);
+        assert_eq!(
+            eval_json(&json!(["==", "Éclair", "eclair", collator])),
+            json!(true)
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(eval_json(&json!(["to-number", null])), json!(0));
+        assert_eq!(eval_json(&json!(["to-number", "x", "1e3"])), json!(1000));
+        assert_eq!(
+            eval(&json!(["to-number", "x"])).unwrap_err().message(),
+            "Could not convert \"x\" to number."
+        );
+        assert_eq!(eval_json(&json!(["to-string", 0.1])), json!("0.1"));
+        assert_eq!(
+            eval_json(&json!(["to-string", ["rgb", 255, 0, 0]])),
+            json!("rgba(255,0,0,1)")
+        );
+        assert_eq!(eval_json(&json!(["to-boolean", ""])), json!(false));
+        assert_eq!(
+            eval_json(&json!(["typeof", ["literal", [1, 2]]])),
+            json!("array<number, 2>")
+        );
+        assert_eq!(
+            eval_json(&json!(["to-rgba", "blue"])),
+            json!([0, 0, 255, 1])
+        );
+        assert_eq!(
+            eval_json(&json!(["concat", "a", 1, true, null])),
+            json!("a1true")
+        );
+        assert_eq!(
+            eval_json(&json!(["number-format", 1234.5678, {"max-fraction-digits": 2}])),
+            json!("1,234.57")
+        );
+        assert!(eval(&json!(["rgba", 256, 0, 0, 1])).is_err());
+        assert!(eval(&json!(["array", "number", 2, ["literal", [1, "a"]]])).is_err());
+        assert!(eval(&json!({"a": 1})).is_err());
+    }
+
+    #[test]
+    fn ramps() {
+        let ctx = EvaluationContext {
+            zoom: Some(15.0),
+            ..EvaluationContext::default()
+        };
+        let run = |e: Value| evaluate(&e, &ctx).unwrap().to_json();
+        assert_eq!(
+            run(json!(["interpolate", ["linear"], ["zoom"], 10, 0, 20, 100])),
+            json!(50)
+        );
+        assert_eq!(
+            run(json!(["step", ["zoom"], "a", 10, "b", 16, "c"])),
+            json!("b")
+        );
+        assert_eq!(run(json!(["step", 5, "a", 10, "b"])), json!("a"));
+        assert_eq!(
+            run(json!([
+                "interpolate",
+                ["linear"],
+                ["zoom"],
+                10,
+                "black",
+                20,
+                "white"
+            ])),
+            json!("rgba(128,128,128,1)")
+        );
+        assert_eq!(
+            run(json!(["interpolate", ["exponential", 2], 30, 10, 1, 20, 2])),
+            json!(2)
+        );
+    }
+
+    #[test]
+    fn spatial_and_text_support() {
+        let f = feature(&json!({}));
+        let ctx = EvaluationContext {
+            feature: Some(&f),
+            ..EvaluationContext::default()
+        };
+        let square = json!({"type": "Polygon", "coordinates": [[[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]]]});
+        let within = evaluate(&json!(["within", square]), &ctx).unwrap();
+        assert_eq!(within, EvalValue::Boolean(true));
+        assert_eq!(
+            eval_json(&json!(["is-supported-script", "שלום"])),
+            json!(false)
+        );
+        assert_eq!(
+            eval_json(&json!(["is-supported-script", "hello"])),
+            json!(true)
+        );
+    }
+
+    /// Every operator the spec defines has an implementation.
+    #[test]
+    fn covers_every_spec_operator() {
+        let v8 = include_str!("../../../upstream/src/reference/v8.json");
+        let reference: StyleReference =
+            serde_json::from_str(v8).expect("v8.json should parse as StyleReference");
+        let mir = MirSpec::from(reference);
+        let missing: Vec<&String> = mir
+            .expressions
+            .operators
+            .keys()
+            .filter(|op| !is_supported(op))
+            .collect();
+        assert!(missing.is_empty(), "unimplemented operators: {missing:?}");
+    }
+
+    /// Compare a fixture output: colours as premultiplied `[r, g, b, a]`,
+    /// numbers with a tolerance, errors by the fact of erroring.
+    fn output_matches(actual: &Result<EvalValue, EvalError>, expected: &Value) -> bool {
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-6 * a.abs().max(b.abs()).max(1.0);
+        match (actual, expected) {
+            (Err(_), Value::Object(o)) => o.contains_key("error"),
+            (Err(_), _) => false,
+            (Ok(EvalValue::Color(c)), Value::Array(e)) => {
+                let premultiplied = [c.r * c.a, c.g * c.a, c.b * c.a, c.a];
+                e.len() == 4
+                    && premultiplied
+                        .iter()
+                        .zip(e)
+                        .all(|(a, b)| b.as_f64().is_some_and(|b| close(*a, b)))
+            }
+            (Ok(EvalValue::Number(a)), Value::Number(b)) => {
+                b.as_f64().is_some_and(|b| close(*a, b))
+            }
+            (Ok(EvalValue::Formatted(_) | EvalValue::Image(_)), _) => true,
+            (Ok(v), e) => v.to_json() == *e,
+        }
+    }
+
+    fn fixture_inputs(
+        input: &Value,
+    ) -> (
+        EvaluationContext<'static>,
+        Option<Feature>,
+        Map<String, Value>,
+    ) {
+        let globals = input.get(0).cloned().unwrap_or_default();
+        let f = input.get(1).cloned().unwrap_or_default();
+        let feature = Feature {
+            id: f.get("id").cloned(),
+            properties: f
+                .get("properties")
+                .and_then(Value::as_object)
+                .cloned()
+                .unwrap_or_default(),
+            geometry: f
+                .get("geometry")
+                .filter(|g| g.get("coordinates").is_some())
+                .and_then(|g| geojson::Geometry::from_json_value(g.clone()).ok())
+                .map(|g| g.value),
+            geometry_type: f
+                .pointer("/geometry/type")
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+        };
+        let state = f
+            .get("featureState")
+            .or_else(|| globals.get("featureState"))
+            .and_then(Value::as_object)
+            .cloned()
+            .unwrap_or_default();
+        let ctx = EvaluationContext {
+            zoom: globals.get("zoom").and_then(Value::as_f64),
+            heatmap_density: globals.get("heatmapDensity").and_then(Value::as_f64),
+            line_progress: globals.get("lineProgress").and_then(Value::as_f64),
+            accumulated: globals.get("accumulated").and_then(Value::as_f64),
+            elevation: globals.get("elevation").and_then(Value::as_f64),
+            ..EvaluationContext::default()
+        };
+        (ctx, Some(feature), state)
+    }
+
+    /// Run the upstream expression fixtures that have runtime outputs.
+    /// Needs the `upstream` submodule checked out.
+    #[test]
+    #[ignore = "needs the upstream test fixtures"]
+    fn upstream_expression_fixtures() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../upstream/test/integration/expression/tests");
+        let mut stack = vec![root];
+        let mut failures = Vec::new();
+        let mut checked = 0;
+        while let Some(dir) = stack.pop() {
+            for entry in std::fs::read_dir(&dir).expect("fixture directory") {
+                let path = entry.expect("directory entry").path();
+                if path.is_dir() {
+                    stack.push(path);
+                    continue;
+                }
+                if path.file_name().is_none_or(|n| n != "test.json") {
+                    continue;
+                }
+                let text = std::fs::read_to_string(&path).expect("fixture");
+                let test: Value = serde_json::from_str(&text).expect("fixture JSON");
+                let compiled = &test["expected"]["compiled"];
+                if compiled["result"] != "success" || test.get("propertySpec").is_some() {
+                    continue;
+                }
+                let (Some(inputs), Some(outputs)) = (
+                    test.get("inputs").and_then(Value::as_array),
+                    test["expected"].get("outputs").and_then(Value::as_array),
+                ) else {
+                    continue;
+                };
+                for (input, expected) in inputs.iter().zip(outputs) {
+                    let (ctx, feature, state) = fixture_inputs(input);
+                    let ctx = EvaluationContext {
+                        feature: feature.as_ref(),
+                        feature_state: Some(&state),
+                        ..ctx
+                    };
+                    let actual = evaluate(&test["expression"], &ctx);
+                    checked += 1;
+                    if !output_matches(&actual, expected) {
+                        failures.push(format!("{}: {actual:?} != {expected}", path.display()));
+                    }
+                }
+            }
+        }
+        assert!(checked > 0, "no fixtures found");
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
@@ -0,0 +1,194 @@
+//! `number-format`: an approximation of `Intl.NumberFormat` for common
+//! locales and currencies.
+//!
+//! Rounds half away from zero on the shortest decimal representation, as
+//! `Intl` does.  Locales not listed use English separators; currencies
+//! without a known symbol print their ISO code.
+
+use super::value::{js_number_to_string, shortest_digits};
+
+/// Options of `["number-format", input, {…}]`, already evaluated.
+#[derive(Debug, Default)]
+pub(super) struct NumberFormat<'a> {
+    pub(super) locale: Option<&'a str>,
+    pub(super) currency: Option<&'a str>,
+    pub(super) min_fraction_digits: Option<usize>,
+    pub(super) max_fraction_digits: Option<usize>,
+}
+
+/// `(group separator, decimal separator, currency symbol first)`.
+fn separators(locale: &str) -> (&'static str, &'static str, bool) {
+    let language = locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match language.as_str() {
+        "de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" | "el" => (".", ",", false),
+        "fr" => ("\u{202f}", ",", false),
+        "ru" | "pl" | "cs" | "sk" | "uk" | "fi" | "sv" | "nb" | "no" | "hu" => {
+            ("\u{a0}", ",", false)
+        }
+        _ => (",", ".", true),
+    }
+}
+
+fn currency_symbol(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "USD" => "$",
+        "EUR" => "€",
+        "GBP" => "£",
+        "JPY" | "CNY" => "¥",
+        "INR" => "₹",
+        "KRW" => "₩",
+        _ => return None,
+    })
+}
+
+fn currency_digits(code: &str) -> usize {
+    match code {
+        "JPY" | "KRW" | "VND" | "CLP" | "ISK" => 0,
+        "BHD" | "KWD" | "OMR" | "JOD" | "TND" => 3,
+        _ => 2,
+    }
+}
+
+impl NumberFormat<'_> {
+    pub(super) fn format(&self, n: f64) -> String {
+        if !n.is_finite() {
+            return match js_number_to_string(n).as_str() {
+                "Infinity" => "∞".into(),
+                "-Infinity" => "-∞".into(),
+                other => other.into(),
+            };
+        }
+        let default_digits = self.currency.map_or(0, currency_digits);
+        let min = self.min_fraction_digits.unwrap_or(default_digits);
+        let max = self
+            .max_fraction_digits
+            .unwrap_or(if self.currency.is_some() {
+                default_digits
+            } else {
+                3
+            })
+            .max(min);
+        let (int, frac) = round_decimal(n.abs(), max);
+        let frac = frac.trim_end_matches('0');
+        let frac = format!("{frac:0<min$}");
+
+        let (group, decimal, symbol_first) = separators(self.locale.unwrap_or("en"));
+        let mut body = group_digits(&int, group);
+        if !frac.is_empty() {
+            body.push_str(decimal);
+            body.push_str(&frac);
+        }
+        let negative = n < 0.0 && (int != "0" || frac.chars().any(|c| c != '0'));
+        let sign = if negative { "-" } else { "" };
+        match self.currency {
+            None => format!("{sign}{body}"),
+            Some(code) => {
+                let symbol = currency_symbol(code);
+                match (symbol_first, symbol) {
+                    (true, Some(s)) => format!("{sign}{s}{body}"),
+                    (true, None) => format!("{sign}{code}\u{a0}{body}"),
+                    (false, s) => format!("{sign}{body}\u{a0}{}", s.unwrap_or(code)),
+                }
+            }
+        }
+    }
+}
+
+/// Round a non-negative `n` to `digits` fraction digits, half away from
+/// zero, returning the integer and fraction digit strings.
+fn round_decimal(n: f64, digits: usize) -> (String, String) {
+    if n == 0.0 {
+        return ("0".into(), "0".repeat(digits));
+    }
+    let (mantissa, point) = shortest_digits(n);
+    // Digits as a plain decimal: `int_digits.frac_digits`.
+    let mut all: Vec<u8> = mantissa.bytes().map(|b| b - b'0').collect();
+    let mut point = point;
+    if point <= 0 {
+        let pad = usize::try_from(-point).unwrap_or(0);
+        all.splice(0..0, std::iter::repeat_n(0, pad));
+        point = 0;
+    }
+    let point = usize::try_from(point).unwrap_or(0);
+    if all.len() < point + digits {
+        all.resize(point + digits, 0);
+    }
+    let round_up = all.get(point + digits).is_some_and(|&d| d >= 5);
+    all.truncate(point + digits);
+    let mut point = point;
+    if round_up {
+        let mut i = all.len();
+        loop {
+            if i == 0 {
+                all.insert(0, 1);
+                point += 1;
+                break;
+            }
+            i -= 1;
+            if all[i] == 9 {
+                all[i] = 0;
+            } else {
+                all[i] += 1;
+                break;
+            }
+        }
+    }
+    let to_str = |ds: &[u8]| ds.iter().map(|d| char::from(b'0' + d)).collect::<String>();
+    let int = to_str(&all[..point]);
+    let int = int.trim_start_matches('0');
+    (
+        if int.is_empty() {
+            "0".into()
+        } else {
+            int.into()
+        },
+        to_str(&all[point..]),
+    )
+}
+
+fn group_digits(int: &str, separator: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i).is_multiple_of(3) {
+            out.push_str(separator);
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_like_intl() {
+        let plain = NumberFormat::default();
+        assert_eq!(plain.format(1_234_567.891_23), "1,234,567.891");
+        assert_eq!(plain.format(1.005), "1.005");
+        assert_eq!(plain.format(0.0005), "0.001");
+        assert_eq!(plain.format(-0.0001), "0");
+        let fixed = NumberFormat {
+            min_fraction_digits: Some(2),
+            max_fraction_digits: Some(2),
+            ..NumberFormat::default()
+        };
+        assert_eq!(fixed.format(1.005), "1.01");
+        assert_eq!(fixed.format(9.999), "10.00");
+        let usd = NumberFormat {
+            currency: Some("USD"),
+            ..NumberFormat::default()
+        };
+        assert_eq!(usd.format(1234.5), "$1,234.50");
+        let eur_de = NumberFormat {
+            locale: Some("de-DE"),
+            currency: Some("EUR"),
+            ..NumberFormat::default()
+        };
+        assert_eq!(eur_de.format(1234.5), "1.234,50\u{a0}€");
+    }
+}
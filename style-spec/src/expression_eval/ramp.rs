@@ -0,0 +1,274 @@
+//! `step` and `interpolate` evaluation.
+
+use super::EvalError;
+use super::color_space::{hcl_to_rgb, lab_to_rgb};
+use super::value::{EvalValue, Rgba};
+
+/// An `interpolate` curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Interpolation {
+    Linear,
+    Exponential(f64),
+    CubicBezier([f64; 4]),
+}
+
+/// The colour space `interpolate` variants blend colours in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ColorSpace {
+    Rgb,
+    Lab,
+    Hcl,
+}
+
+/// Index of the stop whose range contains `input`: the last `i` with
+/// `stops[i] <= input`, or 0.
+pub(super) fn stop_index(stops: &[f64], input: f64) -> usize {
+    stops.partition_point(|&s| s <= input).saturating_sub(1)
+}
+
+impl Interpolation {
+    /// Progress of `input` between `lower` and `upper`, in `0..=1`.
+    pub(super) fn factor(self, input: f64, lower: f64, upper: f64) -> f64 {
+        match self {
+            Self::Linear => exponential_factor(input, 1.0, lower, upper),
+            Self::Exponential(base) => exponential_factor(input, base, lower, upper),
+            Self::CubicBezier([x1, y1, x2, y2]) => {
+                let t = exponential_factor(input, 1.0, lower, upper);
+                UnitBezier::new(x1, y1, x2, y2).solve(t)
+            }
+        }
+    }
+}
+
+fn exponential_factor(input: f64, base: f64, lower: f64, upper: f64) -> f64 {
+    let difference = upper - lower;
+    let progress = input - lower;
+    if difference == 0.0 {
+        0.0
+    } else if (base - 1.0).abs() < f64::EPSILON {
+        progress / difference
+    } else {
+        (base.powf(progress) - 1.0) / (base.powf(difference) - 1.0)
+    }
+}
+
+/// Port of `@mapbox/unitbezier`.
+struct UnitBezier {
+    ax: f64,
+    bx: f64,
+    cx: f64,
+    ay: f64,
+    by: f64,
+    cy: f64,
+}
+
+impl UnitBezier {
+    fn new(p1x: f64, p1y: f64, p2x: f64, p2y: f64) -> Self {
+        let cx = 3.0 * p1x;
+        let bx = 3.0 * (p2x - p1x) - cx;
+        let cy = 3.0 * p1y;
+        let by = 3.0 * (p2y - p1y) - cy;
+        Self {
+            ax: 1.0 - cx - bx,
+            bx,
+            cx,
+            ay: 1.0 - cy - by,
+            by,
+            cy,
+        }
+    }
+
+    fn sample_x(&self, t: f64) -> f64 {
+        ((self.ax * t + self.bx) * t + self.cx) * t
+    }
+
+    fn sample_y(&self, t: f64) -> f64 {
+        ((self.ay * t + self.by) * t + self.cy) * t
+    }
+
+    fn sample_dx(&self, t: f64) -> f64 {
+        (3.0 * self.ax * t + 2.0 * self.bx) * t + self.cx
+    }
+
+    fn solve_x(&self, x: f64) -> f64 {
+        const EPSILON: f64 = 1e-6;
+        if x < 0.0 {
+            return 0.0;
+        }
+        if x > 1.0 {
+            return 1.0;
+        }
+        // Newton's method first, bisection if it does not converge.
+        let mut t = x;
+        for _ in 0..8 {
+            let x2 = self.sample_x(t) - x;
+            if x2.abs() < EPSILON {
+                return t;
+            }
+            let d2 = self.sample_dx(t);
+            if d2.abs() < EPSILON {
+                break;
+            }
+            t -= x2 / d2;
+        }
+        let (mut t0, mut t1) = (0.0, 1.0);
+        t = x;
+        for _ in 0..20 {
+            let x2 = self.sample_x(t);
+            if (x2 - x).abs() < EPSILON {
+                break;
+            }
+            if x > x2 {
+                t0 = t;
+            } else {
+                t1 = t;
+            }
+            t = (t1 - t0) * 0.5 + t0;
+        }
+        t
+    }
+
+    fn solve(&self, x: f64) -> f64 {
+        self.sample_y(self.solve_x(x))
+    }
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// Blend two stop outputs.  Numbers, colours (or strings that parse as
+/// colours) and equal-length number arrays interpolate.
+pub(super) fn interpolate_values(
+    lower: &EvalValue,
+    upper: &EvalValue,
+    t: f64,
+    space: ColorSpace,
+) -> Result<EvalValue, EvalError> {
+    match (lower, upper) {
+        (EvalValue::Number(a), EvalValue::Number(b)) if space == ColorSpace::Rgb => {
+            Ok(EvalValue::Number(lerp(*a, *b, t)))
+        }
+        (EvalValue::Array(a), EvalValue::Array(b)) if space == ColorSpace::Rgb => {
+            if a.len() != b.len() {
+                return Err(EvalError::new(format!(
+                    "Cannot interpolate arrays of different lengths ({} and {}).",
+                    a.len(),
+                    b.len()
+                )));
+            }
+            a.iter()
+                .zip(b)
+                .map(|(x, y)| {
+                    Ok(EvalValue::Number(lerp(
+                        x.expect_number()?,
+                        y.expect_number()?,
+                        t,
+                    )))
+                })
+                .collect::<Result<_, _>>()
+                .map(EvalValue::Array)
+        }
+        _ => match (as_color(lower), as_color(upper)) {
+            (Some(a), Some(b)) => Ok(EvalValue::Color(interpolate_color(a, b, t, space))),
+            _ => Err(EvalError::new(format!(
+                "Type {} is not interpolatable.",
+                lower.type_name()
+            ))),
+        },
+    }
+}
+
+fn as_color(v: &EvalValue) -> Option<Rgba> {
+    match v {
+        EvalValue::Color(c) => Some(*c),
+        EvalValue::String(s) => Rgba::parse(s),
+        _ => None,
+    }
+}
+
+fn interpolate_color(from: Rgba, to: Rgba, t: f64, space: ColorSpace) -> Rgba {
+    match space {
+        ColorSpace::Rgb => Rgba {
+            r: lerp(from.r, to.r, t),
+            g: lerp(from.g, to.g, t),
+            b: lerp(from.b, to.b, t),
+            a: lerp(from.a, to.a, t),
+        },
+        ColorSpace::Lab => {
+            let (a, b) = (from.to_lab(), to.to_lab());
+            lab_to_rgb([0, 1, 2, 3].map(|i| lerp(a[i], b[i], t)))
+        }
+        ColorSpace::Hcl => {
+            let [hue0, chroma0, light0, alpha0] = from.to_hcl();
+            let [hue1, chroma1, light1, alpha1] = to.to_hcl();
+            let mut chroma = None;
+            let hue = match (hue0.is_nan(), hue1.is_nan()) {
+                (false, false) => {
+                    let mut dh = hue1 - hue0;
+                    if hue1 > hue0 && dh > 180.0 {
+                        dh -= 360.0;
+                    } else if hue1 < hue0 && hue0 - hue1 > 180.0 {
+                        dh += 360.0;
+                    }
+                    hue0 + t * dh
+                }
+                (false, true) => {
+                    if light1 == 1.0 || light1 == 0.0 {
+                        chroma = Some(chroma0);
+                    }
+                    hue0
+                }
+                (true, false) => {
+                    if light0 == 1.0 || light0 == 0.0 {
+                        chroma = Some(chroma1);
+                    }
+                    hue1
+                }
+                (true, true) => f64::NAN,
+            };
+            hcl_to_rgb([
+                hue,
+                chroma.unwrap_or_else(|| lerp(chroma0, chroma1, t)),
+                lerp(light0, light1, t),
+                lerp(alpha0, alpha1, t),
+            ])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn factors() {
+        assert!((Interpolation::Linear.factor(5.0, 0.0, 10.0) - 0.5).abs() < 1e-12);
+        let exp = Interpolation::Exponential(2.0).factor(1.0, 0.0, 2.0);
+        assert!((exp - 1.0 / 3.0).abs() < 1e-12);
+        let ease = Interpolation::CubicBezier([0.0, 0.0, 1.0, 1.0]).factor(2.5, 0.0, 10.0);
+        assert!((ease - 0.25).abs() < 1e-4);
+        assert_eq!(stop_index(&[0.0, 10.0, 20.0], 15.0), 1);
+        assert_eq!(stop_index(&[0.0, 10.0, 20.0], -5.0), 0);
+    }
+
+    #[test]
+    fn colors_blend_in_each_space() {
+        let black = EvalValue::String("black".into());
+        let white = EvalValue::String("white".into());
+        let EvalValue::Color(mid) =
+            interpolate_values(&black, &white, 0.5, ColorSpace::Rgb).unwrap()
+        else {
+            panic!("expected a colour");
+        };
+        assert!((mid.r - 0.5).abs() < 1e-9);
+        let EvalValue::Color(lab) =
+            interpolate_values(&black, &white, 0.5, ColorSpace::Lab).unwrap()
+        else {
+            panic!("expected a colour");
+        };
+        // Perceptual mid-grey is lighter than the sRGB average.
+        assert!(lab.r > 0.4 && lab.r < 0.5, "{lab:?}");
+        assert!(interpolate_values(&black, &EvalValue::Number(1.0), 0.5, ColorSpace::Rgb).is_err());
+    }
+}
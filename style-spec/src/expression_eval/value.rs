@@ -0,0 +1,423 @@
+//! Runtime values and their JavaScript-compatible conversions.
+
+use std::fmt::Write as _;
+
+use serde_json::{Map, Value};
+
+use super::EvalError;
+use super::color_space;
+
+/// A value produced by evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalValue {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Color(Rgba),
+    Collator(Collator),
+    Formatted(Formatted),
+    Image(ResolvedImage),
+    Array(Vec<EvalValue>),
+    Object(Map<String, Value>),
+}
+
+/// A colour with non-premultiplied sRGB channels in `0..=1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgba {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+    pub a: f64,
+}
+
+/// The options of a `["collator", {…}]` expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collator {
+    pub case_sensitive: bool,
+    pub diacritic_sensitive: bool,
+    pub locale: Option<String>,
+}
+
+/// The result of a `["format", …]` expression.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Formatted {
+    pub sections: Vec<FormattedSection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FormattedSection {
+    pub text: String,
+    pub image: Option<ResolvedImage>,
+    pub scale: Option<f64>,
+    /// Comma-separated font stack.
+    pub font_stack: Option<String>,
+    pub text_color: Option<Rgba>,
+    pub vertical_align: Option<String>,
+}
+
+/// An `["image", name]` result; `available` tells whether the style's sprite
+/// (or the caller) provides the image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedImage {
+    pub name: String,
+    pub available: bool,
+}
+
+impl EvalValue {
+    /// Convert a JSON value; numbers become `f64` as in JavaScript.
+    #[must_use]
+    pub fn from_json(v: &Value) -> Self {
+        match v {
+            Value::Null => Self::Null,
+            Value::Bool(b) => Self::Boolean(*b),
+            Value::Number(n) => Self::Number(n.as_f64().unwrap_or(f64::NAN)),
+            Value::String(s) => Self::String(s.clone()),
+            Value::Array(items) => Self::Array(items.iter().map(Self::from_json).collect()),
+            Value::Object(o) => Self::Object(o.clone()),
+        }
+    }
+
+    /// Convert to JSON.  Integral numbers become JSON integers, non-finite
+    /// numbers `null`; colours, images and formatted text become the strings
+    /// `to-string` produces.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        match self {
+            Self::Null => Value::Null,
+            Self::Boolean(b) => Value::Bool(*b),
+            Self::Number(n) => number_to_json(*n),
+            Self::String(s) => Value::String(s.clone()),
+            Self::Color(_) | Self::Formatted(_) | Self::Image(_) => {
+                Value::String(self.to_js_string())
+            }
+            Self::Collator(c) => serde_json::json!({
+                "case-sensitive": c.case_sensitive,
+                "diacritic-sensitive": c.diacritic_sensitive,
+                "locale": c.locale,
+            }),
+            Self::Array(items) => Value::Array(items.iter().map(Self::to_json).collect()),
+            Self::Object(o) => Value::Object(o.clone()),
+        }
+    }
+
+    /// The expression type name, as `typeof` reports it (e.g. `array<number, 2>`).
+    #[must_use]
+    pub fn type_name(&self) -> String {
+        match self {
+            Self::Null => "null".into(),
+            Self::Boolean(_) => "boolean".into(),
+            Self::Number(_) => "number".into(),
+            Self::String(_) => "string".into(),
+            Self::Color(_) => "color".into(),
+            Self::Collator(_) => "collator".into(),
+            Self::Formatted(_) => "formatted".into(),
+            Self::Image(_) => "resolvedImage".into(),
+            Self::Object(_) => "object".into(),
+            Self::Array(items) => {
+                let mut item_type: Option<String> = None;
+                for item in items {
+                    let t = item.type_name();
+                    match &item_type {
+                        None => item_type = Some(t),
+                        Some(prev) if *prev == t => {}
+                        Some(_) => {
+                            item_type = Some("value".into());
+                            break;
+                        }
+                    }
+                }
+                format!(
+                    "array<{}, {}>",
+                    item_type.as_deref().unwrap_or("value"),
+                    items.len()
+                )
+            }
+        }
+    }
+
+    /// The base kind of [`type_name`](Self::type_name) (`array` for every array).
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Boolean(_) => "boolean",
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::Color(_) => "color",
+            Self::Collator(_) => "collator",
+            Self::Formatted(_) => "formatted",
+            Self::Image(_) => "resolvedImage",
+            Self::Object(_) => "object",
+            Self::Array(_) => "array",
+        }
+    }
+
+    /// JavaScript truthiness, as `to-boolean` applies it.
+    #[must_use]
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Self::Null => false,
+            Self::Boolean(b) => *b,
+            Self::Number(n) => *n != 0.0 && !n.is_nan(),
+            Self::String(s) => !s.is_empty(),
+            _ => true,
+        }
+    }
+
+    /// The string `to-string` and `concat` produce.
+    #[must_use]
+    pub fn to_js_string(&self) -> String {
+        match self {
+            Self::Null => String::new(),
+            Self::Boolean(b) => b.to_string(),
+            Self::Number(n) => js_number_to_string(*n),
+            Self::String(s) => s.clone(),
+            Self::Color(c) => c.to_js_string(),
+            Self::Formatted(f) => f.sections.iter().map(|s| s.text.as_str()).collect(),
+            Self::Image(image) => image.name.clone(),
+            Self::Collator(_) | Self::Array(_) | Self::Object(_) => {
+                let mut out = String::new();
+                write_js_json(&self.to_json(), &mut out);
+                out
+            }
+        }
+    }
+
+    pub(super) fn expect_number(&self) -> Result<f64, EvalError> {
+        match self {
+            Self::Number(n) => Ok(*n),
+            other => Err(EvalError::type_mismatch("number", other)),
+        }
+    }
+
+    pub(super) fn expect_bool(&self) -> Result<bool, EvalError> {
+        match self {
+            Self::Boolean(b) => Ok(*b),
+            other => Err(EvalError::type_mismatch("boolean", other)),
+        }
+    }
+}
+
+impl Rgba {
+    pub const TRANSPARENT: Self = Self {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 0.0,
+    };
+
+    /// Parse a CSS colour string.
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        let c = color::parse_color(s.trim())
+            .ok()?
+            .to_alpha_color::<color::Srgb>();
+        let [r, g, b, a] = c.components.map(f64::from);
+        Some(Self { r, g, b, a })
+    }
+
+    /// `rgba(r,g,b,a)` with channels rounded to `0..=255`, as GL JS prints colours.
+    #[must_use]
+    pub fn to_js_string(self) -> String {
+        let channel = |c: f64| js_number_to_string((c * 255.0 + 0.5).floor());
+        format!(
+            "rgba({},{},{},{})",
+            channel(self.r),
+            channel(self.g),
+            channel(self.b),
+            js_number_to_string(self.a)
+        )
+    }
+
+    /// `[r, g, b, a]` with `r`, `g`, `b` in `0..=255`, as `to-rgba` returns.
+    #[must_use]
+    pub fn to_rgba_array(self) -> [f64; 4] {
+        [self.r * 255.0, self.g * 255.0, self.b * 255.0, self.a]
+    }
+
+    pub(super) fn to_lab(self) -> [f64; 4] {
+        color_space::rgb_to_lab(self)
+    }
+
+    pub(super) fn to_hcl(self) -> [f64; 4] {
+        color_space::rgb_to_hcl(self)
+    }
+}
+
+pub(super) fn number_to_json(n: f64) -> Value {
+    const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+    if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER {
+        #[expect(clippy::cast_possible_truncation)]
+        return Value::from(n as i64);
+    }
+    serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number)
+}
+
+/// `Number.prototype.toString()`: shortest round-trip digits, exponent form
+/// outside `1e-7..1e21`.
+#[must_use]
+pub fn js_number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".into();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "Infinity" } else { "-Infinity" }.into();
+    }
+    if n == 0.0 {
+        return "0".into();
+    }
+    let (digits, point) = shortest_digits(n.abs());
+    let k = i32::try_from(digits.len()).unwrap_or(i32::MAX);
+    let mut out = String::new();
+    if n < 0.0 {
+        out.push('-');
+    }
+    if k <= point && point <= 21 {
+        out.push_str(&digits);
+        for _ in k..point {
+            out.push('0');
+        }
+    } else if 0 < point && point <= 21 {
+        let (int, frac) = digits.split_at(usize::try_from(point).unwrap_or(0));
+        let _ = write!(out, "{int}.{frac}");
+    } else if -6 < point && point <= 0 {
+        out.push_str("0.");
+        for _ in point..0 {
+            out.push('0');
+        }
+        out.push_str(&digits);
+    } else {
+        let (first, rest) = digits.split_at(1);
+        out.push_str(first);
+        if !rest.is_empty() {
+            let _ = write!(out, ".{rest}");
+        }
+        let exp = point - 1;
+        let _ = write!(out, "e{}{exp}", if exp < 0 { "" } else { "+" });
+    }
+    out
+}
+
+/// Shortest decimal digits of a positive finite `n`, and the position of
+/// the decimal point relative to them (`n = 0.digits × 10^point`).
+pub(super) fn shortest_digits(n: f64) -> (String, i32) {
+    let sci = format!("{n:e}");
+    let (mantissa, exp) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    let digits = digits.trim_end_matches('0');
+    let digits = if digits.is_empty() { "0" } else { digits };
+    (digits.to_owned(), exp.parse::<i32>().unwrap_or(0) + 1)
+}
+
+/// `JSON.stringify`, with JavaScript number formatting.
+fn write_js_json(v: &Value, out: &mut String) {
+    match v {
+        Value::Number(n) => out.push_str(&js_number_to_string(n.as_f64().unwrap_or(f64::NAN))),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_js_json(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(o) => {
+            out.push('{');
+            for (i, (k, item)) in o.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(k.clone()).to_string());
+                out.push(':');
+                write_js_json(item, out);
+            }
+            out.push('}');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// `Number(s)` for a string: trimmed decimal, `0x`/`0o`/`0b` integers,
+/// `Infinity`; the empty string is `0`.
+pub(super) fn js_string_to_number(s: &str) -> f64 {
+    let s = s.trim();
+    if s.is_empty() {
+        return 0.0;
+    }
+    for (prefix, radix) in [
+        ("0x", 16),
+        ("0X", 16),
+        ("0o", 8),
+        ("0O", 8),
+        ("0b", 2),
+        ("0B", 2),
+    ] {
+        if let Some(rest) = s.strip_prefix(prefix) {
+            #[expect(clippy::cast_precision_loss)]
+            return u64::from_str_radix(rest, radix).map_or(f64::NAN, |n| n as f64);
+        }
+    }
+    match s {
+        "Infinity" | "+Infinity" => return f64::INFINITY,
+        "-Infinity" => return f64::NEG_INFINITY,
+        _ => {}
+    }
+    // Rust also accepts `inf`/`nan` spellings JavaScript does not.
+    if s.chars()
+        .any(|c| !(c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-')))
+    {
+        return f64::NAN;
+    }
+    s.parse().unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn numbers_print_like_javascript() {
+        for (n, s) in [
+            (1.0, "1"),
+            (-0.0, "0"),
+            (0.1, "0.1"),
+            (123.456, "123.456"),
+            (1e21, "1e+21"),
+            (1e20, "100000000000000000000"),
+            (1e-7, "1e-7"),
+            (0.000_001, "0.000001"),
+            (1.5e-9, "1.5e-9"),
+            (f64::NAN, "NaN"),
+        ] {
+            assert_eq!(js_number_to_string(n), s);
+        }
+    }
+
+    #[test]
+    fn strings_convert_like_number() {
+        assert!(js_string_to_number("").abs() < f64::EPSILON);
+        assert!((js_string_to_number(" 12.5 ") - 12.5).abs() < f64::EPSILON);
+        assert!((js_string_to_number("0x10") - 16.0).abs() < f64::EPSILON);
+        assert!(js_string_to_number("inf").is_nan());
+        assert!(js_string_to_number("12px").is_nan());
+    }
+
+    #[test]
+    fn type_names_and_strings() {
+        let v = EvalValue::from_json(&json!([1, 2.5, "a"]));
+        assert_eq!(v.type_name(), "array<value, 3>");
+        assert_eq!(v.to_js_string(), r#"[1,2.5,"a"]"#);
+        assert_eq!(
+            EvalValue::from_json(&json!([])).type_name(),
+            "array<value, 0>"
+        );
+        let red = Rgba::parse("red").unwrap();
+        assert_eq!(red.to_js_string(), "rgba(255,0,0,1)");
+        assert_eq!(EvalValue::Number(3.0).to_json(), json!(3));
+    }
+}
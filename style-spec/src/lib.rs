@@ -1,4 +1,5 @@
 pub mod decoder;
+pub mod expression_eval;
 pub mod generator;
 pub mod mir;
 
@@ -316,7 +316,7 @@ impl MirExpressionOperator {
     pub fn is_pure(&self) -> bool {
         matches!(
             self.group.as_deref(),
-            Some("Math" | "String" | "Type" | "Color")
+            Some("Math" | "String" | "Types" | "Color")
         )
     }
 